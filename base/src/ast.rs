@@ -322,12 +322,15 @@ pub enum Expr<Id> {
     Projection(Box<SpannedExpr<Id>>, Id, ArcType<Id>),
     /// Array construction
     Array(Array<Id>),
-    /// Record construction
+    /// Record construction, eg. `{ x = 1, .. base }` or `{ base with x = 1 }`
     Record {
         typ: ArcType<Id>,
         types: Vec<ExprField<Id, ArcType<Id>>>,
         exprs: Vec<ExprField<Id, SpannedExpr<Id>>>,
         base: Option<Box<SpannedExpr<Id>>>,
+        /// `true` if the record was written as `{ base with x = 1 }` which, unlike
+        /// `{ x = 1, .. base }`, may only replace fields which exist in `base`
+        update: bool,
     },
     /// Tuple construction
     Tuple {
@@ -6,14 +6,16 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::io;
 use std::iter::{Extend, FromIterator};
-use std::ops::{Index, IndexMut};
+use std::ops::{Index, IndexMut, Range};
 use std::slice;
 use std::str;
 use std::vec;
 
-use codespan_reporting::{Diagnostic, Label};
+use codespan_reporting::{Diagnostic, Label, LabelStyle};
 
-use crate::pos::{BytePos, Span, Spanned};
+pub use codespan_reporting::Severity;
+
+use crate::pos::{BytePos, Location, Span, Spanned};
 
 /// An error type which can represent multiple errors.
 #[derive(Clone, Debug, PartialEq)]
@@ -197,6 +199,17 @@ impl<E: fmt::Display> InFile<E> {
         self.error
     }
 
+    /// Returns a `SourceDiagnostic` for each of the errors, in the order that they were found
+    pub fn diagnostics(&self, code_map: &::codespan::CodeMap) -> Vec<SourceDiagnostic>
+    where
+        E: AsDiagnostic,
+    {
+        self.error
+            .iter()
+            .map(|error| SourceDiagnostic::new(code_map, &error.as_diagnostic()))
+            .collect()
+    }
+
     pub fn emit_string(&self, code_map: &::codespan::CodeMap) -> io::Result<String>
     where
         E: AsDiagnostic,
@@ -214,16 +227,11 @@ impl<E: fmt::Display> InFile<E> {
         W: ?Sized + ::codespan_reporting::termcolor::WriteColor,
         E: AsDiagnostic,
     {
-        let iter = self
-            .error
-            .iter()
-            .map(AsDiagnostic::as_diagnostic)
-            .enumerate();
-        for (i, diagnostic) in iter {
+        for (i, diagnostic) in self.diagnostics(code_map).iter().enumerate() {
             if i != 0 {
                 writeln!(writer)?;
             }
-            ::codespan_reporting::emit(&mut *writer, &code_map, &diagnostic)?;
+            diagnostic.emit(writer, code_map)?;
         }
         Ok(())
     }
@@ -276,6 +284,131 @@ impl<E, H> From<E> for Help<E, H> {
     }
 }
 
+/// A label of a `SourceDiagnostic` which points into a file of the code map
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLabel {
+    /// The name of the module or the path of the file that the label points into
+    pub source_name: String,
+    /// The span of the label in the code map
+    pub span: Span<BytePos>,
+    /// The span of the label as byte offsets from the start of its file
+    pub byte_range: Range<usize>,
+    pub start: Location,
+    pub end: Location,
+    pub message: Option<String>,
+}
+
+impl SourceLabel {
+    fn new(code_map: &::codespan::CodeMap, label: &Label) -> Option<SourceLabel> {
+        let file = code_map.find_file(label.span.start())?;
+        let location = |pos| {
+            let (line, column) = file.location(pos).ok()?;
+            Some(Location {
+                line,
+                column,
+                absolute: pos,
+            })
+        };
+        let file_start = file.span().start().to_usize();
+        Some(SourceLabel {
+            source_name: match *file.name() {
+                ::codespan::FileName::Real(ref path) => path.display().to_string(),
+                ::codespan::FileName::Virtual(ref name) => name.to_string(),
+            },
+            span: label.span,
+            byte_range: label.span.start().to_usize() - file_start
+                ..label.span.end().to_usize() - file_start,
+            start: location(label.span.start())?,
+            end: location(label.span.end())?,
+            message: label.message.clone(),
+        })
+    }
+}
+
+/// A diagnostic with its labels resolved to positions in the files that they point into, for
+/// tools such as editors which want to present errors themselves instead of displaying the
+/// rendered text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceDiagnostic {
+    pub severity: Severity,
+    pub code: Option<String>,
+    pub message: String,
+    /// Where the diagnostic occurred, `None` if it does not refer to any source
+    pub primary: Option<SourceLabel>,
+    /// Other locations which are related to the diagnostic
+    pub secondary: Vec<SourceLabel>,
+    /// Messages which do not point at a location, such as help texts
+    pub notes: Vec<String>,
+    /// The diagnostic as `emit` displays it, without any colors
+    pub rendered: String,
+}
+
+impl SourceDiagnostic {
+    pub fn new(code_map: &::codespan::CodeMap, diagnostic: &Diagnostic) -> SourceDiagnostic {
+        let mut source_diagnostic = SourceDiagnostic {
+            severity: diagnostic.severity,
+            code: diagnostic.code.clone(),
+            message: diagnostic.message.clone(),
+            primary: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            rendered: String::new(),
+        };
+        for label in &diagnostic.labels {
+            match SourceLabel::new(code_map, label) {
+                Some(source_label) => match label.style {
+                    LabelStyle::Primary if source_diagnostic.primary.is_none() => {
+                        source_diagnostic.primary = Some(source_label)
+                    }
+                    _ => source_diagnostic.secondary.push(source_label),
+                },
+                None => source_diagnostic.notes.extend(label.message.clone()),
+            }
+        }
+
+        let mut output = Vec::new();
+        source_diagnostic
+            .emit(
+                &mut ::codespan_reporting::termcolor::NoColor::new(&mut output),
+                code_map,
+            )
+            .unwrap();
+        source_diagnostic.rendered = String::from_utf8(output).unwrap();
+        source_diagnostic
+    }
+
+    pub fn emit<W>(&self, writer: &mut W, code_map: &::codespan::CodeMap) -> io::Result<()>
+    where
+        W: ?Sized + ::codespan_reporting::termcolor::WriteColor,
+    {
+        let label = |label: &SourceLabel, style| Label {
+            span: label.span,
+            message: label.message.clone(),
+            style,
+        };
+        let diagnostic = Diagnostic {
+            severity: self.severity,
+            code: self.code.clone(),
+            message: self.message.clone(),
+            labels: self
+                .primary
+                .iter()
+                .map(|primary| label(primary, LabelStyle::Primary))
+                .chain(
+                    self.secondary
+                        .iter()
+                        .map(|secondary| label(secondary, LabelStyle::Secondary)),
+                )
+                .chain(self.notes.iter().map(|note| {
+                    Label::new_secondary(Span::new(BytePos::none(), BytePos::none()))
+                        .with_message(note.clone())
+                }))
+                .collect(),
+        };
+        ::codespan_reporting::emit(writer, code_map, &diagnostic)
+    }
+}
+
 pub trait AsDiagnostic {
     fn as_diagnostic(&self) -> Diagnostic;
 }
@@ -120,6 +120,19 @@ impl Metadata {
             .map(|t| t.arguments.as_ref().map_or("", |s| s))
     }
 
+    /// Returns the message of the `#[deprecated("message")]` attribute if there is one. The
+    /// message is empty if the attribute does not have any arguments.
+    pub fn deprecation(&self) -> Option<&str> {
+        self.get_attribute("deprecated").map(|arguments| {
+            let arguments = arguments.trim();
+            if arguments.len() >= 2 && arguments.starts_with('"') && arguments.ends_with('"') {
+                &arguments[1..arguments.len() - 1]
+            } else {
+                arguments
+            }
+        })
+    }
+
     pub fn attributes(&self) -> impl Iterator<Item = &Attribute> {
         self.attributes.iter()
     }
@@ -2069,7 +2069,8 @@ where
     }
 }
 
-fn is_tuple<I, T>(typ: &T) -> bool
+/// Returns true if `typ` is a tuple, a record of the fields `_0`, `_1`, ... and no types
+pub fn is_tuple<I, T>(typ: &T) -> bool
 where
     I: AsRef<str>,
     T: Deref<Target = Type<I, T>>,
@@ -2126,10 +2127,16 @@ where
                         })),
                         "."
                     ].group(),
-                    arena.space(),
+                    // Keep the line break after `.` so that the type is laid out the same way if
+                    // it is formatted again
+                    if printer.only_newlines_before(typ.span().start()) {
+                        arena.newline()
+                    } else {
+                        arena.space()
+                    },
                     top(typ).pretty(printer)
                 ];
-                p.enclose(Prec::Function, arena, doc)
+                p.enclose(Prec::Function, arena, doc).group()
             }
             Type::Variable(ref var) => arena.text(format!("{}", var.id)),
             Type::Skolem(ref skolem) => chain![
@@ -2139,9 +2146,9 @@ where
                 skolem.id.to_string()
             ],
             Type::Generic(ref gen) => arena.text(gen.id.as_ref()),
-            Type::Function(..) => self.pretty_function(printer).nest(INDENT),
+            Type::Function(..) => self.pretty_function(printer).nest(printer.indent),
             Type::App(ref t, ref args) => match self.typ.as_function() {
-                Some(_) => self.pretty_function(printer).nest(INDENT),
+                Some(_) => self.pretty_function(printer).nest(printer.indent),
                 None => {
                     let doc = dt(Prec::Top, t).pretty(printer);
                     let arg_doc = arena.concat(args.iter().map(|arg| {
@@ -2149,7 +2156,7 @@ where
                             .space()
                             .append(dt(Prec::Constructor, arg).pretty(printer))
                     }));
-                    let doc = doc.append(arg_doc.nest(INDENT));
+                    let doc = doc.append(arg_doc.nest(printer.indent));
                     p.enclose(Prec::Constructor, arena, doc).group()
                 }
             },
@@ -2264,7 +2271,7 @@ where
         match **typ {
             Type::App(..) | Type::ExtendRow { .. } | Type::Variant(..) | Type::Function(..) => doc,
             _ => {
-                let comment = printer.comments_before(typ.span().start());
+                let comment = printer.only_comments_before(typ.span().start());
                 comment.append(doc)
             }
         }
@@ -2302,15 +2309,17 @@ where
         };
 
         doc = match **row {
+            // `[||]` would be lexed as the `||` operator
+            Type::EmptyRow if open == "[|" => doc.append(" "),
             Type::EmptyRow => doc,
             Type::ExtendRow { .. } => doc
                 .append(top(row).pretty_row(open, printer, pretty_field))
-                .nest(INDENT),
+                .nest(printer.indent),
             _ => doc
                 .append(arena.space())
                 .append("| ")
                 .append(top(row).pretty(printer))
-                .nest(INDENT),
+                .nest(printer.indent),
         };
         if !empty_fields && open != "(" {
             doc = doc.append(newline);
@@ -2413,12 +2422,17 @@ where
                 match *field.typ {
                     // Records handle nesting on their own
                     Type::Record(_) => (),
-                    _ => rhs = rhs.nest(INDENT),
+                    _ => rhs = rhs.nest(printer.indent),
                 }
+                // Dropped fields are not printed so only put a comma between this field and the
+                // next field which is printed
+                let is_last = fields[i + 1..]
+                    .iter()
+                    .all(|field| printer.filter(&field.name) == Filter::Drop);
                 let f = chain![arena;
                     pretty_field(field),
                     rhs.group(),
-                    if i + 1 != fields.len() {
+                    if !is_last {
                         arena.text(",")
                     } else {
                         arena.nil()
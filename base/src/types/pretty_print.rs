@@ -129,6 +129,7 @@ impl<'a, I, T, A> TypeFormatter<'a, I, T, A> {
             source: &(),
             filter: self.filter,
             annotate_symbol: self.annotate_symbol,
+            indent: super::INDENT,
         })
     }
 
@@ -138,6 +139,7 @@ impl<'a, I, T, A> TypeFormatter<'a, I, T, A> {
             source,
             filter: self.filter,
             annotate_symbol: self.annotate_symbol,
+            indent: super::INDENT,
         }
     }
 }
@@ -167,6 +169,7 @@ pub struct Printer<'a, I: 'a, A: 'a> {
     pub source: &'a Source,
     filter: &'a Fn(&I) -> Filter,
     annotate_symbol: &'a Fn(&I) -> Option<A>,
+    pub(crate) indent: usize,
 }
 
 impl<'a, I, A> Printer<'a, I, A> {
@@ -176,9 +179,16 @@ impl<'a, I, A> Printer<'a, I, A> {
             source,
             filter: &|_| Filter::Retain,
             annotate_symbol: &|_| None,
+            indent: super::INDENT,
         }
     }
 
+    /// Sets the number of spaces that nested lines are indented with
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
     pub fn filter(&self, field: &I) -> Filter {
         (self.filter)(field)
     }
@@ -214,11 +224,23 @@ impl<'a, I, A> Printer<'a, I, A> {
         let doc = self.comments_after(end);
         if let Doc::Nil = doc.1 {
             arena.space()
+        } else if self.starts_with_newline(end) {
+            // The newline already separates `end` from the next token
+            doc
         } else {
             arena.space().append(doc)
         }
     }
 
+    fn starts_with_newline(&self, end: BytePos) -> bool {
+        end != 0.into()
+            && self
+                .source
+                .comments_between(Span::new(end, self.source.span().end()))
+                .next()
+                == Some("")
+    }
+
     pub fn comments_before(&self, pos: BytePos) -> DocBuilder<'a, Arena<'a, A>, A> {
         let (doc, comments) = self.comments_before_(pos);
         if comments {
@@ -228,6 +250,28 @@ impl<'a, I, A> Printer<'a, I, A> {
         }
     }
 
+    /// Like `comments_before` but ignores the whitespace before `pos` if it only contains
+    /// newlines
+    pub fn only_comments_before(&self, pos: BytePos) -> DocBuilder<'a, Arena<'a, A>, A> {
+        if self.only_newlines_before(pos) {
+            self.arena.nil()
+        } else {
+            self.comments_before(pos)
+        }
+    }
+
+    /// Returns true if the whitespace before `pos` contains a line break but no comments
+    pub fn only_newlines_before(&self, pos: BytePos) -> bool {
+        if pos == 0.into() {
+            return false;
+        }
+        let mut iter = self
+            .source
+            .comments_between(Span::new(self.source.span().start(), pos))
+            .rev();
+        iter.next() == Some("") && iter.all(|comment| comment.is_empty())
+    }
+
     fn comments_before_(&self, pos: BytePos) -> (DocBuilder<'a, Arena<'a, A>, A>, bool) {
         let arena = self.arena;
 
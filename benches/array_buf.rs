@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate criterion;
+
+extern crate gluon;
+
+use criterion::{black_box, Bencher, Criterion};
+
+use gluon::vm::api::{OwnedFunction, IO};
+use gluon::vm::types::VmInt;
+use gluon::{new_vm, Compiler};
+
+fn buf_push(b: &mut Bencher, len: &VmInt) {
+    let vm = new_vm();
+    let text = r#"
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+        let array @ { buf } = import! std.array
+        \n ->
+            do xs = buf.new ()
+            rec let fill i =
+                if i == n then wrap ()
+                else
+                    seq buf.push i xs
+                    fill (i + 1)
+            seq fill 0
+            do frozen = buf.freeze xs
+            wrap (array.len frozen)
+        "#;
+    let mut build = Compiler::new()
+        .run_expr::<OwnedFunction<fn(VmInt) -> IO<VmInt>>>(&vm, "buf_push", text)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .0;
+    b.iter(|| {
+        let result = build.call(*len).unwrap();
+        black_box(result)
+    })
+}
+
+fn functional_append(b: &mut Bencher, len: &VmInt) {
+    let vm = new_vm();
+    let text = r#"
+        let array = import! std.array
+        \n ->
+            rec let fill i acc =
+                if i == n then acc
+                else fill (i + 1) (array.append acc [i])
+            array.len (fill 0 [])
+        "#;
+    let mut build = Compiler::new()
+        .run_expr::<OwnedFunction<fn(VmInt) -> VmInt>>(&vm, "functional_append", text)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .0;
+    b.iter(|| {
+        let result = build.call(*len).unwrap();
+        black_box(result)
+    })
+}
+
+fn array_buf_benchmark(c: &mut Criterion) {
+    c.bench_function_over_inputs("buf push", buf_push, vec![1_000, 10_000, 1_000_000]);
+    // Each append copies the whole array, a million elements would take hours
+    c.bench_function_over_inputs("functional append", functional_append, vec![1_000, 10_000]);
+}
+
+criterion_group!(array_buf, array_buf_benchmark);
+criterion_main!(array_buf);
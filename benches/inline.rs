@@ -0,0 +1,49 @@
+#[macro_use]
+extern crate criterion;
+
+extern crate gluon;
+
+use criterion::{black_box, Bencher, Criterion};
+
+use gluon::vm::api::FunctionRef;
+use gluon::{new_vm, Compiler};
+
+// Benchmarks a numeric loop which calls small functions from another module
+fn sum_of_squares(b: &mut Bencher, inline_threshold: usize) {
+    let vm = new_vm();
+    let math = r#"
+    let square x = x #Int* x
+    let add x y = x #Int+ y
+    { square, add }
+    "#;
+    Compiler::new()
+        .implicit_prelude(false)
+        .inline_threshold(inline_threshold)
+        .load_script(&vm, "bench.math", math)
+        .unwrap();
+    let text = r#"
+    let { square, add } = import! bench.math
+    let sum_of_squares acc n =
+        if n #Int== 0 then acc
+        else sum_of_squares (add acc (square n)) (n #Int- 1)
+    sum_of_squares 0
+    "#;
+    Compiler::new()
+        .implicit_prelude(false)
+        .inline_threshold(inline_threshold)
+        .load_script(&vm, "sum_of_squares", text)
+        .unwrap();
+    let mut sum_of_squares: FunctionRef<fn(i32) -> i32> = vm.get_global("sum_of_squares").unwrap();
+    b.iter(|| {
+        let result = sum_of_squares.call(1000).unwrap();
+        black_box(result)
+    })
+}
+
+fn inline_benchmark(c: &mut Criterion) {
+    c.bench_function("sum of squares", |b| sum_of_squares(b, 8));
+    c.bench_function("sum of squares without inlining", |b| sum_of_squares(b, 0));
+}
+
+criterion_group!(inline, inline_benchmark);
+criterion_main!(inline);
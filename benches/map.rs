@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate criterion;
+
+extern crate gluon;
+
+use criterion::{black_box, Bencher, Criterion};
+
+use gluon::vm::api::OwnedFunction;
+use gluon::vm::types::VmInt;
+use gluon::{new_vm, Compiler, RootedThread};
+
+type BuildMap = OwnedFunction<fn(Vec<(VmInt, VmInt)>) -> VmInt>;
+
+fn build_map(vm: &RootedThread, build: &str) -> BuildMap {
+    let text = format!(
+        r#"
+        let map @ {{ Map }} = import! std.map
+        let array = import! std.array
+        let {{ ? }} = import! std.int
+        let build entries : Array (Int, Int) -> Map Int Int = {}
+        \entries -> map.size (build entries)
+        "#,
+        build
+    );
+    Compiler::new()
+        .run_expr::<BuildMap>(vm, "build_map", &text)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .0
+}
+
+/// Keys in a scattered order as inserting sorted keys into the unbalanced tree is quadratic
+fn entries(len: VmInt) -> Vec<(VmInt, VmInt)> {
+    (0..len).map(|i| ((i * 7919) % len, i)).collect()
+}
+
+fn fold_insert(b: &mut Bencher, len: &VmInt) {
+    let vm = new_vm();
+    let mut build = build_map(
+        &vm,
+        "array.foldable.foldl (\\m entry -> map.insert entry._0 entry._1 m) map.empty entries",
+    );
+    let entries = entries(*len);
+    b.iter(|| {
+        let result = build.call(entries.clone()).unwrap();
+        black_box(result)
+    })
+}
+
+fn from_array(b: &mut Bencher, len: &VmInt) {
+    let vm = new_vm();
+    let mut build = build_map(&vm, "map.from_array entries");
+    let entries = entries(*len);
+    b.iter(|| {
+        let result = build.call(entries.clone()).unwrap();
+        black_box(result)
+    })
+}
+
+fn map_benchmark(c: &mut Criterion) {
+    let lengths = vec![100, 10_000];
+    c.bench_function_over_inputs("fold insert", fold_insert, lengths.clone());
+    c.bench_function_over_inputs("from_array", from_array, lengths);
+}
+
+criterion_group!(map, map_benchmark);
+criterion_main!(map);
@@ -0,0 +1,96 @@
+#[macro_use]
+extern crate criterion;
+
+extern crate gluon;
+
+use criterion::{black_box, Bencher, Criterion};
+
+use gluon::vm::api::{OwnedFunction, IO};
+use gluon::vm::types::VmInt;
+use gluon::{new_vm, Compiler, RootedThread};
+
+fn load<T>(vm: &RootedThread, name: &str, expr: &str) -> T
+where
+    T: for<'vm, 'value> gluon::vm::api::Getable<'vm, 'value>
+        + gluon::vm::api::VmType
+        + Send
+        + 'static,
+{
+    let text = format!(
+        r#"
+        let {{ wrap }} = import! std.applicative
+        let {{ ? }} = import! std.io
+        let array = import! std.array
+        let string @ {{ builder }} = import! std.string
+        {}
+        "#,
+        expr
+    );
+    Compiler::new()
+        .run_expr::<T>(vm, name, &text)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .0
+}
+
+fn builder_push(b: &mut Bencher, len: &VmInt) {
+    let vm = new_vm();
+    let mut build: OwnedFunction<fn(VmInt) -> IO<VmInt>> = load(
+        &vm,
+        "builder_push",
+        r#"
+        \n ->
+            do b = builder.new ()
+            rec let loop i =
+                if i == 0 then wrap ()
+                else
+                    seq builder.push "ab" b
+                    loop (i - 1)
+            seq loop n
+            do s = builder.build b
+            wrap (string.len s)
+        "#,
+    );
+    b.iter(|| {
+        let result = build.call(*len).unwrap();
+        black_box(result)
+    })
+}
+
+fn concat_array(b: &mut Bencher, len: &VmInt) {
+    let vm = new_vm();
+    let mut build: OwnedFunction<fn(Vec<String>) -> VmInt> = load(
+        &vm,
+        "concat_array",
+        r#"\pieces -> string.len (string.concat_array pieces)"#,
+    );
+    let pieces = vec!["ab".to_string(); *len as usize];
+    b.iter(|| {
+        let result = build.call(pieces.clone()).unwrap();
+        black_box(result)
+    })
+}
+
+fn fold_append(b: &mut Bencher, len: &VmInt) {
+    let vm = new_vm();
+    let mut build: OwnedFunction<fn(Vec<String>) -> VmInt> = load(
+        &vm,
+        "fold_append",
+        r#"\pieces -> string.len (array.foldable.foldl (\acc s -> acc ++ s) "" pieces)"#,
+    );
+    let pieces = vec!["ab".to_string(); *len as usize];
+    b.iter(|| {
+        let result = build.call(pieces.clone()).unwrap();
+        black_box(result)
+    })
+}
+
+fn string_builder_benchmark(c: &mut Criterion) {
+    let lengths = vec![1_000, 10_000, 100_000];
+    c.bench_function_over_inputs("builder push", builder_push, lengths.clone());
+    c.bench_function_over_inputs("concat_array", concat_array, lengths);
+    // Repeated `++` is quadratic, 100_000 appends would copy gigabytes
+    c.bench_function_over_inputs("fold ++", fold_append, vec![1_000, 10_000]);
+}
+
+criterion_group!(string_builder, string_builder_benchmark);
+criterion_main!(string_builder);
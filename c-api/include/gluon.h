@@ -0,0 +1,153 @@
+#ifndef GLUON_H
+#define GLUON_H
+
+/* Generated with cbindgen from `c-api/src/lib.rs`, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The result of the functions of the C API. Unless `Ok` is returned the message of the error can
+ * be retrieved with `glu_last_error`.
+ */
+typedef enum {
+  Error_Ok,
+  /**
+   * An error returned by gluon, such as a type error or a runtime error
+   */
+  Error_Unknown,
+  /**
+   * A string passed to the function was not valid utf-8
+   */
+  Error_InvalidUtf8,
+  /**
+   * The function panicked, the vm may be in an inconsistent state afterwards
+   */
+  Error_Panic,
+} Error;
+
+/**
+ * Enum signaling a successful or unsuccess ful call to an extern function.
+ * If an error occured the error message is expected to be on the top of the stack.
+ */
+typedef enum {
+  Status_Ok,
+  Status_Yield,
+  Status_Error,
+} Status;
+
+typedef struct Thread Thread;
+
+typedef Status (*Function)(const Thread*);
+
+/**
+ * A function which is passed the `userdata` it was pushed with, see
+ * `glu_push_function_with_userdata`
+ */
+typedef Status (*FunctionWithUserdata)(const Thread*, void*);
+
+typedef uint32_t VmIndex;
+
+typedef int64_t VmInt;
+
+Error glu_call_function(const Thread *thread, VmIndex args);
+
+/**
+ * Releases a string returned by `glu_last_error`. Passing a null pointer does nothing.
+ */
+void glu_free_string(char *s);
+
+void glu_free_vm(const Thread *vm);
+
+Error glu_get_bool(const Thread *vm, VmIndex index, int8_t *out);
+
+Error glu_get_byte(const Thread *vm, VmIndex index, uint8_t *out);
+
+Error glu_get_float(const Thread *vm, VmIndex index, double *out);
+
+Error glu_get_int(const Thread *vm, VmIndex index, VmInt *out);
+
+Error glu_get_light_userdata(const Thread *vm, VmIndex index, void **out);
+
+/**
+ * The returned string is garbage collected and may not be valid after the string is removed from
+ * its slot in the stack
+ */
+Error glu_get_string(const Thread *vm, VmIndex index, const uint8_t **out, uintptr_t *out_len);
+
+/**
+ * Returns a copy of the message of the last error on the calling thread as a nul terminated
+ * string, or a null pointer if no function has failed yet. The string is owned by the caller and
+ * must be released with `glu_free_string`.
+ */
+char *glu_last_error(void);
+
+uintptr_t glu_len(const Thread *vm);
+
+Error glu_load_script(const Thread *vm,
+                      const uint8_t *module,
+                      uintptr_t module_len,
+                      const uint8_t *expr,
+                      uintptr_t expr_len);
+
+/**
+ * Creates a new vm which can import the standard library. Returns a null pointer if the vm could
+ * not be created.
+ */
+const Thread *glu_new_vm(void);
+
+Error glu_pop(const Thread *vm, uintptr_t n);
+
+Error glu_push_bool(const Thread *vm, int8_t b);
+
+Error glu_push_byte(const Thread *vm, uint8_t b);
+
+Error glu_push_float(const Thread *vm, double float_);
+
+Error glu_push_function(const Thread *vm,
+                        const uint8_t *name,
+                        uintptr_t len,
+                        Function function,
+                        VmIndex args);
+
+/**
+ * Pushes a function taking `args` arguments which calls `function` with `userdata` as its second
+ * argument. The arguments are read from the stack in the same way as for `glu_push_function`.
+ * `userdata` is owned by the caller and must stay valid for as long as the function may be
+ * called.
+ */
+Error glu_push_function_with_userdata(const Thread *vm,
+                                      const uint8_t *name,
+                                      uintptr_t len,
+                                      FunctionWithUserdata function,
+                                      void *userdata,
+                                      VmIndex args);
+
+Error glu_push_int(const Thread *vm, VmInt int_);
+
+Error glu_push_light_userdata(const Thread *vm, void *data);
+
+/**
+ * Push a string to the stack. The string must be valid utf-8 or an error will be returned
+ */
+Error glu_push_string(const Thread *vm, const uint8_t *s, uintptr_t len);
+
+/**
+ * Push a string to the stack. If the string is not utf-8 this function will trigger undefined
+ * behaviour.
+ */
+Error glu_push_string_unchecked(const Thread *vm, const uint8_t *s, uintptr_t len);
+
+/**
+ * Compiles and runs `expr`, pushing the resulting value to the stack so that it can be read with
+ * the `glu_get_*` functions. Nothing is pushed if an error is returned.
+ */
+Error glu_run_expr(const Thread *vm,
+                   const uint8_t *module,
+                   uintptr_t module_len,
+                   const uint8_t *expr,
+                   uintptr_t expr_len);
+
+#endif /* GLUON_H */
@@ -1,10 +1,28 @@
 //! A (WIP) C API allowing use of gluon in other langauges than Rust.
+//!
+//! Every function catches panics before they can unwind into the caller and reports failures
+//! through the returned `Error`. The message of the last failure on the calling thread can be
+//! retrieved with `glu_last_error`.
+//!
+//! Strings passed to the API are borrowed for the duration of the call. Strings returned by
+//! `glu_get_string` point into the garbage collected heap and are owned by the vm, while strings
+//! returned by `glu_last_error` are owned by the caller and must be released with
+//! `glu_free_string`.
+//!
+//! The `include/gluon.h` header is generated with `cbindgen` (see `cbindgen.toml`).
 #![doc(html_root_url = "https://docs.rs/gluon_c-api/0.11.1")] // # GLUON
 
 extern crate gluon;
 #[cfg(not(target_arch = "wasm32"))]
 extern crate libc;
 
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
 use std::slice;
 use std::str;
 
@@ -17,26 +35,96 @@ use gluon::Compiler;
 
 pub type Function = extern "C" fn(&Thread) -> Status;
 
-// TODO What should the c api return as errors
-// TODO How should error messages be returned
+/// A function which is passed the `userdata` it was pushed with, see
+/// `glu_push_function_with_userdata`
+#[cfg(not(target_arch = "wasm32"))]
+pub type FunctionWithUserdata = extern "C" fn(&Thread, *mut libc::c_void) -> Status;
+
+/// The result of the functions of the C API. Unless `Ok` is returned the message of the error can
+/// be retrieved with `glu_last_error`.
 #[repr(C)]
 #[derive(Debug, PartialEq)]
 pub enum Error {
     Ok,
+    /// An error returned by gluon, such as a type error or a runtime error
     Unknown,
+    /// A string passed to the function was not valid utf-8
+    InvalidUtf8,
+    /// The function panicked, the vm may be in an inconsistent state afterwards
+    Panic,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = RefCell::new(None);
+}
+
+fn set_last_error(message: String) {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+fn error<E>(err: E) -> Error
+where
+    E: fmt::Display,
+{
+    set_last_error(err.to_string());
+    Error::Unknown
+}
+
+fn to_error(result: gluon::vm::Result<()>) -> Error {
+    match result {
+        Ok(()) => Error::Ok,
+        Err(err) => error(err),
+    }
 }
 
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Unknown panic".to_string())
+}
+
+/// Runs `f`, returning `on_panic` instead of unwinding into the C code if it panics
+fn catch_panic<T, F>(on_panic: T, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => {
+            set_last_error(panic_message(&*payload));
+            on_panic
+        }
+    }
+}
+
+unsafe fn from_utf8<'a>(s: &'a u8, len: usize) -> Result<&'a str, Error> {
+    str::from_utf8(slice::from_raw_parts(s, len)).map_err(|err| {
+        set_last_error(format!("Expected a valid utf-8 string: {}", err));
+        Error::InvalidUtf8
+    })
+}
+
+/// Creates a new vm which can import the standard library. Returns a null pointer if the vm could
+/// not be created.
 #[no_mangle]
 pub extern "C" fn glu_new_vm() -> *const Thread {
-    let vm = RootedThread::new();
-    vm.into_raw()
+    catch_panic(ptr::null(), || {
+        let vm = gluon::new_vm();
+        vm.into_raw()
+    })
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn glu_free_vm(vm: &Thread) {
-    RootedThread::from_raw(vm);
+    catch_panic((), || {
+        RootedThread::from_raw(vm);
+    })
 }
 
+/// Compiles and runs `expr`, pushing the resulting value to the stack so that it can be read with
+/// the `glu_get_*` functions. Nothing is pushed if an error is returned.
 #[no_mangle]
 pub unsafe extern "C" fn glu_run_expr(
     vm: &Thread,
@@ -45,19 +133,21 @@ pub unsafe extern "C" fn glu_run_expr(
     expr: &u8,
     expr_len: usize,
 ) -> Error {
-    let module = match str::from_utf8(slice::from_raw_parts(module, module_len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
-    };
-    let expr = match str::from_utf8(slice::from_raw_parts(expr, expr_len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
-    };
-    let result = Compiler::new().run_expr::<OpaqueValue<&Thread, Hole>>(&vm, module, expr);
-    match result {
-        Ok(_) => Error::Ok,
-        Err(_) => Error::Unknown,
-    }
+    catch_panic(Error::Panic, || {
+        let module = match from_utf8(module, module_len) {
+            Ok(s) => s,
+            Err(err) => return err,
+        };
+        let expr = match from_utf8(expr, expr_len) {
+            Ok(s) => s,
+            Err(err) => return err,
+        };
+        let result = Compiler::new().run_expr::<OpaqueValue<&Thread, Hole>>(&vm, module, expr);
+        match result {
+            Ok((value, _)) => to_error(value.push(&mut vm.current_context())),
+            Err(err) => error(err),
+        }
+    })
 }
 
 #[no_mangle]
@@ -68,64 +158,73 @@ pub unsafe extern "C" fn glu_load_script(
     expr: &u8,
     expr_len: usize,
 ) -> Error {
-    let module = match str::from_utf8(slice::from_raw_parts(module, module_len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
-    };
-    let expr = match str::from_utf8(slice::from_raw_parts(expr, expr_len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
-    };
-    let result = Compiler::new().load_script(vm, module, expr);
-    match result {
-        Ok(_) => Error::Ok,
-        Err(_) => Error::Unknown,
-    }
+    catch_panic(Error::Panic, || {
+        let module = match from_utf8(module, module_len) {
+            Ok(s) => s,
+            Err(err) => return err,
+        };
+        let expr = match from_utf8(expr, expr_len) {
+            Ok(s) => s,
+            Err(err) => return err,
+        };
+        let result = Compiler::new().load_script(vm, module, expr);
+        match result {
+            Ok(_) => Error::Ok,
+            Err(err) => error(err),
+        }
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn glu_call_function(thread: &Thread, args: VmIndex) -> Error {
-    let context = thread.context();
-    match thread.call_function(context, args) {
-        Ok(_) => Error::Ok,
-        Err(_) => Error::Unknown,
-    }
+    catch_panic(Error::Panic, || {
+        let context = thread.context();
+        match thread.call_function(context, args) {
+            Ok(_) => Error::Ok,
+            Err(err) => error(err),
+        }
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn glu_len(vm: &Thread) -> usize {
-    let mut context = vm.context();
-    let stack = context.stack_frame::<stack::State>();
-    stack.len() as usize
+    catch_panic(0, || {
+        let mut context = vm.context();
+        let stack = context.stack_frame::<stack::State>();
+        stack.len() as usize
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn glu_pop(vm: &Thread, n: usize) {
-    let mut context = vm.context();
-    let mut stack = context.stack_frame::<stack::State>();
-    for _ in 0..n {
-        stack.pop();
-    }
+pub extern "C" fn glu_pop(vm: &Thread, n: usize) -> Error {
+    catch_panic(Error::Panic, || {
+        let mut context = vm.context();
+        let mut stack = context.stack_frame::<stack::State>();
+        for _ in 0..n {
+            stack.pop();
+        }
+        Error::Ok
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn glu_push_int(vm: &Thread, int: VmInt) {
-    Thread::push(vm, int).unwrap();
+pub extern "C" fn glu_push_int(vm: &Thread, int: VmInt) -> Error {
+    catch_panic(Error::Panic, || to_error(Thread::push(vm, int)))
 }
 
 #[no_mangle]
-pub extern "C" fn glu_push_byte(vm: &Thread, b: u8) {
-    Thread::push(vm, b).unwrap();
+pub extern "C" fn glu_push_byte(vm: &Thread, b: u8) -> Error {
+    catch_panic(Error::Panic, || to_error(Thread::push(vm, b)))
 }
 
 #[no_mangle]
-pub extern "C" fn glu_push_float(vm: &Thread, float: f64) {
-    Thread::push(vm, float).unwrap();
+pub extern "C" fn glu_push_float(vm: &Thread, float: f64) -> Error {
+    catch_panic(Error::Panic, || to_error(Thread::push(vm, float)))
 }
 
 #[no_mangle]
-pub extern "C" fn glu_push_bool(vm: &Thread, b: i8) {
-    Thread::push(vm, b != 0).unwrap();
+pub extern "C" fn glu_push_bool(vm: &Thread, b: i8) -> Error {
+    catch_panic(Error::Panic, || to_error(Thread::push(vm, b != 0)))
 }
 
 #[no_mangle]
@@ -136,44 +235,96 @@ pub unsafe extern "C" fn glu_push_function(
     function: Function,
     args: VmIndex,
 ) -> Error {
-    let s = match str::from_utf8(slice::from_raw_parts(name, len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
+    catch_panic(Error::Panic, || {
+        let s = match from_utf8(name, len) {
+            Ok(s) => s,
+            Err(err) => return err,
+        };
+        to_error(Thread::push(vm, CPrimitive::new(function, args, s)))
+    })
+}
+
+/// Pushes a function taking `args` arguments which calls `function` with `userdata` as its second
+/// argument. The arguments are read from the stack in the same way as for `glu_push_function`.
+/// `userdata` is owned by the caller and must stay valid for as long as the function may be
+/// called.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn glu_push_function_with_userdata(
+    vm: &Thread,
+    name: &u8,
+    len: usize,
+    function: FunctionWithUserdata,
+    userdata: *mut libc::c_void,
+    args: VmIndex,
+) -> Error {
+    catch_panic(Error::Panic, || {
+        let s = match from_utf8(name, len) {
+            Ok(s) => s,
+            Err(err) => return err,
+        };
+        // `call_with_userdata` takes `function` and `userdata` as two extra arguments, applying it
+        // to only those leaves a function which takes the `args` arguments
+        let result = Thread::push(vm, CPrimitive::new(call_with_userdata, args + 2, s))
+            .and_then(|()| Thread::push(vm, function as usize))
+            .and_then(|()| Thread::push(vm, userdata as usize));
+        match result {
+            Ok(()) => glu_call_function(vm, 2),
+            Err(err) => error(err),
+        }
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+extern "C" fn call_with_userdata(vm: &Thread) -> Status {
+    let (function, userdata) = {
+        let mut context = vm.context();
+        let mut stack = context.stack_frame::<stack::State>();
+        let function = stack.get_value::<usize>(vm, 0);
+        let userdata = stack.get_value::<usize>(vm, 1);
+        // Remove the extra arguments so that `function` finds its own arguments at the same
+        // indexes as a function pushed by `glu_push_function` does
+        stack.remove_range(0, 2);
+        (function, userdata)
     };
-    match Thread::push(vm, CPrimitive::new(function, args, s)) {
-        Ok(()) => Error::Ok,
-        Err(_) => Error::Unknown,
+    match (function, userdata) {
+        (Some(function), Some(userdata)) => {
+            let function = unsafe { std::mem::transmute::<usize, FunctionWithUserdata>(function) };
+            function(vm, userdata as *mut libc::c_void)
+        }
+        _ => {
+            let _ = Thread::push(vm, "Missing the userdata of a C function");
+            Status::Error
+        }
     }
 }
 
 /// Push a string to the stack. The string must be valid utf-8 or an error will be returned
 #[no_mangle]
 pub unsafe extern "C" fn glu_push_string(vm: &Thread, s: &u8, len: usize) -> Error {
-    let s = match str::from_utf8(slice::from_raw_parts(s, len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
-    };
-    match s.push(&mut vm.current_context()) {
-        Ok(()) => Error::Ok,
-        Err(_) => Error::Unknown,
-    }
+    catch_panic(Error::Panic, || {
+        let s = match from_utf8(s, len) {
+            Ok(s) => s,
+            Err(err) => return err,
+        };
+        to_error(s.push(&mut vm.current_context()))
+    })
 }
 
 /// Push a string to the stack. If the string is not utf-8 this function will trigger undefined
 /// behaviour.
 #[no_mangle]
 pub unsafe extern "C" fn glu_push_string_unchecked(vm: &Thread, s: &u8, len: usize) -> Error {
-    let s = str::from_utf8_unchecked(slice::from_raw_parts(s, len));
-    match s.push(&mut vm.current_context()) {
-        Ok(()) => Error::Ok,
-        Err(_) => Error::Unknown,
-    }
+    catch_panic(Error::Panic, || {
+        let s = str::from_utf8_unchecked(slice::from_raw_parts(s, len));
+        to_error(s.push(&mut vm.current_context()))
+    })
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 #[no_mangle]
-pub extern "C" fn glu_push_light_userdata(vm: &Thread, data: *mut libc::c_void) {
-    Thread::push(vm, data as usize).unwrap()
+pub extern "C" fn glu_push_light_userdata(vm: &Thread, data: *mut libc::c_void) -> Error {
+    catch_panic(Error::Panic, || to_error(Thread::push(vm, data as usize)))
 }
 
 #[no_mangle]
@@ -210,19 +361,21 @@ pub unsafe extern "C" fn glu_get_string(
     out: &mut *const u8,
     out_len: &mut usize,
 ) -> Error {
-    let mut context = vm.context();
-    let stack = context.stack_frame::<stack::State>();
-    match stack
-        .get_variant(index)
-        .map(|value| <&str>::from_value(vm, value))
-    {
-        Some(value) => {
-            *out = &*value.as_ptr();
-            *out_len = value.len();
-            Error::Ok
+    catch_panic(Error::Panic, || {
+        let mut context = vm.context();
+        let stack = context.stack_frame::<stack::State>();
+        match stack
+            .get_variant(index)
+            .map(|value| <&str>::from_value(vm, value))
+        {
+            Some(value) => {
+                *out = &*value.as_ptr();
+                *out_len = value.len();
+                Error::Ok
+            }
+            None => error(format!("There is no value at index {}", index)),
         }
-        None => Error::Unknown,
-    }
+    })
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -240,22 +393,49 @@ pub extern "C" fn glu_get_light_userdata(
     err
 }
 
+/// Returns a copy of the message of the last error on the calling thread as a nul terminated
+/// string, or a null pointer if no function has failed yet. The string is owned by the caller and
+/// must be released with `glu_free_string`.
+#[no_mangle]
+pub extern "C" fn glu_last_error() -> *mut c_char {
+    catch_panic(ptr::null_mut(), || {
+        LAST_ERROR.with(|last_error| match *last_error.borrow() {
+            Some(ref message) => CString::new(message.replace('\0', ""))
+                .expect("Nul bytes are removed")
+                .into_raw(),
+            None => ptr::null_mut(),
+        })
+    })
+}
+
+/// Releases a string returned by `glu_last_error`. Passing a null pointer does nothing.
+#[no_mangle]
+pub unsafe extern "C" fn glu_free_string(s: *mut c_char) {
+    catch_panic((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    })
+}
+
 fn get_value<T>(vm: &Thread, index: VmIndex, out: &mut T) -> Error
 where
     T: for<'vm, 'value> Getable<'vm, 'value>,
 {
-    let mut context = vm.context();
-    let stack = context.stack_frame::<stack::State>();
-    match stack
-        .get_variant(index)
-        .map(|value| T::from_value(vm, value))
-    {
-        Some(value) => {
-            *out = value;
-            Error::Ok
+    catch_panic(Error::Panic, || {
+        let mut context = vm.context();
+        let stack = context.stack_frame::<stack::State>();
+        match stack
+            .get_variant(index)
+            .map(|value| T::from_value(vm, value))
+        {
+            Some(value) => {
+                *out = value;
+                Error::Ok
+            }
+            None => error(format!("There is no value at index {}", index)),
         }
-        None => Error::Unknown,
-    }
+    })
 }
 
 #[cfg(test)]
@@ -264,6 +444,7 @@ mod tests {
 
     use gluon::vm::thread::{Status, Thread};
 
+    use std::ffi::CStr;
     use std::ptr;
     use std::slice;
     use std::str;
@@ -355,4 +536,101 @@ mod tests {
             glu_free_vm(vm);
         }
     }
+
+    #[test]
+    fn run_expr() {
+        unsafe {
+            let vm = &*glu_new_vm();
+
+            let module = "test";
+            let expr = "let x = 1 in x + 2";
+            assert_eq!(
+                glu_run_expr(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len()
+                ),
+                Error::Ok
+            );
+            let mut int = 0;
+            assert_eq!(glu_get_int(vm, 0, &mut int), Error::Ok);
+            assert_eq!(int, 3);
+
+            glu_free_vm(vm);
+        }
+    }
+
+    #[test]
+    fn run_expr_error() {
+        unsafe {
+            let vm = &*glu_new_vm();
+
+            let module = "test";
+            let expr = r#"1 + "a""#;
+            assert_eq!(
+                glu_run_expr(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len()
+                ),
+                Error::Unknown
+            );
+            assert_eq!(glu_len(vm), 0);
+
+            let message = glu_last_error();
+            assert!(!message.is_null());
+            let message_str = CStr::from_ptr(message).to_str().unwrap().to_string();
+            glu_free_string(message);
+            assert!(message_str.contains("String"), "{}", message_str);
+
+            let invalid = [0xffu8];
+            assert_eq!(
+                glu_run_expr(vm, &module.as_bytes()[0], module.len(), &invalid[0], 1),
+                Error::InvalidUtf8
+            );
+
+            glu_free_vm(vm);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn call_function_with_userdata() {
+        extern "C" fn add_offset(vm: &Thread, userdata: *mut ::libc::c_void) -> Status {
+            let mut x = 0;
+            assert_eq!(glu_get_int(vm, 0, &mut x), Error::Ok);
+            let offset = unsafe { *(userdata as *const VmInt) };
+            glu_push_int(vm, x + offset);
+            Status::Ok
+        }
+
+        unsafe {
+            let vm = &*glu_new_vm();
+            let mut offset: VmInt = 10;
+            let name = "add_offset";
+            assert_eq!(
+                glu_push_function_with_userdata(
+                    vm,
+                    &name.as_bytes()[0],
+                    name.len(),
+                    add_offset,
+                    &mut offset as *mut VmInt as *mut ::libc::c_void,
+                    1,
+                ),
+                Error::Ok
+            );
+            glu_push_int(vm, 5);
+
+            assert_eq!(glu_call_function(vm, 1), Error::Ok);
+            let mut result = 0;
+            assert_eq!(glu_get_int(vm, 0, &mut result), Error::Ok);
+            assert_eq!(result, 15);
+
+            glu_free_vm(vm);
+        }
+    }
 }
@@ -0,0 +1,87 @@
+/* Embeds gluon through the C API, run by `tests/c_program.rs` */
+#include <stdio.h>
+#include <string.h>
+
+#include "gluon.h"
+
+#define CHECK(cond)                                                                      \
+    do {                                                                                 \
+        if (!(cond)) {                                                                   \
+            fprintf(stderr, "%s:%d: Check failed: %s\n", __FILE__, __LINE__, #cond);     \
+            return 1;                                                                    \
+        }                                                                                \
+    } while (0)
+
+static const char *MODULE = "c_test";
+
+static Error run_expr(const Thread *vm, const char *expr) {
+    return glu_run_expr(vm, (const uint8_t *)MODULE, strlen(MODULE), (const uint8_t *)expr,
+                        strlen(expr));
+}
+
+static Status add_offset(const Thread *vm, void *userdata) {
+    VmInt x = 0;
+    if (glu_get_int(vm, 0, &x) != Error_Ok) {
+        return Status_Error;
+    }
+    glu_push_int(vm, x + *(const VmInt *)userdata);
+    return Status_Ok;
+}
+
+int main(void) {
+    const Thread *vm = glu_new_vm();
+    CHECK(vm != NULL);
+
+    CHECK(run_expr(vm, "let x = 20 in x * 2 + 2") == Error_Ok);
+    VmInt int_result = 0;
+    CHECK(glu_get_int(vm, glu_len(vm) - 1, &int_result) == Error_Ok);
+    CHECK(int_result == 42);
+
+    CHECK(run_expr(vm, "1.5 * 2.0") == Error_Ok);
+    double float_result = 0.0;
+    CHECK(glu_get_float(vm, glu_len(vm) - 1, &float_result) == Error_Ok);
+    CHECK(float_result == 3.0);
+
+    CHECK(run_expr(vm, "1 < 2") == Error_Ok);
+    int8_t bool_result = 0;
+    CHECK(glu_get_bool(vm, glu_len(vm) - 1, &bool_result) == Error_Ok);
+    CHECK(bool_result == 1);
+
+    CHECK(run_expr(vm, "let string = import! std.string in string.trim \"  gluon \"") ==
+          Error_Ok);
+    const uint8_t *string_result = NULL;
+    uintptr_t string_len = 0;
+    CHECK(glu_get_string(vm, glu_len(vm) - 1, &string_result, &string_len) == Error_Ok);
+    CHECK(string_len == 5 && memcmp(string_result, "gluon", 5) == 0);
+
+    CHECK(glu_pop(vm, glu_len(vm)) == Error_Ok);
+    CHECK(glu_len(vm) == 0);
+
+    VmInt offset = 100;
+    const char *name = "add_offset";
+    CHECK(glu_push_function_with_userdata(vm, (const uint8_t *)name, strlen(name), add_offset,
+                                          &offset, 1) == Error_Ok);
+    CHECK(glu_push_int(vm, 23) == Error_Ok);
+    CHECK(glu_call_function(vm, 1) == Error_Ok);
+    CHECK(glu_get_int(vm, 0, &int_result) == Error_Ok);
+    CHECK(int_result == 123);
+    CHECK(glu_pop(vm, 1) == Error_Ok);
+
+    /* A type error is reported with its rendered message and leaves the stack untouched */
+    CHECK(run_expr(vm, "1 + \"a\"") == Error_Unknown);
+    CHECK(glu_len(vm) == 0);
+    char *message = glu_last_error();
+    CHECK(message != NULL);
+    CHECK(strstr(message, "String") != NULL);
+    glu_free_string(message);
+
+    CHECK(glu_get_int(vm, 5, &int_result) == Error_Unknown);
+    message = glu_last_error();
+    CHECK(message != NULL);
+    CHECK(strstr(message, "index 5") != NULL);
+    glu_free_string(message);
+
+    glu_free_vm(vm);
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,50 @@
+//! Compiles `tests/c/embed.c` against `include/gluon.h` and the cdylib of this crate and runs it
+#![cfg(unix)]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The `deps` directory which contains this test, the cdylib is built to it as well since the
+/// library target is a dependency of the test
+fn library_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().unwrap().to_owned()
+}
+
+#[test]
+fn embed_gluon_in_c() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let library_dir = library_dir();
+    let out = env::temp_dir().join(format!("gluon_c_api_embed_{}", std::process::id()));
+
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&compiler)
+        .arg(manifest_dir.join("tests/c/embed.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&library_dir)
+        .arg("-lgluon_c_api")
+        .arg("-o")
+        .arg(&out)
+        .status()
+        .unwrap_or_else(|err| panic!("Unable to run `{}`: {}", compiler, err));
+    assert!(status.success(), "Compiling the C program failed");
+
+    // Run from the root of the repository so that the standard library can be found even if it
+    // is not included in the library
+    let output = Command::new(&out)
+        .current_dir(manifest_dir.parent().unwrap())
+        .env("LD_LIBRARY_PATH", &library_dir)
+        .env("DYLD_LIBRARY_PATH", &library_dir)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(&out);
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,101 @@
+//! Finds the uses of bindings and types which are marked with `#[deprecated(message)]`.
+use std::sync::Arc;
+
+use crate::base::{
+    ast::{self, Expr, SpannedAstType, SpannedExpr, Visitor},
+    fnv::FnvMap,
+    metadata::{Metadata, MetadataEnv},
+    pos::{BytePos, Span, Spanned},
+    symbol::Symbol,
+    types::Type,
+};
+
+use crate::pattern_check::{SpannedWarning, Warning};
+
+struct Checker<'a> {
+    env: &'a MetadataEnv,
+    metadata: &'a FnvMap<Symbol, Arc<Metadata>>,
+    warnings: Vec<SpannedWarning>,
+}
+
+/// Returns a warning for each identifier in `expr` which refers to a deprecated binding or type.
+/// `metadata` is the metadata of the local bindings, as returned by `metadata::metadata`.
+///
+/// The definitions themselves and the fields which only re-export a binding under the same name
+/// (`{ deprecated_function }`) are not counted as uses.
+pub fn check_expr(
+    env: &MetadataEnv,
+    metadata: &FnvMap<Symbol, Arc<Metadata>>,
+    expr: &SpannedExpr<Symbol>,
+) -> Vec<SpannedWarning> {
+    let mut checker = Checker {
+        env,
+        metadata,
+        warnings: Vec::new(),
+    };
+    checker.visit_expr(expr);
+    checker.warnings
+}
+
+impl<'a> Checker<'a> {
+    fn metadata(&self, id: &Symbol) -> Option<&'a Metadata> {
+        let env = self.env;
+        self.metadata
+            .get(id)
+            .or_else(|| env.get_metadata(id))
+            .map(|metadata| &**metadata)
+    }
+
+    fn expr_metadata(&self, expr: &SpannedExpr<Symbol>) -> Option<&'a Metadata> {
+        match expr.value {
+            Expr::Ident(ref id) => self.metadata(&id.name),
+            Expr::Projection(ref expr, ref field, _) => self
+                .expr_metadata(expr)
+                .and_then(|metadata| metadata.module.get(field.definition_name()))
+                .map(|metadata| &**metadata),
+            Expr::MacroExpansion {
+                ref replacement, ..
+            } => self.expr_metadata(replacement),
+            _ => None,
+        }
+    }
+
+    fn check(&mut self, span: Span<BytePos>, name: &Symbol, metadata: Option<&Metadata>) {
+        if let Some(message) = metadata.and_then(|metadata| metadata.deprecation()) {
+            self.warnings.push(Spanned {
+                span,
+                value: Warning::Deprecated {
+                    name: name.declared_name().to_string(),
+                    message: message.to_string(),
+                },
+            });
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Checker<'a> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, expr: &'a SpannedExpr<Symbol>) {
+        match expr.value {
+            Expr::Ident(ref id) => self.check(expr.span, &id.name, self.metadata(&id.name)),
+            Expr::Projection(_, ref field, _) => {
+                self.check(expr.span, field, self.expr_metadata(expr))
+            }
+            Expr::Infix { ref op, .. } => {
+                self.check(op.span, &op.value.name, self.metadata(&op.value.name))
+            }
+            _ => (),
+        }
+        ast::walk_expr(self, expr);
+    }
+
+    fn visit_ast_type(&mut self, typ: &'a SpannedAstType<Symbol>) {
+        match typ.value {
+            Type::Ident(ref id) => self.check(typ.span, id, self.metadata(id)),
+            Type::Alias(ref alias) => self.check(typ.span, &alias.name, self.metadata(&alias.name)),
+            _ => (),
+        }
+        ast::walk_ast_type(self, typ);
+    }
+}
@@ -26,8 +26,10 @@ extern crate gluon_base as base;
 #[macro_use]
 extern crate gluon_codegen;
 
+mod deprecation;
 pub mod kindcheck;
 pub mod metadata;
+pub mod pattern_check;
 mod recursion_check;
 pub mod rename;
 pub mod substitution;
@@ -0,0 +1,141 @@
+//! Finds the alternatives of `match` expressions which can never be selected as an earlier
+//! alternative already matches every value that they match.
+use std::fmt;
+
+use codespan_reporting::{Diagnostic, Label};
+
+use crate::base::{
+    ast::{self, Alternative, Expr, Literal, Pattern, SpannedExpr, SpannedPattern, Visitor},
+    error::AsDiagnostic,
+    pos::{BytePos, Span, Spanned},
+    symbol::Symbol,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    /// The literal has already been matched by the alternative at `previous`
+    UnreachableLiteral {
+        literal: Literal,
+        previous: Span<BytePos>,
+    },
+    /// The alternative at `previous` matches every value
+    UnreachableAlternative { previous: Span<BytePos> },
+    /// `name` refers to a binding or type marked with `#[deprecated(message)]`
+    Deprecated { name: String, message: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::UnreachableLiteral { literal, .. } => write!(
+                f,
+                "Unreachable alternative, `{}` is already matched by an earlier alternative",
+                DisplayLiteral(literal)
+            ),
+            Warning::UnreachableAlternative { .. } => write!(
+                f,
+                "Unreachable alternative, every value is matched by an earlier alternative"
+            ),
+            Warning::Deprecated { name, message } => {
+                if message.is_empty() {
+                    write!(f, "`{}` is deprecated", name)
+                } else {
+                    write!(f, "`{}` is deprecated: {}", name, message)
+                }
+            }
+        }
+    }
+}
+
+impl AsDiagnostic for Warning {
+    fn as_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::new_warning(self.to_string());
+        match self {
+            Warning::UnreachableLiteral { previous, .. }
+            | Warning::UnreachableAlternative { previous } => {
+                diagnostic.with_label(Label::new_secondary(*previous).with_message("Matched here"))
+            }
+            Warning::Deprecated { .. } => diagnostic,
+        }
+    }
+}
+
+pub type SpannedWarning = Spanned<Warning, BytePos>;
+
+struct DisplayLiteral<'a>(&'a Literal);
+
+impl<'a> fmt::Display for DisplayLiteral<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Literal::Byte(b) => write!(f, "{}b", b),
+            Literal::Int(i) => write!(f, "{}", i),
+            Literal::Float(x) => write!(f, "{}", x),
+            Literal::String(s) => write!(f, "{:?}", s),
+            Literal::Char(c) => write!(f, "{:?}", c),
+        }
+    }
+}
+
+struct Checker {
+    warnings: Vec<SpannedWarning>,
+}
+
+/// Returns a warning for each unreachable alternative of the `match` expressions in `expr`
+pub fn check_expr(expr: &SpannedExpr<Symbol>) -> Vec<SpannedWarning> {
+    let mut checker = Checker {
+        warnings: Vec::new(),
+    };
+    checker.visit_expr(expr);
+    checker.warnings
+}
+
+fn unwrap_as(pattern: &SpannedPattern<Symbol>) -> &SpannedPattern<Symbol> {
+    match pattern.value {
+        Pattern::As(_, ref pattern) => unwrap_as(pattern),
+        _ => pattern,
+    }
+}
+
+impl Checker {
+    fn check_alternatives(&mut self, alts: &[Alternative<Symbol>]) {
+        let mut literals: Vec<(&Literal, Span<BytePos>)> = Vec::new();
+        let mut catch_all = None;
+        for alt in alts {
+            let span = alt.pattern.span;
+            if let Some(previous) = catch_all {
+                self.warnings.push(Spanned {
+                    span,
+                    value: Warning::UnreachableAlternative { previous },
+                });
+                continue;
+            }
+            match unwrap_as(&alt.pattern).value {
+                Pattern::Ident(_) => catch_all = Some(span),
+                Pattern::Literal(ref literal) => {
+                    match literals.iter().find(|&&(l, _)| l == literal) {
+                        Some(&(_, previous)) => self.warnings.push(Spanned {
+                            span,
+                            value: Warning::UnreachableLiteral {
+                                literal: literal.clone(),
+                                previous,
+                            },
+                        }),
+                        None => literals.push((literal, span)),
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Checker {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, expr: &'a SpannedExpr<Symbol>) {
+        if let Expr::Match(_, ref alts) = expr.value {
+            self.check_alternatives(alts);
+        }
+        ast::walk_expr(self, expr);
+    }
+}
@@ -32,6 +32,7 @@ use crate::base::{
 use crate::{
     implicits,
     kindcheck::KindCheck,
+    pattern_check::SpannedWarning,
     substitution::{self, Substitution},
     typ::RcType,
     unify, unify_type, TypecheckEnv,
@@ -128,6 +129,7 @@ pub struct Typecheck<'a> {
     pub(crate) subs: Substitution<RcType>,
     named_variables: FnvMap<Symbol, RcType>,
     pub(crate) errors: Errors<SpannedTypeError<Symbol, RcType<Symbol>>>,
+    warnings: Vec<SpannedWarning>,
     /// Type variables `let test: a -> b` (`a` and `b`)
     kind_cache: KindCache,
 
@@ -166,6 +168,7 @@ impl<'a> Typecheck<'a> {
             symbols: symbols,
             named_variables: FnvMap::default(),
             errors: Errors::new(),
+            warnings: Vec::new(),
             kind_cache: interner.kind_cache.clone(),
             implicit_resolver: crate::implicits::ImplicitResolver::new(environment, metadata),
             unbound_variables: ScopedMap::new(),
@@ -435,6 +438,12 @@ impl<'a> Typecheck<'a> {
         self.environment.type_variables.exit_scope();
     }
 
+    /// Returns the warnings found by the last call to `typecheck_expr`. Unlike errors they do not
+    /// prevent the expression from being compiled.
+    pub fn warnings(&self) -> &[SpannedWarning] {
+        &self.warnings
+    }
+
     /// Typecheck `expr`. If successful the type of the expression will be returned and all
     /// identifiers in `expr` will be filled with the inferred type
     pub fn typecheck_expr(&mut self, expr: &mut SpannedExpr<Symbol>) -> Result<ArcType, Error> {
@@ -473,6 +482,12 @@ impl<'a> Typecheck<'a> {
                     .map(|err| pos::spanned(err.span, TypeError::from(err.value).into())),
             );
         }
+        self.warnings = crate::pattern_check::check_expr(expr);
+        self.warnings.extend(crate::deprecation::check_expr(
+            &self.environment,
+            &*self.implicit_resolver.metadata,
+            expr,
+        ));
 
         let temp = expected_type.and_then(|expected| self.create_unifiable_signature(expected));
         let expected_type = temp.as_ref().or(expected_type);
@@ -958,6 +973,7 @@ impl<'a> Typecheck<'a> {
                 ref mut types,
                 exprs: ref mut fields,
                 ref mut base,
+                update,
             } => {
                 let level = self.subs.var_id();
 
@@ -1004,6 +1020,8 @@ impl<'a> Typecheck<'a> {
                 let mut base_record_fields = FnvMap::default();
                 let mut base_types: Vec<Field<_, _>> = Vec::new();
                 let mut base_fields: Vec<Field<_, _>> = Vec::new();
+                // The type that the fields of an update must exist in
+                let mut updated_type = None;
                 if let Some(ref mut base) = *base {
                     let base_type = self.infer_expr(base);
                     let base_type = self.remove_aliases(base_type);
@@ -1011,6 +1029,9 @@ impl<'a> Typecheck<'a> {
                     let record_type = self.poly_record(vec![], vec![], self.subs.new_var());
                     let base_type = self.unify_span(base.span, &record_type, base_type);
                     let base_type = self.subs.zonk(&base_type);
+                    if update {
+                        updated_type = Some(base_type.clone());
+                    }
 
                     base_types.extend(base_type.type_field_iter().cloned());
                     base_fields.extend(base_type.row_iter().cloned());
@@ -1046,7 +1067,18 @@ impl<'a> Typecheck<'a> {
                     if self.error_on_duplicated_field(&mut duplicated_fields, &field.name) {
                         match base_record_types.get(field.name.value.declared_name()) {
                             Some(&i) => base_types[i].typ = alias,
-                            None => new_types.push(Field::new(field.name.value.clone(), alias)),
+                            None => match updated_type {
+                                Some(ref updated_type) => {
+                                    self.error(
+                                        field.name.span,
+                                        TypeError::UndefinedField(
+                                            updated_type.clone(),
+                                            field.name.value.clone(),
+                                        ),
+                                    );
+                                }
+                                None => new_types.push(Field::new(field.name.value.clone(), alias)),
+                            },
                         }
                     }
                 }
@@ -1108,7 +1140,18 @@ impl<'a> Typecheck<'a> {
                     if self.error_on_duplicated_field(&mut duplicated_fields, &field.name) {
                         match base_record_fields.get(field.name.value.declared_name()) {
                             Some(&i) => base_fields[i].typ = typ,
-                            None => new_fields.push(Field::new(field.name.value.clone(), typ)),
+                            None => match updated_type {
+                                Some(ref updated_type) => {
+                                    self.error(
+                                        field.name.span,
+                                        TypeError::UndefinedField(
+                                            updated_type.clone(),
+                                            field.name.value.clone(),
+                                        ),
+                                    );
+                                }
+                                None => new_fields.push(Field::new(field.name.value.clone(), typ)),
+                            },
                         }
                     }
                 }
@@ -95,6 +95,32 @@ impl<'a> State<'a> {
     }
 }
 
+/// The kind of type that a row of fields belongs to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RowKind {
+    Record,
+    Variant,
+    Effect,
+}
+
+impl RowKind {
+    fn of(typ: &RcType) -> RowKind {
+        match **typ {
+            Type::Variant(_) => RowKind::Variant,
+            Type::Effect(_) => RowKind::Effect,
+            _ => RowKind::Record,
+        }
+    }
+
+    fn field_name(self) -> &'static str {
+        match self {
+            RowKind::Record => "fields",
+            RowKind::Variant => "constructors",
+            RowKind::Effect => "effects",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Functor)]
 pub enum TypeError<I, T> {
     UndefinedType(I),
@@ -102,6 +128,19 @@ pub enum TypeError<I, T> {
     SelfRecursiveAlias(I),
     UnableToGeneralize(I),
     MissingFields(T, Vec<I>),
+    /// Two closed rows do not have the same fields
+    FieldsMismatch {
+        kind: RowKind,
+        /// Fields of the expected type which the actual type lacks
+        missing: Vec<I>,
+        /// Fields of the actual type which the expected type lacks
+        extra: Vec<I>,
+        /// Fields which exist in both types but with types that could not be unified
+        conflicting: Vec<I>,
+        /// `(extra, missing)` pairs where the extra field is likely a misspelling of the missing
+        /// field
+        suggestions: Vec<(I, I)>,
+    },
     EscapingSkolem(I),
 }
 
@@ -125,6 +164,47 @@ where
     }
 }
 
+/// Pairs each of the `extra` fields with the `missing` field which it is most likely a
+/// misspelling of, if their names are close enough
+pub fn misspelled_fields<I>(missing: &[I], extra: &[I]) -> Vec<(I, I)>
+where
+    I: AsRef<str> + Clone,
+{
+    extra
+        .iter()
+        .filter_map(|extra_field| {
+            missing
+                .iter()
+                .map(|missing_field| {
+                    // Swapping two adjacent characters counts as a single edit
+                    let distance =
+                        ::strsim::osa_distance(extra_field.as_ref(), missing_field.as_ref());
+                    (missing_field, distance)
+                })
+                // Allow at most one edit for every three characters so that short names are not
+                // all considered similar to each other
+                .filter(|&(missing_field, distance)| distance * 3 <= missing_field.as_ref().len())
+                .min_by_key(|&(_, distance)| distance)
+                .map(|(missing_field, _)| (extra_field.clone(), missing_field.clone()))
+        })
+        .collect()
+}
+
+fn write_fields<I>(f: &mut fmt::Formatter, fields: &[I]) -> fmt::Result
+where
+    I: fmt::Display,
+{
+    for (i, field) in fields.iter().enumerate() {
+        let sep = match i {
+            0 => "",
+            i if i < fields.len() - 1 => ", ",
+            _ => " and ",
+        };
+        write!(f, "{}{}", sep, field)?;
+    }
+    Ok(())
+}
+
 pub fn similarity_filter<'a, I, T>(typ: &'a T, fields: &'a [I]) -> Box<Fn(&I) -> Filter + 'a>
 where
     T: TypeExt<Id = I>,
@@ -182,6 +262,24 @@ where
             | TypeError::UnableToGeneralize(_)
             | TypeError::EscapingSkolem(_) => Box::new(|_| Filter::Retain),
             TypeError::MissingFields(ref typ, ref fields) => similarity_filter(typ, fields),
+            TypeError::FieldsMismatch {
+                ref missing,
+                ref extra,
+                ref conflicting,
+                ..
+            } => Box::new(move |field: &I| {
+                // Only show the fields which differ so large records are easy to compare
+                if missing
+                    .iter()
+                    .chain(extra)
+                    .chain(conflicting)
+                    .any(|f| f.as_ref() == field.as_ref())
+                {
+                    Filter::Retain
+                } else {
+                    Filter::Drop
+                }
+            }),
         }
     }
 
@@ -210,13 +308,37 @@ where
                     "The type `{}` lacks the following fields: ",
                     TypeFormatter::new(typ).filter(filter)
                 )?;
-                for (i, field) in fields.iter().enumerate() {
-                    let sep = match i {
-                        0 => "",
-                        i if i < fields.len() - 1 => ", ",
-                        _ => " and ",
-                    };
-                    write!(f, "{}{}", sep, field)?;
+                write_fields(f, fields)
+            }
+            TypeError::FieldsMismatch {
+                kind,
+                ref missing,
+                ref extra,
+                ref conflicting,
+                ref suggestions,
+            } => {
+                let name = kind.field_name();
+                match kind {
+                    RowKind::Record => write!(f, "The record types do not have the same fields")?,
+                    RowKind::Variant => {
+                        write!(f, "The variant types do not have the same constructors")?
+                    }
+                    RowKind::Effect => write!(f, "The effect rows do not have the same effects")?,
+                }
+                if !missing.is_empty() {
+                    write!(f, "\n    Missing {}: ", name)?;
+                    write_fields(f, missing)?;
+                }
+                if !extra.is_empty() {
+                    write!(f, "\n    Unexpected {}: ", name)?;
+                    write_fields(f, extra)?;
+                }
+                if !conflicting.is_empty() {
+                    write!(f, "\n    Conflicting {}: ", name)?;
+                    write_fields(f, conflicting)?;
+                }
+                for &(ref extra, ref missing) in suggestions {
+                    write!(f, "\n    Did you mean `{}` -> `{}`?", extra, missing)?;
                 }
                 Ok(())
             }
@@ -494,6 +616,17 @@ where
                     }
                 }
 
+                // Report every field which only exists in one of the rows at once instead of
+                // comparing the fields in order, which would cascade after the first difference
+                if l_args.len() != r_args.len()
+                    || l_args
+                        .iter()
+                        .any(|l| r_args.iter().all(|r| !l.name.name_eq(&r.name)))
+                {
+                    report_fields_mismatch(unifier, l_args, r_args);
+                    return Ok(None);
+                }
+
                 // HACK For non polymorphic records we need to care about field order as the
                 // compiler assumes the order the fields occur in the type determines how
                 // to access them
@@ -508,45 +641,6 @@ where
                     opt_type.map(|typ| Field::new(l.name.clone(), typ))
                 });
 
-                {
-                    use std::cmp::Ordering::*;
-                    match l_args.len().cmp(&r_args.len()) {
-                        Equal => (),
-                        Less => {
-                            let context = unifier
-                                .state
-                                .record_context
-                                .as_ref()
-                                .map_or(actual, |p| &p.1)
-                                .clone();
-                            let err = TypeError::MissingFields(
-                                context,
-                                r_args[l_args.len()..]
-                                    .iter()
-                                    .map(|field| field.name.clone())
-                                    .collect(),
-                            );
-                            unifier.report_error(UnifyError::Other(err));
-                        }
-                        Greater => {
-                            let context = unifier
-                                .state
-                                .record_context
-                                .as_ref()
-                                .map_or(expected, |p| &p.0)
-                                .clone();
-                            let err = TypeError::MissingFields(
-                                context,
-                                l_args[r_args.len()..]
-                                    .iter()
-                                    .map(|field| field.name.clone())
-                                    .collect(),
-                            );
-                            unifier.report_error(UnifyError::Other(err));
-                        }
-                    }
-                }
-
                 let new_rest = unifier.try_match(l_rest, r_rest);
                 Ok(merge::merge(
                     l_args,
@@ -780,6 +874,54 @@ where
     (missing_from_left, both, missing_from_right)
 }
 
+/// Unifies the fields which exist in both `l` and `r` and reports a single
+/// `TypeError::FieldsMismatch` which lists the fields which differ
+fn report_fields_mismatch<'a, U>(
+    unifier: &mut UnifierState<'a, U>,
+    l: &[Field<Symbol, RcType>],
+    r: &[Field<Symbol, RcType>],
+) where
+    UnifierState<'a, U>: Unifier<State<'a>, RcType>,
+{
+    let mut conflicting = Vec::new();
+    let mut errors = Vec::new();
+    for l_field in l {
+        if let Some(r_field) = r.iter().find(|r_field| r_field.name.name_eq(&l_field.name)) {
+            if let Err(err) = unifier.try_match_res(&l_field.typ, &r_field.typ) {
+                conflicting.push(l_field.name.clone());
+                errors.push(err);
+            }
+        }
+    }
+    let missing: Vec<_> = l
+        .iter()
+        .filter(|l_field| r.iter().all(|r_field| !r_field.name.name_eq(&l_field.name)))
+        .map(|field| field.name.clone())
+        .collect();
+    let extra: Vec<_> = r
+        .iter()
+        .filter(|r_field| l.iter().all(|l_field| !l_field.name.name_eq(&r_field.name)))
+        .map(|field| field.name.clone())
+        .collect();
+
+    let kind = unifier
+        .state
+        .record_context
+        .as_ref()
+        .map_or(RowKind::Record, |p| RowKind::of(&p.0));
+    let suggestions = misspelled_fields(&missing, &extra);
+    unifier.report_error(UnifyError::Other(TypeError::FieldsMismatch {
+        kind,
+        missing,
+        extra,
+        conflicting,
+        suggestions,
+    }));
+    for err in errors {
+        unifier.report_error(err);
+    }
+}
+
 /// Do unification between two rows. Each row is either `Type::ExtendRow` or `Type::EmptyRow`.
 /// Two rows will unify successfully if all fields they have in common unifies and if either
 /// record have additional fields not found in the other record, the other record can be extended.
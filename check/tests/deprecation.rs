@@ -0,0 +1,113 @@
+#[macro_use]
+extern crate collect_mac;
+extern crate env_logger;
+#[macro_use]
+extern crate quick_error;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_parser as parser;
+
+use crate::base::pos::{BytePos, Span, Spanned};
+
+use crate::check::pattern_check::Warning;
+
+#[macro_use]
+mod support;
+
+/// The span of the `n`th occurrence of `pattern` in `text`
+fn span_of(text: &str, pattern: &str, n: usize) -> Span<BytePos> {
+    let start = text
+        .match_indices(pattern)
+        .nth(n)
+        .unwrap_or_else(|| panic!("`{}` is not in the text", pattern))
+        .0;
+    // Positions in the code map used by the tests start at 1
+    Span::new(
+        BytePos::from(start as u32 + 1),
+        BytePos::from((start + pattern.len()) as u32 + 1),
+    )
+}
+
+fn deprecated(span: Span<BytePos>, name: &str, message: &str) -> Spanned<Warning, BytePos> {
+    Spanned {
+        span,
+        value: Warning::Deprecated {
+            name: name.to_string(),
+            message: message.to_string(),
+        },
+    }
+}
+
+#[test]
+fn deprecated_function_warns_at_each_call_site() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let int =
+    /// Returns its argument
+    #[deprecated("use `id` instead")]
+    let identity x : Int -> Int = x
+    let id x : Int -> Int = x
+    { identity, id }
+let { identity, id } = int
+let f x = identity (id x)
+let y = int.identity 2
+identity (f y)
+"#;
+    let (_, result, warnings) = support::typecheck_expr_with_warnings(text, None);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    assert_eq!(
+        warnings,
+        [
+            deprecated(span_of(text, "identity", 3), "identity", "use `id` instead"),
+            deprecated(
+                span_of(text, "int.identity", 0),
+                "identity",
+                "use `id` instead"
+            ),
+            deprecated(span_of(text, "identity", 5), "identity", "use `id` instead"),
+        ]
+    );
+}
+
+#[test]
+fn deprecated_let_binding_and_type() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+#[deprecated]
+type Count = Int
+#[deprecated("use `add` instead")]
+let plus x y : Int -> Int -> Int = x
+let add x y : Int -> Int -> Int = x
+let total : Count = plus 1 2
+add total 3
+"#;
+    let (_, result, warnings) = support::typecheck_expr_with_warnings(text, None);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    assert_eq!(
+        warnings,
+        [
+            deprecated(span_of(text, "plus", 1), "plus", "use `add` instead"),
+            deprecated(span_of(text, "Count", 1), "Count", ""),
+        ]
+    );
+}
+
+#[test]
+fn re_exporting_a_deprecated_binding_is_not_a_use() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+#[deprecated("use `id` instead")]
+let identity x : Int -> Int = x
+{ identity }
+"#;
+    let (_, result, warnings) = support::typecheck_expr_with_warnings(text, None);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    assert_eq!(warnings, []);
+}
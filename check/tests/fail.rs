@@ -372,6 +372,24 @@ y
     assert_eq!(errors[0].span, Span::new(14.into(), 15.into()));
 }
 
+#[test]
+fn record_update_of_field_which_does_not_exist() {
+    use crate::base::pos::Span;
+
+    let _ = ::env_logger::try_init();
+    let text = r#"
+let r = { x = 1 }
+{ r with w = 2 }
+"#;
+    let result = support::typecheck(text);
+    assert_err!(result, UndefinedField(..));
+
+    let result = support::typecheck_expr(text).1;
+    let errors: Vec<_> = result.unwrap_err().unwrap_check().errors().into();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].span, Span::new(29.into(), 30.into()));
+}
+
 #[test]
 fn issue_286() {
     let _ = ::env_logger::try_init();
@@ -425,9 +443,9 @@ eq (A 0) (B 0.0)
 Expected: test.A
 Found: test.B
 1 errors were found during unification:
-Row labels do not match.
-    Expected: A
-    Found: B
+The variant types do not have the same constructors
+    Missing constructors: A
+    Unexpected constructors: B
 - <test>:5:11
 5 | eq (A 0) (B 0.0)
   |           ^^^^^
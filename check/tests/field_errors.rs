@@ -0,0 +1,175 @@
+//! The rendered errors for records and variants which do not have the same fields
+extern crate env_logger;
+#[macro_use]
+extern crate pretty_assertions;
+#[macro_use]
+extern crate collect_mac;
+#[macro_use]
+extern crate quick_error;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_parser as parser;
+
+use crate::check::typecheck::TypeError;
+use crate::check::unify::Error as UnifyError;
+use crate::check::unify_type::{RowKind, TypeError as UnifyTypeError};
+
+#[macro_use]
+mod support;
+
+fn rendered_error(text: &str) -> String {
+    let result = support::typecheck(text);
+    format!("{}", result.unwrap_err()).replace("\t", "        ")
+}
+
+#[test]
+fn missing_field() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Config = { name : String, width : Int, height : Int, depth : Int, title : String }
+let f c : Config -> Int = c.width
+f { name = "", width = 1, depth = 3, title = "" }
+"#;
+    assert_eq!(
+        rendered_error(text),
+        r#"error: Expected the following types to be equal
+Expected: test.Config
+Found: { ... }
+1 errors were found during unification:
+The record types do not have the same fields
+    Missing fields: height
+- <test>:4:3
+4 | f { name = "", width = 1, depth = 3, title = "" }
+  |   ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+"#
+    );
+}
+
+#[test]
+fn misspelled_field() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Style = { color : String, size : Int }
+let f s : Style -> Int = s.size
+f { colour = "red", size = 1 }
+"#;
+    assert_eq!(
+        rendered_error(text),
+        r#"error: Expected the following types to be equal
+Expected: test.Style
+Found: { ..., colour : String, ... }
+1 errors were found during unification:
+The record types do not have the same fields
+    Missing fields: color
+    Unexpected fields: colour
+    Did you mean `colour` -> `color`?
+- <test>:4:3
+4 | f { colour = "red", size = 1 }
+  |   ^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+"#
+    );
+}
+
+#[test]
+fn extra_and_conflicting_fields() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Point = { x : Int, y : Int }
+let f p : Point -> Int = p.x
+let p = { x = 1, y = "", z = 3 }
+f p
+"#;
+    assert_eq!(
+        rendered_error(text),
+        r#"error: Expected the following types to be equal
+Expected: test.Point
+Found: { ..., y : String, z : Int, ... }
+2 errors were found during unification:
+The record types do not have the same fields
+    Unexpected fields: z
+    Conflicting fields: y
+Types do not match:
+    Expected: Int
+    Found: String
+- <test>:5:3
+5 | f p
+  |   ^
+"#
+    );
+}
+
+#[test]
+fn variant_with_misspelled_constructor() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Answer = | Yes | No | Maybe
+type Reply = | Yes | No | Mabye
+let eq x _ : a -> a -> a = x
+eq Maybe Mabye
+"#;
+    assert_eq!(
+        rendered_error(text),
+        r#"error: Expected the following types to be equal
+Expected: test.Answer
+Found: test.Reply
+1 errors were found during unification:
+The variant types do not have the same constructors
+    Missing constructors: Maybe
+    Unexpected constructors: Mabye
+    Did you mean `Mabye` -> `Maybe`?
+- <test>:5:10
+5 | eq Maybe Mabye
+  |          ^^^^^
+"#
+    );
+}
+
+#[test]
+fn fields_mismatch_is_structured() {
+    let _ = env_logger::try_init();
+    let text = r#"
+let f x y : a -> a -> a = x
+let a = { alpha = 1, beta = 2 }
+let b = { alpah = 1, beta = "" }
+f a b
+"#;
+    let result = support::typecheck(text);
+    let errors: Vec<_> = result.unwrap_err().unwrap_check().errors().into();
+
+    assert_eq!(errors.len(), 1);
+    match errors[0].value.error {
+        TypeError::Unification(_, _, ref errors) => match errors[0] {
+            UnifyError::Other(UnifyTypeError::FieldsMismatch {
+                kind,
+                ref missing,
+                ref extra,
+                ref conflicting,
+                ref suggestions,
+            }) => {
+                let names = |fields: &[_]| {
+                    fields
+                        .iter()
+                        .map(|field: &base::symbol::Symbol| field.declared_name().to_string())
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(kind, RowKind::Record);
+                assert_eq!(names(missing), ["alpha"]);
+                assert_eq!(names(extra), ["alpah"]);
+                assert_eq!(names(conflicting), ["beta"]);
+                assert_eq!(
+                    suggestions
+                        .iter()
+                        .map(|(extra, missing)| (
+                            extra.declared_name().to_string(),
+                            missing.declared_name().to_string()
+                        ))
+                        .collect::<Vec<_>>(),
+                    [("alpah".to_string(), "alpha".to_string())]
+                );
+            }
+            ref err => panic!("Unexpected error {}", err),
+        },
+        ref err => panic!("Unexpected error {}", err),
+    }
+}
@@ -154,6 +154,26 @@ t
     assert_eq!(result, expected);
 }
 
+#[test]
+fn record_update_keeps_the_fields_of_the_base() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let r = { x = 1, y = "" }
+{ r with x = 2 }
+"#;
+    let result = support::typecheck(text);
+    let expected = Ok(Type::record(
+        vec![],
+        vec![
+            Field::new(intern("x"), typ("Int")),
+            Field::new(intern("y"), typ("String")),
+        ],
+    ));
+
+    assert_req!(result, expected);
+}
+
 #[test]
 fn let_binding_type() {
     let _ = env_logger::try_init();
@@ -0,0 +1,155 @@
+#[macro_use]
+extern crate collect_mac;
+extern crate env_logger;
+#[macro_use]
+extern crate quick_error;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_parser as parser;
+
+use crate::base::{
+    ast::Literal,
+    pos::{BytePos, Span},
+};
+
+use crate::check::pattern_check::Warning;
+
+#[macro_use]
+mod support;
+
+/// The span of the `n`th occurrence of `pattern` in `text`
+fn span_of(text: &str, pattern: &str, n: usize) -> Span<BytePos> {
+    let start = text
+        .match_indices(pattern)
+        .nth(n)
+        .unwrap_or_else(|| panic!("`{}` is not in the text", pattern))
+        .0;
+    // Positions in the code map used by the tests start at 1
+    Span::new(
+        BytePos::from(start as u32 + 1),
+        BytePos::from((start + pattern.len()) as u32 + 1),
+    )
+}
+
+#[test]
+fn no_warnings_for_distinct_literals() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+match 1 with
+| 1 -> "a"
+| 2 -> "b"
+| _ -> "c"
+"#;
+    let (_, result, warnings) = support::typecheck_expr_with_warnings(text, None);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    assert_eq!(warnings, []);
+}
+
+#[test]
+fn repeated_int_literal_is_unreachable() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+match 3 with
+| 10 -> "a"
+| 20 -> "b"
+| 10 -> "c"
+| _ -> "d"
+"#;
+    let (_, result, warnings) = support::typecheck_expr_with_warnings(text, None);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].span, span_of(text, "10", 1));
+    assert_eq!(
+        warnings[0].value,
+        Warning::UnreachableLiteral {
+            literal: Literal::Int(10),
+            previous: span_of(text, "10", 0),
+        }
+    );
+}
+
+#[test]
+fn repeated_string_literal_is_unreachable() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+match "a" with
+| "a" -> 1
+| "b" -> 2
+| "a" -> 3
+| _ -> 4
+"#;
+    let (_, result, warnings) = support::typecheck_expr_with_warnings(text, None);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].span, span_of(text, r#""a""#, 2));
+    assert_eq!(
+        warnings[0].value,
+        Warning::UnreachableLiteral {
+            literal: Literal::String("a".into()),
+            previous: span_of(text, r#""a""#, 1),
+        }
+    );
+    assert_eq!(
+        warnings[0].value.to_string(),
+        r#"Unreachable alternative, `"a"` is already matched by an earlier alternative"#
+    );
+}
+
+#[test]
+fn literals_after_a_catch_all_are_unreachable() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+match 1 with
+| 1 -> "a"
+| x -> "b"
+| 2 -> "c"
+| _ -> "d"
+"#;
+    let (_, result, warnings) = support::typecheck_expr_with_warnings(text, None);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    let previous = span_of(text, "x", 0);
+    assert_eq!(
+        warnings,
+        [
+            base::pos::spanned(
+                span_of(text, "2", 0),
+                Warning::UnreachableAlternative { previous }
+            ),
+            base::pos::spanned(
+                span_of(text, "_", 0),
+                Warning::UnreachableAlternative { previous }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn nested_matches_are_checked() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let f x =
+    match x with
+    | 'a' -> 1
+    | _ ->
+        match x with
+        | 'b' -> 2
+        | 'b' -> 3
+        | _ -> 4
+f 'a'
+"#;
+    let (_, result, warnings) = support::typecheck_expr_with_warnings(text, None);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].span, span_of(text, "'b'", 1));
+}
@@ -17,7 +17,7 @@ use self::{
         types::{self, Alias, ArcType, Field, Generic, PrimitiveEnv, Type, TypeCache, TypeEnv},
     },
     check::{
-        metadata, rename,
+        metadata, pattern_check, rename,
         typecheck::{self, Typecheck},
     },
     parser::{parse_partial_expr, reparse_infix, ParseErrors},
@@ -189,11 +189,28 @@ pub fn typecheck_expr_expected(
     text: &str,
     expected: Option<&ArcType>,
 ) -> (SpannedExpr<Symbol>, Result<ArcType, Error>) {
+    let (expr, result, _) = typecheck_expr_with_warnings(text, expected);
+    (expr, result)
+}
+
+/// Typechecks `text` and returns the warnings that were found in addition to the result
+pub fn typecheck_expr_with_warnings(
+    text: &str,
+    expected: Option<&ArcType>,
+) -> (
+    SpannedExpr<Symbol>,
+    Result<ArcType, Error>,
+    Vec<pattern_check::SpannedWarning>,
+) {
     let mut expr = match parse_new(text) {
         Ok(expr) => expr,
         Err((expr, err)) => {
             let err = in_file_error(text, err);
-            return (expr.unwrap_or_else(|| panic!("{}", err)), Err(err.into()));
+            return (
+                expr.unwrap_or_else(|| panic!("{}", err)),
+                Err(err.into()),
+                Vec::new(),
+            );
         }
     };
 
@@ -217,8 +234,13 @@ pub fn typecheck_expr_expected(
     );
 
     let result = tc.typecheck_expr_expected(&mut expr, expected);
+    let warnings = tc.warnings().to_vec();
 
-    (expr, result.map_err(|err| in_file_error(text, err).into()))
+    (
+        expr,
+        result.map_err(|err| in_file_error(text, err).into()),
+        warnings,
+    )
 }
 
 pub fn typecheck_expr(text: &str) -> (SpannedExpr<Symbol>, Result<ArcType, Error>) {
@@ -67,6 +67,40 @@ impl Container {
     }
 }
 
+/// Attributes on a field or an enum variant
+pub struct Field {
+    /// The name of the field or variant on the gluon side, if it differs from the rust name
+    pub rename: Option<String>,
+}
+
+impl Field {
+    pub fn from_ast(attrs: &[syn::Attribute]) -> Field {
+        use syn::NestedMeta::*;
+
+        let mut rename = None;
+
+        for meta_items in attrs.iter().filter_map(get_gluon_meta_items) {
+            for meta_item in meta_items {
+                match meta_item {
+                    // Parse `#[gluon(rename = "foo")]`
+                    Meta(NameValue(ref m)) if m.ident == "rename" => {
+                        rename = Some(get_lit_str(&m.ident, &m.ident, &m.lit).unwrap().value())
+                    }
+
+                    _ => panic!("unexpected gluon field attribute"),
+                }
+            }
+        }
+
+        Field { rename }
+    }
+
+    /// Returns the gluon name of the field or variant named `ident` in rust
+    pub fn name(&self, ident: &Ident) -> String {
+        self.rename.clone().unwrap_or_else(|| ident.to_string())
+    }
+}
+
 fn get_lit_str<'a>(
     attr_name: &Ident,
     _meta_item_name: &Ident,
@@ -55,7 +55,7 @@ where
             .ident
             .as_ref()
             .expect("Struct fields always have names");
-        let quoted_ident = format!("{}", quote! { #ident });
+        let quoted_ident = attr::Field::from_ast(&field.attrs).name(ident);
 
         quote! {
             #ident: if let Some(val) = data.lookup_field(vm, #quoted_ident) {
@@ -44,7 +44,8 @@
 //! Derives `Pushable` for any enum or struct as long as all fields also implement
 //! `Pushable` (generic type parameters included).
 //!
-//! __Note:__ Newtype structs are pushed as their inner type.
+//! __Note:__ Newtype structs are pushed as their inner type and `Box`ed fields are pushed as the
+//! value they contain, letting recursive enums derive `Pushable`.
 //!
 //! #### Examples
 //!
@@ -123,6 +124,27 @@
 //! # fn main() {}
 //! ```
 //!
+//! ### Renaming
+//!
+//! Fields and variants can be given a different name on the gluon side with the
+//! `#[gluon(rename = "<name>")]` attribute. The `Getable`, `Pushable` and `VmType` derives all
+//! use the renamed name.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate gluon_codegen;
+//! extern crate gluon;
+//!
+//! // will map to: `{ is_open : Bool, count : Int }`
+//! #[derive(Getable, Pushable, VmType)]
+//! struct Door {
+//!     #[gluon(rename = "is_open")]
+//!     open: bool,
+//!     count: u32,
+//! }
+//! # fn main() {}
+//! ```
+//!
 //! ### Userdata
 //!
 //! Derives `Userdata` and the required `Traverseable` and `VmType` for a rust type.
@@ -149,6 +171,46 @@
 //! # fn main() {}
 //! ```
 //!
+//! Generic types can derive `Userdata` as well. Fields which mention a type parameter are
+//! traversed by the garbage collector and the type must be registered with its parameters
+//! replaced by the markers in `gluon::vm::api::generic` (`A`, `B`, ... in order):
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate gluon_codegen;
+//! extern crate gluon;
+//! #[macro_use]
+//! extern crate gluon_vm;
+//!
+//! use gluon::vm::api::generic::A;
+//! use gluon::vm::api::Generic;
+//! use gluon::vm::{self, ExternModule};
+//! use gluon::Thread;
+//!
+//! #[derive(Userdata, Debug)]
+//! struct Counter<T> {
+//!     value: T,
+//!     count: usize,
+//! }
+//!
+//! fn new_counter(value: Generic<A>) -> Counter<Generic<A>> {
+//!     Counter { value, count: 0 }
+//! }
+//!
+//! fn load(vm: &Thread) -> vm::Result<ExternModule> {
+//!     vm.register_type::<Counter<A>>("Counter", &["a"])?;
+//!     ExternModule::new(
+//!         vm,
+//!         record! {
+//!             type Counter a => Counter<A>,
+//!             new_counter => primitive!(1, new_counter),
+//!         },
+//!     )
+//! }
+//! # fn main() {}
+//! ```
+//!
+
 
 #![recursion_limit = "128"]
 
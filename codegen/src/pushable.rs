@@ -5,11 +5,11 @@ use proc_macro2::{Span, TokenStream};
 use shared::{map_type_params, split_for_impl};
 
 use syn::{
-    self, Data, DataEnum, DataStruct, DeriveInput, Fields, FieldsNamed, FieldsUnnamed, Generics,
-    Ident, Type,
+    self, Data, DataEnum, DataStruct, DeriveInput, Fields, FieldsNamed, FieldsUnnamed,
+    GenericArgument, Generics, Ident, PathArguments, Type, TypePath,
 };
 
-use attr::{Container, CrateName};
+use attr::{self, Container, CrateName};
 
 pub fn derive(input: TokenStream) -> TokenStream {
     let derive_input = syn::parse2(input).expect("Input is checked by rustc");
@@ -44,10 +44,7 @@ fn derive_struct(
     // Treat newtype structs as just their inner type
     match ast.fields {
         Fields::Unnamed(_) if field_idents.len() == 1 => {
-            let ty = &field_types[0];
-            let push_impl = quote! {
-                <#ty as _gluon_api::Pushable<'__vm>>::push(self.0, ctx)?;
-            };
+            let push_impl = gen_push_field(&quote! { self.0 }, field_types[0]);
             return gen_impl(&container, &ident, generics, push_impl);
         }
         _ => (),
@@ -60,7 +57,8 @@ fn derive_struct(
         Fields::Unit => quote! {},
     };
 
-    let push_impl = gen_push_impl(None, &field_idents, &field_types);
+    let field_names = get_field_names(&ast.fields, &field_idents);
+    let push_impl = gen_push_impl(None, &field_idents, &field_names, &field_types);
 
     gen_impl(
         &container,
@@ -89,7 +87,8 @@ fn derive_enum(
             Fields::Unit => quote! { #ident::#variant_ident },
         };
 
-        let push_impl = gen_push_impl(Some(tag), &field_idents, &field_types);
+        let field_names = get_field_names(&variant.fields, &field_idents);
+        let push_impl = gen_push_impl(Some(tag), &field_idents, &field_names, &field_types);
 
         quote! {
             #pattern => {
@@ -162,16 +161,17 @@ fn gen_impl(
 fn gen_push_impl(
     tag: Option<usize>,
     field_idents: &[Cow<Ident>],
+    field_names: &[String],
     field_types: &[&Type],
 ) -> TokenStream {
     debug_assert!(field_idents.len() == field_types.len());
+    debug_assert!(field_idents.len() == field_names.len());
 
     // push each field onto the stack
-    let stack_pushes = field_idents.iter().zip(field_types).map(|(ident, ty)| {
-        quote! {
-            <#ty as _gluon_api::Pushable<'__vm>>::push(#ident, ctx)?;
-        }
-    });
+    let stack_pushes = field_idents
+        .iter()
+        .zip(field_types)
+        .map(|(ident, ty)| gen_push_field(&quote! { #ident }, ty));
 
     let fields_len = field_idents.len();
     let new_data = match tag {
@@ -179,9 +179,8 @@ fn gen_push_impl(
             ctx.context().push_new_data(vm, #tag as _gluon_types::VmTag, #fields_len)?
         },
         None => {
-            let field_ident_strs = field_idents.iter().map(|i| i.to_string());
             quote! { {
-                let field_names = [#(vm.global_env().intern(#field_ident_strs)?),*];
+                let field_names = [#(vm.global_env().intern(#field_names)?),*];
                 ctx.context().push_new_record(vm, #fields_len, &field_names)?;
             } }
         }
@@ -196,6 +195,40 @@ fn gen_push_impl(
     }
 }
 
+/// Pushes the field `value` of type `ty`. Boxed values are pushed as the value they contain since
+/// `Box` can't implement `Pushable` without conflicting with the implementation for `Userdata`.
+fn gen_push_field(value: &TokenStream, ty: &Type) -> TokenStream {
+    match unbox(ty) {
+        Some(inner) => quote! {
+            <#inner as _gluon_api::Pushable<'__vm>>::push(*#value, ctx)?;
+        },
+        None => quote! {
+            <#ty as _gluon_api::Pushable<'__vm>>::push(#value, ctx)?;
+        },
+    }
+}
+
+/// Returns `T` if `ty` is `Box<T>`
+fn unbox(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(TypePath { qself: None, path }) => path,
+        _ => return None,
+    };
+    let segment = path.segments.iter().last()?;
+    if segment.ident != "Box" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) if arguments.args.len() == 1 => {
+            match &arguments.args[0] {
+                GenericArgument::Type(inner) => Some(inner),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 fn create_pushable_bounds(generics: &Generics) -> Vec<TokenStream> {
     map_type_params(generics, |ty| {
         quote! {
@@ -204,6 +237,15 @@ fn create_pushable_bounds(generics: &Generics) -> Vec<TokenStream> {
     })
 }
 
+/// Returns the names which the fields have on the gluon side
+fn get_field_names(fields: &Fields, field_idents: &[Cow<Ident>]) -> Vec<String> {
+    fields
+        .iter()
+        .zip(field_idents)
+        .map(|(field, ident)| attr::Field::from_ast(&field.attrs).name(ident))
+        .collect()
+}
+
 fn get_info_from_fields(fields: &Fields) -> (Vec<Cow<Ident>>, Vec<&Type>) {
     // get all the fields if there are any
     let fields = match fields {
@@ -1,6 +1,9 @@
-use proc_macro2::{Ident, Span, TokenStream};
+use proc_macro2::{Ident, Span, TokenStream, TokenTree};
 use shared::{map_lifetimes, map_type_params, split_for_impl};
-use syn::{self, Data, DeriveInput, GenericParam, Generics};
+use syn::{
+    self, Data, DeriveInput, Fields, GenericParam, Generics, Index, TraitBoundModifier,
+    TypeParamBound, WherePredicate,
+};
 
 use attr::{Container, CrateName};
 
@@ -17,14 +20,14 @@ pub fn derive(input: TokenStream) -> TokenStream {
     } = derive_input;
 
     let tokens = match data {
-        Data::Struct(_) | Data::Enum(_) => gen_impl(&container, ident, generics),
+        Data::Struct(_) | Data::Enum(_) => gen_impl(&container, ident, generics, &data),
         Data::Union(_) => panic!("Unions are not supported"),
     };
 
     tokens.into()
 }
 
-fn gen_impl(container: &Container, ident: Ident, generics: Generics) -> TokenStream {
+fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data) -> TokenStream {
     let trait_bounds = &map_type_params(&generics, |ty| {
         quote! { #ty: 'static + ::std::fmt::Debug + Sync + Send }
     });
@@ -35,52 +38,276 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics) -> TokenStr
 
     let gluon = match container.crate_name {
         CrateName::Some(ref ident) => quote! {
+            use #ident::base as _gluon_base;
             use #ident::api as _gluon_api;
             use #ident::gc as _gluon_gc;
+            use #ident::thread as _gluon_thread;
         },
         CrateName::GluonVm => quote! {
+            use base as _gluon_base;
             use api as _gluon_api;
             use thread as _gluon_gc;
+            use thread as _gluon_thread;
         },
         CrateName::None => quote! {
+            use gluon::base as _gluon_base;
             use gluon::vm::api as _gluon_api;
             use gluon::vm::gc as _gluon_gc;
+            use gluon::vm::thread as _gluon_thread;
         },
     };
 
     let associated_type_generics = generics.params.iter().map(|param| match param {
-        GenericParam::Type(ty) => quote!( #ty :: Type ),
+        GenericParam::Type(ty) => {
+            let ty = &ty.ident;
+            quote!( #ty :: Type )
+        }
         GenericParam::Lifetime(_) => quote!( 'static ),
         GenericParam::Const(c) => quote!( #c ),
     });
 
+    let (traverse_bounds, traverse_impl) = gen_traverse(&ident, &generics, data);
+    let traverse_bounds = &traverse_bounds;
+
+    let (vm_type_bounds, make_type_impl) = gen_make_type(&ident, &generics);
+
     let dummy_const = Ident::new(&format!("_IMPL_USERDATA_FOR_{}", ident), Span::call_site());
 
     quote! {
-        #[allow(non_upper_case_globals)]
+        #[allow(non_upper_case_globals, unused_imports)]
         const #dummy_const: () = {
             #gluon
 
             #[automatically_derived]
             #[allow(unused_attributes, unused_variables)]
             impl #impl_generics _gluon_api::Userdata for #ident #ty_generics
-            #where_clause #(#trait_bounds,)* #(#lifetime_bounds),*
+            #where_clause #(#trait_bounds,)* #(#lifetime_bounds,)* #(#traverse_bounds,)*
             {
             }
 
             #[automatically_derived]
             #[allow(unused_attributes, unused_variables)]
-            impl #impl_generics _gluon_gc::Traverseable for #ident #ty_generics {}
+            impl #impl_generics _gluon_gc::Traverseable for #ident #ty_generics
+            #where_clause #(#traverse_bounds,)*
+            {
+                #traverse_impl
+            }
 
             #[automatically_derived]
             #[allow(unused_attributes, unused_variables)]
             impl #impl_generics _gluon_api::VmType for #ident #ty_generics
-            #where_clause #(#trait_bounds,)* #(#lifetime_bounds),*
+            #where_clause #(#trait_bounds,)* #(#lifetime_bounds,)* #(#vm_type_bounds,)*
             {
                 type Type = #ident<
                         #(#associated_type_generics),*
                     >;
+
+                #make_type_impl
             }
         };
     }
 }
+
+/// Generates a `traverse` method which traverses every field whose type refers to one of the type
+/// parameters, together with the `Traverseable` bounds that requires. Fields with concrete types
+/// are opaque to the garbage collector and are skipped, same as for non-generic types.
+fn gen_traverse(
+    ident: &Ident,
+    generics: &Generics,
+    data: &Data,
+) -> (Vec<TokenStream>, TokenStream) {
+    let params = map_type_params(generics, |param| param.clone());
+    if params.is_empty() {
+        return (Vec::new(), TokenStream::new());
+    }
+
+    let mut bounds = Vec::new();
+    let mut add_bound = |ty: &syn::Type| {
+        let bound = quote! { #ty: _gluon_gc::Traverseable };
+        if !bounds
+            .iter()
+            .any(|existing: &TokenStream| existing.to_string() == bound.to_string())
+        {
+            bounds.push(bound);
+        }
+    };
+
+    let body = match *data {
+        Data::Struct(ref struct_) => {
+            let traversals = struct_
+                .fields
+                .iter()
+                .enumerate()
+                .filter(|(_, field)| mentions_params(&field.ty, &params))
+                .map(|(i, field)| {
+                    add_bound(&field.ty);
+                    let access = match field.ident {
+                        Some(ref ident) => quote! { self.#ident },
+                        None => {
+                            let index = Index::from(i);
+                            quote! { self.#index }
+                        }
+                    };
+                    quote! { _gluon_gc::Traverseable::traverse(&#access, gc); }
+                })
+                .collect::<Vec<_>>();
+            quote! { #(#traversals)* }
+        }
+        Data::Enum(ref enum_) => {
+            let arms = enum_
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_ident = &variant.ident;
+                    let bindings = variant
+                        .fields
+                        .iter()
+                        .enumerate()
+                        .map(|(i, _)| Ident::new(&format!("_field_{}", i), Span::call_site()))
+                        .collect::<Vec<_>>();
+                    let traversals = variant
+                        .fields
+                        .iter()
+                        .zip(&bindings)
+                        .filter(|(field, _)| mentions_params(&field.ty, &params))
+                        .map(|(field, binding)| {
+                            add_bound(&field.ty);
+                            quote! { _gluon_gc::Traverseable::traverse(#binding, gc); }
+                        })
+                        .collect::<Vec<_>>();
+                    let pattern = match variant.fields {
+                        Fields::Named(_) => {
+                            let names = variant.fields.iter().map(|field| &field.ident);
+                            quote! { { #(#names: ref #bindings),* } }
+                        }
+                        Fields::Unnamed(_) => quote! { ( #(ref #bindings),* ) },
+                        Fields::Unit => quote! {},
+                    };
+                    quote! {
+                        #ident::#variant_ident #pattern => { #(#traversals)* }
+                    }
+                })
+                .collect::<Vec<_>>();
+            quote! {
+                match *self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => unreachable!(),
+    };
+
+    (
+        bounds,
+        quote! {
+            fn traverse(&self, gc: &mut _gluon_gc::Gc) {
+                #body
+            }
+        },
+    )
+}
+
+/// Generates `make_type` for types with type parameters. These must be registered with the
+/// parameters replaced by the generic markers in `vm::api::generic` (in order, `A`, `B`, ...) so
+/// that the registered type can be applied to the actual types of the parameters.
+fn gen_make_type(ident: &Ident, generics: &Generics) -> (Vec<TokenStream>, TokenStream) {
+    let params = map_type_params(generics, |param| param.clone());
+    if params.is_empty() {
+        return (Vec::new(), TokenStream::new());
+    }
+
+    const MARKERS: &[&str] = &[
+        "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R",
+        "X", "Y", "Z",
+    ];
+    if params.len() > MARKERS.len() {
+        panic!(
+            "Userdata can only be derived for types with at most {} type parameters",
+            MARKERS.len()
+        );
+    }
+
+    let marker_generics = generics.params.iter().scan(0, |type_index, param| {
+        Some(match param {
+            GenericParam::Type(_) => {
+                let marker = Ident::new(MARKERS[*type_index], Span::call_site());
+                *type_index += 1;
+                quote!( _gluon_api::generic::#marker )
+            }
+            GenericParam::Lifetime(_) => quote!( 'static ),
+            GenericParam::Const(c) => quote!( #c ),
+        })
+    });
+
+    // `Self::Type` must satisfy the bounds declared on the type so they are repeated for the
+    // associated types of the parameters
+    let mut bounds = map_type_params(generics, |param| {
+        quote! { #param: _gluon_api::VmType, #param::Type: Sized }
+    });
+    for param in generics.type_params() {
+        let ident = &param.ident;
+        let param_bounds = param
+            .bounds
+            .iter()
+            .filter(|bound| match **bound {
+                TypeParamBound::Trait(ref bound) => bound.modifier == TraitBoundModifier::None,
+                TypeParamBound::Lifetime(_) => true,
+            })
+            .collect::<Vec<_>>();
+        if !param_bounds.is_empty() {
+            bounds.push(quote! { #ident::Type: #(#param_bounds)+* });
+        }
+    }
+
+    if let Some(ref where_clause) = generics.where_clause {
+        for predicate in &where_clause.predicates {
+            if let WherePredicate::Type(ref predicate) = *predicate {
+                if let syn::Type::Path(ref path) = predicate.bounded_ty {
+                    if path.qself.is_none() && path.path.segments.len() == 1 {
+                        let ident = &path.path.segments[0].ident;
+                        if params.contains(ident) {
+                            let predicate_bounds = &predicate.bounds;
+                            bounds.push(quote! { #ident::Type: #predicate_bounds });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let applications = params.iter().map(|param| {
+        quote! {
+            vec.push(<#param as _gluon_api::VmType>::make_type(vm));
+        }
+    });
+    let name = ident.to_string();
+
+    (
+        bounds,
+        quote! {
+            fn make_type(vm: &_gluon_thread::Thread) -> _gluon_base::types::ArcType {
+                let ty = vm.get_type::<#ident<#(#marker_generics),*>>().unwrap_or_else(|| {
+                    panic!(
+                        "Expected type `{}` to be registered before get_type call. \
+                         Did you forget to call `Thread::register_type`?",
+                        #name
+                    )
+                });
+                let mut vec = _gluon_base::types::AppVec::new();
+                #(#applications)*
+                _gluon_base::types::Type::app(ty, vec)
+            }
+        },
+    )
+}
+
+fn mentions_params(ty: &syn::Type, params: &[Ident]) -> bool {
+    fn mentions(tokens: TokenStream, params: &[Ident]) -> bool {
+        tokens.into_iter().any(|token| match token {
+            TokenTree::Ident(ref ident) => params.contains(ident),
+            TokenTree::Group(ref group) => mentions(group.stream(), params),
+            _ => false,
+        })
+    }
+    mentions(quote! { #ty }, params)
+}
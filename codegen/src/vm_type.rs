@@ -1,8 +1,11 @@
 use proc_macro2::{Ident, Span, TokenStream};
 use shared::{map_type_params, split_for_impl};
-use syn::{self, Data, DeriveInput, Fields, GenericParam, Generics};
+use syn::{
+    self, Data, DeriveInput, Fields, GenericParam, Generics, PredicateType, TraitBoundModifier,
+    TypeParamBound, TypePath, WherePredicate,
+};
 
-use attr::{Container, CrateName};
+use attr::{self, Container, CrateName};
 
 pub fn derive(input: TokenStream) -> TokenStream {
     let derive_input = syn::parse2(input).expect("Input is checked by rustc");
@@ -65,7 +68,8 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data
             Data::Struct(ref struct_) => match struct_.fields {
                 Fields::Named(ref fields) => {
                     let fields = fields.named.iter().map(|field| {
-                        let ident = field.ident.as_ref().unwrap().to_string();
+                        let ident =
+                            attr::Field::from_ast(&field.attrs).name(field.ident.as_ref().unwrap());
                         let typ = &field.ty;
                         quote! {
                             _gluon_base::types::Field {
@@ -100,7 +104,7 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data
             },
             Data::Enum(ref enum_) => {
                 let variants = enum_.variants.iter().map(|variant| {
-                    let ident = variant.ident.to_string();
+                    let ident = attr::Field::from_ast(&variant.attrs).name(&variant.ident);
                     let args = variant.fields.iter().map(|field| {
                         let typ = &field.ty;
                         quote! {
@@ -131,10 +135,17 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data
     };
 
     let associated_type_generics = generics.params.iter().map(|param| match param {
-        GenericParam::Type(ty) => quote!( #ty :: Type ),
+        GenericParam::Type(ty) => {
+            let ident = &ty.ident;
+            quote!( #ident :: Type )
+        }
         GenericParam::Lifetime(_) => quote!( 'static ),
-        GenericParam::Const(c) => quote!( #c ),
+        GenericParam::Const(c) => {
+            let ident = &c.ident;
+            quote!( #ident )
+        }
     });
+    let associated_type_bounds = create_associated_type_bounds(&generics);
 
     let dummy_const = Ident::new(&format!("_IMPL_VM_TYPE_FOR_{}", ident), Span::call_site());
 
@@ -146,7 +157,7 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data
             #[automatically_derived]
             #[allow(unused_attributes, unused_variables)]
             impl #impl_generics _gluon_api::VmType for #ident #ty_generics
-            #where_clause #(#trait_bounds,)*
+            #where_clause #(#trait_bounds,)* #(#associated_type_bounds,)*
             {
                 type Type = #ident<
                         #(#associated_type_generics),*
@@ -160,6 +171,61 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data
     }
 }
 
+/// `Self::Type` is the type applied to the `VmType::Type` of each type parameter, so any bounds on
+/// a type parameter must hold for its `VmType::Type` as well
+fn create_associated_type_bounds(generics: &Generics) -> Vec<TokenStream> {
+    let is_type_param = |ident: &Ident| {
+        generics.params.iter().any(|param| match param {
+            GenericParam::Type(ty) => ty.ident == *ident,
+            _ => false,
+        })
+    };
+
+    let param_bounds = generics.params.iter().filter_map(|param| match param {
+        GenericParam::Type(ty) => Some((&ty.ident, &ty.bounds)),
+        _ => None,
+    });
+    let predicate_bounds = generics
+        .where_clause
+        .iter()
+        .flat_map(|clause| &clause.predicates)
+        .filter_map(|predicate| match predicate {
+            WherePredicate::Type(PredicateType {
+                bounded_ty: syn::Type::Path(TypePath { qself: None, path }),
+                bounds,
+                ..
+            }) if path.segments.len() == 1
+                && path.segments[0].arguments.is_empty()
+                && is_type_param(&path.segments[0].ident) =>
+            {
+                Some((&path.segments[0].ident, bounds))
+            }
+            _ => None,
+        });
+
+    param_bounds
+        .chain(predicate_bounds)
+        .filter_map(|(ident, bounds)| {
+            // `?Sized` can only be used on the type parameter itself
+            let bounds = bounds
+                .iter()
+                .filter(|bound| match bound {
+                    TypeParamBound::Trait(bound) => match bound.modifier {
+                        TraitBoundModifier::Maybe(_) => false,
+                        TraitBoundModifier::None => true,
+                    },
+                    TypeParamBound::Lifetime(_) => true,
+                })
+                .collect::<Vec<_>>();
+            if bounds.is_empty() {
+                None
+            } else {
+                Some(quote! { #ident::Type: #(#bounds)+* })
+            }
+        })
+        .collect()
+}
+
 fn gen_type_application(generics: &Generics) -> TokenStream {
     let applications = map_type_params(generics, |param| {
         quote! {
@@ -9,7 +9,7 @@ extern crate gluon_vm;
 
 mod init;
 
-use gluon::vm::api::{self, generic::A, OpaqueValue};
+use gluon::vm::api::{self, generic::A, OpaqueValue, RuntimeResult, Structured};
 use gluon::vm::{self, ExternModule};
 use gluon::{import, Compiler, RootedThread, Thread};
 use init::new_vm;
@@ -248,3 +248,67 @@ fn normal_enum() {
         panic!("{}", why);
     }
 }
+
+#[derive(Pushable, VmType, Serialize, Deserialize)]
+#[gluon(vm_type = "types.SeedError")]
+enum SeedError {
+    Empty,
+    WrongLength(u32),
+}
+
+fn load_seed_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    let module = record! {
+        seed => primitive!(1, seed),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn seed(bytes: Vec<u8>) -> RuntimeResult<u32, Structured<SeedError>> {
+    match bytes.len() {
+        0 => RuntimeResult::Panic(Structured(SeedError::Empty)),
+        4 => RuntimeResult::Return(bytes.iter().map(|&b| u32::from(b)).sum()),
+        len => RuntimeResult::Panic(Structured(SeedError::WrongLength(len as u32))),
+    }
+}
+
+#[test]
+fn structured_runtime_error() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let src = api::typ::make_source::<SeedError>(&vm).unwrap();
+    compiler.load_script(&vm, "types", &src).unwrap();
+    import::add_extern_module(&vm, "functions", load_seed_mod);
+
+    let script = r#"
+        let { SeedError } = import! types
+        let { seed } = import! functions
+        let { assert } = import! std.test
+        let { Result } = import! std.result
+        let { wrap } = import! std.applicative
+        let { Eff, run_pure, ? } = import! std.effect
+        let { ok_or_throw, run_error } = import! std.effect.error
+
+        match seed [1b, 2b, 3b, 4b] with
+        | Ok x -> assert (x == 10)
+        | Err _ -> error "Expected a seed"
+
+        match seed [1b, 2b] with
+        | Err (WrongLength len) -> assert (len == 2)
+        | _ -> error "Expected WrongLength"
+
+        let sum_seeds =
+            do x = ok_or_throw (seed [1b, 1b, 1b, 1b])
+            do y = ok_or_throw (seed [])
+            wrap (x + y)
+
+        match run_pure (run_error sum_seeds) with
+        | Err Empty -> ()
+        | _ -> error "Expected the Empty error to be thrown"
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
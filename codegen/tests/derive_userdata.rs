@@ -6,6 +6,8 @@ extern crate gluon_vm;
 
 mod init;
 
+use gluon::vm::api::generic::A;
+use gluon::vm::api::Generic;
 use gluon::vm::{self, ExternModule};
 use gluon::{import, Compiler, Thread};
 use init::new_vm;
@@ -65,3 +67,68 @@ fn userdata() {
         panic!("{}", why);
     }
 }
+
+#[derive(Userdata, Debug)]
+struct Counter<T: Clone + Send> {
+    value: T,
+    count: usize,
+}
+
+fn load_counter_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    vm.register_type::<Counter<A>>("Counter", &["a"])?;
+
+    let module = record! {
+           type Counter a => Counter<A>,
+           new_counter
+    => primitive!(1, new_counter),
+           increment => primitive!(1, increment),
+           value => primitive!(1, value),
+           count => primitive!(1, count),
+       };
+
+    ExternModule::new(vm, module)
+}
+
+fn new_counter(value: Generic<A>) -> Counter<Generic<A>> {
+    Counter { value, count: 0 }
+}
+
+fn increment(counter: &Counter<Generic<A>>) -> Counter<Generic<A>> {
+    Counter {
+        value: counter.value.clone(),
+        count: counter.count + 1,
+    }
+}
+
+fn value(counter: &Counter<Generic<A>>) -> Generic<A> {
+    counter.value.clone()
+}
+
+fn count(counter: &Counter<Generic<A>>) -> usize {
+    counter.count
+}
+
+#[test]
+fn generic_userdata() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    import::add_extern_module(&vm, "counter", load_counter_mod);
+
+    let script = r#"
+        let { assert } = import! std.test
+        let { Counter, new_counter, increment, value, count } = import! counter
+
+        let counter : Counter String = increment (increment (new_counter "abc"))
+        let int_counter = new_counter 123
+
+        assert (value counter == "abc")
+        assert (count counter == 2)
+        assert (value int_counter == 123)
+        assert (count int_counter == 0)
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
@@ -56,3 +56,38 @@ fn newtype() {
         Struct::make_type(&vm).to_string(),
     );
 }
+
+#[derive(VmType)]
+#[allow(unused)]
+enum Renamed<T: Copy> {
+    #[gluon(rename = "Zero")]
+    Empty,
+    One(T),
+    Two {
+        #[gluon(rename = "first")]
+        a: T,
+        b: T,
+    },
+}
+
+#[derive(VmType)]
+#[allow(unused)]
+struct RenamedStruct {
+    #[gluon(rename = "label")]
+    name: String,
+    count: u32,
+}
+
+#[test]
+fn renamed() {
+    let vm = new_vm();
+
+    assert_eq!(
+        Renamed::<f64>::make_type(&vm).to_string(),
+        "| Zero\n| One Float\n| Two Float Float"
+    );
+    assert_eq!(
+        RenamedStruct::make_type(&vm).to_string(),
+        "{ label : String, count : Int }"
+    );
+}
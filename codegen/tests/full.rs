@@ -6,6 +6,7 @@ extern crate gluon_vm;
 
 mod init;
 
+use gluon::vm::api::OwnedFunction;
 use gluon::vm::{self, ExternModule};
 use gluon::{import, Compiler, Thread};
 use init::new_vm;
@@ -100,3 +101,170 @@ fn newtype() {
         })
     );
 }
+
+/// Loads the gluon definitions of the enums below as the module `types`
+fn load_types(vm: &Thread, compiler: &mut Compiler) {
+    let src = r#"
+        type Shape a = | Circle a | Poly (Array (a, a)) | Labeled String a
+        type Pair a = | Both a Int | Swapped a a
+        type Tree = | Leaf Int | Node Tree Tree
+        { Shape, Pair, Tree }
+    "#;
+    compiler
+        .load_script(vm, "types", src)
+        .unwrap_or_else(|why| panic!("{}", why));
+}
+
+#[derive(Debug, PartialEq, VmType, Pushable, Getable)]
+#[gluon(vm_type = "types.Shape")]
+enum Shape<T: Copy> {
+    Circle { radius: T },
+    Poly(Vec<(T, T)>),
+    Labeled(String, T),
+}
+
+#[test]
+fn generic_enum_with_struct_and_tuple_variants() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+    load_types(&vm, &mut compiler);
+
+    let script = r#"
+        let { Shape } = import! types
+        let { (++) } = import! std.string
+        \shape ->
+            match shape with
+            | Circle radius -> Circle (radius * 2.0)
+            | Poly points -> Poly points
+            | Labeled label x -> Labeled (label ++ "!") (x + 1.0)
+    "#;
+
+    let (mut scale, _) = compiler
+        .run_expr::<OwnedFunction<fn(Shape<f64>) -> Shape<f64>>>(&vm, "test", script)
+        .unwrap_or_else(|why| panic!("{}", why));
+
+    assert_eq!(
+        scale.call(Shape::Circle { radius: 1.5 }),
+        Ok(Shape::Circle { radius: 3.0 })
+    );
+    assert_eq!(
+        scale.call(Shape::Poly(vec![(0.0, 1.0), (2.0, 3.0)])),
+        Ok(Shape::Poly(vec![(0.0, 1.0), (2.0, 3.0)]))
+    );
+    assert_eq!(
+        scale.call(Shape::Labeled("origin".into(), 0.0)),
+        Ok(Shape::Labeled("origin!".into(), 1.0))
+    );
+}
+
+#[derive(Debug, PartialEq, VmType, Pushable, Getable)]
+#[gluon(vm_type = "types.Pair")]
+enum Pair<T>
+where
+    T: Clone,
+{
+    Both { value: T, count: i32 },
+    Swapped(T, T),
+}
+
+#[test]
+fn enum_with_where_clause() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+    load_types(&vm, &mut compiler);
+
+    let script = r#"
+        let { Pair } = import! types
+        \pair ->
+            match pair with
+            | Both value count -> Both value (count + 1)
+            | Swapped l r -> Swapped r l
+    "#;
+
+    let (mut next, _) = compiler
+        .run_expr::<OwnedFunction<fn(Pair<String>) -> Pair<String>>>(&vm, "test", script)
+        .unwrap_or_else(|why| panic!("{}", why));
+
+    assert_eq!(
+        next.call(Pair::Both {
+            value: "x".into(),
+            count: 1
+        }),
+        Ok(Pair::Both {
+            value: "x".into(),
+            count: 2
+        })
+    );
+    assert_eq!(
+        next.call(Pair::Swapped("l".into(), "r".into())),
+        Ok(Pair::Swapped("r".into(), "l".into()))
+    );
+}
+
+#[derive(Debug, PartialEq, VmType, Pushable, Getable)]
+#[gluon(vm_type = "types.Tree")]
+enum Tree {
+    Leaf(i32),
+    Node(Box<Tree>, Box<Tree>),
+}
+
+#[test]
+fn recursive_enum() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+    load_types(&vm, &mut compiler);
+
+    let script = r#"
+        let { Tree } = import! types
+        rec let mirror tree : Tree -> Tree =
+            match tree with
+            | Leaf x -> Leaf x
+            | Node l r -> Node (mirror r) (mirror l)
+        mirror
+    "#;
+
+    let (mut mirror, _) = compiler
+        .run_expr::<OwnedFunction<fn(Tree) -> Tree>>(&vm, "test", script)
+        .unwrap_or_else(|why| panic!("{}", why));
+
+    let node = |l, r| Tree::Node(Box::new(l), Box::new(r));
+    assert_eq!(
+        mirror.call(node(Tree::Leaf(1), node(Tree::Leaf(2), Tree::Leaf(3)))),
+        Ok(node(node(Tree::Leaf(3), Tree::Leaf(2)), Tree::Leaf(1)))
+    );
+}
+
+#[derive(Debug, PartialEq, VmType, Pushable, Getable)]
+struct Renamed {
+    #[gluon(rename = "is_open")]
+    open: bool,
+    count: i32,
+}
+
+#[test]
+fn renamed_fields() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let script = r#"
+        let { not } = import! std.bool
+        let toggle r : { is_open : Bool, count : Int } -> { is_open : Bool, count : Int } =
+            { is_open = not r.is_open, count = r.count + 1 }
+        toggle
+    "#;
+
+    let (mut toggle, _) = compiler
+        .run_expr::<OwnedFunction<fn(Renamed) -> Renamed>>(&vm, "test", script)
+        .unwrap_or_else(|why| panic!("{}", why));
+
+    assert_eq!(
+        toggle.call(Renamed {
+            open: false,
+            count: 1
+        }),
+        Ok(Renamed {
+            open: true,
+            count: 2
+        })
+    );
+}
@@ -18,8 +18,9 @@ use itertools::Itertools;
 
 use crate::base::{
     ast::{
-        walk_expr, walk_pattern, AstType, Expr, Pattern, PatternField, SpannedExpr, SpannedIdent,
-        SpannedPattern, Typed, TypedIdent, Visitor,
+        expr_to_path, walk_ast_type, walk_expr, walk_pattern, AstType, Expr, Literal, Pattern,
+        PatternField, SpannedAstType, SpannedExpr, SpannedIdent, SpannedPattern, Typed, TypedIdent,
+        Visitor,
     },
     filename_to_module,
     fnv::{FnvMap, FnvSet},
@@ -698,8 +699,14 @@ where
                 }
             }
             Expr::MacroExpansion {
-                ref replacement, ..
-            } => self.visit_expr(replacement),
+                ref original,
+                ref replacement,
+            } => match replacement.value {
+                // A macro which failed to expand only leaves an error behind so look at what was
+                // written instead (such as the path of an `import!` which is still being typed)
+                Expr::Error(..) => self.visit_expr(original),
+                _ => self.visit_expr(replacement),
+            },
             Expr::Annotated(..) => unimplemented!(), // FIXME
             Expr::Error(..) => (),
         }
@@ -978,6 +985,432 @@ pub fn symbol(
     completion(extract, source_span, expr, pos)
 }
 
+/// The location of a binding or of a use of a binding, `span` being a span in the source of
+/// `module`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub module: String,
+    pub span: Span<BytePos>,
+}
+
+/// The typechecked modules which `definition` and `references` look through when they follow a
+/// binding across `import!`. Modules which are missing, such as the modules defined in Rust,
+/// end the search at the module which imported them.
+#[derive(Default)]
+pub struct Modules<'a> {
+    modules: Vec<(String, Span<BytePos>, &'a SpannedExpr<Symbol>)>,
+}
+
+impl<'a> Modules<'a> {
+    pub fn new() -> Self {
+        Modules::default()
+    }
+
+    /// Adds the typechecked `expr` of `module`. `source_span` is the span of the source of the
+    /// module, locations which lie outside of it (such as the implicit prelude) are never returned.
+    pub fn insert(
+        &mut self,
+        module: &str,
+        source_span: Span<BytePos>,
+        expr: &'a SpannedExpr<Symbol>,
+    ) {
+        self.modules.retain(|&(ref name, ..)| name != module);
+        self.modules.push((module.to_string(), source_span, expr));
+    }
+
+    fn get(&self, module: &str) -> Option<(Span<BytePos>, &'a SpannedExpr<Symbol>)> {
+        self.modules
+            .iter()
+            .find(|&&(ref name, ..)| name == module)
+            .map(|&(_, source_span, expr)| (source_span, expr))
+    }
+}
+
+enum Binding<'a> {
+    /// `let x = expr`
+    Let(Span<BytePos>, &'a SpannedExpr<Symbol>),
+    /// A field of a record pattern which destructures `expr`, along with the path of fields that
+    /// leads to it
+    Field(Span<BytePos>, &'a SpannedExpr<Symbol>, Vec<&'a str>),
+    /// Any other binding, such as a function, an argument or a type
+    Other(Span<BytePos>),
+}
+
+/// Returns the span of the binding of `symbol` in `pattern`. The names of the record fields
+/// leading to the binding are pushed to `path`, or `path` is set to `None` if the binding is not
+/// found through record fields alone.
+fn pattern_binding<'a>(
+    pattern: &'a SpannedPattern<Symbol>,
+    symbol: &SymbolRef,
+    path: &mut Option<Vec<&'a str>>,
+) -> Option<Span<BytePos>> {
+    match pattern.value {
+        Pattern::Ident(ref id) if *id.name == *symbol => Some(pattern.span),
+        Pattern::As(ref id, _) if *id.value == *symbol => Some(id.span),
+        Pattern::As(_, ref pattern) => pattern_binding(pattern, symbol, path),
+        Pattern::Record {
+            ref types,
+            ref fields,
+            ..
+        } => {
+            let type_field = types
+                .iter()
+                .find(|field| **field.value.as_ref().unwrap_or(&field.name.value) == *symbol);
+            if let Some(field) = type_field {
+                if let Some(ref mut path) = *path {
+                    path.push(field.name.value.declared_name());
+                }
+                return Some(field.name.span);
+            }
+            fields.iter().find_map(|field| {
+                if let Some(ref mut path) = *path {
+                    path.push(field.name.value.declared_name());
+                }
+                let span = match field.value {
+                    Some(ref pattern) => pattern_binding(pattern, symbol, path),
+                    None if *field.name.value == *symbol => Some(field.name.span),
+                    None => None,
+                };
+                if span.is_none() {
+                    if let Some(ref mut path) = *path {
+                        path.pop();
+                    }
+                }
+                span
+            })
+        }
+        Pattern::Constructor(_, ref args)
+        | Pattern::Tuple {
+            elems: ref args, ..
+        } => {
+            let mut inner_path = None;
+            let span = args
+                .iter()
+                .find_map(|arg| pattern_binding(arg, symbol, &mut inner_path));
+            if span.is_some() {
+                *path = None;
+            }
+            span
+        }
+        Pattern::Ident(_) | Pattern::Literal(_) | Pattern::Error => None,
+    }
+}
+
+fn find_binding<'a>(expr: &'a SpannedExpr<Symbol>, symbol: &SymbolRef) -> Option<Binding<'a>> {
+    struct FindBinding<'a, 's> {
+        symbol: &'s SymbolRef,
+        found: Option<Binding<'a>>,
+    }
+
+    impl<'a, 's> FindBinding<'a, 's> {
+        fn pattern(&mut self, pattern: &'a SpannedPattern<Symbol>) {
+            if let Some(span) = pattern_binding(pattern, self.symbol, &mut None) {
+                self.found = Some(Binding::Other(span));
+            }
+        }
+
+        fn args(&mut self, args: &'a [crate::base::ast::Argument<SpannedIdent<Symbol>>]) {
+            if let Some(arg) = args.iter().find(|arg| *arg.name.value.name == *self.symbol) {
+                self.found = Some(Binding::Other(arg.name.span));
+            }
+        }
+    }
+
+    impl<'a, 's> Visitor<'a> for FindBinding<'a, 's> {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, e: &'a SpannedExpr<Self::Ident>) {
+            if self.found.is_some() {
+                return;
+            }
+            match e.value {
+                Expr::LetBindings(ref binds, _) => {
+                    for bind in binds {
+                        let mut path = Some(Vec::new());
+                        if let Some(span) = pattern_binding(&bind.name, self.symbol, &mut path) {
+                            self.found = Some(match (&bind.name.value, path) {
+                                (Pattern::Ident(_), _) if bind.args.is_empty() => {
+                                    Binding::Let(span, &bind.expr)
+                                }
+                                (Pattern::Record { .. }, Some(path)) => {
+                                    Binding::Field(span, &bind.expr, path)
+                                }
+                                _ => Binding::Other(span),
+                            });
+                            return;
+                        }
+                        self.args(&bind.args);
+                    }
+                }
+                Expr::TypeBindings(ref binds, _) => {
+                    if let Some(bind) = binds.iter().find(|bind| *bind.name.value == *self.symbol) {
+                        self.found = Some(Binding::Other(bind.name.span));
+                    }
+                }
+                Expr::Lambda(ref lambda) => self.args(&lambda.args),
+                Expr::Match(_, ref alts) => {
+                    for alt in alts {
+                        self.pattern(&alt.pattern);
+                    }
+                }
+                Expr::Do(ref do_expr) => {
+                    if let Some(ref pattern) = do_expr.id {
+                        self.pattern(pattern);
+                    }
+                }
+                _ => (),
+            }
+            if self.found.is_none() {
+                walk_expr(self, e);
+            }
+        }
+    }
+
+    let mut visitor = FindBinding {
+        symbol,
+        found: None,
+    };
+    visitor.visit_expr(expr);
+    visitor.found
+}
+
+/// Follows bindings, through `import!` and the fields of records, to the place where they are
+/// first defined
+struct Resolver<'m, 'a: 'm> {
+    modules: &'m Modules<'a>,
+    /// Bounds the number of steps so that recursive bindings such as `rec let x = y let y = x`
+    /// can't loop forever
+    remaining_steps: usize,
+}
+
+impl<'m, 'a> Resolver<'m, 'a> {
+    fn new(modules: &'m Modules<'a>) -> Self {
+        Resolver {
+            modules,
+            remaining_steps: 256,
+        }
+    }
+
+    fn location(&self, module: &str, span: Span<BytePos>) -> Option<Location> {
+        let (source_span, _) = self.modules.get(module)?;
+        if source_span.contains(span) {
+            Some(Location {
+                module: module.to_string(),
+                span,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Resolves the binding of `symbol` in `module`, followed by the fields in `path`
+    fn resolve_symbol(
+        &mut self,
+        module: &str,
+        symbol: &SymbolRef,
+        path: &[&str],
+    ) -> Option<Location> {
+        if self.remaining_steps == 0 {
+            return None;
+        }
+        self.remaining_steps -= 1;
+
+        let (_, expr) = self.modules.get(module)?;
+        let (span, definition) = match find_binding(expr, symbol)? {
+            // A binding of a whole module (`let m = import! m`) is the definition of `m`, only the
+            // fields of the module are followed
+            Binding::Let(span, bound) if !path.is_empty() => {
+                (span, self.resolve_expr(module, bound, path))
+            }
+            Binding::Let(span, _) | Binding::Other(span) => (span, None),
+            Binding::Field(span, bound, mut field_path) => {
+                field_path.extend(path);
+                (span, self.resolve_expr(module, bound, &field_path))
+            }
+        };
+        // Bindings which can't be followed to the fields in `path` are left for the caller to
+        // resolve instead
+        if path.is_empty() {
+            definition.or_else(|| self.location(module, span))
+        } else {
+            definition
+        }
+    }
+
+    /// Resolves the fields in `path` of `expr`, an expression in `module`
+    fn resolve_expr(
+        &mut self,
+        module: &str,
+        expr: &'a SpannedExpr<Symbol>,
+        path: &[&str],
+    ) -> Option<Location> {
+        match expr.value {
+            Expr::Ident(ref id) if id.name.is_global() => {
+                let module = id.name.definition_name();
+                let (_, expr) = self.modules.get(module)?;
+                if path.is_empty() {
+                    None
+                } else {
+                    self.resolve_expr(module, expr, path)
+                }
+            }
+            Expr::Ident(ref id) => self.resolve_symbol(module, &id.name, path),
+            Expr::Projection(ref expr, ref field, _) => {
+                let mut field_path = vec![field.declared_name()];
+                field_path.extend(path);
+                self.resolve_expr(module, expr, &field_path)
+            }
+            Expr::Record {
+                ref types,
+                ref exprs,
+                ..
+            } => {
+                let (name, rest) = path.split_first()?;
+                if let Some(field) = exprs
+                    .iter()
+                    .find(|field| field.name.value.declared_name() == *name)
+                {
+                    match field.value {
+                        Some(ref value) => self.resolve_expr(module, value, rest),
+                        None => self.resolve_symbol(module, &field.name.value, rest),
+                    }
+                    .or_else(|| self.location(module, field.name.span))
+                } else {
+                    let field = types
+                        .iter()
+                        .find(|field| field.name.value.declared_name() == *name)?;
+                    self.resolve_symbol(module, &field.name.value, rest)
+                }
+            }
+            Expr::LetBindings(_, ref body) | Expr::TypeBindings(_, ref body) => {
+                self.resolve_expr(module, body, path)
+            }
+            Expr::MacroExpansion {
+                ref replacement, ..
+            } => self.resolve_expr(module, replacement, path),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the location where the binding at `pos` in `module` is defined. Bindings which are
+/// imported from another module, directly or through re-exports in other modules, resolve to
+/// their definition in the module which defines them. Bindings of modules which are not part of
+/// `modules`, such as the primitives defined in Rust, resolve to the binding which imports them.
+pub fn definition(modules: &Modules, module: &str, pos: BytePos) -> Result<Location, ()> {
+    let (source_span, expr) = modules.get(module).ok_or(())?;
+    let found = complete_at((), source_span, expr, pos)?;
+    let enclosing_match = found.enclosing_match().clone();
+    let mut resolver = Resolver::new(modules);
+    let location = match found.match_.ok_or(())? {
+        Match::Expr(expr) => resolver.resolve_expr(module, expr, &[]),
+        Match::Pattern(&Spanned {
+            value: Pattern::Ident(ref id),
+            ..
+        }) => resolver.resolve_symbol(module, &id.name, &[]),
+        Match::Pattern(&Spanned {
+            value: Pattern::As(ref id, _),
+            ..
+        }) => resolver.resolve_symbol(module, &id.value, &[]),
+        Match::Pattern(_) => None,
+        Match::Ident(_, id, _) => match enclosing_match {
+            Match::Expr(
+                expr @ &Spanned {
+                    value: Expr::Projection(..),
+                    ..
+                },
+            ) => resolver.resolve_expr(module, expr, &[]),
+            _ => resolver.resolve_symbol(module, id, &[]),
+        },
+        Match::Type(_, id, _) => resolver.resolve_symbol(module, id, &[]),
+    };
+    location.ok_or(())
+}
+
+/// Returns the location of every use of the binding at `pos` in `module`, looking through all of
+/// `modules`. Uses which refer to the binding through `import!` or through re-exports are
+/// included, the definition itself is not.
+pub fn references(modules: &Modules, module: &str, pos: BytePos) -> Result<Vec<Location>, ()> {
+    struct Uses<'a> {
+        source_span: Span<BytePos>,
+        uses: Vec<(Span<BytePos>, Either<&'a SpannedExpr<Symbol>, &'a Symbol>)>,
+    }
+
+    impl<'a> Uses<'a> {
+        fn push(&mut self, span: Span<BytePos>, use_: Either<&'a SpannedExpr<Symbol>, &'a Symbol>) {
+            if self.source_span.contains(span) {
+                self.uses.push((span, use_));
+            }
+        }
+    }
+
+    impl<'a> Visitor<'a> for Uses<'a> {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, e: &'a SpannedExpr<Self::Ident>) {
+            match e.value {
+                Expr::Ident(ref id) if !id.name.is_global() => self.push(e.span, Either::Left(e)),
+                Expr::Projection(_, ref field, _) => {
+                    // Only the name of the field refers to the field
+                    let start = e.span.end() - ByteOffset::from(field.declared_name().len() as i64);
+                    self.push(Span::new(start, e.span.end()), Either::Left(e))
+                }
+                Expr::Infix { ref op, .. } => self.push(op.span, Either::Right(&op.value.name)),
+                Expr::Record { ref exprs, .. } => {
+                    for field in exprs.iter().filter(|field| field.value.is_none()) {
+                        self.push(field.name.span, Either::Right(&field.name.value));
+                    }
+                }
+                _ => (),
+            }
+            walk_expr(self, e);
+        }
+
+        fn visit_pattern(&mut self, p: &'a SpannedPattern<Self::Ident>) {
+            if let Pattern::Record { ref fields, .. } = p.value {
+                // `{ x }` refers to the field `x` of the record being matched
+                for field in fields.iter().filter(|field| field.value.is_none()) {
+                    self.push(field.name.span, Either::Right(&field.name.value));
+                }
+            }
+            walk_pattern(self, &p.value);
+        }
+
+        fn visit_ast_type(&mut self, typ: &'a SpannedAstType<Self::Ident>) {
+            if let Type::Ident(ref id) = typ.value {
+                self.push(typ.span, Either::Right(id));
+            }
+            walk_ast_type(self, typ);
+        }
+    }
+
+    let definition = definition(modules, module, pos)?;
+
+    let mut references = Vec::new();
+    for &(ref name, source_span, expr) in &modules.modules {
+        let mut visitor = Uses {
+            source_span,
+            uses: Vec::new(),
+        };
+        visitor.visit_expr(expr);
+        visitor.uses.sort_by_key(|&(span, _)| span.start());
+        for (span, use_) in visitor.uses {
+            let mut resolver = Resolver::new(modules);
+            let location = match use_ {
+                Either::Left(expr) => resolver.resolve_expr(name, expr, &[]),
+                Either::Right(symbol) => resolver.resolve_symbol(name, symbol, &[]),
+            };
+            if location.as_ref() == Some(&definition) && span != definition.span {
+                references.push(Location {
+                    module: name.clone(),
+                    span,
+                });
+            }
+        }
+    }
+    Ok(references)
+}
+
 #[derive(Debug, PartialEq)]
 pub enum CompletionSymbol<'a> {
     Value {
@@ -1044,6 +1477,41 @@ pub fn all_symbols(
     visitor.result
 }
 
+/// Returns the module path of the `import!` that is being written at the position of `found`.
+/// Only `import!`s which failed to expand are found, the other ones have been replaced by the
+/// module they import.
+fn import_path(found: &Found) -> Option<String> {
+    found
+        .enclosing_matches
+        .iter()
+        .rev()
+        .find_map(|match_| match *match_ {
+            Match::Expr(&Spanned {
+                value:
+                    Expr::App {
+                        ref func, ref args, ..
+                    },
+                ..
+            }) => match func.value {
+                Expr::Ident(ref id) if id.name.declared_name() == "import!" => {
+                    let arg = args.first()?;
+                    match arg.value {
+                        Expr::Literal(Literal::String(ref filename)) => {
+                            Some(filename_to_module(filename))
+                        }
+                        _ => {
+                            let mut path = String::new();
+                            expr_to_path(arg, &mut path).ok()?;
+                            Some(path)
+                        }
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+}
+
 pub fn suggest<T>(
     env: &T,
     source_span: Span<BytePos>,
@@ -1156,6 +1624,11 @@ impl SuggestionQuery {
         };
         let mut result = vec![];
 
+        if let Some(path) = import_path(&found) {
+            self.suggest_module_import(env, &path, &mut result);
+            return result;
+        }
+
         let enclosing_match = found.enclosing_matches.last().unwrap();
         match found.match_ {
             Some(match_) => match match_ {
@@ -1481,14 +1954,8 @@ impl SuggestionQuery {
                             Match::Expr(&Spanned {
                                 value: Expr::Projection(ref expr, _, _),
                                 ..
-                            }) => {
-                                if let Expr::Ident(ref expr_ident) = expr.value {
-                                    env.get(&expr_ident.name)
-                                        .and_then(|metadata| metadata.module.get(name))
-                                } else {
-                                    None
-                                }
-                            }
+                            }) => expr_metadata(env, expr)
+                                .and_then(|metadata| metadata.module.get(name)),
                             _ => None,
                         },
                         _ => None,
@@ -1590,6 +2057,22 @@ pub fn signature_help(
         })
 }
 
+/// Returns the metadata of `expr` if it is a variable or a (nested) field of a variable
+fn expr_metadata<'a>(
+    env: &'a FnvMap<Symbol, Arc<Metadata>>,
+    expr: &SpannedExpr<Symbol>,
+) -> Option<&'a Arc<Metadata>> {
+    match expr.value {
+        Expr::Ident(ref id) => env.get(&id.name),
+        Expr::Projection(ref expr, ref field, _) => expr_metadata(env, expr)
+            .and_then(|metadata| metadata.module.get(field.declared_name())),
+        Expr::MacroExpansion {
+            ref replacement, ..
+        } => expr_metadata(env, replacement),
+        _ => None,
+    }
+}
+
 pub fn get_metadata<'a>(
     env: &'a FnvMap<Symbol, Arc<Metadata>>,
     source_span: Span<BytePos>,
@@ -1614,14 +2097,7 @@ pub fn get_metadata<'a>(
                 Match::Expr(&Spanned {
                     value: Expr::Projection(ref expr, _, _),
                     ..
-                }) => {
-                    if let Expr::Ident(ref expr_id) = expr.value {
-                        env.get(&expr_id.name)
-                            .and_then(|metadata| metadata.module.get(id.as_ref()))
-                    } else {
-                        None
-                    }
-                }
+                }) => expr_metadata(env, expr).and_then(|metadata| metadata.module.get(id.as_ref())),
                 Match::Expr(&Spanned {
                     value: Expr::Infix { .. },
                     ..
@@ -1633,6 +2109,26 @@ pub fn get_metadata<'a>(
         .map(|m| &**m)
 }
 
+/// Returns the documentation to display when hovering over a binding with `metadata`. The
+/// deprecation notice of the binding, if any, is placed before the documentation comment.
+pub fn hover_documentation(metadata: &Metadata) -> String {
+    let mut documentation = String::new();
+    if let Some(message) = metadata.deprecation() {
+        documentation.push_str("Deprecated");
+        if !message.is_empty() {
+            documentation.push_str(": ");
+            documentation.push_str(message);
+        }
+    }
+    if let Some(ref comment) = metadata.comment {
+        if !documentation.is_empty() {
+            documentation.push_str("\n\n");
+        }
+        documentation.push_str(&comment.content);
+    }
+    documentation
+}
+
 pub fn suggest_metadata<'a, T>(
     env: &'a FnvMap<Symbol, Arc<Metadata>>,
     type_env: &T,
@@ -396,6 +396,25 @@ abc
     assert_eq!(result, expected);
 }
 
+#[test]
+fn hover_documentation_includes_the_deprecation() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+/// Returns its argument
+#[deprecated("use `id` instead")]
+let identity x = x
+identity 1
+"#;
+    let position = BytePos::from(text.rfind("identity").unwrap() as u32 + 1);
+    let result = get_metadata(text, position).map(|meta| completion::hover_documentation(&meta));
+
+    assert_eq!(
+        result,
+        Some("Deprecated: use `id` instead\n\nReturns its argument".to_string())
+    );
+}
+
 #[test]
 fn metadata_at_binop() {
     let _ = env_logger::try_init();
@@ -439,6 +458,28 @@ module.abc
     assert_eq!(result, expected);
 }
 
+#[test]
+fn metadata_at_nested_field_access() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let module = {
+        inner = {
+            /// test
+            abc = 1,
+        },
+    }
+module.inner.abc
+"#;
+    let result = get_metadata(text, loc(text, 7, 15));
+
+    let expected = Some(Metadata {
+        comment: Some(line_comment("test".to_string())),
+        ..Metadata::default()
+    });
+    assert_eq!(result, expected);
+}
+
 #[test]
 fn metadata_at_type_pattern() {
     let _ = env_logger::try_init();
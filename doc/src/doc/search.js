@@ -0,0 +1,55 @@
+// Searches the bindings listed in `search-index.json` as the user types in the search box
+(function () {
+    var input = document.getElementById("search");
+    var results = document.getElementById("search-results");
+    var index = null;
+
+    function load(callback) {
+        if (index !== null) {
+            return callback(index);
+        }
+        var request = new XMLHttpRequest();
+        request.open("GET", SEARCH_ROOT + "search-index.json");
+        request.onload = function () {
+            index = JSON.parse(request.responseText);
+            callback(index);
+        };
+        request.send();
+    }
+
+    function link(entry) {
+        return SEARCH_ROOT + entry.module.replace(/\./g, "/") + ".html#" + entry.kind + "." + entry.name;
+    }
+
+    function show(query) {
+        results.innerHTML = "";
+        if (query === "") {
+            return;
+        }
+        load(function (index) {
+            var matches = index.filter(function (entry) {
+                return entry.name.toLowerCase().indexOf(query) !== -1;
+            });
+            matches.slice(0, 20).forEach(function (entry) {
+                var item = document.createElement("a");
+                item.className = "list-group-item list-group-item-action";
+                item.href = link(entry);
+
+                var name = document.createElement("code");
+                name.textContent = entry.module + "." + entry.name;
+                item.appendChild(name);
+
+                var doc = document.createElement("small");
+                doc.className = "d-block text-muted";
+                doc.textContent = entry.doc;
+                item.appendChild(doc);
+
+                results.appendChild(item);
+            });
+        });
+    }
+
+    input.addEventListener("input", function () {
+        show(input.value.trim().toLowerCase());
+    });
+})();
@@ -24,10 +24,12 @@ extern crate gluon;
 
 use std::{
     collections::{BTreeMap, BTreeSet},
+    fmt,
     fs::{self, create_dir_all, File},
     io::{self, Read},
     path::{Path, PathBuf},
     result::Result as StdResult,
+    sync::Arc,
 };
 
 use failure::ResultExt;
@@ -82,6 +84,248 @@ pub struct Field {
     #[serde(rename = "type")]
     pub typ: String,
     pub comment: String,
+    pub deprecation: Option<Deprecation>,
+}
+
+/// The `#[deprecated(message)]` attribute of a field
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct Deprecation {
+    pub message: String,
+}
+
+/// Options which decide which bindings are documented
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Leaves out the bindings which do not have a documentation comment
+    pub exclude_undocumented: bool,
+}
+
+/// A problem in the documentation of a module which does not stop it from being generated
+#[derive(Clone, PartialEq, Debug)]
+pub struct Warning {
+    pub path: PathBuf,
+    /// The line and column that the problem starts at, counting from 1
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some((line, column)) = self.location {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        write!(f, ": warning: {}", self.message)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ModuleBindings {
+    types: BTreeSet<String>,
+    values: BTreeSet<String>,
+}
+
+/// The modules, types and values which are documented together. Used to link to the page which
+/// documents a type or a name referenced in a documentation comment.
+#[derive(Debug, Default)]
+pub struct Index {
+    options: Options,
+    modules: BTreeMap<String, ModuleBindings>,
+    /// The module documenting each type, keyed by the name of the type's definition
+    type_modules: BTreeMap<String, String>,
+}
+
+impl Index {
+    pub fn new(options: Options) -> Index {
+        Index {
+            options,
+            ..Index::default()
+        }
+    }
+
+    fn documented(&self, meta: &Metadata, field: &str) -> bool {
+        !hidden(meta, field)
+            && (!self.options.exclude_undocumented || !comment(meta, field).is_empty())
+    }
+
+    /// Adds the types and values exported by `module`. A type which is re-exported by other
+    /// modules links to the module which defines it, unless that module is not documented.
+    pub fn add_module(&mut self, module: &str, typ: &ArcType, meta: &Metadata) {
+        let parent = Name::new(module).module().as_str();
+        if !parent.is_empty() {
+            self.modules.entry(parent.to_string()).or_default();
+        }
+
+        let mut bindings = ModuleBindings::default();
+        for field in typ.type_field_iter() {
+            if !self.documented(meta, field.name.as_ref()) {
+                continue;
+            }
+            bindings
+                .types
+                .insert(field.name.definition_name().to_string());
+
+            let definition = field.typ.name.definition_name();
+            if Name::new(definition).module().as_str() == module {
+                self.type_modules
+                    .insert(definition.to_string(), module.to_string());
+            } else {
+                self.type_modules
+                    .entry(definition.to_string())
+                    .or_insert_with(|| module.to_string());
+            }
+        }
+        for field in typ.row_iter() {
+            if self.documented(meta, field.name.as_ref()) {
+                bindings
+                    .values
+                    .insert(field.name.definition_name().to_string());
+            }
+        }
+        self.modules.insert(module.to_string(), bindings);
+    }
+
+    /// Links to the documentation of `symbol` from the page of `current_module`
+    fn type_link(&self, current_module: &str, symbol: &Symbol) -> Option<String> {
+        let definition = symbol.definition_name();
+        self.type_modules.get(definition).map(|module| {
+            format!(
+                "{}#type.{}",
+                module_link(current_module, module),
+                Name::new(definition).name().as_str()
+            )
+        })
+    }
+
+    /// Resolves a `[name]` reference written in the documentation of `scope`. The name is looked
+    /// up relative to `scope` first and then relative to each module containing `scope`, so
+    /// `[Option]`, `[option.Option]` and `[std.option.Option]` may all refer to the same type.
+    fn resolve(&self, scope: &str, reference: &str) -> Option<(&str, Option<String>)> {
+        let mut prefix = scope;
+        loop {
+            let candidate = if prefix.is_empty() {
+                reference.to_string()
+            } else {
+                format!("{}.{}", prefix, reference)
+            };
+            if let Some((module, _)) = self.modules.get_key_value(&candidate) {
+                return Some((module, None));
+            }
+
+            let name = Name::new(&candidate);
+            if let Some((module, bindings)) = self.modules.get_key_value(name.module().as_str()) {
+                let name = name.name().as_str();
+                if bindings.types.contains(name) {
+                    return Some((module, Some(format!("type.{}", name))));
+                }
+                if bindings.values.contains(name) {
+                    return Some((module, Some(format!("value.{}", name))));
+                }
+            }
+
+            if prefix.is_empty() {
+                return None;
+            }
+            prefix = Name::new(prefix).module().as_str();
+        }
+    }
+
+    /// Turns each `[name]` reference in `comment`, written in the documentation of `scope`, into
+    /// a link from the page of `current_module`. References which do not resolve are left as is.
+    fn link_references(&self, scope: &str, current_module: &str, comment: &str) -> String {
+        let mut linked = String::with_capacity(comment.len());
+        let mut last = 0;
+        for reference in references(comment) {
+            if let Some((module, anchor)) = self.resolve(scope, reference.name) {
+                linked.push_str(&comment[last..reference.end]);
+                linked.push_str(&format!(
+                    "({}{})",
+                    module_link(current_module, module),
+                    anchor.map_or(String::new(), |anchor| format!("#{}", anchor))
+                ));
+                last = reference.end;
+            }
+        }
+        linked.push_str(&comment[last..]);
+        linked
+    }
+}
+
+/// A `[name]` in a documentation comment
+struct Reference<'a> {
+    name: &'a str,
+    /// The line of the reference in the comment, starting from 0
+    line: usize,
+    /// The byte offset of `[` in the line
+    column: usize,
+    /// The byte offset after `]` in the comment
+    end: usize,
+}
+
+/// Finds the `[name]` references in `comment` which are not part of code or of a markdown link
+fn references(comment: &str) -> Vec<Reference> {
+    lazy_static! {
+        static ref REGEX: regex::Regex =
+            regex::Regex::new(r"\[([A-Za-z_][A-Za-z0-9_]*(?:\.[A-Za-z_][A-Za-z0-9_]*)*)\]")
+                .unwrap();
+    }
+
+    let mut references = Vec::new();
+    let mut in_code_block = false;
+    let mut line_start = 0;
+    for (line_number, line) in comment.split('\n').enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        } else if !in_code_block {
+            // Skip the inline code between pairs of backticks
+            let mut offset = 0;
+            for (i, text) in line.split('`').enumerate() {
+                if i % 2 == 0 {
+                    for captures in REGEX.captures_iter(text) {
+                        let whole = captures.get(0).unwrap();
+                        let before = text[..whole.start()].chars().next_back();
+                        let after = text[whole.end()..].chars().next();
+                        // `[text](url)`, `[text][label]` and `[label]: url` are already links
+                        if before == Some(']') || after.map_or(false, |c| "([:".contains(c)) {
+                            continue;
+                        }
+                        references.push(Reference {
+                            name: captures.get(1).unwrap().as_str(),
+                            line: line_number,
+                            column: offset + whole.start(),
+                            end: line_start + offset + whole.end(),
+                        });
+                    }
+                }
+                offset += text.len() + 1;
+            }
+        }
+        line_start += line.len() + 1;
+    }
+    references
+}
+
+/// Finds the line that `comment` starts at in `source`, starting from 0
+fn comment_line(source: &[&str], comment: &str) -> Option<usize> {
+    let comment_lines: Vec<_> = comment.split('\n').collect();
+    (0..source.len()).find(|&start| {
+        comment_lines.len() <= source.len() - start
+            && comment_lines
+                .iter()
+                .zip(&source[start..])
+                .all(|(comment_line, source_line)| {
+                    strip_comment_prefix(source_line).map(str::trim) == Some(comment_line.trim())
+                })
+    })
+}
+
+fn strip_comment_prefix(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with("///") || line.starts_with("//!") {
+        Some(&line[3..])
+    } else {
+        None
+    }
 }
 
 struct SymbolLinkRenderer {
@@ -124,9 +368,8 @@ impl pretty::RenderAnnotated<String> for SymbolLinkRenderer {
     }
 }
 
-fn print_type(current_module: &str, typ: &ArcType) -> String {
-    let annotate_symbol =
-        |symbol: &Symbol| Some(symbol_link(false, current_module, symbol.as_ref()));
+fn print_type(index: &Index, current_module: &str, typ: &ArcType) -> String {
+    let annotate_symbol = |symbol: &Symbol| index.type_link(current_module, symbol);
     let arena = Arena::new();
     let mut doc = typ
         .display(80)
@@ -156,11 +399,29 @@ fn hidden(meta: &Metadata, field: &str) -> bool {
     })
 }
 
-pub fn record(current_module: &str, typ: &ArcType, meta: &Metadata) -> Record {
+fn comment<'a>(meta: &'a Metadata, field: &str) -> &'a str {
+    meta.module
+        .get(field)
+        .and_then(|meta| meta.comment.as_ref().map(|s| &s.content[..]))
+        .unwrap_or("")
+}
+
+fn deprecation(meta: &Metadata, field: &str) -> Option<Deprecation> {
+    meta.module
+        .get(field)
+        .and_then(|meta| meta.deprecation())
+        .map(|message| Deprecation {
+            message: message.to_string(),
+        })
+}
+
+/// Collects the documented types and values of `current_module`, linking the types in their
+/// signatures to the pages of the modules in `index`
+pub fn record(index: &Index, current_module: &str, typ: &ArcType, meta: &Metadata) -> Record {
     Record {
         types: typ
             .type_field_iter()
-            .filter(|field| !hidden(meta, field.name.as_ref()))
+            .filter(|field| index.documented(meta, field.name.as_ref()))
             .map(|field| Field {
                 name: field.name.definition_name().to_string(),
                 args: field
@@ -172,19 +433,19 @@ pub fn record(current_module: &str, typ: &ArcType, meta: &Metadata) -> Record {
                         name: gen.id.to_string(),
                     })
                     .collect(),
-                typ: print_type(current_module, &field.typ.unresolved_type().remove_forall()),
-                comment: meta
-                    .module
-                    .get(AsRef::<str>::as_ref(&field.name))
-                    .and_then(|meta| meta.comment.as_ref().map(|s| &s.content[..]))
-                    .unwrap_or("")
-                    .to_string(),
+                typ: print_type(
+                    index,
+                    current_module,
+                    &field.typ.unresolved_type().remove_forall(),
+                ),
+                comment: comment(meta, field.name.as_ref()).to_string(),
+                deprecation: deprecation(meta, field.name.as_ref()),
             })
             .collect(),
 
         values: typ
             .row_iter()
-            .filter(|field| !hidden(meta, field.name.as_ref()))
+            .filter(|field| index.documented(meta, field.name.as_ref()))
             .map(|field| {
                 let meta_opt = meta.module.get(AsRef::<str>::as_ref(&field.name));
                 Field {
@@ -200,12 +461,10 @@ pub fn record(current_module: &str, typ: &ArcType, meta: &Metadata) -> Record {
                                 .collect()
                         })
                         .unwrap_or_default(),
-                    typ: print_type(current_module, &field.typ),
+                    typ: print_type(index, current_module, &field.typ),
 
-                    comment: meta_opt
-                        .and_then(|meta| meta.comment.as_ref().map(|s| &s.content[..]))
-                        .unwrap_or("")
-                        .to_string(),
+                    comment: comment(meta, field.name.as_ref()).to_string(),
+                    deprecation: deprecation(meta, field.name.as_ref()),
                 }
             })
             .collect(),
@@ -215,36 +474,43 @@ pub fn record(current_module: &str, typ: &ArcType, meta: &Metadata) -> Record {
 #[derive(Serialize, Debug)]
 pub struct TemplateModule<'a> {
     pub name: &'a str,
-    pub comment: &'a str,
-    pub record: &'a Record,
-    pub sub_modules: Vec<&'a Module>,
+    pub comment: String,
+    pub record: Record,
+    pub sub_modules: Vec<TemplateSubModule<'a>>,
     pub sibling_modules: Vec<&'a str>,
 }
 
-const MODULE_TEMPLATE: &str = "module";
+#[derive(Serialize, Debug)]
+pub struct TemplateSubModule<'a> {
+    pub name: &'a str,
+    pub comment: String,
+}
 
-fn symbol_link(index: bool, current_module: &str, param: &str) -> String {
-    let skipped = if index { 0 } else { 1 };
+/// An exported binding as it is written to `search-index.json`
+#[derive(Serialize, Debug)]
+struct SearchEntry<'a> {
+    name: &'a str,
+    module: &'a str,
+    kind: &'static str,
+    doc: &'a str,
+}
 
-    let parts: Vec<_> = param.split('.').collect();
-    let (typ, module_parts) = parts.split_last().unwrap();
+const MODULE_TEMPLATE: &str = "module";
 
-    format!(
-        "{}{}.html#type.{}",
-        current_module
-            .split('.')
-            .skip(skipped)
-            .map(|_| "../")
-            .format(""),
-        module_parts.iter().format("/"),
-        typ
-    )
+/// The relative path from the page of `current_module` to the root of the documentation
+fn root_path(current_module: &str) -> String {
+    current_module
+        .split('.')
+        .skip(1)
+        .map(|_| "../")
+        .format("")
+        .to_string()
 }
 
 fn module_link(current_module: &str, param: &str) -> String {
     format!(
         "{}{}.html",
-        current_module.split('.').skip(1).map(|_| "../").format(""),
+        root_path(current_module),
         param.replace(".", "/"),
     )
 }
@@ -312,7 +578,7 @@ fn handlebars() -> Result<Handlebars> {
         out: &mut Output,
     ) -> ::std::result::Result<(), RenderError> {
         let current_module = &context.data()["name"].as_str().expect("name").to_string();
-        let relative_path = current_module.split('.').map(|_| "../").format("");
+        let relative_path = root_path(current_module);
 
         out.write(&format!(r#"
 <link rel="stylesheet" href="https://maxcdn.bootstrapcdn.com/bootstrap/4.0.0/css/bootstrap.min.css" integrity="sha384-Gn5384xqQ1aoWXA+058RXPxPg6fy4IWvTNh0E263XmFcJlSAwiGgFAW/dAiS6JXm" crossorigin="anonymous">
@@ -322,6 +588,19 @@ fn handlebars() -> Result<Handlebars> {
     }
     reg.register_helper("style", Box::new(style));
 
+    fn root(
+        _: &Helper,
+        _: &Handlebars,
+        context: &Context,
+        _: &mut RenderContext,
+        out: &mut Output,
+    ) -> ::std::result::Result<(), RenderError> {
+        let current_module = context.data()["name"].as_str().expect("name");
+        out.write(&root_path(current_module))?;
+        Ok(())
+    }
+    reg.register_helper("root", Box::new(root));
+
     fn markdown(
         h: &Helper,
         _: &Handlebars,
@@ -395,18 +674,143 @@ where
     Ok(())
 }
 
-pub fn generate_for_path<P, Q>(thread: &Thread, path: &P, out_path: &Q) -> Result<()>
+/// Like `generate` but with the default `Options`.
+pub fn generate_for_path<P, Q>(thread: &Thread, path: &P, out_path: &Q) -> Result<Vec<Warning>>
 where
     P: ?Sized + AsRef<Path>,
     Q: ?Sized + AsRef<Path>,
 {
-    generate_for_path_(thread, path.as_ref(), out_path.as_ref())
+    generate(thread, path, out_path, &Options::default())
 }
 
-pub fn generate_for_path_(thread: &Thread, path: &Path, out_path: &Path) -> Result<()> {
-    let mut directories = BTreeMap::<_, BTreeMap<_, Module>>::new();
-    let mut modules = BTreeSet::new();
-    let mut content = String::new();
+/// Documents the gluon files at `path`, writing the pages along with a `search-index.json` to
+/// `out_path`. Returns the problems found in the documentation comments.
+pub fn generate<P, Q>(
+    thread: &Thread,
+    path: &P,
+    out_path: &Q,
+    options: &Options,
+) -> Result<Vec<Warning>>
+where
+    P: ?Sized + AsRef<Path>,
+    Q: ?Sized + AsRef<Path>,
+{
+    generate_for_path_(thread, path.as_ref(), out_path.as_ref(), options)
+}
+
+/// A module which has been typechecked but not yet documented
+struct SourceModule {
+    name: String,
+    path: PathBuf,
+    content: String,
+    comment: String,
+    typ: ArcType,
+    meta: Arc<Metadata>,
+}
+
+/// Warns about the references in the documentation of `module` which do not resolve
+fn check_references(index: &Index, module: &SourceModule, warnings: &mut Vec<Warning>) {
+    let source: Vec<_> = module.content.lines().collect();
+    let fields = module
+        .typ
+        .type_field_iter()
+        .map(|field| &field.name)
+        .chain(module.typ.row_iter().map(|field| &field.name))
+        .filter(|name| index.documented(&module.meta, name.as_ref()))
+        .map(|name| comment(&module.meta, name.as_ref()));
+
+    for comment in Some(&module.comment[..]).into_iter().chain(fields) {
+        let start = comment_line(&source, comment);
+        for reference in references(comment) {
+            if index.resolve(&module.name, reference.name).is_some() {
+                continue;
+            }
+            let location = start.map(|start| {
+                let source_line = source[start + reference.line];
+                let comment_line = comment.split('\n').nth(reference.line).unwrap();
+                let source_indent = source_line.len()
+                    - strip_comment_prefix(source_line)
+                        .unwrap()
+                        .trim_start()
+                        .len();
+                let comment_indent = comment_line.len() - comment_line.trim_start().len();
+                (
+                    start + reference.line + 1,
+                    source_indent + reference.column - comment_indent + 1,
+                )
+            });
+            warnings.push(Warning {
+                path: module.path.clone(),
+                location,
+                message: format!(
+                    "`[{}]` does not refer to any documented module, type or value",
+                    reference.name
+                ),
+            });
+        }
+    }
+}
+
+fn linked_record(index: &Index, module: &str, record: &Record) -> Record {
+    let link = |fields: &[Field]| {
+        fields
+            .iter()
+            .map(|field| Field {
+                name: field.name.clone(),
+                args: field
+                    .args
+                    .iter()
+                    .map(|arg| Argument {
+                        implicit: arg.implicit,
+                        name: arg.name.clone(),
+                    })
+                    .collect(),
+                typ: field.typ.clone(),
+                comment: index.link_references(module, module, &field.comment),
+                deprecation: field.deprecation.clone(),
+            })
+            .collect()
+    };
+    Record {
+        types: link(&record.types),
+        values: link(&record.values),
+    }
+}
+
+fn first_line(field: &Field) -> &str {
+    field.comment.lines().next().unwrap_or("").trim()
+}
+
+fn search_index(modules: &BTreeMap<String, BTreeMap<String, Module>>) -> Result<String> {
+    let entries: Vec<_> = modules
+        .values()
+        .flat_map(|modules| modules.values())
+        .flat_map(|module| {
+            let types = module.record.types.iter().map(move |field| SearchEntry {
+                name: &field.name,
+                module: &module.name,
+                kind: "type",
+                doc: first_line(field),
+            });
+            let values = module.record.values.iter().map(move |field| SearchEntry {
+                name: &field.name,
+                module: &module.name,
+                kind: "value",
+                doc: first_line(field),
+            });
+            types.chain(values)
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+pub fn generate_for_path_(
+    thread: &Thread,
+    path: &Path,
+    out_path: &Path,
+    options: &Options,
+) -> Result<Vec<Warning>> {
+    let mut sources = Vec::new();
 
     for entry in walkdir::WalkDir::new(path) {
         let entry = entry?;
@@ -424,7 +828,7 @@ pub fn generate_for_path_(thread: &Thread, path: &Path, out_path: &Path) -> Resu
                 err
             )
         })?;
-        content.clear();
+        let mut content = String::new();
         input.read_to_string(&mut content)?;
 
         let name = filename_to_module(
@@ -450,13 +854,33 @@ pub fn generate_for_path_(thread: &Thread, path: &Path, out_path: &Path) -> Resu
             .format("\n")
             .to_string();
 
-        let module = Module {
-            record: record(&name, &typ, &meta),
+        sources.push(SourceModule {
             name,
+            path: entry.path().to_owned(),
+            content,
             comment,
+            typ,
+            meta,
+        });
+    }
+    sources.sort_by(|l, r| l.name.cmp(&r.name));
+
+    let mut index = Index::new(options.clone());
+    for source in &sources {
+        index.add_module(&source.name, &source.typ, &source.meta);
+    }
+
+    let mut warnings = Vec::new();
+    let mut directories = BTreeMap::<_, BTreeMap<_, Module>>::new();
+    for source in &sources {
+        check_references(&index, source, &mut warnings);
+
+        let module = Module {
+            record: record(&index, &source.name, &source.typ, &source.meta),
+            name: source.name.clone(),
+            comment: source.comment.clone(),
         };
 
-        modules.insert(module.name.clone());
         let name = Name::new(&module.name);
         directories
             .entry(name.module().as_str().to_owned())
@@ -504,12 +928,20 @@ pub fn generate_for_path_(thread: &Thread, path: &Path, out_path: &Path) -> Resu
                 &mut doc_file,
                 &TemplateModule {
                     name: &module.name,
-                    comment: &module.comment,
-                    record: &module.record,
+                    comment: index.link_references(&module.name, &module.name, &module.comment),
+                    record: linked_record(&index, &module.name, &module.record),
                     sub_modules: directories
                         .get(&module.name)
                         .iter()
                         .flat_map(|sub_modules| sub_modules.values())
+                        .map(|sub_module| TemplateSubModule {
+                            name: &sub_module.name,
+                            comment: index.link_references(
+                                &sub_module.name,
+                                &module.name,
+                                &sub_module.comment,
+                            ),
+                        })
                         .collect(),
                     sibling_modules: modules.keys().map(|s| s as &str).collect(),
                 },
@@ -524,8 +956,16 @@ pub fn generate_for_path_(thread: &Thread, path: &Path, out_path: &Path) -> Resu
         out_path.join("style.css"),
         &include_bytes!("doc/style.css")[..],
     )?;
+    fs::write(
+        out_path.join("search.js"),
+        &include_bytes!("doc/search.js")[..],
+    )?;
+    fs::write(
+        out_path.join("search-index.json"),
+        search_index(&directories)?,
+    )?;
 
-    Ok(())
+    Ok(warnings)
 }
 
 const LONG_VERSION: &str = concat!(crate_version!(), "\n", "commit: ", env!("GIT_HASH"));
@@ -541,8 +981,19 @@ pub struct Opt {
     #[structopt(long = "jobs")]
     #[structopt(help = "How many threads to run in parallel")]
     pub jobs: Option<usize>,
+    #[structopt(long = "exclude-undocumented")]
+    #[structopt(help = "Leaves out the bindings which do not have a documentation comment")]
+    pub exclude_undocumented: bool,
     #[structopt(help = "Documents the file or directory")]
     pub input: String,
     #[structopt(help = "Outputs the documentation to this directory")]
     pub output: String,
 }
+
+impl Opt {
+    pub fn options(&self) -> Options {
+        Options {
+            exclude_undocumented: self.exclude_undocumented,
+        }
+    }
+}
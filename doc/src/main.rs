@@ -30,7 +30,10 @@ fn main_() -> Result<(), failure::Error> {
             .build_global()?;
     }
 
-    gluon_doc::generate_for_path(&gluon::new_vm(), &opt.input, &opt.output)?;
+    let warnings = gluon_doc::generate(&gluon::new_vm(), &opt.input, &opt.output, &opt.options())?;
+    for warning in warnings {
+        eprintln!("{}", warning);
+    }
 
     if opt.open {
         let path = Path::new(&opt.output)
@@ -2,12 +2,16 @@ extern crate gluon;
 extern crate gluon_doc as doc;
 extern crate handlebars;
 
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
 use gluon::check::metadata::metadata;
 use gluon::{Compiler, RootedThread};
 
 fn new_vm() -> RootedThread {
     ::gluon::VmBuilder::new()
-        .import_paths(Some(vec!["..".into()]))
+        .import_paths(Some(vec![".".into(), "..".into()]))
         .build()
 }
 
@@ -18,7 +22,7 @@ fn doc_check(module: &str, expected: doc::Record) {
         .unwrap();
     let (meta, _) = metadata(&*vm.get_env(), &expr);
 
-    let out = doc::record("basic", &typ, &meta);
+    let out = doc::record(&doc::Index::default(), "basic", &typ, &meta);
     assert_eq!(out, expected,);
 }
 
@@ -41,6 +45,35 @@ let test x = x
                 }],
                 typ: handlebars::html_escape("forall a . a -> a"),
                 comment: "This is the test function".to_string(),
+                deprecation: None,
+            }],
+        },
+    );
+}
+
+#[test]
+fn deprecated() {
+    let module = r#"
+/// This is the test function
+#[deprecated("use `id` instead")]
+let test x = x
+{ test }
+"#;
+    doc_check(
+        module,
+        doc::Record {
+            types: Vec::new(),
+            values: vec![doc::Field {
+                name: "test".to_string(),
+                args: vec![doc::Argument {
+                    implicit: false,
+                    name: "x".to_string(),
+                }],
+                typ: handlebars::html_escape("forall a . a -> a"),
+                comment: "This is the test function".to_string(),
+                deprecation: Some(doc::Deprecation {
+                    message: "use `id` instead".to_string(),
+                }),
             }],
         },
     );
@@ -63,3 +96,97 @@ let test x = x
         },
     );
 }
+
+/// Documents the modules in `tests/fixture` into a fresh directory
+fn generate_fixture(name: &str, options: &doc::Options) -> (PathBuf, Vec<String>) {
+    let out = env::temp_dir().join(name);
+    if out.exists() {
+        fs::remove_dir_all(&out).unwrap();
+    }
+    let warnings = doc::generate(&new_vm(), "tests/fixture", &out, options)
+        .unwrap_or_else(|err| panic!("{}", err));
+    (out, warnings.iter().map(|w| w.to_string()).collect())
+}
+
+fn generated_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            generated_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// Compares the documentation of `tests/fixture` with the files in `tests/golden`. Set
+/// `UPDATE_GOLDEN=1` to write the current output to `tests/golden` instead.
+#[test]
+fn golden_files() {
+    let (out, warnings) = generate_fixture("gluon_doc_golden", &doc::Options::default());
+
+    let golden = Path::new("tests/golden");
+    let mut files = Vec::new();
+    generated_files(&out, &mut files);
+    files.sort();
+
+    let mut expected_files = Vec::new();
+    for file in files {
+        let relative = file.strip_prefix(&out).unwrap();
+        // The stylesheet and script are copied as is
+        if relative == Path::new("style.css") || relative == Path::new("search.js") {
+            continue;
+        }
+        let actual = fs::read_to_string(&file).unwrap();
+        let golden_file = golden.join(relative);
+        if env::var("UPDATE_GOLDEN").is_ok() {
+            fs::create_dir_all(golden_file.parent().unwrap()).unwrap();
+            fs::write(&golden_file, &actual).unwrap();
+        }
+        let expected = fs::read_to_string(&golden_file)
+            .unwrap_or_else(|err| panic!("{}: {}", golden_file.display(), err));
+        assert_eq!(
+            actual,
+            expected,
+            "`{}` differs from the golden file",
+            relative.display()
+        );
+        expected_files.push(golden_file);
+    }
+
+    let mut golden_files = Vec::new();
+    generated_files(golden, &mut golden_files);
+    golden_files.sort();
+    assert_eq!(
+        golden_files, expected_files,
+        "Some golden files were not generated"
+    );
+
+    assert_eq!(
+        warnings,
+        [
+            "tests/fixture/extra/polygon.glu:7:35: warning: `[Point]` does not refer to any \
+             documented module, type or value",
+            "tests/fixture/shapes.glu:14:53: warning: `[circumference]` does not refer to any \
+             documented module, type or value",
+        ]
+    );
+}
+
+#[test]
+fn exclude_undocumented() {
+    let (out, _) = generate_fixture(
+        "gluon_doc_exclude_undocumented",
+        &doc::Options {
+            exclude_undocumented: true,
+        },
+    );
+
+    let search_index = fs::read_to_string(out.join("search-index.json")).unwrap();
+    assert!(search_index.contains(r#""name": "area""#));
+    assert!(!search_index.contains(r#""name": "center""#));
+
+    let shapes = fs::read_to_string(out.join("tests/fixture/shapes.html")).unwrap();
+    assert!(shapes.contains(r#"id="value.area""#));
+    assert!(!shapes.contains(r#"id="value.center""#));
+}
@@ -9,7 +9,10 @@ extern crate pretty;
 
 use base::{ast::SpannedExpr, symbol::Symbol};
 
+pub use crate::settings::{FormatSettings, NewlineStyle, SettingsError, SETTINGS_FILE_NAME};
+
 mod pretty_print;
+mod settings;
 
 pub fn pretty_expr(input: &str, expr: &SpannedExpr<Symbol>) -> String {
     Formatter::default().pretty_expr(input, expr)
@@ -21,26 +24,16 @@ pub struct Formatter {
     ///
     /// NOTE: This is only provided for debug purposes and is likely to have have bugs
     pub expanded: bool,
+    pub settings: FormatSettings,
 }
 
 impl Formatter {
     pub fn pretty_expr(&self, input: &str, expr: &SpannedExpr<Symbol>) -> String {
-        let newline = match input.find(|c: char| c == '\n' || c == '\r') {
-            Some(i) => {
-                if input[i..].starts_with("\r\n") {
-                    "\r\n"
-                } else if input[i..].starts_with("\r") {
-                    "\r"
-                } else {
-                    "\n"
-                }
-            }
-            None => "\n",
-        };
+        let newline = self.settings.get_newline_style().newline(input);
 
         let source = codespan::FileMap::new("test".into(), input.into());
-        let arena = pretty::Arena::<()>::new();
+        let arena = pretty::Arena::new();
         let printer = pretty_print::Printer::new(&arena, &source, self.clone());
-        printer.format(100, newline, &expr)
+        printer.format(self.settings.get_line_width(), newline, &expr)
     }
 }
@@ -1,8 +1,8 @@
-use std::{iter, ops};
+use std::{fmt, iter, ops};
 
 use codespan::{ByteOffset, RawOffset};
 use itertools::{Either, Itertools};
-use pretty::{Arena, Doc, DocAllocator, DocBuilder};
+use pretty::{Arena, Doc, DocAllocator, DocBuilder, Render, RenderAnnotated};
 
 use self::types::pretty_print as pretty_types;
 use base::ast::{
@@ -14,8 +14,6 @@ use base::pos::{self, BytePos, HasSpan, Span, Spanned};
 use base::source;
 use base::types::{self, ArgType, Prec, Type};
 
-const INDENT: usize = 4;
-
 macro_rules! newlines_iter {
     ($self_:ident, $iterable:expr) => {
         $iterable
@@ -50,39 +48,105 @@ fn is_nil<'a, A>(doc: &DocBuilder<'a, Arena<'a, A>, A>) -> bool {
     }
 }
 
+/// Annotates the position of the trailing comma of a record which is only known to be needed
+/// once the renderer has decided to put the fields on separate lines
+#[derive(Clone, Debug)]
+pub(super) struct TrailingComma;
+
+/// Collects the rendered text along with the positions of any `TrailingComma` annotations
+#[derive(Default)]
+struct Output {
+    text: String,
+    trailing_commas: Vec<usize>,
+}
+
+impl Render for Output {
+    type Error = fmt::Error;
+
+    fn write_str(&mut self, s: &str) -> Result<usize, fmt::Error> {
+        self.text.push_str(s);
+        Ok(s.len())
+    }
+}
+
+impl RenderAnnotated<TrailingComma> for Output {
+    fn push_annotation(&mut self, _: &TrailingComma) -> Result<(), fmt::Error> {
+        self.trailing_commas.push(self.text.len());
+        Ok(())
+    }
+
+    fn pop_annotation(&mut self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+}
+
+impl Output {
+    /// Writes the trailing commas of the records which were broken into several lines
+    fn finish(self) -> String {
+        let mut text = String::with_capacity(self.text.len());
+        let mut last = 0;
+        for &pos in &self.trailing_commas {
+            text.push_str(&self.text[last..pos]);
+            if self.text[pos..]
+                .trim_start_matches(' ')
+                .starts_with(|c| c == '\n' || c == '\r')
+            {
+                text.push(',');
+            }
+            last = pos;
+        }
+        text.push_str(&self.text[last..]);
+        text
+    }
+}
+
 pub(super) struct Printer<'a, I: 'a, A: 'a> {
     printer: pretty_types::Printer<'a, I, A>,
     formatter: crate::Formatter,
+    indent: usize,
+}
+
+impl<'a, I> Printer<'a, I, TrailingComma>
+where
+    I: AsRef<str>,
+{
+    pub(super) fn format(
+        &self,
+        width: usize,
+        newline: &'a str,
+        expr: &'a SpannedExpr<I>,
+    ) -> String {
+        let mut output = Output::default();
+        self.pretty_expr(expr)
+            .1
+            .render_raw(width, &mut output)
+            .unwrap();
+        output
+            .finish()
+            .lines()
+            .map(|s| format!("{}{}", s.trim_end(), newline))
+            .collect()
+    }
 }
 
 impl<'a, I, A> Printer<'a, I, A>
 where
     I: AsRef<str>,
+    A: From<TrailingComma>,
 {
     pub(super) fn new(
         arena: &'a Arena<'a, A>,
         source: &'a source::Source,
         formatter: crate::Formatter,
     ) -> Self {
+        let indent = formatter.settings.get_indent_width();
         Printer {
-            printer: pretty_types::Printer::new(arena, source),
+            printer: pretty_types::Printer::new(arena, source).indent(indent),
             formatter,
+            indent,
         }
     }
 
-    pub(super) fn format(&self, width: usize, newline: &'a str, expr: &'a SpannedExpr<I>) -> String
-    where
-        A: Clone,
-    {
-        self.pretty_expr(expr)
-            .1
-            .pretty(width)
-            .to_string()
-            .lines()
-            .map(|s| format!("{}{}", s.trim_end(), newline))
-            .collect()
-    }
-
     fn pretty_expr(&self, expr: &'a SpannedExpr<I>) -> DocBuilder<'a, Arena<'a, A>, A>
     where
         A: Clone,
@@ -160,7 +224,7 @@ where
                             .append(pretty(arg))
                     });
                 pretty(func)
-                    .append(arena.concat(arg_iter).nest(INDENT))
+                    .append(arena.concat(arg_iter).nest(self.indent))
                     .group()
             }
 
@@ -209,7 +273,7 @@ where
                         arena.space(),
                         "then"
                     ].group(),
-                    space.clone().append(pretty(if_true)).nest(INDENT).group(),
+                    self.then_space(&space, if_true).append(pretty(if_true)).nest(self.indent).group(),
                     space.clone(),
                     "else",
                     self.pretty_else_expr(space, if_false)
@@ -228,7 +292,7 @@ where
                     op.value.name.as_ref(),
                     " ",
                     pretty(rhs).group()
-                ].nest(INDENT)
+                ].nest(self.indent)
             ],
 
             Expr::Lambda(_) => {
@@ -260,7 +324,7 @@ where
                             Some(ref typ) => arena.text(": ")
                                 .append(types::pretty_print(self, typ))
                                 .append(self.space_after(typ.span().end()))
-                                .nest(INDENT),
+                                .nest(self.indent),
                         },
                         "="
                     ];
@@ -298,7 +362,7 @@ where
                     } else {
                         arena.nil()
                     },
-                    self.pretty_expr_(binds.last().unwrap().span().end(), body).group()
+                    self.pretty_expr_(self.skip_in_keyword(binds.last().unwrap().span().end()), body).group()
                 ]
             }
 
@@ -402,7 +466,7 @@ where
                         let mut type_doc = types::pretty_print(self, typ);
                         match **typ {
                             Type::Record(_) | Type::Variant(_) => (),
-                            _ => type_doc = type_doc.nest(INDENT),
+                            _ => type_doc = type_doc.nest(self.indent),
                         }
                         chain![arena;
                             if i != 0 {
@@ -441,7 +505,7 @@ where
                                         "=",
                                         arena.newline(),
                                         type_doc
-                                    ].nest(INDENT)
+                                    ].nest(self.indent)
                                 }
                                 _ => {
                                     chain![arena;
@@ -462,7 +526,7 @@ where
                     } else {
                         arena.nil()
                     },
-                    self.pretty_expr_(binds.last().unwrap().alias.span.end(), body)
+                    self.pretty_expr_(self.skip_in_keyword(binds.last().unwrap().alias.span.end()), body)
                 ].group()
             }
 
@@ -480,7 +544,8 @@ where
                             self.pretty_pattern(pattern),
                             self.space_after(pattern.span.end()),
                             "="
-                        ],
+                        ]
+                        .group(),
                         None => arena.text("seq"),
                     },
                     self.hang(arena.nil(), bound).group()
@@ -503,6 +568,23 @@ where
         comments.append(doc)
     }
 
+    /// Returns the position after the `in` which may follow the bindings ending at `end`, so that
+    /// only the whitespace after it is kept before the body
+    fn skip_in_keyword(&self, end: BytePos) -> BytePos {
+        if end == 0.into() {
+            return end;
+        }
+        let rest = self
+            .source
+            .src_slice(Span::new(end, self.source.span().end()));
+        let trimmed = rest.trim_start();
+        if trimmed.starts_with("in") && trimmed[2..].starts_with(char::is_whitespace) {
+            end + ByteOffset::from((rest.len() - trimmed.len() + 2) as RawOffset)
+        } else {
+            end
+        }
+    }
+
     fn space(&self, span: Span<BytePos>) -> DocBuilder<'a, Arena<'a, A>, A> {
         self.whitespace(span, self.arena.space())
     }
@@ -526,6 +608,23 @@ where
         }
     }
 
+    /// The whitespace after `then`. A line break after `then` is kept as the branch may contain
+    /// line breaks which would otherwise let it fit after the `then` when it is formatted again
+    fn then_space(
+        &self,
+        space: &DocBuilder<'a, Arena<'a, A>, A>,
+        if_true: &'a SpannedExpr<I>,
+    ) -> DocBuilder<'a, Arena<'a, A>, A>
+    where
+        A: Clone,
+    {
+        if self.only_newlines_before(if_true.span.start()) {
+            self.arena.newline()
+        } else {
+            space.clone()
+        }
+    }
+
     fn pretty_else_expr(
         &self,
         space: DocBuilder<'a, Arena<'a, A>, A>,
@@ -544,12 +643,12 @@ where
                     arena.space(),
                     "then"
                 ].group(),
-                space.clone().append(pretty(if_true)).nest(INDENT).group(),
+                self.then_space(&space, if_true).append(pretty(if_true)).nest(self.indent).group(),
                 space.clone(),
                 "else",
                 self.pretty_else_expr(space, if_false)
             ],
-            _ => space.append(pretty(if_false)).nest(INDENT).group(),
+            _ => space.append(pretty(if_false)).nest(self.indent).group(),
         }
     }
 
@@ -577,7 +676,12 @@ where
                 let (next_lambda, body) =
                     self.pretty_lambda(lambda.body.span.start(), &lambda.body);
                 if let Doc::Nil = next_lambda.1 {
-                    let decl = decl.append(self.space_before(lambda.body.span.start()));
+                    // Like the body of a binding, a body which does not fit on the line of the
+                    // `->` needs to start on the next line
+                    let body = self
+                        .space_before(lambda.body.span.start())
+                        .append(body)
+                        .group();
                     (decl, body)
                 } else {
                     (decl.append(arena.space()).append(next_lambda), body)
@@ -587,6 +691,7 @@ where
                 ref types,
                 ref exprs,
                 ref base,
+                update,
                 ..
             } => {
                 let ordered_iter = || expr.value.field_iter();
@@ -623,19 +728,38 @@ where
                         .comment
                         .is_some()
                 });
-                let newline_in_base = base
-                    .as_ref()
-                    .map_or(false, |base| !is_nil(&self.space_before(base.span.start())));
-                if newline_in_fields || newline_in_base | newline_from_doc_comment {
+                let newline_in_base = base.as_ref().map_or(false, |base| {
+                    // The base of an update directly follows the `{` so only an explicit line
+                    // break before it forces the fields onto separate lines
+                    let before_base = if update {
+                        self.comments_before(base.span.start())
+                    } else {
+                        self.space_before(base.span.start())
+                    };
+                    !is_nil(&before_base)
+                });
+                let too_many_fields = self
+                    .formatter
+                    .settings
+                    .get_max_single_line_record_fields()
+                    .map_or(false, |max| types.len() + exprs.len() > max);
+                if newline_in_fields
+                    || newline_in_base | newline_from_doc_comment
+                    || too_many_fields
+                {
                     line = arena.newline();
                 }
 
-                let last_field_end = spans()
-                    .last()
-                    .map_or(expr.span.start() + 1.into(), |s| s.end());
-                let last_element_end = base.as_ref().map_or(last_field_end, |base| base.span.end());
+                let last_field_end = spans().last().map(|s| s.end());
+                let last_element_end = match *base {
+                    Some(ref base) if !update => base.span.end(),
+                    // The fields of an update are written after the base
+                    Some(ref base) => last_field_end.unwrap_or(base.span.end()),
+                    None => last_field_end.unwrap_or(expr.span.start() + 1.into()),
+                };
+                let last_field_end = last_field_end.unwrap_or(expr.span.start() + 1.into());
 
-                let record = arena
+                let fields = arena
                     .concat(self.comma_sep(
                         ordered_iter().map(|either| match either {
                             Either::Left(l) => pos::spanned(
@@ -650,10 +774,11 @@ where
                                     match r.value {
                                         Some(ref expr) => {
                                             let x = chain![arena;
-                                            id,
-                                            self.space_after(r.name.span.end()),
-                                            "="
-                                        ];
+                                                id,
+                                                self.space_after(r.name.span.end()),
+                                                "="
+                                            ]
+                                            .group();
                                             self.hang(x, expr)
                                         }
                                         None => id,
@@ -663,31 +788,44 @@ where
                             }
                         }),
                         |spanned| spanned.value,
-                    ))
-                    .append(
-                        if (!exprs.is_empty() || !types.is_empty()) && is_newline(&line) {
-                            arena.text(",")
-                        } else {
-                            arena.nil()
-                        },
-                    )
-                    .append(match *base {
-                        Some(ref base) => {
-                            let comments = self.comments_after(last_field_end);
-                            chain![arena;
-                                if let Doc::Nil = comments.1 {
-                                    line.clone()
-                                } else {
-                                    comments
-                                },
-                                "..",
-                                self.space_before(base.span.start()),
-                                self.pretty_expr_(base.span.start(), base)
-                            ]
-                        }
-                        None => arena.nil(),
-                    })
-                    .nest(INDENT)
+                    ).newlines(too_many_fields))
+                    .append(if exprs.is_empty() && types.is_empty() {
+                        arena.nil()
+                    } else if is_newline(&line) {
+                        arena.text(",")
+                    } else if is_nil(&self.comments(Span::new(last_element_end, expr.span.end())))
+                    {
+                        // Whether the fields are put on separate lines depends on the width so
+                        // only the renderer can decide if this record needs a trailing comma
+                        arena.nil().annotate(TrailingComma.into())
+                    } else {
+                        arena.nil()
+                    });
+                let record = match *base {
+                    Some(ref base) if update => chain![arena;
+                        self.space_before(base.span.start()),
+                        self.pretty_expr_(base.span.start(), base),
+                        " with",
+                        fields
+                    ],
+                    Some(ref base) => {
+                        let comments = self.comments_after(last_field_end);
+                        chain![arena;
+                            fields,
+                            if let Doc::Nil = comments.1 {
+                                line.clone()
+                            } else {
+                                comments
+                            },
+                            "..",
+                            self.space_before(base.span.start()),
+                            self.pretty_expr_(base.span.start(), base)
+                        ]
+                    }
+                    None => fields,
+                };
+                let record = record
+                    .nest(self.indent)
                     .append(
                         self.whitespace(Span::new(last_element_end, expr.span.end()), line.clone()),
                     )
@@ -715,6 +853,7 @@ where
             f,
             i: 0,
             parens: false,
+            newlines: false,
             _marker: ::std::marker::PhantomData,
         }
     }
@@ -810,12 +949,31 @@ where
                         ),
                     |spanned| spanned.value,
                 );
-                let doc = arena.concat(iter).nest(INDENT);
+                let doc = arena.concat(iter).nest(self.indent);
+                let spans = || {
+                    types
+                        .iter()
+                        .map(|field| field.name.span)
+                        .chain(fields.iter().map(|field| {
+                            Span::new(
+                                field.name.span.start(),
+                                field
+                                    .value
+                                    .as_ref()
+                                    .map_or(field.name.span.end(), |new_name| new_name.span.end()),
+                            )
+                        }))
+                        .chain(implicit_import.as_ref().map(|spanned| spanned.span))
+                };
+                // The fields are already on separate lines if they were broken up in the source
+                let newline_in_fields = rev_newlines_iter!(self, spans()).any(|doc| !is_nil(&doc));
                 chain![arena;
                     "{",
                     doc,
                     if types.is_empty() && fields.is_empty() && implicit_import.is_none() {
                         arena.nil()
+                    } else if newline_in_fields {
+                        arena.newline()
                     } else {
                         arena.space()
                     },
@@ -878,7 +1036,7 @@ where
                 ]
                 .group();
                 if needs_indent {
-                    doc.nest(INDENT)
+                    doc.nest(self.indent)
                 } else {
                     doc
                 }
@@ -892,16 +1050,23 @@ where
                     }
                 }
 
-                from.append(
-                    chain![arena;
+                let doc = match expr.value {
+                    Expr::Lambda(..) => chain![arena;
                         spaces,
                         arguments
                     ]
                     .group()
-                    .append(body)
-                    .nest(INDENT),
-                )
-                .group()
+                    .append(body),
+                    // The lines of a broken up expression must be indented further than its
+                    // first token so move the expression to the next line if it does not fit
+                    _ => chain![arena;
+                        spaces,
+                        arguments,
+                        body
+                    ]
+                    .group(),
+                };
+                from.append(doc.nest(self.indent)).group()
             }
         }
     }
@@ -922,6 +1087,7 @@ where
             f,
             i: 0,
             parens: true,
+            newlines: false,
             _marker: ::std::marker::PhantomData,
         }
     }
@@ -971,13 +1137,26 @@ where
     iter: ::std::iter::Peekable<J>,
     f: F,
     parens: bool,
+    newlines: bool,
     i: usize,
     _marker: ::std::marker::PhantomData<U>,
 }
 
+impl<'a, 'e, F, I, J, U, A> CommaSeparated<'a, 'e, F, I, J, U, A>
+where
+    J: Iterator,
+{
+    /// Puts each element on a separate line instead of letting the enclosing group decide
+    fn newlines(mut self, newlines: bool) -> Self {
+        self.newlines = newlines;
+        self
+    }
+}
+
 impl<'a, 'e, F, I, J, T, U, A> Iterator for CommaSeparated<'a, 'e, F, I, J, U, A>
 where
     I: AsRef<str>,
+    A: From<TrailingComma>,
     F: FnMut(T) -> DocBuilder<'a, Arena<'a, A>, A>,
     J: Iterator<Item = T>,
     T: ::std::borrow::Borrow<Spanned<U, BytePos>>,
@@ -993,6 +1172,8 @@ where
             chain![arena;
                 if i == 0 && self.parens {
                     self.printer.comments_before(span.start())
+                } else if self.newlines && is_nil(&self.printer.comments_before(span.start())) {
+                    arena.newline()
                 } else {
                     self.printer.space_before(span.start())
                 },
@@ -0,0 +1,296 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The name of the file which `FormatSettings::for_file` reads the settings from
+pub const SETTINGS_FILE_NAME: &str = "gluon-fmt.toml";
+
+/// The line endings written by the formatter
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NewlineStyle {
+    /// Uses the same line ending as the first line of the input
+    Auto,
+    /// `\n`
+    Unix,
+    /// `\r\n`
+    Windows,
+}
+
+impl NewlineStyle {
+    pub(crate) fn newline(self, input: &str) -> &'static str {
+        match self {
+            NewlineStyle::Auto => match input.find(|c: char| c == '\n' || c == '\r') {
+                Some(i) => {
+                    if input[i..].starts_with("\r\n") {
+                        "\r\n"
+                    } else if input[i..].starts_with('\r') {
+                        "\r"
+                    } else {
+                        "\n"
+                    }
+                }
+                None => "\n",
+            },
+            NewlineStyle::Unix => "\n",
+            NewlineStyle::Windows => "\r\n",
+        }
+    }
+}
+
+/// The layout options of the formatter. The defaults are the layout used by the standard library.
+///
+/// ```
+/// use gluon_format::{FormatSettings, NewlineStyle};
+///
+/// let settings = FormatSettings::new()
+///     .line_width(80)
+///     .indent_width(2)
+///     .newline_style(NewlineStyle::Unix);
+/// assert_eq!(settings.get_line_width(), 80);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FormatSettings {
+    line_width: usize,
+    indent_width: usize,
+    max_single_line_record_fields: Option<usize>,
+    newline_style: NewlineStyle,
+}
+
+impl Default for FormatSettings {
+    fn default() -> Self {
+        FormatSettings {
+            line_width: 100,
+            indent_width: 4,
+            max_single_line_record_fields: None,
+            newline_style: NewlineStyle::Auto,
+        }
+    }
+}
+
+impl FormatSettings {
+    pub fn new() -> FormatSettings {
+        FormatSettings::default()
+    }
+
+    /// Sets the width that the formatter tries to fit each line within
+    pub fn line_width(mut self, line_width: usize) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    /// Sets the number of spaces that nested lines are indented with
+    pub fn indent_width(mut self, indent_width: usize) -> Self {
+        self.indent_width = indent_width;
+        self
+    }
+
+    /// Puts the fields of record expressions with more than `max` fields on separate lines, even
+    /// if the record would fit on a single line. `None` only breaks records which do not fit.
+    pub fn max_single_line_record_fields(mut self, max: Option<usize>) -> Self {
+        self.max_single_line_record_fields = max;
+        self
+    }
+
+    pub fn newline_style(mut self, newline_style: NewlineStyle) -> Self {
+        self.newline_style = newline_style;
+        self
+    }
+
+    pub fn get_line_width(&self) -> usize {
+        self.line_width
+    }
+
+    pub fn get_indent_width(&self) -> usize {
+        self.indent_width
+    }
+
+    pub fn get_max_single_line_record_fields(&self) -> Option<usize> {
+        self.max_single_line_record_fields
+    }
+
+    pub fn get_newline_style(&self) -> NewlineStyle {
+        self.newline_style
+    }
+
+    /// Reads the settings from the `gluon-fmt.toml` file in the same directory as `path`, using
+    /// the default settings if there is no such file.
+    pub fn for_file(path: &Path) -> Result<FormatSettings, SettingsError> {
+        let settings_path = path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(SETTINGS_FILE_NAME);
+        match fs::read_to_string(&settings_path) {
+            Ok(contents) => FormatSettings::from_toml(&contents),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(FormatSettings::default()),
+            Err(err) => Err(SettingsError::Io(err)),
+        }
+    }
+
+    /// Parses settings written as TOML. Only `key = value` lines are supported, without any
+    /// tables, where each key is the name of one of the builder methods.
+    ///
+    /// ```toml
+    /// line_width = 80
+    /// indent_width = 2
+    /// # Omit the key to only break records which do not fit on a line
+    /// max_single_line_record_fields = 3
+    /// # One of "auto", "unix" or "windows"
+    /// newline_style = "unix"
+    /// ```
+    pub fn from_toml(input: &str) -> Result<FormatSettings, SettingsError> {
+        let mut settings = FormatSettings::default();
+        for (i, line) in input.lines().enumerate() {
+            let line_number = i + 1;
+            let line = match line.find('#') {
+                Some(i) => &line[..i],
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut iter = line.splitn(2, '=');
+            let (key, value) = match (iter.next(), iter.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => return Err(SettingsError::Syntax { line: line_number }),
+            };
+            let invalid_value = || SettingsError::InvalidValue {
+                line: line_number,
+                key: key.to_string(),
+                value: value.to_string(),
+            };
+            let integer = || value.parse::<usize>().map_err(|_| invalid_value());
+            match key {
+                "line_width" => settings.line_width = integer()?,
+                "indent_width" => settings.indent_width = integer()?,
+                "max_single_line_record_fields" => {
+                    settings.max_single_line_record_fields = Some(integer()?)
+                }
+                "newline_style" => {
+                    settings.newline_style = match value {
+                        "\"auto\"" => NewlineStyle::Auto,
+                        "\"unix\"" => NewlineStyle::Unix,
+                        "\"windows\"" => NewlineStyle::Windows,
+                        _ => return Err(invalid_value()),
+                    }
+                }
+                _ => {
+                    return Err(SettingsError::UnknownKey {
+                        line: line_number,
+                        key: key.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(io::Error),
+    /// The line is not a `key = value` pair
+    Syntax {
+        line: usize,
+    },
+    UnknownKey {
+        line: usize,
+        key: String,
+    },
+    InvalidValue {
+        line: usize,
+        key: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SettingsError::Io(ref err) => write!(f, "{}", err),
+            SettingsError::Syntax { line } => write!(
+                f,
+                "{}:{}: Expected a `key = value` pair",
+                SETTINGS_FILE_NAME, line
+            ),
+            SettingsError::UnknownKey { line, ref key } => {
+                write!(
+                    f,
+                    "{}:{}: Unknown setting `{}`",
+                    SETTINGS_FILE_NAME, line, key
+                )
+            }
+            SettingsError::InvalidValue {
+                line,
+                ref key,
+                ref value,
+            } => write!(
+                f,
+                "{}:{}: `{}` is not a valid value for `{}`",
+                SETTINGS_FILE_NAME, line, value, key
+            ),
+        }
+    }
+}
+
+impl StdError for SettingsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_every_setting() {
+        let settings = FormatSettings::from_toml(
+            r#"
+# Layout of the project
+line_width = 80
+indent_width = 2 # Spaces
+max_single_line_record_fields = 3
+newline_style = "windows"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings,
+            FormatSettings::new()
+                .line_width(80)
+                .indent_width(2)
+                .max_single_line_record_fields(Some(3))
+                .newline_style(NewlineStyle::Windows)
+        );
+    }
+
+    #[test]
+    fn empty_settings_are_the_default() {
+        assert_eq!(
+            FormatSettings::from_toml("").unwrap(),
+            FormatSettings::default()
+        );
+    }
+
+    #[test]
+    fn report_the_line_of_an_invalid_setting() {
+        assert_eq!(
+            FormatSettings::from_toml("line_width = 80\nline_width = wide")
+                .unwrap_err()
+                .to_string(),
+            "gluon-fmt.toml:2: `wide` is not a valid value for `line_width`"
+        );
+        assert_eq!(
+            FormatSettings::from_toml("tab_width = 8")
+                .unwrap_err()
+                .to_string(),
+            "gluon-fmt.toml:1: Unknown setting `tab_width`"
+        );
+        assert_eq!(
+            FormatSettings::from_toml("line_width")
+                .unwrap_err()
+                .to_string(),
+            "gluon-fmt.toml:1: Expected a `key = value` pair"
+        );
+    }
+}
@@ -31,7 +31,10 @@ fn format_expr_expanded(expr: &str) -> gluon::Result<String> {
     let mut compiler = Compiler::new();
     let thread = new_vm();
     compiler.format_expr(
-        &mut format::Formatter { expanded: true },
+        &mut format::Formatter {
+            expanded: true,
+            ..format::Formatter::default()
+        },
         &thread,
         "test",
         expr,
@@ -356,6 +359,47 @@ fn preserve_comments_in_record_base() {
     assert_diff!(&format_expr(expr).unwrap(), expr, " ", 0);
 }
 
+#[test]
+fn record_update_on_one_line() {
+    let expr = r#"
+let r = { x = 1, y = "a" }
+{ r with x = 2, y }
+"#;
+    assert_diff!(&format_expr(expr).unwrap(), expr, " ", 0);
+}
+
+#[test]
+fn record_update_on_multiple_lines() {
+    let expr = r#"
+{
+    record with
+    x = 1,
+    // y
+    y = 2,
+}
+"#;
+    assert_diff!(&format_expr(expr).unwrap(), expr, " ", 0);
+}
+
+#[test]
+fn record_pattern_punning_and_renaming() {
+    let expr = r#"
+let { x, y = z } = { x = 1, y = 2 }
+{ x, z }
+"#;
+    assert_diff!(&format_expr(expr).unwrap(), expr, " ", 0);
+}
+
+#[test]
+fn preserve_radix_and_separators_of_literals() {
+    let expr = r#"
+let mask = 0xFF_00 + 0o755 + 0b1010_1010 + 1_000_000
+let x = 1_000.000_5
+"\x41\x7f"
+"#;
+    assert_diff!(&format_expr(expr).unwrap(), expr, " ", 0);
+}
+
 #[test]
 fn small_record_in_let() {
     let expr = r#"
@@ -659,8 +703,7 @@ rec let deserialize_Record : Deserialize Record =
     let { (<*>) } = import! std.applicative
     let { (<|>) } = import! std.alternative
     let deserializer : ValueDeserializer Record
-        = map (\x y -> { x, y }) (field "x" deserializer)
-            <*> field "y" deserializer
+        = map (\x y -> { x, y }) (field "x" deserializer) <*> field "y" deserializer
     { deserializer = deserializer }
 ()
 "#;
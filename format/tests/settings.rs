@@ -0,0 +1,184 @@
+extern crate env_logger;
+#[macro_use]
+extern crate pretty_assertions;
+
+extern crate gluon;
+extern crate gluon_format as format;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use gluon::{Compiler, RootedThread, VmBuilder};
+
+use format::{FormatSettings, NewlineStyle};
+
+fn new_vm() -> RootedThread {
+    VmBuilder::new()
+        .import_paths(Some(vec![".".into(), "..".into()]))
+        .build()
+}
+
+fn format_with(settings: &FormatSettings, name: &str, input: &str) -> String {
+    let mut compiler = Compiler::new();
+    let thread = new_vm();
+    compiler
+        .format_expr(
+            &mut format::Formatter {
+                settings: settings.clone(),
+                ..format::Formatter::default()
+            },
+            &thread,
+            name,
+            input,
+        )
+        .unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn std_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            std_files(&path, files);
+        } else if path.extension().map_or(false, |ext| ext == "glu") {
+            files.push(path);
+        }
+    }
+}
+
+/// Formats every file of the standard library with `settings` and checks that formatting the
+/// result again does not change it
+fn test_idempotent(settings: FormatSettings) {
+    let _ = env_logger::try_init();
+
+    let mut files = Vec::new();
+    std_files(Path::new("../std"), &mut files);
+    files.sort();
+    assert!(!files.is_empty());
+
+    for file in files {
+        let name = file.display().to_string();
+        let contents = fs::read_to_string(&file).unwrap();
+        let once = format_with(&settings, &name, &contents);
+        let twice = format_with(&settings, &name, &once);
+        assert_eq!(
+            once, twice,
+            "`{}` changed when it was formatted again",
+            name
+        );
+    }
+}
+
+#[test]
+fn idempotent_at_width_90() {
+    test_idempotent(FormatSettings::new().line_width(90));
+}
+
+#[test]
+fn idempotent_at_width_80_with_an_indent_of_2() {
+    test_idempotent(FormatSettings::new().line_width(80).indent_width(2));
+}
+
+#[test]
+fn idempotent_with_multi_line_records_and_windows_newlines() {
+    test_idempotent(
+        FormatSettings::new()
+            .line_width(120)
+            .max_single_line_record_fields(Some(2))
+            .newline_style(NewlineStyle::Windows),
+    );
+}
+
+#[test]
+fn indent_width() {
+    let expr = r#"
+let f x =
+    if x then 1 else 2
+f
+"#;
+    assert_eq!(
+        format_with(&FormatSettings::new().indent_width(2), "test", expr),
+        r#"
+let f x =
+  if x then 1 else 2
+f
+"#
+    );
+}
+
+#[test]
+fn line_width() {
+    let expr = r#"
+let x = some_function argument_one argument_two
+x
+"#;
+    assert_eq!(
+        format_with(&FormatSettings::new().line_width(45), "test", expr),
+        r#"
+let x =
+    some_function argument_one argument_two
+x
+"#
+    );
+}
+
+#[test]
+fn records_with_many_fields_are_broken_up() {
+    let expr = r#"
+let x = { a = 1, b = 2, c = 3 }
+let y = { a = 1, b = 2 }
+x
+"#;
+    assert_eq!(
+        format_with(
+            &FormatSettings::new().max_single_line_record_fields(Some(2)),
+            "test",
+            expr
+        ),
+        r#"
+let x = {
+    a = 1,
+    b = 2,
+    c = 3,
+}
+let y = { a = 1, b = 2 }
+x
+"#
+    );
+}
+
+#[test]
+fn newline_style() {
+    let expr = "let x = 1\nx\n";
+    assert_eq!(
+        format_with(
+            &FormatSettings::new().newline_style(NewlineStyle::Windows),
+            "test",
+            expr
+        ),
+        "let x = 1\r\nx\r\n"
+    );
+    assert_eq!(
+        format_with(&FormatSettings::new(), "test", "let x = 1\r\nx\r\n"),
+        "let x = 1\r\nx\r\n"
+    );
+}
+
+#[test]
+fn settings_are_read_from_the_directory_of_the_file() {
+    let dir = std::env::temp_dir().join("gluon_format_settings_test");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join(format::SETTINGS_FILE_NAME),
+        "line_width = 60\nindent_width = 2\n",
+    )
+    .unwrap();
+
+    let settings = FormatSettings::for_file(&dir.join("test.glu")).unwrap();
+    assert_eq!(
+        settings,
+        FormatSettings::new().line_width(60).indent_width(2)
+    );
+
+    let missing = FormatSettings::for_file(&dir.join("missing").join("test.glu")).unwrap();
+    assert_eq!(missing, FormatSettings::default());
+}
@@ -548,6 +548,10 @@ where
                 | (&Token::Then, _) => {
                     self.scan_for_next_block(Context::Block { emit_semi: false })?
                 }
+                // `match` opens an expression context so a `with` directly inside of braces is
+                // a record update, `{ record with x = 1 }`, rather than the start of the
+                // alternatives of a match
+                (&Token::With, Context::Brace) => (),
                 (&Token::With, _) => self.scan_for_next_block(Context::MatchClause)?,
 
                 (&Token::Else, _) => {
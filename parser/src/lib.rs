@@ -26,7 +26,9 @@ use std::fmt;
 use std::hash::Hash;
 use std::sync::Arc;
 
-use crate::base::ast::{Do, Expr, IdentEnv, SpannedExpr, SpannedPattern, TypedIdent, ValueBinding};
+use crate::base::ast::{
+    Do, Expr, ExprField, IdentEnv, SpannedExpr, SpannedPattern, TypedIdent, ValueBinding,
+};
 use crate::base::error::{AsDiagnostic, Errors};
 use crate::base::fnv::FnvMap;
 use crate::base::metadata::Metadata;
@@ -62,6 +64,40 @@ fn new_ident<Id>(type_cache: &TypeCache<Id, ArcType<Id>>, name: Id) -> TypedIden
 type LalrpopError<'input> =
     lalrpop_util::ParseError<BytePos, Token<'input>, Spanned<Error, BytePos>>;
 
+/// Creates the record written as `{ fields, .. base }` or, if `update` is true, as
+/// `{ base with fields }`
+fn new_record<Id>(
+    type_cache: &TypeCache<Id, ArcType<Id>>,
+    fields: Vec<Box<FieldExpr<Id>>>,
+    base: Option<SpannedExpr<Id>>,
+    update: bool,
+) -> Expr<Id> {
+    let mut types = Vec::new();
+    let mut exprs = Vec::new();
+    for field in fields {
+        match *field {
+            FieldExpr::Type(metadata, name, value) => types.push(ExprField {
+                metadata,
+                name,
+                value,
+            }),
+            FieldExpr::Value(metadata, name, value) => exprs.push(ExprField {
+                metadata,
+                name,
+                value,
+            }),
+        }
+    }
+
+    Expr::Record {
+        typ: type_cache.hole(),
+        types,
+        exprs,
+        base: base.map(Box::new),
+        update,
+    }
+}
+
 /// Shrink hidden spans to fit the visible expressions and flatten singleton blocks.
 fn shrink_hidden_spans<Id>(mut expr: SpannedExpr<Id>) -> SpannedExpr<Id> {
     match expr.value {
@@ -491,6 +527,26 @@ where
     }
 }
 
+/// Returns `true` if every error in `errors` could be fixed by appending more input to the end of
+/// the source spanned by `source_span`, such as an unclosed parenthesis or a `let` binding which
+/// is missing its expression. The REPL uses this to ask for another line instead of reporting an
+/// error.
+pub fn is_incomplete(source_span: Span<BytePos>, errors: &ParseErrors) -> bool {
+    errors.has_errors()
+        && errors.iter().all(|err| match err.value {
+            Error::UnexpectedEof(_)
+            | Error::Token(TokenizeError::UnexpectedEof)
+            | Error::Token(TokenizeError::UnterminatedStringLiteral) => true,
+            // The layout algorithm closes every open block at the end of the input so a missing
+            // expression is reported as one of the tokens it inserted
+            Error::UnexpectedToken(ref token, _) => {
+                err.span.start() == source_span.end()
+                    && ["CloseBlock", "Semi", "In"].contains(&&token[..])
+            }
+            _ => false,
+        })
+}
+
 pub fn reparse_infix<Id>(
     metadata: &FnvMap<Id, Arc<Metadata>>,
     symbols: &IdentEnv<Ident = Id>,
@@ -2,7 +2,7 @@ use crate::base::ast::is_operator_byte;
 use crate::base::metadata::{Comment, CommentType};
 use crate::base::pos::{self, BytePos, Column, Line, Location, Spanned};
 
-use std::{fmt, str};
+use std::{borrow::Cow, fmt, str};
 
 use codespan::ByteOffset;
 
@@ -165,6 +165,12 @@ quick_error! {
         HexLiteralIncomplete {
             description("cannot parse hex literal, incomplete")
         }
+        IntLiteralIncomplete {
+            description("cannot parse integer literal, expected digits after the radix prefix")
+        }
+        InvalidHexEscape {
+            description("invalid hex escape, expected `\\xNN` where NN is at most 7F")
+        }
         UnexpectedAnd {
             description("`and` has been removed, recursive bindings are now written with `rec (let BIND = EXPR)+ in ...`")
         }
@@ -199,6 +205,14 @@ fn is_hex(ch: u8) -> bool {
     (ch as char).is_digit(16)
 }
 
+fn is_octal(ch: u8) -> bool {
+    (ch as char).is_digit(8)
+}
+
+fn is_binary(ch: u8) -> bool {
+    ch == b'0' || ch == b'1'
+}
+
 struct CharLocations<'input> {
     location: Location,
     chars: str_suffix::Iter<'input>,
@@ -278,6 +292,11 @@ impl<'input> Tokenizer<'input> {
         error(location, code)
     }
 
+    fn span_error<T>(&mut self, start: Location, end: Location, code: Error) -> Result<T, SpError> {
+        self.skip_to_end();
+        Err(pos::spanned2(start, end, code))
+    }
+
     fn next_loc(&self) -> Location {
         self.lookahead()
             .as_ref()
@@ -398,6 +417,23 @@ impl<'input> Tokenizer<'input> {
             Some((_, b'n')) => Ok(b'\n'),
             Some((_, b'r')) => Ok(b'\r'),
             Some((_, b't')) => Ok(b'\t'),
+            Some((start, b'x')) => {
+                let mut code = 0;
+                for _ in 0..2 {
+                    match self.lookahead() {
+                        Some((_, ch)) if is_hex(ch) => {
+                            self.bump();
+                            code = code * 16 + (ch as char).to_digit(16).unwrap() as u8;
+                        }
+                        _ => return self.error(start, InvalidHexEscape),
+                    }
+                }
+                // Larger codes would not be valid UTF-8 on their own
+                if code > 0x7f {
+                    return self.error(start, InvalidHexEscape);
+                }
+                Ok(code)
+            }
             // TODO: Unicode escape codes
             Some((start, ch)) => {
                 let ch = self.chars.chars.as_str_suffix().restore_char(&[ch]);
@@ -505,45 +541,90 @@ impl<'input> Tokenizer<'input> {
         }
     }
 
+    /// Takes the digits accepted by `is_digit` along with any `_` separators between them. A
+    /// separator must be followed by another digit.
+    fn digits(
+        &mut self,
+        start: Location,
+        is_digit: fn(u8) -> bool,
+    ) -> Result<(Location, &'input str), SpError> {
+        loop {
+            let (end, digits) = self.take_while(start, is_digit);
+            match self.lookahead() {
+                Some((separator, b'_')) => {
+                    self.take_while(separator, |ch| ch == b'_');
+                    if !self.test_lookahead(is_digit) {
+                        return self.error(separator, UnexpectedChar('_'));
+                    }
+                }
+                _ => return Ok((end, digits)),
+            }
+        }
+    }
+
     fn numeric_literal(&mut self, start: Location) -> Result<SpannedToken<'input>, SpError> {
-        let (end, int) = self.take_while(start, is_digit);
+        let (end, int) = self.digits(start, is_digit)?;
 
         let (start, end, token) = match self.lookahead() {
             Some((_, b'.')) => {
                 self.bump(); // Skip b'.'
-                let (end, float) = self.take_while(start, is_digit);
+                let (end, float) = if self.test_lookahead(is_digit) {
+                    self.digits(start, is_digit)?
+                } else {
+                    self.take_while(start, is_digit)
+                };
                 match self.lookahead() {
                     Some((_, ch)) if is_ident_start(ch) => {
                         let ch = self.chars.chars.as_str_suffix().restore_char(&[ch]);
                         return self.error(end, UnexpectedChar(ch));
                     }
-                    _ => (start, end, Token::FloatLiteral(float.parse().unwrap())),
+                    _ => (
+                        start,
+                        end,
+                        Token::FloatLiteral(without_separators(float).parse().unwrap()),
+                    ),
                 }
             }
             Some((_, b'x')) => {
                 self.bump(); // Skip b'x'
-                let int_start = self.next_loc();
-                let (end, hex) = self.take_while(int_start, is_hex);
                 match int {
-                    "0" | "-0" => match self.lookahead() {
-                        Some((_, ch)) if is_ident_start(ch) => {
-                            let ch = self.chars.chars.as_str_suffix().restore_char(&[ch]);
-                            return self.error(end, UnexpectedChar(ch));
+                    "0" | "-0" => {
+                        let (end, hex) = self.radix_digits(is_hex)?;
+                        if hex.is_empty() {
+                            return self.error(start, HexLiteralIncomplete);
                         }
-                        _ => {
-                            if hex.is_empty() {
-                                return self.error(start, HexLiteralIncomplete);
-                            }
-                            let is_positive = int == "0";
-                            match i64_from_hex(hex, is_positive) {
-                                Ok(val) => (start, end, Token::IntLiteral(val)),
-                                Err(err) => return self.error(start, err),
+                        let is_positive = int == "0";
+                        match i64_from_radix(hex, 16, is_positive) {
+                            Some(val) => (start, end, Token::IntLiteral(val)),
+                            None if is_positive => {
+                                return self.span_error(start, end, HexLiteralOverflow);
                             }
+                            None => return self.span_error(start, end, HexLiteralUnderflow),
                         }
-                    },
+                    }
                     _ => return self.error(start, HexLiteralWrongPrefix),
                 }
             }
+            Some((_, b'o')) if int == "0" || int == "-0" => {
+                self.bump(); // Skip b'o'
+                let (end, octal) = self.radix_digits(is_octal)?;
+                self.radix_literal(start, end, int, octal, 8)?
+            }
+            // `0b` on its own is the byte `0`
+            Some((_, b'b'))
+                if (int == "0" || int == "-0")
+                    && self
+                        .chars
+                        .chars
+                        .as_str_suffix()
+                        .split_first()
+                        .and_then(|(_, rest)| rest.first())
+                        .map_or(false, is_binary) =>
+            {
+                self.bump(); // Skip b'b'
+                let (end, binary) = self.radix_digits(is_binary)?;
+                self.radix_literal(start, end, int, binary, 2)?
+            }
             Some((_, b'b')) => {
                 self.bump(); // Skip b'b'
                 let end = self.next_loc();
@@ -553,10 +634,10 @@ impl<'input> Tokenizer<'input> {
                         return self.error(pos, UnexpectedChar(ch));
                     }
                     _ => {
-                        if let Ok(val) = int.parse() {
+                        if let Ok(val) = without_separators(int).parse() {
                             (start, end, Token::ByteLiteral(val))
                         } else {
-                            return self.error(start, NonParseableInt);
+                            return self.span_error(start, end, NonParseableInt);
                         }
                     }
                 }
@@ -566,10 +647,10 @@ impl<'input> Tokenizer<'input> {
                 return self.error(start, UnexpectedChar(ch));
             }
             None | Some(_) => {
-                if let Ok(val) = int.parse() {
+                if let Ok(val) = without_separators(int).parse() {
                     (start, end, Token::IntLiteral(val))
                 } else {
-                    return self.error(start, NonParseableInt);
+                    return self.span_error(start, end, NonParseableInt);
                 }
             }
         };
@@ -577,6 +658,44 @@ impl<'input> Tokenizer<'input> {
         Ok(pos::spanned2(start, end, token))
     }
 
+    /// Takes the digits which follow a radix prefix such as `0x`, which may not be directly
+    /// followed by an identifier
+    fn radix_digits(
+        &mut self,
+        is_radix_digit: fn(u8) -> bool,
+    ) -> Result<(Location, &'input str), SpError> {
+        let digits_start = self.next_loc();
+        let (end, digits) = if self.test_lookahead(is_radix_digit) {
+            self.digits(digits_start, is_radix_digit)?
+        } else {
+            self.take_while(digits_start, is_radix_digit)
+        };
+        match self.lookahead() {
+            Some((_, ch)) if is_ident_start(ch) || is_digit(ch) => {
+                let ch = self.chars.chars.as_str_suffix().restore_char(&[ch]);
+                self.error(end, UnexpectedChar(ch))
+            }
+            _ => Ok((end, digits)),
+        }
+    }
+
+    fn radix_literal(
+        &mut self,
+        start: Location,
+        end: Location,
+        prefix: &str,
+        digits: &str,
+        radix: u32,
+    ) -> Result<(Location, Location, Token<'input>), SpError> {
+        if digits.is_empty() {
+            return self.error(start, IntLiteralIncomplete);
+        }
+        match i64_from_radix(digits, radix, prefix == "0") {
+            Some(val) => Ok((start, end, Token::IntLiteral(val))),
+            None => self.span_error(start, end, NonParseableInt),
+        }
+    }
+
     fn identifier(&mut self, start: Location) -> Result<SpannedToken<'input>, SpError> {
         let (mut end, mut ident) = self.take_while(start, is_ident_continue);
         match self.lookahead() {
@@ -680,28 +799,28 @@ impl<'input> Iterator for Tokenizer<'input> {
     }
 }
 
-/// Converts partial hex literal (i.e. part after `0x` or `-0x`) to 64 bit signed integer.
+fn without_separators(digits: &str) -> Cow<str> {
+    if digits.contains('_') {
+        Cow::Owned(digits.replace('_', ""))
+    } else {
+        Cow::Borrowed(digits)
+    }
+}
+
+/// Converts the digits of a literal with a radix prefix (i.e. the part after `0x` or `-0x`) to a
+/// 64 bit signed integer, returning `None` if it does not fit.
 ///
 /// This is basically a copy and adaptation of `std::num::from_str_radix`.
-fn i64_from_hex(hex: &str, is_positive: bool) -> Result<i64, Error> {
-    const RADIX: u32 = 16;
-    let digits = hex.as_bytes();
+fn i64_from_radix(digits: &str, radix: u32, is_positive: bool) -> Option<i64> {
     let sign: i64 = if is_positive { 1 } else { -1 };
     let mut result = 0i64;
-    for &c in digits {
-        let x = (c as char).to_digit(RADIX).expect("valid hex literal");
+    for c in digits.chars().filter(|&c| c != '_') {
+        let x = c.to_digit(radix).expect("valid digit");
         result = result
-            .checked_mul(RADIX as i64)
-            .and_then(|result| result.checked_add((x as i64) * sign))
-            .ok_or_else(|| {
-                if is_positive {
-                    HexLiteralOverflow
-                } else {
-                    HexLiteralUnderflow
-                }
-            })?;
+            .checked_mul(i64::from(radix))
+            .and_then(|result| result.checked_add(i64::from(x) * sign))?;
     }
-    Ok(result)
+    Some(result)
 }
 
 #[cfg(test)]
@@ -892,6 +1011,31 @@ mod test {
         );
     }
 
+    #[test]
+    fn string_literal_hex_escapes() {
+        test(
+            r#""\x41\x7f\x0a""#,
+            vec![(r#"~~~~~~~~~~~~~~"#, StringLiteral("A\x7f\n".to_string()))],
+        );
+        test(r#"'\x61'"#, vec![(r#"~~~~~~"#, CharLiteral('a'))]);
+    }
+
+    #[test]
+    fn string_literal_invalid_hex_escapes() {
+        assert_eq!(
+            tokenizer(r#""\x80""#).last(),
+            Some(error(loc(2), InvalidHexEscape))
+        );
+        assert_eq!(
+            tokenizer(r#""\x4""#).last(),
+            Some(error(loc(2), InvalidHexEscape))
+        );
+        assert_eq!(
+            tokenizer(r#""\xg0""#).last(),
+            Some(error(loc(2), InvalidHexEscape))
+        );
+    }
+
     #[test]
     fn string_literal_unterminated() {
         assert_eq!(
@@ -997,7 +1141,7 @@ mod test {
     fn hex_literals_overflow() {
         assert_eq!(
             tokenizer(r#"0x8000000000000000"#).last(),
-            Some(error(loc(0), HexLiteralOverflow))
+            Some(Err(pos::spanned2(loc(0), loc(18), HexLiteralOverflow)))
         );
     }
 
@@ -1005,7 +1149,7 @@ mod test {
     fn hex_literals_underflow() {
         assert_eq!(
             tokenizer(r#"-0x8000000000000001"#).last(),
-            Some(error(loc(0), HexLiteralUnderflow))
+            Some(Err(pos::spanned2(loc(0), loc(19), HexLiteralUnderflow)))
         );
     }
 
@@ -1061,7 +1205,97 @@ mod test {
     fn int_literal_overflow() {
         assert_eq!(
             tokenizer(r#"12345678901234567890"#).last(),
-            Some(error(loc(0), NonParseableInt))
+            Some(Err(pos::spanned2(loc(0), loc(20), NonParseableInt)))
+        );
+    }
+
+    #[test]
+    fn int_literals_with_separators() {
+        test(
+            r#"1_000 -1_0__0 0xFF_FF 1_0b"#,
+            vec![
+                (r#"~~~~~                     "#, IntLiteral(1000)),
+                (r#"      ~~~~~~~             "#, IntLiteral(-100)),
+                (r#"              ~~~~~~~     "#, IntLiteral(0xFFFF)),
+                (r#"                      ~~~~"#, ByteLiteral(10)),
+            ],
+        );
+    }
+
+    #[test]
+    fn separator_must_be_followed_by_a_digit() {
+        assert_eq!(
+            tokenizer(r#"1_"#).last(),
+            Some(error(loc(1), UnexpectedChar('_')))
+        );
+        assert_eq!(
+            tokenizer(r#"1__ "#).last(),
+            Some(error(loc(1), UnexpectedChar('_')))
+        );
+        assert_eq!(
+            tokenizer(r#"1._5"#).last(),
+            Some(error(loc(2), UnexpectedChar('_')))
+        );
+    }
+
+    #[test]
+    fn octal_literals() {
+        test(
+            r#"0o755 0o0 -0o17 0o7_7"#,
+            vec![
+                (r#"~~~~~                "#, IntLiteral(0o755)),
+                (r#"      ~~~            "#, IntLiteral(0)),
+                (r#"          ~~~~~      "#, IntLiteral(-0o17)),
+                (r#"                ~~~~~"#, IntLiteral(0o77)),
+            ],
+        );
+    }
+
+    #[test]
+    fn octal_literals_errors() {
+        assert_eq!(
+            tokenizer(r#"0o"#).last(),
+            Some(error(loc(0), IntLiteralIncomplete))
+        );
+        assert_eq!(
+            tokenizer(r#"0o78"#).last(),
+            Some(error(loc(3), UnexpectedChar('8')))
+        );
+        assert_eq!(
+            tokenizer(r#"0o1000000000000000000000"#).last(),
+            Some(Err(pos::spanned2(loc(0), loc(24), NonParseableInt)))
+        );
+    }
+
+    #[test]
+    fn binary_literals() {
+        test(
+            r#"0b1010_1010 -0b1 0b0"#,
+            vec![
+                (r#"~~~~~~~~~~~         "#, IntLiteral(0b1010_1010)),
+                (r#"            ~~~~    "#, IntLiteral(-1)),
+                (r#"                 ~~~"#, IntLiteral(0)),
+            ],
+        );
+    }
+
+    #[test]
+    fn binary_literals_errors() {
+        assert_eq!(
+            tokenizer(r#"0b12"#).last(),
+            Some(error(loc(3), UnexpectedChar('2')))
+        );
+        assert_eq!(
+            tokenizer(&format!("0b1{}", "0".repeat(63))).last(),
+            Some(Err(pos::spanned2(loc(0), loc(66), NonParseableInt)))
+        );
+    }
+
+    #[test]
+    fn binary_literal_bounds() {
+        test(
+            &format!("-0b1{}", "0".repeat(63)),
+            vec![(&"~".repeat(67), IntLiteral(::std::i64::MIN))],
         );
     }
 
@@ -1097,6 +1331,17 @@ mod test {
         );
     }
 
+    #[test]
+    fn float_literals_with_separators() {
+        test(
+            r#"1_000.000_5 -0.2_5"#,
+            vec![
+                (r#"~~~~~~~~~~~       "#, FloatLiteral(1000.0005)),
+                (r#"            ~~~~~~"#, FloatLiteral(-0.25)),
+            ],
+        );
+    }
+
     #[test]
     fn float_literals_unexpected_char() {
         assert_eq!(
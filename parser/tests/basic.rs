@@ -16,7 +16,7 @@ use crate::base::metadata::*;
 use crate::base::pos::{self, BytePos, Span, Spanned};
 use crate::base::types::{Alias, Field, Type};
 
-use crate::parser::ReplLine;
+use crate::parser::{ParserSource, ReplLine};
 
 #[test]
 fn dangling_in() {
@@ -288,6 +288,53 @@ fn record_trailing_comma() {
     );
 }
 
+#[test]
+fn record_update() {
+    let _ = ::env_logger::try_init();
+    let e = parse_clear_span!("{ r with y, x = z }");
+    assert_eq!(
+        e,
+        no_loc(Expr::Record {
+            typ: Type::hole(),
+            types: Vec::new(),
+            exprs: vec![
+                ExprField {
+                    metadata: Metadata::default(),
+                    name: no_loc("y".into()),
+                    value: None,
+                },
+                ExprField {
+                    metadata: Metadata::default(),
+                    name: no_loc("x".into()),
+                    value: Some(id("z")),
+                },
+            ],
+            base: Some(Box::new(id("r"))),
+            update: true,
+        })
+    );
+}
+
+#[test]
+fn record_update_in_match() {
+    let _ = ::env_logger::try_init();
+    let text = r"
+match x with
+| { y } -> { x with y = y }
+";
+    parse_clear_span!(text);
+}
+
+#[test]
+fn int_literals_with_radix_prefixes() {
+    let _ = ::env_logger::try_init();
+    let e = parse_clear_span!("[0xFF, 0o755, 0b1010_1010, 1_000]");
+    assert_eq!(
+        e,
+        array(vec![int(255), int(0o755), int(0b1010_1010), int(1000)])
+    );
+}
+
 #[test]
 fn array_trailing_comma() {
     let _ = ::env_logger::try_init();
@@ -800,6 +847,7 @@ fn doc_comment_on_record_field() {
                 value: Some(int(1)),
             }],
             base: None,
+            update: false,
         })
     )
 }
@@ -970,3 +1018,33 @@ type OptionEff a = forall r . (| OptionEff .. r)
 "#;
     parse_clear_span!(text);
 }
+
+#[test]
+fn incomplete_repl_lines() {
+    let _ = ::env_logger::try_init();
+
+    let is_incomplete = |line: &str| {
+        let mut module = MockEnv::<String>::new();
+        match parser::parse_partial_repl_line(&mut module, line) {
+            Ok(_) => false,
+            Err((_, err)) => parser::is_incomplete(line.span(), &err),
+        }
+    };
+
+    for line in &[
+        "let f x =",
+        "let x = 1 +",
+        "(1 +",
+        "[1, 2",
+        "{ x = 1,",
+        "match x with",
+        "if x then 1",
+        "\"abc",
+        "/* comment",
+    ] {
+        assert!(is_incomplete(line), "`{}` should be incomplete", line);
+    }
+    for line in &["let f x =\n    x + 1", "1 )", "let = 1", "1 + 2"] {
+        assert!(!is_incomplete(line), "`{}` should not be incomplete", line);
+    }
+}
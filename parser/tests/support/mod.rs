@@ -352,6 +352,7 @@ pub fn record_a(
             })
             .collect(),
         base: None,
+        update: false,
     })
 }
 
@@ -170,12 +170,19 @@ fn init_env_logger() {
 #[cfg(not(feature = "env_logger"))]
 fn init_env_logger() {}
 
-fn format(file: &str, file_map: Arc<codespan::FileMap>) -> Result<String> {
+fn format(
+    file: &str,
+    file_map: Arc<codespan::FileMap>,
+    settings: gluon_format::FormatSettings,
+) -> Result<String> {
     let mut compiler = Compiler::new();
     let thread = new_vm();
 
     Ok(compiler.format_expr(
-        &mut gluon_format::Formatter::default(),
+        &mut gluon_format::Formatter {
+            settings,
+            ..gluon_format::Formatter::default()
+        },
         &thread,
         file,
         file_map.src(),
@@ -195,7 +202,9 @@ fn fmt_file(name: &Path) -> Result<()> {
     let module_name = filename_to_module(&name.display().to_string());
     let mut code_map = codespan::CodeMap::new();
     let file_map = code_map.add_filemap(module_name.clone().into(), buffer);
-    let formatted = format(&module_name, file_map.clone())?;
+    let settings =
+        gluon_format::FormatSettings::for_file(name).map_err(|err| Error::Other(err.into()))?;
+    let formatted = format(&module_name, file_map.clone(), settings)?;
 
     // Avoid touching the .glu file if it did not change
     if file_map.src() != formatted {
@@ -220,7 +229,7 @@ fn fmt_stdio() -> Result<()> {
     let mut code_map = codespan::CodeMap::new();
     let file_map = code_map.add_filemap("STDIN".into(), buffer);
 
-    let formatted = format("STDIN", file_map)?;
+    let formatted = format("STDIN", file_map, Default::default())?;
     stdout().write_all(formatted.as_bytes())?;
     Ok(())
 }
@@ -265,8 +274,11 @@ fn run(
         Some(SubOpt::Doc(ref doc_opt)) => {
             let input = &doc_opt.input;
             let output = &doc_opt.output;
-            gluon_doc::generate_for_path(&new_vm(), input, output)
+            let warnings = gluon_doc::generate(&new_vm(), input, output, &doc_opt.options())
                 .map_err(|err| format!("{}\n{}", err, err.backtrace()))?;
+            for warning in warnings {
+                eprintln!("{}", warning);
+            }
         }
         None => {
             if opt.interactive {
@@ -285,6 +297,16 @@ fn run(
     Ok(())
 }
 
+fn emit_warnings(compiler: &mut Compiler, color: Color) {
+    let code_map = compiler.code_map();
+    let mut stderr = termcolor::StandardStream::stderr(color.into());
+    for warning in compiler.take_warnings() {
+        if let Err(err) = warning.emit(&mut stderr, &code_map) {
+            eprintln!("{}", err);
+        }
+    }
+}
+
 fn main() {
     init_env_logger();
 
@@ -293,7 +315,9 @@ fn main() {
     let mut compiler = Compiler::new().run_io(true);
     let vm = new_vm();
 
-    if let Err(err) = run(&opt, &mut compiler, opt.color, &vm) {
+    let result = run(&opt, &mut compiler, opt.color, &vm);
+    emit_warnings(&mut compiler, opt.color);
+    if let Err(err) = result {
         match err {
             Error::VM(VMError::Message(_)) => eprintln!("{}\n{}", err, vm.context().stacktrace(0)),
             _ => {
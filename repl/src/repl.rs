@@ -4,7 +4,13 @@ extern crate rustyline;
 
 extern crate gluon_completion as completion;
 
-use std::{error::Error as StdError, path::PathBuf, str::FromStr, sync::Mutex};
+use std::{
+    error::Error as StdError,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
 use futures::{
     future::{self, Either},
@@ -15,27 +21,27 @@ use futures::{
 use crate::base::{
     ast::{Expr, Pattern, SpannedPattern, Typed, TypedIdent},
     error::InFile,
+    fnv::FnvMap,
     kind::Kind,
     pos, resolve,
-    symbol::{Symbol, SymbolModule},
+    symbol::{Name, Symbol, SymbolModule},
     types::{ArcType, TypeExt},
     DebugLevel,
 };
-use crate::parser::{parse_partial_repl_line, ReplLine};
+use crate::parser::{is_incomplete, parse_partial_repl_line, ReplLine};
 use crate::vm::{
     api::{
         de::De,
         generic::A,
         {Generic, Getable, OpaqueValue, OwnedFunction, Pushable, VmType, WithVM, IO},
     },
-    internal::ValuePrinter,
-    thread::{ActiveThread, RootedValue, Thread, ThreadInternal},
+    thread::{ActiveThread, RenderOptions, RootedValue, Thread, ThreadInternal},
     {self, Error as VMError, Result as VMResult},
 };
 
 use gluon::{
     compiler_pipeline::{Executable, ExecuteValue},
-    import::add_extern_module,
+    import::{add_extern_module, Import},
     {Compiler, Error as GluonError, Result as GluonResult, RootedThread},
 };
 
@@ -57,8 +63,17 @@ fn type_of_expr(args: WithVM<&str>) -> IO<Result<String, String>> {
     let mut compiler = Compiler::new();
     IO::Value(match compiler.typecheck_str(vm, "<repl>", &args, None) {
         Ok((expr, _)) => {
-            let env = vm.get_env();
-            Ok(format!("{}", expr.env_type_of(&*env)))
+            let global_env = vm.global_env();
+            let env = global_env.get_env();
+            let typ = expr.env_type_of(&*env);
+            // Show what the aliases in the type stand for as well as they may hide its structure
+            let resolved =
+                resolve::remove_aliases(&*env, &mut global_env.type_cache(), typ.clone());
+            if resolved == typ {
+                Ok(format!("{}", typ))
+            } else {
+                Ok(format!("{}\n= {}", typ, resolved))
+            }
         }
         Err(msg) => Err(format!("{}", msg)),
     })
@@ -106,18 +121,68 @@ fn find_info(args: WithVM<&str>) -> IO<Result<String, String>> {
             }
         }
     }
-    let maybe_comment = env
-        .get_metadata(args)
-        .ok()
-        .and_then(|metadata| metadata.comment.as_ref());
-    if let Some(comment) = maybe_comment {
-        for line in comment.content.lines() {
-            write!(&mut buffer, "\n/// {}", line).unwrap();
+    if let Ok(metadata) = env.get_metadata(args) {
+        if let Some(ref comment) = metadata.comment {
+            for line in comment.content.lines() {
+                write!(&mut buffer, "\n/// {}", line).unwrap();
+            }
+        }
+        for attribute in &metadata.attributes {
+            match attribute.arguments {
+                Some(ref arguments) => {
+                    write!(&mut buffer, "\n#[{}({})]", attribute.name, arguments).unwrap()
+                }
+                None => write!(&mut buffer, "\n#[{}]", attribute.name).unwrap(),
+            }
         }
     }
+    if let Some(location) = find_location(vm, args) {
+        write!(&mut buffer, "\nDefined in {}", location).unwrap();
+    }
     IO::Value(Ok(buffer))
 }
 
+/// Describes where the module which defines `name` was loaded from. Returns `None` for names
+/// which were not loaded through `import!`, such as the bindings made in the repl.
+fn find_location(vm: &Thread, name: &str) -> Option<String> {
+    let mut module = Name::new(name);
+    while !vm.global_env().global_exists(module.as_str()) {
+        module = module.module();
+        if module.as_str().is_empty() {
+            return None;
+        }
+    }
+    let module = module.as_str();
+
+    let import = vm.get_macros().get("import")?;
+    let import = import.downcast_ref::<Import>()?;
+    if import.loaders.read().unwrap().contains_key(module) {
+        return Some(format!("the extern module `{}`", module));
+    }
+
+    let mut filename = module.replace(".", "/");
+    filename.push_str(".glu");
+    if import.modules().iter().any(|m| m == module) {
+        return Some(format!("`{}` ({})", module, filename));
+    }
+    let paths = import.paths.read().unwrap();
+    let path = paths
+        .iter()
+        .map(|path| path.join(&filename))
+        .find(|path| path.is_file())?;
+    Some(format!("`{}` ({})", module, path.display()))
+}
+
+fn is_incomplete_line(line: &str) -> bool {
+    let mut compiler = Compiler::new();
+    let filemap = compiler.add_filemap("line", line);
+    let mut module = SymbolModule::new("line".into(), compiler.mut_symbols());
+    match parse_partial_repl_line(&mut module, &*filemap) {
+        Ok(_) => false,
+        Err((_, err)) => is_incomplete(filemap.span(), &err),
+    }
+}
+
 fn switch_debug_level(args: WithVM<&str>) -> IO<Result<String, String>> {
     let vm = args.vm;
     let args = args.value.trim();
@@ -202,6 +267,152 @@ struct CpuPool(self::futures_cpupool::CpuPool);
 
 impl_userdata! { CpuPool }
 
+/// Where a binding in the scope of the repl was defined
+#[derive(Clone, Debug, PartialEq)]
+enum Origin {
+    Repl,
+    File(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Origin::Repl => write!(f, "the repl"),
+            Origin::File(ref path) => write!(f, "`{}`", path),
+        }
+    }
+}
+
+#[derive(Default)]
+struct LoadedFilesState {
+    /// Every file loaded with `:load`, in the order they were first loaded
+    files: Vec<String>,
+    origins: FnvMap<String, Origin>,
+}
+
+/// Tracks the files loaded into the repl so that `:reload` can load them again, along with where
+/// each binding in scope was defined so that replacing one from somewhere else gives a warning
+#[derive(Clone, Default, Userdata)]
+struct LoadedFiles(Arc<Mutex<LoadedFilesState>>);
+
+impl_userdata! { LoadedFiles }
+
+impl LoadedFiles {
+    fn add_file(&self, path: &str) {
+        let mut state = self.0.lock().unwrap();
+        let files = &mut state.files;
+        if !files.iter().any(|file| file == path) {
+            files.push(path.to_string());
+        }
+    }
+
+    /// Records that `name` is now defined by `origin`, returning a warning if that replaced a
+    /// binding defined somewhere else
+    fn define(&self, name: &str, origin: Origin) -> Option<String> {
+        let previous = self
+            .0
+            .lock()
+            .unwrap()
+            .origins
+            .insert(name.to_string(), origin.clone());
+        match previous {
+            Some(ref previous) if *previous != origin => Some(format!(
+                "Warning: `{}` from {} replaces the binding from {}",
+                name, origin, previous
+            )),
+            _ => None,
+        }
+    }
+}
+
+fn loaded_files(files: &LoadedFiles) -> IO<Vec<String>> {
+    IO::Value(files.0.lock().unwrap().files.clone())
+}
+
+/// Compiles the file at `path` as the module named after the file and brings every value it
+/// exports into the scope of the repl, as `let { .. } = import! module` would
+fn load_file(
+    files: &LoadedFiles,
+    WithVM { vm, value: path }: WithVM<&str>,
+) -> impl Future<Item = IO<String>, Error = vm::Error> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            let msg = format!("Unable to read `{}`: {}", path, err);
+            return Either::A(future::ok(IO::Exception(msg)));
+        }
+    };
+    // Remember the file even if it fails to compile so that it can be fixed and reloaded
+    files.add_file(path);
+
+    let files = files.clone();
+    let path = path.to_string();
+    let module = Path::new(&path)
+        .file_stem()
+        .map_or_else(|| path.clone(), |stem| stem.to_string_lossy().into_owned());
+    let vm = vm.root_thread();
+    let mut compiler = Compiler::new();
+    let future = source
+        .as_str()
+        .load_script(&mut compiler, vm.clone(), &module, &source, None);
+    Either::B(future.then(move |result| {
+        use std::fmt::Write;
+
+        let result = result.and_then(|()| bind_exports(&vm, &module));
+        Ok(match result {
+            Ok(names) => {
+                let mut msg = String::new();
+                for name in names {
+                    if let Some(warning) = files.define(&name, Origin::File(path.clone())) {
+                        writeln!(msg, "{}", warning).unwrap();
+                    }
+                }
+                write!(msg, "Loaded {}", module).unwrap();
+                IO::Value(msg)
+            }
+            Err(err) => IO::Exception(
+                err.emit_string(&compiler.code_map())
+                    .unwrap_or_else(|_| err.to_string()),
+            ),
+        })
+    }))
+}
+
+/// Sets a global for every value field of the `module` record, returning the names of the fields
+fn bind_exports(vm: &Thread, module: &str) -> GluonResult<Vec<String>> {
+    let (value, typ, metadata) = {
+        let env = vm.get_env();
+        let (value, typ) = env.get_binding(module)?;
+        let metadata = env.get_metadata(module)?.clone();
+        (vm.root_value::<&Thread>(value), typ.into_owned(), metadata)
+    };
+    let resolved_type = {
+        let env = vm.global_env();
+        resolve::remove_aliases(&*env.get_env(), &mut env.type_cache(), typ)
+    };
+
+    resolved_type
+        .row_iter()
+        .map(|field| {
+            let name = field.name.declared_name();
+            let field_value = value
+                .get_field(name)
+                .unwrap_or_else(|| panic!("module `{}` doesn't have field `{}`", module, name));
+            let field_metadata = metadata
+                .module
+                .get(name)
+                .map_or_else(Default::default, |metadata| (**metadata).clone());
+            vm.set_global(
+                Symbol::from(format!("@{}", name)),
+                field.typ.clone(),
+                field_metadata,
+                field_value.get_value(),
+            )?;
+            Ok(name.to_string())
+        })
+        .collect()
+}
+
 #[derive(Serialize, Deserialize)]
 pub enum ReadlineError {
     Eof,
@@ -230,6 +441,8 @@ impl<'vm> Pushable<'vm> for ReadlineError {
     }
 }
 
+/// The directory which the history is stored in, `$XDG_DATA_HOME/gluon-repl` (usually
+/// `~/.local/share/gluon-repl`) on unix
 fn app_dir_root() -> Result<PathBuf, Box<StdError>> {
     Ok(::app_dirs::app_root(
         ::app_dirs::AppDataType::UserData,
@@ -274,10 +487,11 @@ fn new_cpu_pool(size: usize) -> IO<CpuPool> {
 }
 
 fn eval_line(
+    files: &LoadedFiles,
     De(color): De<crate::Color>,
     WithVM { vm, value: line }: WithVM<&str>,
 ) -> impl Future<Item = IO<()>, Error = vm::Error> {
-    eval_line_(vm.root_thread(), line).then(move |result| {
+    eval_line_(files.clone(), vm.root_thread(), line).then(move |result| {
         Ok(match result {
             Ok(x) => IO::Value(x),
             Err((compiler, err)) => {
@@ -292,6 +506,7 @@ fn eval_line(
 }
 
 fn eval_line_(
+    files: LoadedFiles,
     vm: RootedThread,
     line: &str,
 ) -> impl Future<Item = (), Error = (Compiler, GluonError)> {
@@ -344,8 +559,19 @@ fn eval_line_(
                     .and_then(move |value| {
                         // Hack to get around borrow-checker. Method-chaining didn't work,
                         // even with #[feature(nll)]. Seems like a bug
-                        let temp =
-                            set_globals(&vm, &unpack_pattern, &value.typ, &value.value.as_ref());
+                        let mut names = Vec::new();
+                        let temp = set_globals(
+                            &vm,
+                            &unpack_pattern,
+                            &value.typ,
+                            &value.value.as_ref(),
+                            &mut names,
+                        );
+                        for name in &names {
+                            if let Some(warning) = files.define(name, Origin::Repl) {
+                                eprintln!("{}", warning);
+                            }
+                        }
                         temp.and(Ok(value))
                     }),
             )
@@ -355,24 +581,26 @@ fn eval_line_(
         future
             .map_err(|x| (compiler, x))
             .map(move |ExecuteValue { value, typ, .. }| {
-                let vm = value.vm();
-                let env = vm.global_env().get_env();
-                let debug_level = vm.global_env().get_debug_level();
+                let options = RenderOptions {
+                    max_depth: 5,
+                    max_len: 100,
+                    ..RenderOptions::default()
+                };
                 println!(
                     "{}",
-                    ValuePrinter::new(&*env, &typ, value.get_variant(), &debug_level)
-                        .width(80)
-                        .max_level(5)
+                    value.vm().render_value(&typ, value.get_variant(), &options)
                 );
             }),
     )
 }
 
+/// Sets a global for each variable bound by `pattern`, pushing their names to `names`
 fn set_globals(
     vm: &Thread,
     pattern: &SpannedPattern<Symbol>,
     typ: &ArcType,
     value: &RootedValue<&Thread>,
+    names: &mut Vec<String>,
 ) -> GluonResult<()> {
     match pattern.value {
         Pattern::Ident(ref id) => {
@@ -382,6 +610,7 @@ fn set_globals(
                 Default::default(),
                 value.get_value(),
             )?;
+            names.push(id.name.declared_name().to_string());
             Ok(())
         }
         Pattern::Tuple { ref elems, .. } => {
@@ -389,7 +618,7 @@ fn set_globals(
                 .iter()
                 .zip(crate::vm::dynamic::field_iter(&value, typ, vm));
             for (elem_pattern, (elem_value, elem_type)) in iter {
-                set_globals(vm, elem_pattern, &elem_type, &elem_value)?;
+                set_globals(vm, elem_pattern, &elem_type, &elem_value, names)?;
             }
             Ok(())
         }
@@ -422,14 +651,17 @@ fn set_globals(
                     .clone();
                 match pattern_field.value {
                     Some(ref sub_pattern) => {
-                        set_globals(vm, sub_pattern, &field_type, &field_value)?
+                        set_globals(vm, sub_pattern, &field_type, &field_value, names)?
+                    }
+                    None => {
+                        vm.set_global(
+                            Symbol::from(format!("@{}", field_name.declared_name())),
+                            field_type.to_owned(),
+                            Default::default(),
+                            field_value.get_value(),
+                        )?;
+                        names.push(field_name.declared_name().to_string());
                     }
-                    None => vm.set_global(
-                        Symbol::from(format!("@{}", pattern_field.name.value.declared_name())),
-                        field_type.to_owned(),
-                        Default::default(),
-                        field_value.get_value(),
-                    )?,
                 }
             }
             Ok(())
@@ -441,7 +673,8 @@ fn set_globals(
                 Default::default(),
                 value.get_value(),
             )?;
-            set_globals(vm, pattern, typ, value)
+            names.push(id.value.declared_name().to_string());
+            set_globals(vm, pattern, typ, value, names)
         }
         Pattern::Constructor(..) | Pattern::Literal(_) | Pattern::Error => {
             Err(VMError::Message("The repl cannot bind variables from this pattern".into()).into())
@@ -505,7 +738,7 @@ fn save_history(editor: &Editor) -> IO<()> {
     });
 
     if let Err(err) = history_result {
-        warn!("Unable to load history: {}", err);
+        warn!("Unable to save history: {}", err);
     }
     IO::Value(())
 }
@@ -533,17 +766,26 @@ struct Settings<'a> {
 }
 
 fn load_repl(vm: &Thread) -> vm::Result<vm::ExternModule> {
+    vm.register_type::<LoadedFiles>("LoadedFiles", &[])?;
+
     vm::ExternModule::new(
         vm,
         record!(
             type Color => Color,
             type Settings => Settings<'static>,
+            type LoadedFiles => LoadedFiles,
             type_of_expr => primitive!(1, type_of_expr),
             find_info => primitive!(1, find_info),
             find_kind => primitive!(1, find_kind),
+            is_incomplete => primitive!(1, "is_incomplete", is_incomplete_line),
             parse_color => primitive!(1, "parse_color", |s: &str| s.parse::<Color>()),
             switch_debug_level => primitive!(1, switch_debug_level),
-            eval_line => primitive!(2, async fn eval_line),
+            eval_line => primitive!(3, async fn eval_line),
+            new_loaded_files => primitive!(1, "new_loaded_files", |()| IO::Value(
+                LoadedFiles::default()
+            )),
+            loaded_files => primitive!(1, loaded_files),
+            load_file => primitive!(2, async fn load_file),
             finish_or_interrupt => primitive!(3, async fn finish_or_interrupt),
             new_cpu_pool => primitive!(1, new_cpu_pool)
         ),
@@ -627,20 +869,28 @@ mod tests {
         compile_repl(&mut Compiler::new(), &vm).unwrap_or_else(|err| panic!("{}", err));
 
         // pattern with field names out of order
-        eval_line_(vm.clone(), r#"let {y, x} = {x = "x", y = "y"}"#)
-            .wait()
-            .map_err(|(_, err)| err)
-            .expect("Error evaluating let binding");
+        eval_line_(
+            LoadedFiles::default(),
+            vm.clone(),
+            r#"let {y, x} = {x = "x", y = "y"}"#,
+        )
+        .wait()
+        .map_err(|(_, err)| err)
+        .expect("Error evaluating let binding");
         let x: String = vm.get_global("x").expect("Error getting x");
         assert_eq!(x, "x");
         let y: String = vm.get_global("y").expect("Error getting y");
         assert_eq!(y, "y");
 
         // pattern with field names out of order and different field types
-        eval_line_(vm.clone(), r#"let {y} = {x = "x", y = ()}"#)
-            .wait()
-            .map_err(|(_, err)| err)
-            .expect("Error evaluating let binding 2");
+        eval_line_(
+            LoadedFiles::default(),
+            vm.clone(),
+            r#"let {y} = {x = "x", y = ()}"#,
+        )
+        .wait()
+        .map_err(|(_, err)| err)
+        .expect("Error evaluating let binding 2");
         let () = vm.get_global("y").expect("Error getting y");
     }
 
@@ -653,6 +903,12 @@ mod tests {
         compile_repl(&mut Compiler::new(), &vm).unwrap_or_else(|err| panic!("{}", err));
         let mut type_of: FunctionRef<QueryFn> = vm.get_global("repl.prim.type_of_expr").unwrap();
         assert_eq!(type_of.call("123"), Ok(IO::Value(Ok("Int".into()))));
+        assert_eq!(
+            type_of.call("let { Option } = import! std.option in Some 1"),
+            Ok(IO::Value(Ok(
+                "std.types.Option Int\n= | None\n| Some Int".into()
+            )))
+        );
     }
 
     #[test]
@@ -687,6 +943,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn find_info_shows_attributes_and_location() {
+        let _ = ::env_logger::try_init();
+        let vm = new_vm();
+        compile_repl(&mut Compiler::new(), &vm).unwrap_or_else(|err| panic!("{}", err));
+        let mut find_info: FunctionRef<QueryFn> = vm.get_global("repl.prim.find_info").unwrap();
+        match find_info.call("std.prelude.Semigroup") {
+            Ok(IO::Value(Ok(ref info))) => assert!(
+                info.ends_with("\n#[implicit]\nDefined in `std.prelude` (std/prelude.glu)"),
+                "{}",
+                info
+            ),
+            x => assert!(false, "{:?}", x),
+        }
+        assert_eq!(
+            find_info.call("std.io.prim.println"),
+            Ok(IO::Value(Ok(
+                "std.io.prim.println: String -> IO ()\nDefined in the extern module `std.io.prim`"
+                    .into()
+            )))
+        );
+    }
+
+    #[test]
+    fn incomplete_lines() {
+        assert!(is_incomplete_line("let f x ="));
+        assert!(is_incomplete_line("let x = { y = 1,"));
+        assert!(!is_incomplete_line("let f x =\n    x + 1"));
+        assert!(!is_incomplete_line("1 )"));
+        assert!(!is_incomplete_line(""));
+    }
+
     #[test]
     fn complete_repl_empty() {
         let _ = ::env_logger::try_init();
@@ -2,7 +2,11 @@
 
 extern crate rexpect;
 
+use std::fs;
+use std::path::Path;
 use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use rexpect::errors::*;
 use rexpect::session::{spawn_command, PtySession};
@@ -21,6 +25,18 @@ impl REPL {
     /// Defines the command, timeout, and prompt settings.
     /// Wraps a rexpect::session::PtySession. expecting the prompt after launch.
     fn new_() -> Result<REPL> {
+        REPL::with_command(|_| ())
+    }
+
+    /// Starts the repl with `$XDG_DATA_HOME` set to `data_home` so that the history is stored in it
+    fn with_data_home(data_home: &Path) -> REPL {
+        REPL::with_command(|command| {
+            command.env("XDG_DATA_HOME", data_home);
+        })
+        .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn with_command(configure: impl FnOnce(&mut Command)) -> Result<REPL> {
         let timeout: u64 = 30_000;
         let prompt: &'static str = "REXPECT> ";
 
@@ -28,6 +44,7 @@ impl REPL {
         command
             .args(&["-i", "--color", "never", "--prompt", prompt])
             .env("GLUON_PATH", "..");
+        configure(&mut command);
         let mut session = spawn_command(command, Some(timeout))?;
 
         session.exp_string(prompt)?;
@@ -55,6 +72,19 @@ impl REPL {
         Ok(())
     }
 
+    /// Sends a line which the repl should ask to continue on the next line
+    fn test_incomplete(&mut self, send: &str) {
+        self.test_incomplete_(send)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    fn test_incomplete_(&mut self, send: &str) -> Result<()> {
+        self.session.send_line(send)?;
+        self.session.exp_string(send)?;
+        self.session.exp_string("........ ")?;
+        Ok(())
+    }
+
     fn quit(&mut self) {
         self.quit_().unwrap_or_else(|err| panic!("{}", err));
     }
@@ -170,3 +200,101 @@ fn assert() {
     repl.test("let { assert } = import! std.test", None);
     repl.test("assert False", None);
 }
+
+#[test]
+fn multiline_let_binding() {
+    let mut repl = REPL::new();
+
+    repl.test_incomplete("let add x y =");
+    repl.test("    x + y", None);
+    repl.test("add 1 2", Some("3"));
+
+    repl.test_incomplete("let record = {");
+    repl.test_incomplete("    x = 1,");
+    repl.test("    }", None);
+    repl.test("record.x", Some("1"));
+}
+
+#[test]
+fn incomplete_line_is_reported_after_an_empty_line() {
+    let mut repl = REPL::new();
+
+    repl.test_incomplete("let x =");
+    repl.test("", Some("Unexpected token"));
+}
+
+#[test]
+fn history_is_saved_on_quit() {
+    let data_home = ::std::env::temp_dir().join("gluon_repl_history_is_saved_on_quit");
+    let _ = fs::remove_dir_all(&data_home);
+    let history = data_home.join("gluon-repl").join("history");
+
+    let mut repl = REPL::with_data_home(&data_home);
+    repl.test("1 + 2", Some("3"));
+    repl.session
+        .send_line(":q")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let start = Instant::now();
+    loop {
+        if let Ok(contents) = fs::read_to_string(&history) {
+            if contents.lines().any(|line| line == "1 + 2") {
+                break;
+            }
+        }
+        assert!(
+            start.elapsed() < Duration::from_secs(30),
+            "History was not saved to `{}`",
+            history.display()
+        );
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn load_and_reload() {
+    let dir = ::std::env::temp_dir().join("gluon_repl_load_and_reload");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("reload_test.glu");
+    fs::write(
+        &file,
+        "let value = 1\nlet add x = x + value\n{ value, add }",
+    )
+    .unwrap();
+
+    let mut repl = REPL::new();
+
+    repl.test(
+        &format!(":l {}", file.display()),
+        Some("Loaded reload_test"),
+    );
+    repl.test("value", Some("1"));
+    repl.test("add 1", Some("2"));
+
+    repl.test("let other = 10", None);
+    repl.test("let add x = x", None);
+    repl.test("add 1", Some("1"));
+
+    fs::write(
+        &file,
+        "let value = 2\nlet add x = x + value\n{ value, add }",
+    )
+    .unwrap();
+    repl.test(
+        ":r",
+        Some(&format!(
+            "Warning: `add` from `{}` replaces the binding from the repl",
+            file.display()
+        )),
+    );
+    repl.test("value", Some("2"));
+    repl.test("add 1", Some("3"));
+    repl.test("other", Some("10"));
+
+    // A file which fails to compile keeps the bindings from the last successful load
+    fs::write(&file, "let value = \"\" + 1\n{ value }").unwrap();
+    repl.test(":r", Some("Expected the following types to be equal"));
+    repl.test("value", Some("2"));
+    repl.test("other", Some("10"));
+}
@@ -0,0 +1,58 @@
+//! Module containing primitives for working with binary data stored in an `Array Byte`.
+
+use crate::real_std::cmp::Ordering;
+
+use crate::vm::api::RuntimeResult;
+use crate::vm::{self, thread::Thread, ExternModule};
+
+fn eq(l: &[u8], r: &[u8]) -> bool {
+    l == r
+}
+
+fn compare(l: &[u8], r: &[u8]) -> Ordering {
+    l.cmp(r)
+}
+
+fn decode_utf8(bytes: Vec<u8>) -> RuntimeResult<String, String> {
+    String::from_utf8(bytes)
+        .map_err(|err| format!("Invalid UTF-8: {}", err.utf8_error()))
+        .into()
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+fn encode_latin1(s: &str) -> RuntimeResult<Vec<u8>, String> {
+    s.chars()
+        .map(|c| {
+            if (c as u32) < 256 {
+                Ok(c as u8)
+            } else {
+                Err(format!("Character `{}` can not be encoded as Latin-1", c))
+            }
+        })
+        .collect::<Result<_, _>>()
+        .into()
+}
+
+mod std {
+    pub mod bytes {
+        pub use crate::bytes_bind as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    use self::std;
+
+    ExternModule::new(
+        vm,
+        record! {
+            eq => primitive!(2, std::bytes::prim::eq),
+            compare => primitive!(2, std::bytes::prim::compare),
+            decode_utf8 => primitive!(1, std::bytes::prim::decode_utf8),
+            decode_latin1 => primitive!(1, std::bytes::prim::decode_latin1),
+            encode_latin1 => primitive!(1, std::bytes::prim::encode_latin1)
+        },
+    )
+}
@@ -13,6 +13,9 @@ use std::ops::Deref;
 use std::result::Result as StdResult;
 use std::sync::Arc;
 
+#[cfg(feature = "serialization")]
+extern crate bincode;
+
 #[cfg(feature = "serde")]
 use either::Either;
 
@@ -533,7 +536,16 @@ where
                     &mut metadata_map,
                 );
 
-                tc.typecheck_expr_expected(expr.borrow_mut(), expected_type)
+                let result = tc.typecheck_expr_expected(expr.borrow_mut(), expected_type);
+                if !tc.warnings().is_empty() {
+                    let warnings = tc.warnings().iter().cloned().collect();
+                    let mut state = compiler.state();
+                    let warnings = InFile::new(state.code_map.clone(), warnings);
+                    warn!("{}", warnings);
+                    let diagnostics = warnings.diagnostics(&state.code_map);
+                    state.warnings.extend(diagnostics);
+                }
+                result
             };
             result.map_err(|err| {
                 info!("Error when typechecking `{}`: {}", file, err);
@@ -612,15 +624,48 @@ where
     ) -> Result<CompileValue<Self::Expr>> {
         use crate::vm::compiler::Compiler;
         info!("Compile `{}`", filename);
+        let file_map = compiler.get_filemap(filename);
         let mut module = {
             let env = thread.get_env();
 
             let translator = core::Translator::new(&*env);
+            let mut inlined = Vec::new();
             let expr = {
                 let expr = translator.translate_expr(self.expr.borrow());
 
                 debug!("Translation returned: {}", expr);
 
+                let threshold = compiler.settings.inline_threshold;
+                let expr = match file_map {
+                    Some(ref file_map) if threshold > 0 => {
+                        let global_env = thread.global_env();
+                        global_env.set_inline_functions(
+                            filename,
+                            core::inline::InlineFunctions::new(
+                                filename,
+                                file_map.clone(),
+                                expr,
+                                threshold,
+                            ),
+                        );
+                        let (expr, sources) = core::inline::inline(
+                            &translator.allocator,
+                            &|id| global_env.get_inline_functions(id.definition_name()),
+                            threshold,
+                            file_map.span().end(),
+                            expr,
+                        );
+                        inlined = sources;
+                        expr
+                    }
+                    _ => expr,
+                };
+
+                let expr = if compiler.settings.fold_constants {
+                    core::optimize::fold_constants(&translator.allocator, expr)
+                } else {
+                    expr
+                };
                 core::optimize::optimize(&translator.allocator, expr)
             };
 
@@ -640,6 +685,7 @@ where
                 filename.to_string(),
                 compiler.settings.emit_debug_info,
             );
+            compiler.set_inlined(inlined);
             compiler.compile_expr(expr)?
         };
         module.function.id = Symbol::from(filename);
@@ -959,6 +1005,116 @@ where
         .map_err(Either::Right)
 }
 
+/// Identifies the format written by `compile_to_bytes`. Bytes written by any other version are
+/// rejected as the instructions and the layout of the serialized module may have changed.
+#[cfg(feature = "serialization")]
+const BYTES_VERSION: &str = concat!("gluon-", env!("CARGO_PKG_VERSION"), "-bytecode-1");
+
+/// Compiles `expr_str` and serializes the module with `bincode`. The bytes start with
+/// `BYTES_VERSION` followed by the names of the modules that need to be loaded before the module
+/// itself can be deserialized (see `load_bytes`).
+#[cfg(feature = "serialization")]
+pub fn compile_to_bytes(
+    compiler: &mut Compiler,
+    thread: &Thread,
+    name: &str,
+    expr_str: &str,
+) -> Result<Vec<u8>> {
+    use self::bincode::SerializerAcceptor;
+    use crate::serde::ser::SerializeState;
+    use crate::vm::serialization::SeSeed;
+
+    struct SerializeModule<'a>(&'a Module);
+
+    impl<'a> SerializerAcceptor for SerializeModule<'a> {
+        type Output = StdResult<(), String>;
+
+        fn accept<S>(self, serializer: S) -> Self::Output
+        where
+            S: crate::serde::Serializer,
+        {
+            self.0
+                .serialize_state(serializer, &SeSeed::new())
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        }
+    }
+
+    let CompileValue {
+        expr: _,
+        typ,
+        metadata,
+        module,
+    } = expr_str.compile(compiler, thread, name, expr_str, None)?;
+    let dependencies: Vec<&str> = module
+        .module_globals
+        .iter()
+        .map(|global| global.definition_name())
+        .collect();
+    let mut bytes =
+        bincode::serialize(&(BYTES_VERSION, &dependencies)).map_err(|err| err.to_string())?;
+    let module = Module {
+        typ,
+        metadata,
+        module,
+    };
+    bincode::config().with_serializer(&mut bytes, SerializeModule(&module))?;
+    Ok(bytes)
+}
+
+/// Loads a module written by `compile_to_bytes` into the global `name`. Any modules the module
+/// depends on which are not loaded yet are imported first, which in turn loads their own
+/// dependencies.
+#[cfg(feature = "serialization")]
+pub fn load_bytes(thread: &Thread, name: &str, mut bytes: &[u8]) -> Result<()> {
+    use self::bincode::{DeserializerAcceptor, SliceReader};
+    use crate::vm::serialization::DeSeed;
+
+    struct DeserializeModule<'t>(&'t Thread);
+
+    impl<'de, 't> DeserializerAcceptor<'de> for DeserializeModule<'t> {
+        type Output = StdResult<Module, String>;
+
+        fn accept<D>(self, deserializer: D) -> Self::Output
+        where
+            D: crate::serde::Deserializer<'de>,
+        {
+            DeSeed::new(self.0)
+                .deserialize(deserializer)
+                .map_err(|err| err.to_string())
+        }
+    }
+
+    let (version, dependencies): (String, Vec<String>) =
+        bincode::deserialize_from(&mut bytes).map_err(|err| err.to_string())?;
+    if version != BYTES_VERSION {
+        return Err(format!(
+            "`{}` was compiled to `{}` but only `{}` can be loaded",
+            name, version, BYTES_VERSION
+        )
+        .into());
+    }
+
+    for dependency in &dependencies {
+        if !thread.global_env().global_exists(dependency) {
+            Compiler::new().load_module(thread, dependency)?;
+        }
+    }
+
+    let Module {
+        typ,
+        metadata,
+        mut module,
+    } = bincode::config().with_deserializer(SliceReader::new(bytes), DeserializeModule(thread))?;
+    let module_id = Symbol::from(format!("@{}", name));
+    module.function.id = module_id.clone();
+    let closure = thread.global_env().new_global_thunk(module)?;
+    let value = thread.call_thunk_top(closure).wait()?;
+    thread.set_global(module_id, typ, (*metadata).clone(), value.get_value())?;
+    info!("Loaded module `{}` from bytes", name);
+    Ok(())
+}
+
 pub fn run_io<'vm, T, E>(
     vm: T,
     v: ExecuteValue<RootedThread, E>,
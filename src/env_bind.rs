@@ -0,0 +1,120 @@
+//! Module containing bindings for inspecting and modifying the environment of the process.
+
+use crate::real_std::env;
+use crate::real_std::ffi::OsString;
+use crate::real_std::result::Result as StdResult;
+
+use crate::vm::{api::IO, thread::Thread, ExternModule, Result};
+
+/// `env::set_var` and `env::remove_var` panic on keys and values which the platform can't
+/// represent so those are checked before calling them
+fn check_key(key: &str) -> StdResult<(), String> {
+    if key.is_empty() || key.contains('=') || key.contains('\0') {
+        Err(format!("Invalid environment variable name `{}`", key))
+    } else {
+        Ok(())
+    }
+}
+
+fn into_string(value: OsString, what: &str) -> IO<String> {
+    value
+        .into_string()
+        .map_err(|value| format!("{} is not valid unicode: {:?}", what, value))
+        .into()
+}
+
+fn var(key: &str) -> IO<Option<String>> {
+    IO::Value(env::var_os(key).and_then(|value| value.into_string().ok()))
+}
+
+fn vars() -> IO<Vec<(String, String)>> {
+    IO::Value(
+        env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+            .collect(),
+    )
+}
+
+fn set_var(key: &str, value: &str) -> IO<()> {
+    let result = check_key(key).and_then(|()| {
+        if value.contains('\0') {
+            Err(format!(
+                "Value of environment variable `{}` may not contain NUL",
+                key
+            ))
+        } else {
+            env::set_var(key, value);
+            Ok(())
+        }
+    });
+    result.into()
+}
+
+fn remove_var(key: &str) -> IO<()> {
+    check_key(key).map(|()| env::remove_var(key)).into()
+}
+
+fn current_dir() -> IO<String> {
+    match env::current_dir() {
+        Ok(dir) => into_string(dir.into_os_string(), "The current directory"),
+        Err(err) => IO::Error(err.into()),
+    }
+}
+
+fn set_current_dir(dir: &str) -> IO<()> {
+    env::set_current_dir(dir).into()
+}
+
+fn args() -> IO<Vec<String>> {
+    let mut args = Vec::new();
+    for arg in env::args_os() {
+        match into_string(arg, "Argument") {
+            IO::Value(arg) => args.push(arg),
+            IO::Exception(err) => return IO::Exception(err),
+            IO::Error(err) => return IO::Error(err),
+        }
+    }
+    IO::Value(args)
+}
+
+mod std {
+    pub mod env {
+        pub use crate::env_bind as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            var => primitive!(1, std::env::prim::var),
+            vars => primitive!(0, std::env::prim::vars),
+            set_var => primitive!(2, std::env::prim::set_var),
+            remove_var => primitive!(1, std::env::prim::remove_var),
+            current_dir => primitive!(0, std::env::prim::current_dir),
+            set_current_dir => primitive!(1, std::env::prim::set_current_dir),
+            args => primitive!(0, std::env::prim::args)
+        },
+    )
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use crate::real_std::ffi::OsStr;
+    use crate::real_std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn var_returns_none_for_invalid_unicode() {
+        let key = "GLUON_ENV_BIND_INVALID_UNICODE";
+        env::set_var(key, OsStr::from_bytes(&[0x66, 0x6f, 0x80]));
+        assert_eq!(var(key), IO::Value(None));
+        match vars() {
+            IO::Value(vars) => assert!(vars.iter().all(|(k, _)| k != key)),
+            IO::Exception(err) => panic!("{}", err),
+            IO::Error(err) => panic!("{}", err),
+        }
+        env::remove_var(key);
+    }
+}
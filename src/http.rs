@@ -286,6 +286,13 @@ fn listen(
                                         .body("".into())
                                         .unwrap())
                                 }
+                                IO::Error(err) => {
+                                    info!("{}", err);
+                                    Ok(http::Response::builder()
+                                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                                        .body("".into())
+                                        .unwrap())
+                                }
                             }
                         }
                         Err(err) => {
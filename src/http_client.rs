@@ -0,0 +1,369 @@
+//! Module containing bindings for making HTTP requests.
+//!
+//! Only plain `http` URLs are supported at the moment.
+
+extern crate hyper;
+extern crate tokio_executor;
+extern crate tokio_timer;
+
+use crate::real_std::{
+    result::Result as StdResult,
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{self, Either},
+    sync::oneshot,
+    Future, Stream,
+};
+
+use self::{
+    hyper::{
+        client::HttpConnector,
+        header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, LOCATION},
+        Body, Client, Method, StatusCode, Uri,
+    },
+    tokio_executor::DefaultExecutor,
+};
+
+use crate::vm::{self, api::IO, thread::Thread, types::VmInt, ExternModule};
+
+/// The maximum number of redirects that are followed before giving up with `TooManyRedirects`
+const MAX_REDIRECTS: usize = 10;
+
+/// The statuses of redirect responses that are followed
+const REDIRECT_STATUSES: [StatusCode; 5] = [
+    StatusCode::MOVED_PERMANENTLY,
+    StatusCode::FOUND,
+    StatusCode::SEE_OTHER,
+    StatusCode::TEMPORARY_REDIRECT,
+    StatusCode::PERMANENT_REDIRECT,
+];
+
+#[derive(Getable, VmType)]
+#[gluon(vm_type = "std.http.client.types.Request")]
+#[gluon(crate_name = "::vm")]
+struct Request {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+    body: String,
+    timeout: Option<f64>,
+    follow_redirects: bool,
+}
+
+#[derive(Debug, Pushable, VmType)]
+#[gluon(vm_type = "std.http.client.types.Response")]
+#[gluon(crate_name = "::vm")]
+struct Response {
+    status: VmInt,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+#[derive(Debug, Pushable, VmType)]
+#[gluon(vm_type = "std.http.client.types.HttpError")]
+#[gluon(crate_name = "::vm")]
+enum HttpError {
+    InvalidUrl(String),
+    InvalidRequest(String),
+    ConnectionFailed(String),
+    Timeout,
+    TooManyRedirects,
+    InvalidResponse(String),
+}
+
+impl From<hyper::Error> for HttpError {
+    fn from(err: hyper::Error) -> Self {
+        if err.is_connect() {
+            HttpError::ConnectionFailed(err.to_string())
+        } else {
+            HttpError::InvalidResponse(err.to_string())
+        }
+    }
+}
+
+/// Spawns the background tasks of the client onto the tokio runtime driving the VM. If there is
+/// no such runtime, as when the VM is run synchronously, each task is run on its own OS thread
+/// instead.
+#[derive(Clone, Copy)]
+struct Executor;
+
+impl<F> future::Executor<F> for Executor
+where
+    F: Future<Item = (), Error = ()> + Send + 'static,
+{
+    fn execute(&self, future: F) -> StdResult<(), future::ExecuteError<F>> {
+        future::Executor::execute(&DefaultExecutor::current(), future).or_else(|err| {
+            let future = err.into_future();
+            thread::spawn(move || future.wait());
+            Ok(())
+        })
+    }
+}
+
+/// Resolves once `deadline` has passed. Like `std.time.sleep` this falls back to blocking a
+/// separate OS thread if the timer of a tokio runtime is not available.
+fn delay(deadline: Instant) -> impl Future<Item = (), Error = HttpError> {
+    tokio_timer::Delay::new(deadline).or_else(move |err| {
+        if err.is_shutdown() {
+            let (sender, receiver) = oneshot::channel();
+            thread::spawn(move || {
+                if let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                    thread::sleep(remaining);
+                }
+                let _ = sender.send(());
+            });
+            Either::A(receiver.map_err(|_| HttpError::Timeout))
+        } else {
+            Either::B(future::err(HttpError::InvalidRequest(err.to_string())))
+        }
+    })
+}
+
+/// The parts of a request which are needed to send it again when following a redirect
+struct Outgoing {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+}
+
+impl Outgoing {
+    fn new(request: Request) -> StdResult<Self, HttpError> {
+        let uri = parse_uri(&request.uri)?;
+        let method = Method::from_bytes(request.method.as_bytes()).map_err(|_| {
+            HttpError::InvalidRequest(format!("Invalid method `{}`", request.method))
+        })?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in request.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                HttpError::InvalidRequest(format!("Invalid header name `{}`", name))
+            })?;
+            let value = HeaderValue::from_str(&value).map_err(|_| {
+                HttpError::InvalidRequest(format!("Invalid value for header `{}`", name))
+            })?;
+            headers.append(name, value);
+        }
+        Ok(Outgoing {
+            method,
+            uri,
+            headers,
+            body: request.body,
+        })
+    }
+
+    fn to_request(&self) -> hyper::Request<Body> {
+        let mut request = hyper::Request::new(Body::from(self.body.clone()));
+        *request.method_mut() = self.method.clone();
+        *request.uri_mut() = self.uri.clone();
+        *request.headers_mut() = self.headers.clone();
+        request
+    }
+
+    /// Returns the request to send after receiving a redirect with `status` to `uri`
+    fn redirect(mut self, status: StatusCode, uri: Uri) -> Self {
+        let use_get = match status {
+            StatusCode::SEE_OTHER => self.method != Method::HEAD,
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => self.method == Method::POST,
+            _ => false,
+        };
+        if use_get {
+            self.method = Method::GET;
+            self.body.clear();
+            self.headers.remove(CONTENT_LENGTH);
+            self.headers.remove(CONTENT_TYPE);
+        }
+        self.uri = uri;
+        self
+    }
+}
+
+fn parse_uri(uri: &str) -> StdResult<Uri, HttpError> {
+    let parsed: Uri = uri
+        .parse()
+        .map_err(|err| HttpError::InvalidUrl(format!("`{}`: {}", uri, err)))?;
+    match parsed.scheme_str() {
+        Some("http") => (),
+        Some(scheme) => {
+            return Err(HttpError::InvalidUrl(format!(
+                "`{}`: Unsupported scheme `{}`, only `http` is supported",
+                uri, scheme
+            )));
+        }
+        None => {
+            return Err(HttpError::InvalidUrl(format!(
+                "`{}`: Expected an absolute URL",
+                uri
+            )));
+        }
+    }
+    Ok(parsed)
+}
+
+/// Resolves the `Location` of a redirect against the URL that was requested
+fn resolve_location(base: &Uri, location: &HeaderValue) -> StdResult<Uri, HttpError> {
+    let location = location
+        .to_str()
+        .map_err(|err| HttpError::InvalidResponse(format!("Invalid redirect location: {}", err)))?;
+    if location.contains("://") {
+        return parse_uri(location);
+    }
+
+    let (path, query) = location.split_at(location.find('?').unwrap_or(location.len()));
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        let base_path = base.path();
+        let dir_end = base_path.rfind('/').map_or(0, |i| i + 1);
+        format!("{}{}", &base_path[..dir_end], path)
+    };
+    let authority = base
+        .authority_part()
+        .map_or("", |authority| authority.as_str());
+    parse_uri(&format!(
+        "http://{}{}{}",
+        authority,
+        remove_dot_segments(&path),
+        query
+    ))
+}
+
+/// Removes the `.` and `..` segments from an absolute path
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments = Vec::new();
+    let mut parts = path.split('/').skip(1).peekable();
+    while let Some(segment) = parts.next() {
+        match segment {
+            "." | ".." => {
+                if segment == ".." {
+                    segments.pop();
+                }
+                // A trailing `.` or `..` still refers to a directory
+                if parts.peek().is_none() {
+                    segments.push("");
+                }
+            }
+            _ => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+fn fetch(
+    client: Client<HttpConnector>,
+    outgoing: Outgoing,
+    follow_redirects: bool,
+    redirects: usize,
+) -> Box<dyn Future<Item = hyper::Response<Body>, Error = HttpError> + Send> {
+    Box::new(
+        client
+            .request(outgoing.to_request())
+            .map_err(HttpError::from)
+            .and_then(move |response| {
+                let status = response.status();
+                let is_redirect = REDIRECT_STATUSES.contains(&status);
+                let location = match response.headers().get(LOCATION) {
+                    Some(location) if follow_redirects && is_redirect => location,
+                    _ => return Either::A(future::ok(response)),
+                };
+                if redirects == MAX_REDIRECTS {
+                    return Either::A(future::err(HttpError::TooManyRedirects));
+                }
+                let uri = match resolve_location(&outgoing.uri, location) {
+                    Ok(uri) => uri,
+                    Err(err) => return Either::A(future::err(err)),
+                };
+                Either::B(fetch(
+                    client,
+                    outgoing.redirect(status, uri),
+                    follow_redirects,
+                    redirects + 1,
+                ))
+            }),
+    )
+}
+
+fn read_response(
+    response: hyper::Response<Body>,
+) -> impl Future<Item = Response, Error = HttpError> {
+    let (parts, body) = response.into_parts();
+    body.concat2()
+        .map_err(HttpError::from)
+        .and_then(move |chunk| {
+            let body = String::from_utf8(chunk.to_vec()).map_err(|err| {
+                HttpError::InvalidResponse(format!("The body is not valid UTF-8: {}", err))
+            })?;
+            let headers = parts
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.as_str().to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect();
+            Ok(Response {
+                status: VmInt::from(parts.status.as_u16()),
+                headers,
+                body,
+            })
+        })
+}
+
+fn send(
+    request: Request,
+) -> impl Future<Item = IO<Result<Response, HttpError>>, Error = vm::Error> {
+    let timeout = match request.timeout {
+        Some(secs) if secs >= 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+        Some(secs) => {
+            return Either::A(future::ok(IO::Value(Err(HttpError::InvalidRequest(
+                format!("Expected a finite, non-negative timeout, got {}", secs),
+            )))));
+        }
+        None => None,
+    };
+    let follow_redirects = request.follow_redirects;
+    let outgoing = match Outgoing::new(request) {
+        Ok(outgoing) => outgoing,
+        Err(err) => return Either::A(future::ok(IO::Value(Err(err)))),
+    };
+
+    let client = Client::builder()
+        .keep_alive(false)
+        .executor(Executor)
+        .build_http();
+    let response = fetch(client, outgoing, follow_redirects, 0).and_then(read_response);
+    let response = match timeout {
+        Some(timeout) => Either::A(response.select2(delay(Instant::now() + timeout)).then(
+            |result| match result {
+                Ok(Either::A((response, _))) => Ok(response),
+                Ok(Either::B(((), _))) => Err(HttpError::Timeout),
+                Err(Either::A((err, _))) | Err(Either::B((err, _))) => Err(err),
+            },
+        )),
+        None => Either::B(response),
+    };
+    Either::B(response.then(|result| Ok(IO::Value(result))))
+}
+
+mod std {
+    pub mod http {
+        pub mod client {
+            pub use crate::http_client as prim;
+        }
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    use self::std;
+
+    ExternModule::new(
+        vm,
+        record! {
+            send => primitive!(1, async fn std::http::client::prim::send)
+        },
+    )
+}
@@ -18,7 +18,7 @@ use itertools::Itertools;
 use crate::base::ast::{expr_to_path, Expr, Literal, SpannedExpr, Typed, TypedIdent};
 use crate::base::error::{Errors, InFile};
 use crate::base::filename_to_module;
-use crate::base::fnv::FnvMap;
+use crate::base::fnv::{FnvMap, FnvSet};
 use crate::base::pos::{self, BytePos, Span};
 use crate::base::symbol::Symbol;
 use crate::base::types::{ArcType, Type};
@@ -121,6 +121,17 @@ impl Importer for DefaultImporter {
 enum UnloadedModule {
     Source(Cow<'static, str>),
     Extern(ExternModule),
+    /// The module is a submodule of the extern module `parent` which had not been loaded yet
+    Submodule {
+        parent: String,
+        module: ExternModule,
+    },
+}
+
+struct ModuleSource {
+    source: String,
+    /// Set when the source is replaced so that the module is compiled again on the next import
+    stale: bool,
 }
 
 /// Macro which rewrites occurances of `import! "filename"` to a load of that file if it is not
@@ -130,6 +141,12 @@ pub struct Import<I = DefaultImporter> {
     pub loaders: RwLock<FnvMap<String, ExternLoader>>,
     pub importer: I,
 
+    /// Sources of modules which do not exist as files, see `add_module_source`
+    sources: RwLock<FnvMap<String, ModuleSource>>,
+
+    /// Map from each module to the modules which imported it
+    dependents: RwLock<FnvMap<String, FnvSet<String>>>,
+
     /// Map of modules currently being loaded
     loading: Mutex<FnvMap<String, future::Shared<oneshot::Receiver<()>>>>,
 }
@@ -141,6 +158,8 @@ impl<I> Import<I> {
             paths: RwLock::new(vec![PathBuf::from(".")]),
             loaders: RwLock::default(),
             importer: importer,
+            sources: RwLock::default(),
+            dependents: RwLock::default(),
             loading: Mutex::default(),
         }
     }
@@ -161,14 +180,59 @@ impl<I> Import<I> {
             .insert(String::from(module), loader);
     }
 
+    /// Makes `import! module` compile `source` instead of searching for the module in the
+    /// standard library and the paths. Errors in the module are reported with `module` as the
+    /// filename.
+    ///
+    /// If `module` has already been imported it is compiled again the next time it is imported
+    /// with the new source. Modules which imported the old module keep using it until they are
+    /// compiled again themselves.
+    pub fn add_module_source(&self, module: &str, source: &str) {
+        let mut sources = self.sources.write().unwrap();
+        match sources.get(module) {
+            Some(old) if old.source == source => return,
+            _ => (),
+        }
+        sources.insert(
+            String::from(module),
+            ModuleSource {
+                source: String::from(source),
+                stale: true,
+            },
+        );
+    }
+
     pub fn modules(&self) -> Vec<Cow<'static, str>> {
         STD_LIBS
             .iter()
             .map(|t| Cow::Borrowed(t.0))
             .chain(self.loaders.read().unwrap().keys().cloned().map(Cow::Owned))
+            .chain(self.sources.read().unwrap().keys().cloned().map(Cow::Owned))
             .collect()
     }
 
+    /// Unloads `module` along with every module which imported it, directly or indirectly, so
+    /// that they are compiled again from their current sources the next time they are imported.
+    pub fn invalidate_module(&self, vm: &Thread, module: &str) {
+        let mut dependents = self.dependents.write().unwrap();
+        let mut pending = vec![module.to_string()];
+        while let Some(module) = pending.pop() {
+            vm.global_env().remove_global(&module);
+            // The edges are added back when the dependents are imported again
+            if let Some(importers) = dependents.remove(&module) {
+                pending.extend(importers);
+            }
+        }
+    }
+
+    /// Returns `true` if the source of `module` has been replaced since it was last imported
+    fn take_stale_source(&self, module: &str) -> bool {
+        match self.sources.write().unwrap().get_mut(module) {
+            Some(source) => mem::replace(&mut source.stale, false),
+            None => false,
+        }
+    }
+
     fn get_unloaded_module(
         &self,
         vm: &Thread,
@@ -177,6 +241,10 @@ impl<I> Import<I> {
     ) -> Result<UnloadedModule, MacroError> {
         let mut buffer = String::new();
 
+        if let Some(source) = self.sources.read().unwrap().get(module) {
+            return Ok(UnloadedModule::Source(Cow::Owned(source.source.clone())));
+        }
+
         // Retrieve the source, first looking in the standard library included in the
         // binary
 
@@ -193,6 +261,21 @@ impl<I> Import<I> {
                         let value = loader(vm)?;
                         return Ok(UnloadedModule::Extern(value));
                     }
+
+                    let mut parent = module;
+                    while let Some(i) = parent.rfind('.') {
+                        parent = &parent[..i];
+                        if vm.global_env().global_exists(parent) {
+                            break;
+                        }
+                        if let Some(loader) = loaders.get_mut(parent) {
+                            let value = loader(vm)?;
+                            return Ok(UnloadedModule::Submodule {
+                                parent: parent.to_string(),
+                                module: value,
+                            });
+                        }
+                    }
                 }
                 let paths = self.paths.read().unwrap();
                 let file = paths
@@ -313,13 +396,23 @@ impl<I> Import<I> {
             .map_err(|err| (None, err.into()))?;
 
         match unloaded_module {
-            UnloadedModule::Extern(ExternModule {
-                value,
-                typ,
-                metadata,
-            }) => {
-                vm.set_global(module_id.clone(), typ, metadata, value.get_value())
-                    .map_err(|err| (None, err.into()))?;
+            UnloadedModule::Extern(module) => {
+                set_extern_module(vm, module_id, module).map_err(|err| (None, err.into()))?;
+            }
+            UnloadedModule::Submodule { parent, module } => {
+                let parent_id = Symbol::from(format!("@{}", parent));
+                set_extern_module(vm, &parent_id, module).map_err(|err| (None, err.into()))?;
+                if !vm.global_env().global_exists(&modulename) {
+                    return Err((
+                        None,
+                        Error::String(format!(
+                            "Module '{}' does not have a submodule named '{}'",
+                            parent,
+                            &modulename[parent.len() + 1..]
+                        ))
+                        .into(),
+                    ));
+                }
             }
             UnloadedModule::Source(file_contents) => {
                 // Modules marked as this would create a cyclic dependency if they included the implicit
@@ -370,7 +463,26 @@ impl<I> Import<I> {
     }
 }
 
+/// Sets `module` as the global `module_id` along with each of its submodules
+fn set_extern_module(vm: &Thread, module_id: &Symbol, module: ExternModule) -> vm::Result<()> {
+    let submodules = module.submodules();
+    let ExternModule {
+        value,
+        typ,
+        metadata,
+    } = module;
+    vm.set_global(module_id.clone(), typ, metadata, value.get_value())?;
+
+    for (name, submodule) in submodules {
+        let submodule_id = Symbol::from(format!("@{}.{}", module_id.definition_name(), name));
+        set_extern_module(vm, &submodule_id, submodule)?;
+    }
+    Ok(())
+}
+
 /// Adds an extern module to `thread`, letting it be loaded with `import! name` from gluon code.
+/// Fields of the module which are records themselves are exposed as submodules, so a `nested`
+/// field can be loaded directly with `import! name.nested`.
 ///
 /// ```
 /// extern crate gluon;
@@ -419,18 +531,57 @@ where
     add_extern_module_(thread, name, Box::new(loader))
 }
 
-fn add_extern_module_(thread: &Thread, name: &str, loader: ExternLoader) {
+fn with_import<R>(thread: &Thread, f: impl FnOnce(&Import) -> R) -> R {
     let opt_macro = thread.get_macros().get("import");
     let import = opt_macro
         .as_ref()
         .and_then(|mac| mac.downcast_ref::<Import>())
         .unwrap_or_else(|| {
             ice!(
-                "Can't add an extern module with a import macro. \
+                "Can't find the import macro. \
                  Did you mean to create this `Thread` with `gluon::new_vm`"
             )
         });
-    import.add_loader(name, loader);
+    f(import)
+}
+
+fn add_extern_module_(thread: &Thread, name: &str, loader: ExternLoader) {
+    with_import(thread, |import| import.add_loader(name, loader))
+}
+
+/// Adds `source` as the source of the module `name`, letting it be loaded with `import! name`
+/// without a file existing for it. See `Import::add_module_source`.
+///
+/// ```
+/// extern crate gluon;
+///
+/// use gluon::import::add_module_source;
+/// use gluon::Compiler;
+///
+/// fn main_() -> gluon::Result<()> {
+///     let thread = gluon::new_vm();
+///     add_module_source(&thread, "my.module", r#" { message = "Hello World!" } "#);
+///     let script = r#"
+///         let module = import! my.module
+///         module.message
+///     "#;
+///     let (result, _) = Compiler::new().run_expr::<String>(&thread, "example", script)?;
+///     assert_eq!(result, "Hello World!");
+///     Ok(())
+/// }
+/// fn main() {
+///     if let Err(err) = main_() {
+///         panic!("{}", err)
+///     }
+/// }
+/// ```
+pub fn add_module_source(thread: &Thread, name: &str, source: &str) {
+    with_import(thread, |import| import.add_module_source(name, source))
+}
+
+/// Unloads `module` and the modules depending on it, see `Import::invalidate_module`
+pub fn invalidate_module(thread: &Thread, module: &str) {
+    with_import(thread, |import| import.invalidate_module(thread, module))
 }
 
 macro_rules! add_extern_module_if {
@@ -495,9 +646,10 @@ where
                     format!("@{}", filename_to_module(filename))
                 }
                 _ => {
-                    return Err(
-                        Error::String("Expected a string literal or path to import".into()).into(),
-                    );
+                    return Err(Error::String(
+                        "Expected a string literal or path to import".into(),
+                    )
+                    .into());
                 }
             };
             Ok(modulename)
@@ -518,6 +670,20 @@ where
             format!("@{}", modulename)
         });
 
+        if self.take_stale_source(name.definition_name()) {
+            vm.global_env().remove_global(name.definition_name());
+        }
+
+        // Remember which module is importing `name` so that it can be unloaded along with `name`
+        if let Some(importer) = get_state(macros).visited.last() {
+            self.dependents
+                .write()
+                .unwrap()
+                .entry(name.definition_name().to_string())
+                .or_insert_with(FnvSet::default)
+                .insert(filename_to_module(importer));
+        }
+
         // Only load the script if it is not already loaded
         debug!("Import '{}' {:?}", modulename, get_state(macros).visited);
         if !vm.global_env().global_exists(&modulename) {
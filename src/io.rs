@@ -12,11 +12,11 @@ use futures::{
 
 use crate::vm::api::generic::{A, B};
 use crate::vm::api::{
-    Getable, OpaqueValue, OwnedFunction, RuntimeResult, TypedBytecode, WithVM, IO,
+    Getable, IOError, OpaqueValue, OwnedFunction, Pushable, RuntimeResult, TypedBytecode, VmType,
+    WithVM, IO,
 };
-use crate::vm::internal::ValuePrinter;
 use crate::vm::stack::{self, StackFrame};
-use crate::vm::thread::{RootedThread, Thread, ThreadInternal};
+use crate::vm::thread::{RenderOptions, RootedThread, Thread, ThreadInternal};
 use crate::vm::types::*;
 use crate::vm::{self, ExternModule, Result};
 
@@ -37,7 +37,7 @@ fn println(s: &str) -> IO<()> {
 fn flush_stdout() -> IO<()> {
     match io::stdout().flush() {
         Ok(_) => IO::Value(()),
-        Err(err) => IO::Exception(err.to_string()),
+        Err(err) => IO::Error(err.into()),
     }
 }
 
@@ -95,17 +95,17 @@ fn open_file_with(path: &str, opts: Vec<OpenOptions>) -> IO<GluonFile> {
         };
     }
 
-    open_with
-        .open(path)
-        .map(|file| GluonFile(Mutex::new(Some(file))))
-        .into()
+    match open_with.open(path) {
+        Ok(file) => IO::Value(GluonFile(Mutex::new(Some(file)))),
+        Err(err) => IO::Error(err.into()),
+    }
 }
 
 fn read_file_to_array(s: &str) -> IO<Vec<u8>> {
     let mut buffer = Vec::new();
     match File::open(s).and_then(|mut file| file.read_to_end(&mut buffer)) {
         Ok(_) => IO::Value(buffer),
-        Err(err) => IO::Exception(err.to_string()),
+        Err(err) => IO::Error(err.into()),
     }
 }
 
@@ -113,12 +113,7 @@ fn read_file_to_string(s: &str) -> IO<String> {
     let mut buffer = String::new();
     match File::open(s).and_then(|mut file| file.read_to_string(&mut buffer)) {
         Ok(_) => IO::Value(buffer),
-        Err(err) => {
-            use crate::real_std::fmt::Write;
-            buffer.clear();
-            let _ = write!(&mut buffer, "{}", err);
-            IO::Exception(buffer)
-        }
+        Err(err) => IO::Error(err.into()),
     }
 }
 
@@ -135,7 +130,7 @@ fn read_file(file: &GluonFile, count: usize) -> IO<RuntimeResult<Option<Vec<u8>>
                 buffer.truncate(bytes_read);
                 IO::Value(RuntimeResult::Return(Some(buffer)))
             }
-            Err(err) => IO::Exception(format!("{}", err)),
+            Err(err) => IO::Error(err.into()),
         }
     }
 }
@@ -147,7 +142,7 @@ fn read_file_to_end(file: &GluonFile) -> IO<RuntimeResult<Vec<u8>, String>> {
 
     match file.read_to_end(&mut buf) {
         Ok(_) => IO::Value(RuntimeResult::Return(buf)),
-        Err(err) => IO::Exception(err.to_string()),
+        Err(err) => IO::Error(err.into()),
     }
 }
 
@@ -177,7 +172,7 @@ fn write_slice_file(
 
     match file.write(&buf[start..end]) {
         Ok(bytes_written) => IO::Value(RuntimeResult::Return(bytes_written)),
-        Err(why) => IO::Exception(why.to_string()),
+        Err(why) => IO::Error(why.into()),
     }
 }
 
@@ -186,7 +181,7 @@ fn flush_file(file: &GluonFile) -> IO<RuntimeResult<(), String>> {
 
     match unwrap_file!(file).flush() {
         Ok(_) => IO::Value(RuntimeResult::Return(())),
-        Err(why) => IO::Exception(why.to_string()),
+        Err(why) => IO::Error(why.into()),
     }
 }
 
@@ -194,7 +189,10 @@ fn close_file(file: &GluonFile) -> IO<()> {
     let mut file = file.0.lock().unwrap();
 
     match file.take() {
-        Some(mut file) => file.flush().into(),
+        Some(mut file) => match file.flush() {
+            Ok(()) => IO::Value(()),
+            Err(err) => IO::Error(err.into()),
+        },
         None => IO::Value(()),
     }
 }
@@ -209,7 +207,7 @@ fn read_char() -> IO<char> {
             Ok(b) => crate::real_std::char::from_u32(b as u32)
                 .map(IO::Value)
                 .unwrap_or_else(|| IO::Exception("Not a valid char".into())),
-            Err(err) => IO::Exception(format!("{}", err)),
+            Err(err) => IO::Error(err.into()),
         },
         None => IO::Exception("No read".into()),
     }
@@ -219,20 +217,81 @@ fn read_line() -> IO<String> {
     let mut buffer = String::new();
     match io::stdin().read_line(&mut buffer) {
         Ok(_) => IO::Value(buffer),
-        Err(err) => {
-            use crate::real_std::fmt::Write;
-            buffer.clear();
-            let _ = write!(&mut buffer, "{}", err);
-            IO::Exception(buffer)
-        }
+        Err(err) => IO::Error(err.into()),
     }
 }
 
 /// IO a -> (String -> IO a) -> IO a
 fn catch<'vm>(
     action: OpaqueValue<&'vm Thread, IO<A>>,
-    mut catch: OwnedFunction<fn(String) -> IO<OpaqueValue<RootedThread, A>>>,
+    catch: OwnedFunction<fn(String) -> IO<OpaqueValue<RootedThread, A>>>,
 ) -> impl Future<Item = IO<OpaqueValue<RootedThread, A>>, Error = vm::Error> {
+    catch_with(action, catch, |err| format!("{}", err))
+}
+
+/// IO a -> (IOError -> IO a) -> IO a
+fn catch_error<'vm>(
+    action: OpaqueValue<&'vm Thread, IO<A>>,
+    catch: OwnedFunction<fn(IOError) -> IO<OpaqueValue<RootedThread, A>>>,
+) -> impl Future<Item = IO<OpaqueValue<RootedThread, A>>, Error = vm::Error> {
+    catch_with(action, catch, |err| match err {
+        vm::Error::IO(err, _) => err,
+        vm::Error::Panic(msg, _) => IOError::Other(msg),
+        err => IOError::Other(err.to_string()),
+    })
+}
+
+type Frame = record_type! {
+    name => String,
+    module => Option<String>,
+    line => Option<VmInt>
+};
+
+type ErrorWithStacktrace = record_type! {
+    message => String,
+    stacktrace => Vec<Frame>
+};
+
+/// IO a -> ({ message : String, stacktrace : Array StackFrame } -> IO a) -> IO a
+fn catch_with_stacktrace<'vm>(
+    action: OpaqueValue<&'vm Thread, IO<A>>,
+    catch: OwnedFunction<fn(ErrorWithStacktrace) -> IO<OpaqueValue<RootedThread, A>>>,
+) -> impl Future<Item = IO<OpaqueValue<RootedThread, A>>, Error = vm::Error> {
+    catch_with(action, catch, |err| {
+        let stacktrace = err
+            .stacktrace()
+            .into_iter()
+            .flat_map(|stacktrace| stacktrace.frames.iter())
+            .filter_map(|frame| frame.as_ref())
+            .map(|frame| {
+                record_no_decl! {
+                    name => frame.name.declared_name().to_string(),
+                    module => frame.source_name.clone(),
+                    line => frame.line.map(|line| line.to_usize() as VmInt + 1)
+                }
+            })
+            .collect();
+        let message = match err {
+            vm::Error::Panic(msg, _) => msg,
+            vm::Error::IO(err, _) => err.to_string(),
+            err => err.to_string(),
+        };
+        record_no_decl! {
+            message => message,
+            stacktrace => stacktrace
+        }
+    })
+}
+
+/// Runs `action`, calling `catch` with the error converted by `to_error` if it fails
+fn catch_with<'vm, E>(
+    action: OpaqueValue<&'vm Thread, IO<A>>,
+    mut catch: OwnedFunction<fn(E) -> IO<OpaqueValue<RootedThread, A>>>,
+    to_error: fn(vm::Error) -> E,
+) -> impl Future<Item = IO<OpaqueValue<RootedThread, A>>, Error = vm::Error>
+where
+    E: for<'a> Pushable<'a> + VmType + Send + 'static,
+{
     let vm = action.vm().root_thread();
     let frame_level = vm.context().frame_level();
     let mut action: OwnedFunction<fn(()) -> OpaqueValue<RootedThread, A>> =
@@ -240,6 +299,8 @@ fn catch<'vm>(
 
     action.call_fast_async(()).then(move |result| match result {
         Ok(value) => Either::A(future::ok(IO::Value(value))),
+        // Interrupts must stop the whole thread so they are not caught
+        Err(err @ vm::Error::Interrupted) => Either::A(future::err(err)),
         Err(err) => {
             {
                 let mut context = vm.context();
@@ -255,7 +316,7 @@ fn catch<'vm>(
                 let len = stack.len();
                 stack.pop_many(len - 2);
             }
-            Either::B(catch.call_fast_async(format!("{}", err)).then(|result| {
+            Either::B(catch.call_fast_async(to_error(err)).then(|result| {
                 Ok(match result {
                     Ok(value) => value,
                     Err(err) => IO::Exception(format!("{}", err)),
@@ -265,6 +326,55 @@ fn catch<'vm>(
     })
 }
 
+/// a -> (a -> IO ()) -> (a -> IO b) -> IO b
+fn using_resource<'vm>(
+    resource: OpaqueValue<&'vm Thread, A>,
+    mut close: OwnedFunction<fn(OpaqueValue<RootedThread, A>) -> IO<()>>,
+    mut body: OwnedFunction<fn(OpaqueValue<RootedThread, A>) -> IO<OpaqueValue<RootedThread, B>>>,
+) -> impl Future<Item = IO<OpaqueValue<RootedThread, B>>, Error = vm::Error> {
+    let vm = resource.vm().root_thread();
+    let frame_level = vm.context().frame_level();
+    let resource: OpaqueValue<RootedThread, A> = Getable::from_value(&vm, resource.get_variant());
+
+    body.call_fast_async(resource.clone())
+        .then(move |result| match result {
+            Ok(value) => Either::A(close.call_fast_async(resource).then(|close_result| {
+                Ok(match close_result {
+                    Ok(IO::Value(())) => value,
+                    Ok(IO::Exception(err)) => IO::Exception(err),
+                    Ok(IO::Error(err)) => IO::Error(err),
+                    Err(err) => IO::Exception(format!("{}", err)),
+                })
+            })),
+            // Interrupts must stop the whole thread so no cleanup is run
+            Err(err @ vm::Error::Interrupted) => Either::B(Either::A(future::err(err))),
+            Err(err) => {
+                {
+                    let mut context = vm.context();
+                    {
+                        let stack = context.stack_frame::<stack::State>();
+
+                        if let Err(err) = crate::vm::thread::reset_stack(stack, frame_level) {
+                            return Either::B(Either::A(future::ok(IO::Exception(
+                                err.to_string().into(),
+                            ))));
+                        }
+                    }
+
+                    let mut stack = context.stack_frame::<stack::State>();
+                    let len = stack.len();
+                    stack.pop_many(len - 3);
+                }
+                // The error of `body` is raised even if closing the resource fails as well
+                Either::B(Either::B(
+                    close
+                        .call_fast_async(resource)
+                        .then(move |_| Ok(IO::Exception(format!("{}", err)))),
+                ))
+            }
+        })
+}
+
 fn throw(msg: String) -> IO<OpaqueValue<RootedThread, A>> {
     IO::Exception(msg)
 }
@@ -276,13 +386,14 @@ fn clear_frames<T>(mut err: Error, stack: StackFrame) -> IO<T> {
     };
     match err {
         // Ignore the stacktrace as we take a more specific range of the stack here
-        Error::VM(vm::Error::Panic(_, ref mut trace)) => *trace = Some(new_trace),
+        Error::VM(vm::Error::Panic(_, ref mut trace))
+        | Error::VM(vm::Error::IO(_, ref mut trace)) => *trace = Some(new_trace),
         _ => (),
     }
     IO::Exception(err.to_string())
 }
 
-field_decl! { value, typ }
+field_decl! { value, typ, name, module, line, message, stacktrace }
 
 // Can't create a minimal reproduction for why this reports as being unused...
 #[allow(dead_code)]
@@ -300,11 +411,9 @@ fn run_expr(
             let stack = context.stack_frame::<stack::State>();
             Ok(match run_result {
                 Ok(execute_value) => {
-                    let env = vm.global_env().get_env();
                     let typ = execute_value.typ;
-                    let debug_level = vm.global_env().get_debug_level();
                     IO::Value(record_no_decl!{
-                        value => ValuePrinter::new(&*env, &typ, execute_value.value.get_variant(), &debug_level).width(80).to_string(),
+                        value => vm.render_value(&typ, execute_value.value.get_variant(), &RenderOptions::default()),
                         typ => typ.to_string()
                     })
                 }
@@ -338,6 +447,23 @@ mod std {
     }
 }
 
+fn show_io_error(err: IOError) -> String {
+    err.to_string()
+}
+
+pub fn load_error(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
+    ExternModule::new(
+        vm,
+        record! {
+            catch_error => primitive!(2, async fn std::io::prim::catch_error),
+            catch_with_stacktrace => primitive!(2, async fn std::io::prim::catch_with_stacktrace),
+            show_io_error => primitive!(1, std::io::prim::show_io_error),
+        },
+    )
+}
+
 pub fn load(vm: &Thread) -> Result<ExternModule> {
     vm.register_type::<GluonFile>("File", &[])?;
 
@@ -382,6 +508,7 @@ pub fn load(vm: &Thread) -> Result<ExternModule> {
             eprint => primitive!(1, std::io::prim::eprint),
             eprintln => primitive!(1, std::io::prim::eprintln),
             catch => primitive!(2, async fn std::io::prim::catch),
+            using_resource => primitive!(3, async fn std::io::prim::using_resource),
             throw => primitive!(1, std::io::prim::throw),
             run_expr => primitive!(1, async fn std::io::prim::run_expr),
             load_script => primitive!(2, async fn std::io::prim::load_script),
@@ -5,6 +5,7 @@
 //! [tutorial](http://gluon-lang.org/book/index.html) which contains examples
 //! on how to write gluon programs as well as how to run them using this library.
 #![doc(html_root_url = "https://docs.rs/gluon/0.11.1")] // # GLUON
+#![recursion_limit = "256"]
 
 #[cfg(test)]
 extern crate env_logger;
@@ -37,9 +38,13 @@ extern crate gluon_codegen;
 #[macro_use]
 pub extern crate gluon_vm as vm;
 
+pub mod bytes_bind;
 pub mod compiler_pipeline;
+pub mod env_bind;
 #[cfg(feature = "http")]
 pub mod http;
+#[cfg(all(feature = "web", not(target_arch = "wasm32")))]
+pub mod http_client;
 #[macro_use]
 pub mod import;
 pub mod io;
@@ -48,6 +53,8 @@ pub mod process;
 pub mod rand_bind;
 #[cfg(feature = "regex")]
 pub mod regex_bind;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod time_bind;
 
 pub use crate::vm::thread::{RootedThread, Thread};
 
@@ -58,12 +65,13 @@ use either::Either;
 use std as real_std;
 use std::env;
 use std::error::Error as StdError;
+use std::mem;
 use std::path::PathBuf;
 use std::result::Result as StdResult;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 use crate::base::ast::{self, SpannedExpr};
-use crate::base::error::{Errors, InFile};
+use crate::base::error::{Errors, InFile, SourceDiagnostic};
 use crate::base::filename_to_module;
 use crate::base::fnv::FnvMap;
 use crate::base::metadata::Metadata;
@@ -75,9 +83,10 @@ use crate::format::Formatter;
 
 use crate::compiler_pipeline::*;
 use crate::import::{add_extern_module, DefaultImporter, Import};
-use crate::vm::api::{Getable, Hole, OpaqueValue, VmType};
+use crate::vm::api::{Getable, Hole, OpaqueValue, VmType, IO};
 use crate::vm::compiler::CompiledModule;
 use crate::vm::macros;
+use crate::vm::types::{VmIndex, VmInt};
 
 quick_error! {
 /// Error type wrapping all possible errors that can be generated from gluon
@@ -173,6 +182,15 @@ impl From<Errors<Error>> for Error {
 }
 
 impl Error {
+    /// Returns the stacktrace of the runtime error contained in `self`, if there is one
+    pub fn stacktrace(&self) -> Option<&crate::vm::stack::Stacktrace> {
+        match *self {
+            Error::VM(ref err) => err.stacktrace(),
+            Error::Multiple(ref errors) => errors.iter().filter_map(|err| err.stacktrace()).next(),
+            _ => None,
+        }
+    }
+
     pub fn emit_string(&self, code_map: &::codespan::CodeMap) -> ::std::io::Result<String> {
         let mut output = Vec::new();
         self.emit(
@@ -201,6 +219,24 @@ impl Error {
             }
         }
     }
+
+    /// Returns a `SourceDiagnostic` for each error contained in `self`. Errors which do not come
+    /// from a source file, such as IO errors, are returned without any labels.
+    pub fn diagnostics(&self, code_map: &::codespan::CodeMap) -> Vec<SourceDiagnostic> {
+        match *self {
+            Error::Parse(ref err) => err.diagnostics(code_map),
+            Error::Typecheck(ref err) => err.diagnostics(code_map),
+            Error::Macro(ref err) => err.diagnostics(code_map),
+            Error::IO(_) | Error::VM(_) | Error::Other(_) => vec![SourceDiagnostic::new(
+                code_map,
+                &::codespan_reporting::Diagnostic::new_error(self.to_string()),
+            )],
+            Error::Multiple(ref errors) => errors
+                .iter()
+                .flat_map(|err| err.diagnostics(code_map))
+                .collect(),
+        }
+    }
 }
 
 /// Type alias for results returned by gluon
@@ -210,6 +246,7 @@ pub type Result<T> = StdResult<T, Error>;
 struct State {
     code_map: codespan::CodeMap,
     index_map: FnvMap<String, BytePos>,
+    warnings: Vec<SourceDiagnostic>,
 }
 
 impl State {
@@ -259,6 +296,8 @@ struct Settings {
     emit_debug_info: bool,
     run_io: bool,
     full_metadata: bool,
+    fold_constants: bool,
+    inline_threshold: usize,
 }
 
 impl Default for Settings {
@@ -268,6 +307,8 @@ impl Default for Settings {
             emit_debug_info: true,
             run_io: false,
             full_metadata: false,
+            fold_constants: true,
+            inline_threshold: 8,
         }
     }
 }
@@ -285,6 +326,16 @@ impl Default for Compiler {
     }
 }
 
+/// The outcome of running the tests of a gluon test file with `Compiler::run_test_file`
+#[derive(Clone, Debug, PartialEq, Getable, VmType)]
+#[gluon(crate_name = "::vm")]
+pub struct TestReport {
+    pub passed: VmInt,
+    pub failed: VmInt,
+    /// The failed tests and their messages followed by the number of passed and failed tests
+    pub report: String,
+}
+
 macro_rules! option {
 ($(#[$attr:meta])* $name: ident $set_name: ident : $typ: ty) => {
     $(#[$attr])*
@@ -348,6 +399,22 @@ impl Compiler {
         full_metadata set_full_metadata: bool
     }
 
+    option_settings! {
+        /// Sets whether operations on literals are evaluated at compile time, removing the
+        /// branches and unused bindings that this makes dead. Turn it off to see every binding of
+        /// the source when debugging.
+        /// (default: true)
+        fold_constants set_fold_constants: bool
+    }
+
+    option_settings! {
+        /// Sets the number of expressions a function exported by a module may contain and still be
+        /// inlined into the modules which import it. Setting it to 0 turns off inlining, which
+        /// is necessary to stop at the breakpoints of the imported functions.
+        /// (default: 8)
+        inline_threshold set_inline_threshold: usize
+    }
+
     fn state(&self) -> MutexGuard<State> {
         self.state.lock().unwrap()
     }
@@ -356,6 +423,12 @@ impl Compiler {
         self.state().code_map.clone()
     }
 
+    /// Returns the warnings found since the last call to `take_warnings`, such as uses of
+    /// deprecated bindings. Unlike errors they do not stop the code from being compiled.
+    pub fn take_warnings(&mut self) -> Vec<SourceDiagnostic> {
+        mem::replace(&mut self.state().warnings, Vec::new())
+    }
+
     pub fn update_filemap<S>(&mut self, file: &str, source: S) -> Option<Arc<codespan::FileMap>>
     where
         S: Into<String>,
@@ -442,6 +515,53 @@ impl Compiler {
         Ok((expr, typ))
     }
 
+    /// Parses and typechecks `expr_str` like `typecheck_str` but returns the expression even if
+    /// errors were found. Everything that could be parsed is typechecked, so tools such as
+    /// completion can still query the types in front of an error.
+    pub fn typecheck_partial_str(
+        &mut self,
+        vm: &Thread,
+        file: &str,
+        expr_str: &str,
+        expected_type: Option<&ArcType>,
+    ) -> (Option<SpannedExpr<Symbol>>, Result<ArcType>) {
+        let (reparsed, reparse_error) = match expr_str.reparse_infix(self, vm, file, expr_str) {
+            Ok(reparsed) => (reparsed, None),
+            Err((Some(reparsed), err)) => (reparsed, Some(err)),
+            Err((None, err)) => return (None, Err(err)),
+        };
+        let InfixReparsed {
+            mut expr,
+            metadata_map,
+            metadata,
+        } = reparsed;
+        let result = InfixReparsed {
+            expr: &mut expr,
+            metadata_map,
+            metadata,
+        }
+        .typecheck_expected(self, vm, file, expr_str, expected_type)
+        .map(|value| value.typ);
+        let result = match (reparse_error, result) {
+            (None, result) => result,
+            (Some(err), Ok(_)) => Err(err),
+            (Some(err), Err(typecheck_err)) => Err(Errors::from(vec![err, typecheck_err]).into()),
+        };
+        (Some(expr), result)
+    }
+
+    /// Parses and typechecks `expr_str`, returning a diagnostic for every error that was found
+    /// instead of stopping at the first one, followed by the warnings
+    pub fn check_expr(&mut self, vm: &Thread, file: &str, expr_str: &str) -> Vec<SourceDiagnostic> {
+        self.take_warnings();
+        let mut diagnostics = match self.typecheck_str(vm, file, expr_str, None) {
+            Ok(_) => Vec::new(),
+            Err(err) => err.diagnostics(&self.code_map()),
+        };
+        diagnostics.extend(self.take_warnings());
+        diagnostics
+    }
+
     /// Compiles `expr` into a function which can be added and run by the `vm`
     pub fn compile_script(
         &mut self,
@@ -493,6 +613,28 @@ impl Compiler {
         Precompiled(deserializer).load_script(self, thread, name, "", ())
     }
 
+    /// Compiles `expr_str` into bytes which `load_bytes` can load into another VM without parsing,
+    /// typechecking or compiling the source again.
+    ///
+    /// The modules imported by `expr_str` are not included, only their names are recorded so
+    /// that `load_bytes` can import them if they are missing.
+    #[cfg(feature = "serialization")]
+    pub fn compile_to_bytes(
+        &mut self,
+        thread: &Thread,
+        name: &str,
+        expr_str: &str,
+    ) -> Result<Vec<u8>> {
+        compiler_pipeline::compile_to_bytes(self, thread, name, expr_str)
+    }
+
+    /// Loads bytes created by `compile_to_bytes` and stores the module into `name`. Fails if the
+    /// bytes were created by a different version of gluon.
+    #[cfg(feature = "serialization")]
+    pub fn load_bytes(&mut self, thread: &Thread, name: &str, bytes: &[u8]) -> Result<()> {
+        compiler_pipeline::load_bytes(thread, name, bytes)
+    }
+
     /// Parses and typechecks `expr_str` followed by extracting metadata from the created
     /// expression
     pub fn extract_metadata(
@@ -535,6 +677,21 @@ impl Compiler {
         &mut self,
         vm: &'vm Thread,
         filename: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        let module_name = Symbol::from(format!("@{}", filename_to_module(filename)));
+        self.load_module_async(vm, module_name)
+    }
+
+    /// Loads `module` in the same way as `import! module` unless it is already loaded
+    pub(crate) fn load_module(&mut self, vm: &Thread, module: &str) -> Result<()> {
+        self.load_module_async(vm, Symbol::from(format!("@{}", module)))
+            .wait()
+    }
+
+    fn load_module_async(
+        &mut self,
+        vm: &Thread,
+        module_name: Symbol,
     ) -> impl Future<Item = (), Error = Error> {
         use crate::macros::MacroExpander;
 
@@ -552,7 +709,6 @@ impl Compiler {
                 &owned_import
             }
         };
-        let module_name = Symbol::from(format!("@{}", filename_to_module(filename)));
         let mut macros = MacroExpander::new(vm);
         if let Err((_, err)) =
             import.load_module(self, vm, &mut macros, &module_name, Span::default())
@@ -562,6 +718,18 @@ impl Compiler {
         macros.finish().map_err(|err| err.into()).into_future()
     }
 
+    /// Compiles `module` again from its current source, for instance after its file has been
+    /// edited, and replaces the loaded module with the new one.
+    ///
+    /// Every module which imported `module` is unloaded as well and is compiled again the next
+    /// time it is imported. Values which were already taken from the old modules, such as
+    /// functions held by the host or captured by other closures, keep running the old code.
+    /// Userdata types keep the registration they got when they were first loaded.
+    pub fn reload_module(&mut self, vm: &Thread, module: &str) -> Result<()> {
+        import::invalidate_module(vm, module);
+        self.load_module(vm, module)
+    }
+
     /// Compiles and runs the expression in `expr_str`. If successful the value from running the
     /// expression is returned
     ///
@@ -650,6 +818,47 @@ impl Compiler {
             })
     }
 
+    /// Loads `filename`, which must evaluate to a `std.test.TestCase`, and runs every test in it
+    /// with `std.test.run_tests`. If any of the tests fail an error containing the report is
+    /// returned, so the tests of a gluon file can be run from Rust with
+    ///
+    /// ```no_run
+    /// # extern crate gluon;
+    /// # use gluon::{new_vm, Compiler};
+    /// # fn main() {
+    /// let vm = new_vm();
+    /// Compiler::new()
+    ///     .run_test_file(&vm, "tests/my_tests.glu")
+    ///     .unwrap_or_else(|err| panic!("{}", err));
+    /// # }
+    /// ```
+    pub fn run_test_file(&mut self, vm: &Thread, filename: &str) -> Result<TestReport> {
+        self.load_file(vm, filename)?;
+        let expr = format!(
+            r#"
+                let {{ run_tests, format_report }} = import! std.test
+                let io = import! std.io
+                let to_report report = {{
+                    passed = report.passed,
+                    failed = report.failed,
+                    report = format_report report,
+                }}
+                io.functor.map to_report (run_tests (import! {:?}))
+            "#,
+            filename
+        );
+        let (result, _) =
+            self.split()
+                .run_io(true)
+                .run_expr::<IO<TestReport>>(vm, "<test runner>", &expr)?;
+        match result {
+            IO::Value(ref report) if report.failed == 0 => Ok(report.clone()),
+            IO::Value(report) => Err(report.report.into()),
+            IO::Exception(err) => Err(err.into()),
+            IO::Error(err) => Err(err.to_string().into()),
+        }
+    }
+
     fn include_implicit_prelude(
         &mut self,
         type_cache: &TypeCache<Symbol, ArcType>,
@@ -745,6 +954,11 @@ in ()
 #[derive(Default)]
 pub struct VmBuilder {
     import_paths: Option<Vec<PathBuf>>,
+    memory_limit: Option<usize>,
+    max_stack_size: Option<VmIndex>,
+    fuel: Option<u64>,
+    collect_growth_factor: Option<f64>,
+    collect_threshold: Option<usize>,
 }
 
 impl VmBuilder {
@@ -757,9 +971,45 @@ impl VmBuilder {
         import_paths set_import_paths: Option<Vec<PathBuf>>
     }
 
+    option! {
+        /// Limits the memory the created thread may allocate, see `Thread::set_memory_limit`
+        /// (default: None)
+        memory_limit set_memory_limit: Option<usize>
+    }
+
+    option! {
+        /// Limits the stack size of the created thread, see `Context::set_max_stack_size`
+        /// (default: None)
+        max_stack_size set_max_stack_size: Option<VmIndex>
+    }
+
+    option! {
+        /// Limits the number of instructions the created thread may execute, see
+        /// `Context::set_fuel` (default: None)
+        fuel set_fuel: Option<u64>
+    }
+
+    option! {
+        /// How much the heap of the created thread may grow between collections, see
+        /// `Thread::set_collect_growth_factor` (default: 2.0)
+        collect_growth_factor set_collect_growth_factor: Option<f64>
+    }
+
+    option! {
+        /// How many bytes the created thread must allocate before any collection is run, see
+        /// `Thread::set_collect_threshold` (default: 100)
+        collect_threshold set_collect_threshold: Option<usize>
+    }
+
     pub fn build(self) -> RootedThread {
         let vm =
             RootedThread::with_global_state(crate::vm::vm::GlobalVmStateBuilder::new().build());
+        if let Some(collect_growth_factor) = self.collect_growth_factor {
+            vm.set_collect_growth_factor(collect_growth_factor);
+        }
+        if let Some(collect_threshold) = self.collect_threshold {
+            vm.set_collect_threshold(collect_threshold);
+        }
 
         let import = Import::new(DefaultImporter);
         if let Some(import_paths) = self.import_paths {
@@ -791,18 +1041,24 @@ impl VmBuilder {
         add_extern_module(&vm, "std.float.prim", crate::vm::primitives::load_float);
         add_extern_module(&vm, "std.string.prim", crate::vm::primitives::load_string);
         add_extern_module(&vm, "std.fs.prim", crate::vm::primitives::load_fs);
+        add_extern_module(&vm, "std.gc.prim", crate::vm::primitives::load_gc);
         add_extern_module(&vm, "std.path.prim", crate::vm::primitives::load_path);
         add_extern_module(&vm, "std.char.prim", crate::vm::primitives::load_char);
         add_extern_module(&vm, "std.array.prim", crate::vm::primitives::load_array);
+        add_extern_module(&vm, "std.bytes.prim", crate::bytes_bind::load);
 
         add_extern_module(&vm, "std.lazy.prim", crate::vm::lazy::load);
         add_extern_module(&vm, "std.reference.prim", crate::vm::reference::load);
+        add_extern_module(&vm, "std.atomic.prim", crate::vm::atomic::load);
+        add_extern_module(&vm, "std.map.prim", crate::vm::map::load);
 
         add_extern_module(&vm, "std.channel.prim", crate::vm::channel::load_channel);
         add_extern_module(&vm, "std.thread.prim", crate::vm::channel::load_thread);
         add_extern_module(&vm, "std.debug.prim", crate::vm::debug::load);
         add_extern_module(&vm, "std.io.prim", crate::io::load);
+        add_extern_module(&vm, "std.io.error.prim", crate::io::load_error);
         add_extern_module(&vm, "std.process.prim", crate::process::load);
+        add_extern_module(&vm, "std.env.prim", crate::env_bind::load);
 
         add_extern_module_if!(
             #[cfg(feature = "serialization")],
@@ -810,6 +1066,12 @@ impl VmBuilder {
             args(&vm, "std.json.prim", crate::vm::api::json::load)
         );
 
+        add_extern_module_if!(
+            #[cfg(feature = "serialization")],
+            available_if = "gluon is compiled with the 'serialization' feature",
+            args(&vm, "std.json.stream.prim", crate::vm::api::json::stream::load)
+        );
+
         add_extern_module_if!(
             #[cfg(feature = "regex")],
             available_if = "gluon is compiled with the 'regex' feature",
@@ -828,12 +1090,33 @@ impl VmBuilder {
             args(&vm, "std.http.prim", crate::http::load)
         );
 
+        add_extern_module_if!(
+            #[cfg(all(feature = "web", not(target_arch = "wasm32")))],
+            available_if = "gluon is compiled with the 'web' feature and is not targeting WASM",
+            args(&vm, "std.http.client.prim", crate::http_client::load)
+        );
+
         add_extern_module_if!(
             #[cfg(all(feature = "random", not(target_arch = "wasm32")))],
             available_if = "gluon is compiled with the 'random' feature and is not targeting WASM",
             args(&vm, "std.random.prim", crate::rand_bind::load)
         );
 
+        add_extern_module_if!(
+            #[cfg(not(target_arch = "wasm32"))],
+            available_if = "gluon is not targeting WASM",
+            args(&vm, "std.time.prim", crate::time_bind::load)
+        );
+
+        // Set the limits last so that loading the standard library is not restricted by them
+        if let Some(memory_limit) = self.memory_limit {
+            vm.set_memory_limit(memory_limit);
+        }
+        if let Some(max_stack_size) = self.max_stack_size {
+            vm.set_max_stack_size(max_stack_size);
+        }
+        vm.set_fuel(self.fuel);
+
         vm
     }
 }
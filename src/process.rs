@@ -1,5 +1,23 @@
-use crate::real_std::process::Command;
-use crate::vm::{api::IO, thread::Thread, ExternModule, Result};
+#[cfg(not(target_arch = "wasm32"))]
+extern crate tokio_timer;
+
+use crate::real_std::io;
+use crate::real_std::process::{self, Command};
+use crate::real_std::result::Result as StdResult;
+use crate::real_std::sync::{Arc, Mutex, MutexGuard};
+use crate::real_std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::real_std::time::Instant;
+
+use futures::future::{self, Either, Loop};
+use futures::Future;
+
+use crate::vm::{
+    self,
+    api::{WithVM, IO},
+    thread::{InterruptToken, Thread},
+    ExternModule, Result,
+};
 
 #[derive(Getable, VmType)]
 #[gluon(crate_name = "::vm")]
@@ -10,24 +28,133 @@ struct CreateProcess<'a> {
     current_dir: Option<&'a str>,
 }
 
-fn execute(create: CreateProcess) -> IO<Option<i32>> {
+#[derive(Pushable, VmType)]
+#[gluon(crate_name = "::vm")]
+struct Output {
+    status: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+/// The lock is only held while the child is inspected or signalled, never while waiting for it to
+/// exit, so that `kill` can stop a child which another thread is waiting on
+#[derive(Debug, Userdata)]
+#[gluon(crate_name = "::vm")]
+struct Child(Arc<Mutex<process::Child>>);
+
+/// How long `wait` sleeps between checks of whether the child has exited
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+fn lock_child(child: &Mutex<process::Child>) -> StdResult<MutexGuard<process::Child>, String> {
+    child
+        .lock()
+        .map_err(|_| "The child process was poisoned by a panic".to_string())
+}
+
+fn command(create: &CreateProcess) -> Command {
     let mut command = Command::new(create.command);
     for arg in &create.args {
         command.arg(arg);
     }
-    match create.env {
-        Some(env) => {
-            command.env_clear();
-            for (key, value) in &env {
-                command.env(key, value);
-            }
+    if let Some(ref env) = create.env {
+        command.env_clear();
+        for (key, value) in env {
+            command.env(key, value);
         }
-        None => (),
     }
     if let Some(current_dir) = create.current_dir {
         command.current_dir(current_dir);
     }
-    IO::from(command.status().map(|status| status.code()))
+    command
+}
+
+/// Raises the errors of failed process operations as the matching `IOError`
+fn io_result<T>(result: io::Result<T>) -> IO<T> {
+    match result {
+        Ok(value) => IO::Value(value),
+        Err(err) => IO::Error(err.into()),
+    }
+}
+
+fn execute(create: CreateProcess) -> IO<Option<i32>> {
+    io_result(command(&create).status().map(|status| status.code()))
+}
+
+/// Output which is not valid UTF-8 is converted lossily, replacing invalid sequences with `U+FFFD`
+fn output(create: CreateProcess) -> IO<Output> {
+    io_result(command(&create).output().map(|output| Output {
+        status: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }))
+}
+
+fn spawn(create: CreateProcess) -> IO<Child> {
+    io_result(
+        command(&create)
+            .spawn()
+            .map(|child| Child(Arc::new(Mutex::new(child)))),
+    )
+}
+
+/// Suspends the task for `duration`. Like `std.time.sleep` this uses the timer of the tokio
+/// runtime driving the VM if there is one and blocks the OS thread otherwise.
+#[cfg(not(target_arch = "wasm32"))]
+fn delay(
+    interrupt: &InterruptToken,
+    duration: Duration,
+) -> impl Future<Item = (), Error = vm::Error> {
+    let deadline = Instant::now() + duration;
+    let interrupt = interrupt.clone();
+    tokio_timer::Delay::new(deadline).or_else(move |err| {
+        if err.is_shutdown() {
+            if let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                interrupt.sleep(remaining);
+            }
+            Ok(())
+        } else {
+            Err(vm::Error::Message(format!("wait: {}", err)))
+        }
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+fn delay(
+    interrupt: &InterruptToken,
+    duration: Duration,
+) -> impl Future<Item = (), Error = vm::Error> {
+    interrupt.sleep(duration);
+    future::ok(())
+}
+
+/// Checks whether the child has exited every `WAIT_POLL_INTERVAL`, without keeping the child
+/// locked in between so that it can be killed while it is waited on
+fn wait(
+    WithVM { vm, value: child }: WithVM<&Child>,
+) -> impl Future<Item = IO<Option<i32>>, Error = vm::Error> {
+    let child = child.0.clone();
+    let interrupt = vm.interrupt_token();
+    future::loop_fn((), move |()| {
+        if interrupt.is_cancelled() {
+            return Either::A(future::err(vm::Error::Interrupted));
+        }
+        let status = match lock_child(&child) {
+            Ok(mut child) => child.try_wait(),
+            Err(err) => return Either::A(future::ok(Loop::Break(IO::Exception(err)))),
+        };
+        match status {
+            Ok(Some(status)) => Either::A(future::ok(Loop::Break(IO::Value(status.code())))),
+            Ok(None) => Either::B(delay(&interrupt, WAIT_POLL_INTERVAL).map(Loop::Continue)),
+            Err(err) => Either::A(future::ok(Loop::Break(IO::Error(err.into())))),
+        }
+    })
+}
+
+fn kill(child: &Child) -> IO<()> {
+    match lock_child(&child.0) {
+        Ok(mut child) => io_result(child.kill()),
+        Err(err) => IO::Exception(err),
+    }
 }
 
 mod std {
@@ -37,10 +164,18 @@ mod std {
 }
 
 pub fn load(vm: &Thread) -> Result<ExternModule> {
+    vm.register_type::<Child>("Child", &[])?;
+
     ExternModule::new(
         vm,
         record! {
-            execute => primitive!(1, std::process::prim::execute)
+            type Child => Child,
+
+            execute => primitive!(1, std::process::prim::execute),
+            output => primitive!(1, std::process::prim::output),
+            spawn => primitive!(1, std::process::prim::spawn),
+            wait => primitive!(1, async fn std::process::prim::wait),
+            kill => primitive!(1, std::process::prim::kill)
         },
     )
 }
@@ -1,77 +1,1980 @@
 //! Module containing bindings to the `rand` library.
 
+extern crate bincode;
 extern crate rand;
+extern crate rand_chacha;
 extern crate rand_xorshift;
+extern crate siphasher;
 
-use self::rand::{Rng, SeedableRng};
+use ::std::cell::RefCell;
+use ::std::convert::TryFrom;
+use ::std::fmt;
+use ::std::hash::Hasher;
+use ::std::sync::atomic::{AtomicUsize, Ordering};
+use ::std::sync::{Arc, Mutex};
 
-use crate::vm::api::{RuntimeResult, IO};
-use crate::vm::thread::Thread;
+use self::rand::distributions::{
+    Alphanumeric, Binomial, Distribution, Exp, Exp1, LogNormal, Normal, Open01, OpenClosed01,
+    Poisson, Standard, Triangular, Uniform, WeightedIndex,
+};
+use self::rand::seq::{index, SliceRandom};
+use self::rand::{FromEntropy, Rng, RngCore, SeedableRng};
+use self::siphasher::sip128::{Hasher128, SipHasher24};
+
+use futures::Future;
+
+use crate::vm::api::generic::A;
+use crate::vm::api::{
+    Generic, Getable, OpaqueValue, OwnedFunction, RuntimeResult, Userdata, VmType, WithVM, IO,
+};
+use crate::vm::base::types::{ArcType, Type};
+use crate::vm::gc::{Gc, GcPtr, Move, Traverseable};
+use crate::vm::internal::Cloner;
+use crate::vm::thread::{RootedThread, Thread};
 use crate::vm::types::VmInt;
 use crate::vm::{self, ExternModule};
 
-#[derive(Clone, Debug, Userdata)]
+#[derive(Clone, Userdata)]
 #[gluon(crate_name = "::vm")]
 struct XorShiftRng(self::rand_xorshift::XorShiftRng);
 
-field_decl! { value, gen }
+/// Shows the state of the generator as the hex string of the bytes returned by
+/// `xor_shift_serialize`, so a logged generator can be restored with `xor_shift_deserialize`
+impl fmt::Debug for XorShiftRng {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "XorShiftRng(0x")?;
+        for byte in xor_shift_serialize(self) {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Clone, Debug, Userdata)]
+#[gluon(crate_name = "::vm")]
+struct ChaChaRng(self::rand_chacha::ChaChaRng);
+
+#[derive(Clone, Debug, Userdata)]
+#[gluon(crate_name = "::vm")]
+struct StdRng(self::rand::rngs::StdRng);
+
+/// A `XorShiftRng` which counts every byte that has been drawn from it. Knowing how much of a
+/// seeded stream has been consumed lets a checkpoint record exactly where to resume the stream.
+#[derive(Clone, Debug, Userdata)]
+#[gluon(crate_name = "::vm")]
+struct CountingRng {
+    rng: self::rand_xorshift::XorShiftRng,
+    bytes: u64,
+}
+
+impl RngCore for CountingRng {
+    fn next_u32(&mut self) -> u32 {
+        self.bytes += 4;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.bytes += 8;
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.bytes += dest.len() as u64;
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), self::rand::Error> {
+        self.bytes += dest.len() as u64;
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// A uniform distribution over a fixed range of integers. `Uniform` computes the constants needed
+/// to sample without bias once when it is created while `gen_int_range` computes them on every
+/// call, so sampling the same range in a loop is cheaper through this.
+#[derive(Clone, Debug)]
+struct UniformInt(Uniform<VmInt>);
+
+impl Userdata for UniformInt {
+    fn deep_clone(&self, deep_cloner: &mut Cloner) -> vm::Result<GcPtr<Box<Userdata>>> {
+        let data: Box<Userdata> = Box::new(self.clone());
+        deep_cloner.gc().alloc(Move(data))
+    }
+}
+
+impl Traverseable for UniformInt {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+impl VmType for UniformInt {
+    type Type = UniformInt;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let env = vm.global_env().get_env();
+        let symbol = env.find_type_info("UniformInt").unwrap().name.clone();
+        Type::ident(symbol)
+    }
+}
+
+/// Picks indices with a probability proportional to a fixed array of weights. The cumulative
+/// weights are computed once by `weighted_alias_new`, where `choose_weighted` recomputes them on
+/// every call, so each sample only needs a binary search.
+#[derive(Clone, Debug)]
+struct WeightedSampler(WeightedIndex<f64>);
+
+impl Userdata for WeightedSampler {
+    fn deep_clone(&self, deep_cloner: &mut Cloner) -> vm::Result<GcPtr<Box<Userdata>>> {
+        let data: Box<Userdata> = Box::new(self.clone());
+        deep_cloner.gc().alloc(Move(data))
+    }
+}
+
+impl Traverseable for WeightedSampler {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+impl VmType for WeightedSampler {
+    type Type = WeightedSampler;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let env = vm.global_env().get_env();
+        let symbol = env.find_type_info("WeightedSampler").unwrap().name.clone();
+        Type::ident(symbol)
+    }
+}
+
+/// A `StdRng` which is updated in place. Every clone of it, including those made when it is sent
+/// to another thread, draws from the same stream of values.
+#[derive(Clone)]
+struct SharedRng(Arc<Mutex<self::rand::rngs::StdRng>>);
+
+impl Userdata for SharedRng {
+    fn deep_clone(&self, deep_cloner: &mut Cloner) -> vm::Result<GcPtr<Box<Userdata>>> {
+        // The generator is not part of any gc heap so the clone can keep referring to the same one
+        let data: Box<Userdata> = Box::new(self.clone());
+        deep_cloner.gc().alloc(Move(data))
+    }
+}
+
+impl fmt::Debug for SharedRng {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedRng({:?})", self.0)
+    }
+}
+
+impl Traverseable for SharedRng {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+impl VmType for SharedRng {
+    type Type = SharedRng;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let env = vm.global_env().get_env();
+        let symbol = env.find_type_info("SharedRng").unwrap().name.clone();
+        Type::ident(symbol)
+    }
+}
+
+/// The ways the fallible functions of `std.random` can fail. The IO functions raise these using
+/// their `Display` message while the pure functions return them in a `Result` so they can be
+/// matched on.
+#[derive(Clone, Debug, PartialEq, Getable, Pushable, VmType)]
+#[gluon(vm_type = "std.random.types.RandError")]
+#[gluon(crate_name = "::vm")]
+pub enum RandError {
+    BadSeedLength { expected: usize, got: usize },
+    EmptyRange { low: VmInt, high: VmInt },
+    EmptyInclusiveRange { low: VmInt, high: VmInt },
+    EmptyFloatRange { low: f64, high: f64 },
+    NonFiniteRange { low: f64, high: f64 },
+    InvalidProbability(f64),
+    InvalidMean(f64),
+    InvalidStdDev(f64),
+    InvalidLambda(f64),
+    InvalidSampleSize { amount: VmInt, length: VmInt },
+    NegativeLength(VmInt),
+    InvalidState(String),
+    WeightsLengthMismatch { elements: VmInt, weights: VmInt },
+    InvalidWeight(f64),
+    AllWeightsZero,
+    InvalidSides(VmInt),
+    EmptyCharRange { low: char, high: char },
+    InvalidDimension(VmInt),
+    InvalidTriangle { min: f64, mode: f64, max: f64 },
+    InvalidBitCount(VmInt),
+    EmptyInclusiveFloatRange { low: f64, high: f64 },
+    InvalidDurationRange { min: VmInt, max: VmInt },
+    InvalidSigma(f64),
+}
+
+impl fmt::Display for RandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RandError::BadSeedLength { expected, got } => {
+                write!(f, "Expected seed to have {} bytes, got {}", expected, got)
+            }
+            RandError::EmptyRange { low, high } => {
+                write!(f, "low must be < high, got `{}..{}`", low, high)
+            }
+            RandError::EmptyInclusiveRange { low, high } => {
+                write!(f, "low must be <= high, got `{}..={}`", low, high)
+            }
+            RandError::EmptyFloatRange { low, high } => {
+                write!(f, "low must be < high, got `{}..{}`", low, high)
+            }
+            RandError::NonFiniteRange { low, high } => {
+                write!(f, "Range bounds must be finite, got `{}..{}`", low, high)
+            }
+            RandError::InvalidProbability(probability) => write!(
+                f,
+                "Expected a probability between 0.0 and 1.0, got {}",
+                probability
+            ),
+            RandError::InvalidMean(mean) => write!(f, "mean must be finite, got {}", mean),
+            RandError::InvalidStdDev(std_dev) => write!(
+                f,
+                "std_dev must be finite and non-negative, got {}",
+                std_dev
+            ),
+            RandError::InvalidLambda(lambda) if lambda.is_infinite() => {
+                write!(f, "lambda must be finite, got {}", lambda)
+            }
+            RandError::InvalidLambda(lambda) => write!(f, "lambda must be > 0, got {}", lambda),
+            RandError::InvalidSampleSize { amount, length } => write!(
+                f,
+                "Cannot sample {} elements from an array of length {}",
+                amount, length
+            ),
+            RandError::NegativeLength(len) => {
+                write!(f, "Expected a non-negative length, got {}", len)
+            }
+            RandError::InvalidState(ref reason) => write!(f, "Invalid generator state: {}", reason),
+            RandError::WeightsLengthMismatch { elements, weights } => write!(
+                f,
+                "Expected one weight per element, got {} elements and {} weights",
+                elements, weights
+            ),
+            RandError::InvalidWeight(weight) => {
+                write!(f, "Weights must be finite and non-negative, got {}", weight)
+            }
+            RandError::AllWeightsZero => write!(f, "At least one weight must be greater than 0"),
+            RandError::InvalidSides(sides) => {
+                write!(f, "Expected dice to have at least one side, got {}", sides)
+            }
+            RandError::EmptyCharRange { low, high } => {
+                write!(f, "low must be <= high, got `{:?}..={:?}`", low, high)
+            }
+            RandError::InvalidDimension(n) => write!(
+                f,
+                "Expected a simplex with at least one dimension, got {}",
+                n
+            ),
+            RandError::InvalidTriangle { min, mode, max } => write!(
+                f,
+                "Expected finite bounds with min <= mode <= max and min < max, got min = {}, mode = {}, max = {}",
+                min, mode, max
+            ),
+            RandError::InvalidBitCount(bits) => {
+                write!(f, "Expected between 0 and 63 bits, got {}", bits)
+            }
+            RandError::EmptyInclusiveFloatRange { low, high } => {
+                write!(f, "low must be <= high, got `{}..={}`", low, high)
+            }
+            RandError::InvalidDurationRange { min, max } => write!(
+                f,
+                "Expected a duration range with 0 <= min <= max, got `{}..={}`",
+                min, max
+            ),
+            RandError::InvalidSigma(sigma) => {
+                write!(f, "sigma must be finite and > 0, got {}", sigma)
+            }
+        }
+    }
+}
+
+fn show_error(err: RandError) -> String {
+    err.to_string()
+}
+
+field_decl! { value, next, gen, left, right }
+
+/// The size of the state of `XorShiftRng`, four `u32`s
+const XOR_SHIFT_STATE_LEN: usize = 16;
+
+/// Selects the generator behind the `thread_rng` functions of `std.random`, see `load_with`
+#[derive(Clone, Debug, PartialEq)]
+pub enum RngConfig {
+    /// `rand::thread_rng`, a generator which reseeds itself from the operating system
+    ThreadRng,
+    /// A `StdRng` which is seeded from the operating system, this is what `load` uses
+    Entropy,
+    /// A `StdRng` which is seeded with the given seed, making the draws reproducible
+    Seed(u64),
+}
+
+impl Default for RngConfig {
+    fn default() -> Self {
+        RngConfig::Entropy
+    }
+}
+
+/// The configuration passed to the last call of `load` or `load_with`
+static RNG_CONFIG: Mutex<Option<RngConfig>> = Mutex::new(None);
+/// Incremented on every call of `load` or `load_with` so that the generator of each OS thread is
+/// created again from the new configuration
+static RNG_CONFIG_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+fn set_rng_config(config: RngConfig) {
+    *RNG_CONFIG.lock().unwrap() = Some(config);
+    RNG_CONFIG_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+enum GlobalRng {
+    /// Draws from `rand::thread_rng()`, which is looked up on each draw as it can't be sent to
+    /// another thread
+    Thread,
+    Std(rand::rngs::StdRng),
+}
+
+impl GlobalRng {
+    fn new(config: &RngConfig) -> Self {
+        match *config {
+            RngConfig::ThreadRng => GlobalRng::Thread,
+            RngConfig::Entropy => GlobalRng::Std(rand::rngs::StdRng::from_entropy()),
+            RngConfig::Seed(seed) => GlobalRng::Std(rand::rngs::StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    fn seeded(seed: VmInt) -> Self {
+        GlobalRng::Std(rand::rngs::StdRng::seed_from_u64(seed as u64))
+    }
+}
+
+impl RngCore for GlobalRng {
+    fn next_u32(&mut self) -> u32 {
+        match *self {
+            GlobalRng::Thread => rand::thread_rng().next_u32(),
+            GlobalRng::Std(ref mut rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match *self {
+            GlobalRng::Thread => rand::thread_rng().next_u64(),
+            GlobalRng::Std(ref mut rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match *self {
+            GlobalRng::Thread => rand::thread_rng().fill_bytes(dest),
+            GlobalRng::Std(ref mut rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match *self {
+            GlobalRng::Thread => rand::thread_rng().try_fill_bytes(dest),
+            GlobalRng::Std(ref mut rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+thread_local! {
+    /// The generator behind the `thread_rng` functions together with the generation of the
+    /// configuration it was created from. This is per OS thread state which is created from the
+    /// configuration of `load_with` until `seed_global` is called on the thread, explicitly seeded
+    /// generators have no generation and are kept when the configuration changes.
+    static GLOBAL_RNG: RefCell<Option<(Option<usize>, GlobalRng)>> = RefCell::new(None);
+}
+
+fn with_global_rng<T>(f: impl FnOnce(&mut GlobalRng) -> T) -> T {
+    GLOBAL_RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        let generation = RNG_CONFIG_GENERATION.load(Ordering::SeqCst);
+        match *rng {
+            Some((None, _)) => (),
+            Some((Some(rng_generation), _)) if rng_generation == generation => (),
+            _ => {
+                let config = RNG_CONFIG.lock().unwrap().clone().unwrap_or_default();
+                *rng = Some((Some(generation), GlobalRng::new(&config)));
+            }
+        }
+        f(&mut rng.as_mut().unwrap().1)
+    })
+}
+
+/// Replaces the generator of the current OS thread with the explicitly seeded `new`, returning
+/// the previous generator
+fn replace_global_rng(new: GlobalRng) -> Option<(Option<usize>, GlobalRng)> {
+    GLOBAL_RNG.with(|rng| ::std::mem::replace(&mut *rng.borrow_mut(), Some((None, new))))
+}
+
+fn seed_global(seed: VmInt) -> IO<()> {
+    replace_global_rng(GlobalRng::seeded(seed));
+    IO::Value(())
+}
+
+/// Puts the generator it was created with back as the global generator when dropped, so the
+/// previous state is restored even if the action run by `with_seed` fails or is cancelled
+struct GlobalRngGuard(Option<Option<(Option<usize>, GlobalRng)>>);
+
+impl GlobalRngGuard {
+    fn reseed(seed: VmInt) -> Self {
+        GlobalRngGuard(Some(replace_global_rng(GlobalRng::seeded(seed))))
+    }
+}
+
+impl Drop for GlobalRngGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0.take() {
+            // The thread local may already be destroyed if the thread is exiting
+            let _ = GLOBAL_RNG.try_with(|rng| *rng.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Int -> IO a -> IO a
+fn with_seed(
+    seed: VmInt,
+    action: OpaqueValue<&Thread, IO<A>>,
+) -> impl Future<Item = IO<OpaqueValue<RootedThread, A>>, Error = vm::Error> {
+    let vm = action.vm().root_thread();
+    let mut action: OwnedFunction<fn(()) -> OpaqueValue<RootedThread, A>> =
+        Getable::from_value(&vm, action.get_variant());
+
+    let guard = GlobalRngGuard::reseed(seed);
+    action.call_fast_async(()).then(move |result| {
+        drop(guard);
+        result.map(IO::Value)
+    })
+}
+
+/// Draws from the generator of a thread in deterministic mode (see `Thread::set_seed`) and from
+/// the generator behind the `thread_rng` functions otherwise
+struct VmRng<'a>(&'a Thread);
+
+impl<'a> RngCore for VmRng<'a> {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0
+            .next_seeded_u64()
+            .unwrap_or_else(|| with_global_rng(|rng| rng.next_u64()))
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+fn next_int(WithVM { vm, .. }: WithVM<()>) -> IO<VmInt> {
+    IO::Value(VmRng(vm).gen())
+}
+
+/// Draws all 64 bits of a `u64` and reinterprets them as a `VmInt`, so the sign bit is random as
+/// well and about half of the draws are negative
+fn next_u64(WithVM { vm, .. }: WithVM<()>) -> IO<VmInt> {
+    IO::Value(VmRng(vm).next_u64() as VmInt)
+}
+
+/// Draws a `u64` and keeps its `bits` lowest bits, so the result lies in `0..2^bits`. At most 63
+/// bits may be drawn as the sign bit would make the result negative.
+fn next_int_bits(bits: VmInt) -> IO<RuntimeResult<VmInt, RandError>> {
+    IO::Value(if bits < 0 || bits > 63 {
+        RuntimeResult::Panic(RandError::InvalidBitCount(bits))
+    } else {
+        let mask = (1u64 << bits) - 1;
+        RuntimeResult::Return(with_global_rng(|rng| (rng.next_u64() & mask) as VmInt))
+    })
+}
+
+fn next_float(WithVM { vm, .. }: WithVM<()>) -> IO<f64> {
+    IO::Value(VmRng(vm).gen())
+}
+
+/// Fills the 52 bits of the mantissa of a `Float` in `[1, 2)`, where every representable value is
+/// equally far apart, and subtracts 1. The result is a multiple of 2^-52 in `[0, 1)`, each equally
+/// likely and computed without any rounding.
+fn next_float_canonical(_: ()) -> IO<f64> {
+    const ONE_BITS: u64 = 0x3FF0_0000_0000_0000;
+    IO::Value(with_global_rng(|rng| {
+        f64::from_bits(ONE_BITS | (rng.next_u64() >> 12)) - 1.0
+    }))
+}
+
+/// Draws a `Float` from `(0, 1)`, unlike `next_float` which may return `0.0` neither endpoint can
+/// be returned
+fn next_float_open(_: ()) -> IO<f64> {
+    IO::Value(with_global_rng(|rng| rng.sample(Open01)))
+}
+
+/// Draws an `f32` and widens it to a `Float`, only the 24 bits of mantissa that an `f32` has are
+/// random and the rest are always zero
+fn next_float32(_: ()) -> IO<f64> {
+    IO::Value(f64::from(with_global_rng(|rng| rng.gen::<f32>())))
+}
+
+fn fill_bytes(len: VmInt) -> IO<RuntimeResult<Vec<u8>, RandError>> {
+    IO::Value(if len < 0 {
+        RuntimeResult::Panic(RandError::NegativeLength(len))
+    } else {
+        let mut buf = vec![0; len as usize];
+        with_global_rng(|rng| rng.fill_bytes(&mut buf));
+        RuntimeResult::Return(buf)
+    })
+}
+
+/// Draws `len` integers at once. Filling the array in a single call avoids crossing from gluon into
+/// Rust once per element as a loop over `next_int` would.
+fn gen_int_array(len: VmInt) -> IO<RuntimeResult<Vec<VmInt>, RandError>> {
+    IO::Value(if len < 0 {
+        RuntimeResult::Panic(RandError::NegativeLength(len))
+    } else {
+        RuntimeResult::Return(with_global_rng(|rng| {
+            rng.sample_iter(&Standard).take(len as usize).collect()
+        }))
+    })
+}
+
+/// Generates a string of `len` characters drawn from `[A-Za-z0-9]`
+fn gen_alphanumeric(len: VmInt) -> IO<RuntimeResult<String, RandError>> {
+    IO::Value(if len < 0 {
+        RuntimeResult::Panic(RandError::NegativeLength(len))
+    } else {
+        RuntimeResult::Return(with_global_rng(|rng| {
+            rng.sample_iter(&Alphanumeric).take(len as usize).collect()
+        }))
+    })
+}
+
+/// Samples uniformly from `low..high`. `rand` computes the width of the range as an unsigned
+/// integer (`high.wrapping_sub(low) as u64`) so negative ranges and ranges wider than
+/// `VmInt::max_value()`, up to `VmInt::min_value()..VmInt::max_value()`, are sampled without
+/// overflowing.
+fn sample_int_range<R: Rng>(rng: &mut R, low: VmInt, high: VmInt) -> Result<VmInt, RandError> {
+    if low < high {
+        Ok(rng.gen_range(low, high))
+    } else {
+        Err(RandError::EmptyRange { low, high })
+    }
+}
+
+fn gen_int_range(
+    WithVM { vm, value: low }: WithVM<VmInt>,
+    high: VmInt,
+) -> IO<RuntimeResult<VmInt, RandError>> {
+    IO::Value(sample_int_range(&mut VmRng(vm), low, high).into())
+}
+
+/// Draws from `low..high` like `gen_int_range` but swaps reversed bounds instead of failing.
+/// Equal bounds return `low` itself without drawing from the generator.
+fn gen_int_range_saturating(low: VmInt, high: VmInt) -> IO<VmInt> {
+    let (low, high) = if low > high { (high, low) } else { (low, high) };
+    if low == high {
+        IO::Value(low)
+    } else {
+        IO::Value(with_global_rng(|rng| rng.gen_range(low, high)))
+    }
+}
+
+/// Creates a distribution over `low..high`, failing in the same way as `gen_int_range`
+fn uniform_int_new(low: VmInt, high: VmInt) -> RuntimeResult<UniformInt, RandError> {
+    if low < high {
+        RuntimeResult::Return(UniformInt(Uniform::new(low, high)))
+    } else {
+        RuntimeResult::Panic(RandError::EmptyRange { low, high })
+    }
+}
+
+fn uniform_int_sample(uniform: &UniformInt) -> IO<VmInt> {
+    IO::Value(with_global_rng(|rng| uniform.0.sample(rng)))
+}
+
+fn sample_int_range_inclusive<R: Rng>(
+    rng: &mut R,
+    low: VmInt,
+    high: VmInt,
+) -> Result<VmInt, RandError> {
+    if low <= high {
+        Ok(Uniform::new_inclusive(low, high).sample(rng))
+    } else {
+        Err(RandError::EmptyInclusiveRange { low, high })
+    }
+}
+
+fn gen_int_range_inclusive(low: VmInt, high: VmInt) -> IO<RuntimeResult<VmInt, RandError>> {
+    IO::Value(with_global_rng(|rng| sample_int_range_inclusive(rng, low, high)).into())
+}
+
+/// Draws a number of milliseconds from `min..=max`, where a negative `min` is rejected as well
+/// since it can not be waited for
+fn gen_duration_millis(min: VmInt, max: VmInt) -> IO<RuntimeResult<VmInt, RandError>> {
+    IO::Value(if 0 <= min && min <= max {
+        RuntimeResult::Return(with_global_rng(|rng| {
+            Uniform::new_inclusive(min, max).sample(rng)
+        }))
+    } else {
+        RuntimeResult::Panic(RandError::InvalidDurationRange { min, max })
+    })
+}
+
+/// The surrogate code points `0xD800..0xE000` are not valid `char`s
+const SURROGATE_START: u32 = 0xD800;
+const SURROGATE_COUNT: u32 = 0x800;
+
+/// Samples uniformly from the `char`s in `low..=high`. The scalar values are sampled with the
+/// surrogate gap removed so every `char` in the range is equally likely.
+fn sample_char_range<R: Rng>(rng: &mut R, low: char, high: char) -> Result<char, RandError> {
+    fn to_index(c: char) -> u32 {
+        let c = c as u32;
+        if c >= SURROGATE_START {
+            c - SURROGATE_COUNT
+        } else {
+            c
+        }
+    }
+
+    if low > high {
+        return Err(RandError::EmptyCharRange { low, high });
+    }
+    let index = Uniform::new_inclusive(to_index(low), to_index(high)).sample(rng);
+    let scalar = if index >= SURROGATE_START {
+        index + SURROGATE_COUNT
+    } else {
+        index
+    };
+    Ok(::std::char::from_u32(scalar).expect("Sampled a surrogate code point"))
+}
+
+fn gen_char_range(low: char, high: char) -> IO<RuntimeResult<char, RandError>> {
+    IO::Value(with_global_rng(|rng| sample_char_range(rng, low, high)).into())
+}
+
+/// Rolls `n` dice with `sides` sides each
+fn roll(n: VmInt, sides: VmInt) -> IO<RuntimeResult<Vec<VmInt>, RandError>> {
+    IO::Value(if n < 0 {
+        RuntimeResult::Panic(RandError::NegativeLength(n))
+    } else if sides < 1 {
+        RuntimeResult::Panic(RandError::InvalidSides(sides))
+    } else {
+        let die = Uniform::new_inclusive(1, sides);
+        RuntimeResult::Return(with_global_rng(|rng| {
+            (0..n).map(|_| die.sample(rng)).collect()
+        }))
+    })
+}
+
+fn sample_float_range<R: Rng>(rng: &mut R, low: f64, high: f64) -> Result<f64, RandError> {
+    if !low.is_finite() || !high.is_finite() {
+        Err(RandError::NonFiniteRange { low, high })
+    } else if low < high {
+        Ok(rng.gen_range(low, high))
+    } else {
+        Err(RandError::EmptyFloatRange { low, high })
+    }
+}
+
+fn gen_float_range(low: f64, high: f64) -> IO<RuntimeResult<f64, RandError>> {
+    IO::Value(with_global_rng(|rng| sample_float_range(rng, low, high)).into())
+}
+
+fn sample_float_range_inclusive<R: Rng>(
+    rng: &mut R,
+    low: f64,
+    high: f64,
+) -> Result<f64, RandError> {
+    if !low.is_finite() || !high.is_finite() {
+        Err(RandError::NonFiniteRange { low, high })
+    } else if low <= high {
+        Ok(Uniform::new_inclusive(low, high).sample(rng))
+    } else {
+        Err(RandError::EmptyInclusiveFloatRange { low, high })
+    }
+}
+
+fn gen_float_range_inclusive(low: f64, high: f64) -> IO<RuntimeResult<f64, RandError>> {
+    IO::Value(with_global_rng(|rng| sample_float_range_inclusive(rng, low, high)).into())
+}
+
+fn sample_bool<R: Rng>(rng: &mut R, probability: f64) -> Result<bool, RandError> {
+    if (0.0..=1.0).contains(&probability) {
+        Ok(rng.gen_bool(probability))
+    } else {
+        Err(RandError::InvalidProbability(probability))
+    }
+}
+
+fn gen_bool(probability: f64) -> IO<RuntimeResult<bool, RandError>> {
+    IO::Value(with_global_rng(|rng| sample_bool(rng, probability)).into())
+}
+
+/// Counts the successes of `n` independent trials which each succeed with `probability`
+fn bernoulli_trials(n: VmInt, probability: f64) -> IO<RuntimeResult<VmInt, RandError>> {
+    IO::Value(if n < 0 {
+        RuntimeResult::Panic(RandError::NegativeLength(n))
+    } else if !(0.0..=1.0).contains(&probability) {
+        RuntimeResult::Panic(RandError::InvalidProbability(probability))
+    } else {
+        let binomial = Binomial::new(n as u64, probability);
+        RuntimeResult::Return(with_global_rng(|rng| binomial.sample(rng) as VmInt))
+    })
+}
+
+fn normal_distribution(mean: f64, std_dev: f64) -> Result<Normal, RandError> {
+    if !mean.is_finite() {
+        Err(RandError::InvalidMean(mean))
+    } else if !(std_dev >= 0.0 && std_dev.is_finite()) {
+        Err(RandError::InvalidStdDev(std_dev))
+    } else {
+        Ok(Normal::new(mean, std_dev))
+    }
+}
+
+/// `LogNormal::new` panics on a negative `sigma` and accepts 0 which would always give `e^mu` so
+/// the parameters are checked first
+fn log_normal_distribution(mu: f64, sigma: f64) -> Result<LogNormal, RandError> {
+    if !mu.is_finite() {
+        Err(RandError::InvalidMean(mu))
+    } else if !(sigma > 0.0 && sigma.is_finite()) {
+        Err(RandError::InvalidSigma(sigma))
+    } else {
+        Ok(LogNormal::new(mu, sigma))
+    }
+}
+
+fn exponential_distribution(lambda: f64) -> Result<Exp, RandError> {
+    if lambda > 0.0 {
+        Ok(Exp::new(lambda))
+    } else {
+        Err(RandError::InvalidLambda(lambda))
+    }
+}
+
+/// `Poisson` never finishes sampling with an infinite `lambda` so that is rejected as well
+fn poisson_distribution(lambda: f64) -> Result<Poisson, RandError> {
+    if lambda > 0.0 && lambda.is_finite() {
+        Ok(Poisson::new(lambda))
+    } else {
+        Err(RandError::InvalidLambda(lambda))
+    }
+}
+
+/// The number of failed trials before the first success when every trial succeeds with
+/// probability `p`. `rand` 0.6 does not provide this distribution so it is sampled by inverting
+/// its distribution function.
+struct Geometric {
+    /// `ln(1 - p)`, computed with `ln_1p` so that tiny probabilities do not round to `ln(1)`
+    ln_failure: f64,
+}
+
+impl Distribution<u64> for Geometric {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        // `u` lies in `(0, 1]` so the logarithm is finite. A `p` of 1 gives a `ln_failure` of
+        // negative infinity so every draw is 0. The cast saturates draws which do not fit.
+        let u: f64 = rng.sample(OpenClosed01);
+        (u.ln() / self.ln_failure).floor() as u64
+    }
+}
+
+fn geometric_distribution(p: f64) -> Result<Geometric, RandError> {
+    // A `p` of 0 would never succeed (and `NaN` fails the comparison)
+    if p > 0.0 && p <= 1.0 {
+        Ok(Geometric {
+            ln_failure: (-p).ln_1p(),
+        })
+    } else {
+        Err(RandError::InvalidProbability(p))
+    }
+}
+
+/// `Triangular::new` panics instead of returning an error so the bounds are checked first
+fn triangular_distribution(min: f64, mode: f64, max: f64) -> Result<Triangular, RandError> {
+    // `NaN` fails the comparisons
+    if min.is_finite() && max.is_finite() && min <= mode && mode <= max && min < max {
+        Ok(Triangular::new(min, max, mode))
+    } else {
+        Err(RandError::InvalidTriangle { min, mode, max })
+    }
+}
+
+fn next_normal(mean: f64, std_dev: f64) -> IO<RuntimeResult<f64, RandError>> {
+    IO::Value(
+        normal_distribution(mean, std_dev)
+            .map(|normal| with_global_rng(|rng| normal.sample(rng)))
+            .into(),
+    )
+}
+
+fn next_log_normal(mu: f64, sigma: f64) -> IO<RuntimeResult<f64, RandError>> {
+    IO::Value(
+        log_normal_distribution(mu, sigma)
+            .map(|log_normal| with_global_rng(|rng| log_normal.sample(rng)))
+            .into(),
+    )
+}
+
+/// Draws `len` values from a normal distribution. The distribution is only constructed and
+/// validated once, making this faster than calling `next_normal` for each element.
+fn sample_normal_array(
+    len: VmInt,
+    mean: f64,
+    std_dev: f64,
+) -> IO<RuntimeResult<Vec<f64>, RandError>> {
+    IO::Value(if len < 0 {
+        RuntimeResult::Panic(RandError::NegativeLength(len))
+    } else {
+        normal_distribution(mean, std_dev)
+            .map(|normal| {
+                with_global_rng(|rng| rng.sample_iter(&normal).take(len as usize).collect())
+            })
+            .into()
+    })
+}
+
+/// Draws a point uniformly from the `n` dimensional probability simplex, that is `n` non-negative
+/// values which sum to 1, which is the same as sampling a Dirichlet distribution where every
+/// alpha is 1. Each value is a draw from Gamma(1, 1), which is the exponential distribution with
+/// a `lambda` of 1, divided by the sum of the draws.
+fn sample_simplex(n: VmInt) -> IO<RuntimeResult<Vec<f64>, RandError>> {
+    IO::Value(if n < 1 {
+        RuntimeResult::Panic(RandError::InvalidDimension(n))
+    } else {
+        RuntimeResult::Return(with_global_rng(|rng| loop {
+            let draws: Vec<f64> = rng.sample_iter(&Exp1).take(n as usize).collect();
+            let sum: f64 = draws.iter().sum();
+            // Every draw being 0 is possible, if vanishingly unlikely, so draw again instead of
+            // dividing by 0
+            if sum > 0.0 {
+                break draws.into_iter().map(|x| x / sum).collect();
+            }
+        }))
+    })
+}
+
+fn next_exponential(lambda: f64) -> IO<RuntimeResult<f64, RandError>> {
+    IO::Value(
+        exponential_distribution(lambda)
+            .map(|exp| with_global_rng(|rng| exp.sample(rng)))
+            .into(),
+    )
+}
 
-fn next_int(_: ()) -> IO<VmInt> {
-    IO::Value(rand::thread_rng().gen())
+fn next_triangular(min: f64, mode: f64, max: f64) -> IO<RuntimeResult<f64, RandError>> {
+    IO::Value(
+        triangular_distribution(min, mode, max)
+            .map(|triangular| with_global_rng(|rng| triangular.sample(rng)))
+            .into(),
+    )
 }
 
-fn next_float(_: ()) -> IO<f64> {
-    IO::Value(rand::thread_rng().gen())
+/// Draws the number of events in an interval where `lambda` events are expected. The draw is
+/// computed as a `Float` and truncated, so for a `lambda` above 2^53 not every `Int` can be drawn
+/// and draws above the largest `Int` saturate to it.
+fn next_poisson(lambda: f64) -> IO<RuntimeResult<VmInt, RandError>> {
+    IO::Value(
+        poisson_distribution(lambda)
+            .map(|poisson| {
+                let count: u64 = with_global_rng(|rng| poisson.sample(rng));
+                VmInt::try_from(count).unwrap_or(VmInt::MAX)
+            })
+            .into(),
+    )
 }
 
-fn gen_int_range(low: VmInt, high: VmInt) -> IO<VmInt> {
-    IO::Value(rand::thread_rng().gen_range(low, high))
+/// Draws the number of failed trials before the first success when every trial succeeds with
+/// probability `p`. Draws above the largest `Int`, which only a tiny `p` makes likely, saturate to
+/// it.
+fn next_geometric(p: f64) -> IO<RuntimeResult<VmInt, RandError>> {
+    IO::Value(
+        geometric_distribution(p)
+            .map(|geometric| {
+                let count = with_global_rng(|rng| geometric.sample(rng));
+                VmInt::try_from(count).unwrap_or(VmInt::MAX)
+            })
+            .into(),
+    )
 }
 
-type RngNext<G> = record_type! {
-    value => VmInt,
+/// A drawn value together with the generator to use for the next draw. `gen` is the old name of
+/// `next` and holds the same generator, it is still returned so that existing code keeps working.
+type RngNext<T, G> = record_type! {
+    value => T,
+    next => G,
     gen => G
 };
 
-fn xor_shift_new(seed: &[u8]) -> RuntimeResult<XorShiftRng, String> {
-    if seed.len() == 16 {
-        let seed = unsafe { *(seed.as_ptr() as *const [u8; 16]) };
-        RuntimeResult::Return(XorShiftRng(self::rand_xorshift::XorShiftRng::from_seed(
-            seed,
-        )))
+fn rng_value<T, G: Clone>(value: T, gen: G) -> RngNext<T, G> {
+    record_no_decl! {
+        value => value,
+        next => gen.clone(),
+        gen => gen
+    }
+}
+
+fn rng_next<T, G: Clone>(result: Result<T, RandError>, gen: G) -> Result<RngNext<T, G>, RandError> {
+    result.map(|value| rng_value(value, gen))
+}
+
+type Elements = Vec<Generic<A>>;
+
+/// Samples `amount` distinct indices below `length`, checking the arguments since
+/// `index::sample` panics on invalid ones
+fn sample_index_vec<R: Rng>(
+    rng: &mut R,
+    length: VmInt,
+    amount: VmInt,
+) -> Result<index::IndexVec, RandError> {
+    if length < 0 || amount < 0 || amount > length {
+        Err(RandError::InvalidSampleSize { amount, length })
     } else {
-        RuntimeResult::Panic("Expected xorshift seed to have 4 elements".to_string())
+        Ok(index::sample(rng, length as usize, amount as usize))
+    }
+}
+
+fn sample_elements<R: Rng>(
+    rng: &mut R,
+    array: &[Generic<A>],
+    amount: VmInt,
+) -> Result<Elements, RandError> {
+    sample_index_vec(rng, array.len() as VmInt, amount)
+        .map(|indices| indices.into_iter().map(|i| array[i].clone()).collect())
+}
+
+fn shuffle(mut array: Elements) -> IO<Elements> {
+    with_global_rng(|rng| array.shuffle(rng));
+    IO::Value(array)
+}
+
+/// Shuffles the characters of `s`. The `char`s are shuffled rather than the bytes so multi-byte
+/// characters stay intact and the result is always valid UTF-8.
+fn shuffle_string(s: &str) -> IO<String> {
+    let mut chars = s.chars().collect::<Vec<_>>();
+    with_global_rng(|rng| chars.shuffle(rng));
+    IO::Value(chars.into_iter().collect())
+}
+
+/// Returns the integers `0..n` in a random order
+fn permutation(n: VmInt) -> IO<RuntimeResult<Vec<VmInt>, RandError>> {
+    IO::Value(if n < 0 {
+        RuntimeResult::Panic(RandError::NegativeLength(n))
+    } else {
+        let mut indices = (0..n).collect::<Vec<_>>();
+        with_global_rng(|rng| indices.shuffle(rng));
+        RuntimeResult::Return(indices)
+    })
+}
+
+fn choose(array: Elements) -> IO<Option<Generic<A>>> {
+    IO::Value(with_global_rng(|rng| array.choose(rng).cloned()))
+}
+
+/// Checks `weights` before creating the `WeightedIndex` since it accepts NaN and infinite weights
+/// but panics when sampling with them. Returns `None` if there are no weights.
+fn weighted_index(
+    elements: usize,
+    weights: &[f64],
+) -> Result<Option<WeightedIndex<f64>>, RandError> {
+    if elements != weights.len() {
+        return Err(RandError::WeightsLengthMismatch {
+            elements: elements as VmInt,
+            weights: weights.len() as VmInt,
+        });
+    }
+    if weights.is_empty() {
+        return Ok(None);
     }
+    if let Some(&weight) = weights
+        .iter()
+        .find(|weight| !(weight.is_finite() && **weight >= 0.0))
+    {
+        return Err(RandError::InvalidWeight(weight));
+    }
+    let total: f64 = weights.iter().sum();
+    if !total.is_finite() {
+        return Err(RandError::InvalidWeight(total));
+    }
+    if total == 0.0 {
+        return Err(RandError::AllWeightsZero);
+    }
+    Ok(Some(
+        WeightedIndex::new(weights).expect("Weights are valid"),
+    ))
+}
+
+fn choose_weighted_element<R: Rng>(
+    rng: &mut R,
+    array: &[Generic<A>],
+    weights: &[f64],
+) -> Result<Option<Generic<A>>, RandError> {
+    weighted_index(array.len(), weights)
+        .map(|index| index.map(|index| array[index.sample(rng)].clone()))
+}
+
+fn choose_weighted(
+    array: Elements,
+    weights: &[f64],
+) -> IO<RuntimeResult<Option<Generic<A>>, RandError>> {
+    IO::Value(with_global_rng(|rng| choose_weighted_element(rng, &array, weights)).into())
+}
+
+/// Validates `weights` in the same way as `choose_weighted`. As there are no elements to return
+/// `None` for, an empty array of weights is rejected like one where every weight is zero.
+fn weighted_alias_new(weights: &[f64]) -> RuntimeResult<WeightedSampler, RandError> {
+    match weighted_index(weights.len(), weights) {
+        Ok(Some(index)) => RuntimeResult::Return(WeightedSampler(index)),
+        Ok(None) => RuntimeResult::Panic(RandError::AllWeightsZero),
+        Err(err) => RuntimeResult::Panic(err),
+    }
+}
+
+fn weighted_sample(sampler: &WeightedSampler) -> IO<VmInt> {
+    IO::Value(with_global_rng(|rng| sampler.0.sample(rng) as VmInt))
+}
+
+fn sample(array: Elements, amount: VmInt) -> IO<RuntimeResult<Elements, RandError>> {
+    IO::Value(with_global_rng(|rng| sample_elements(rng, &array, amount)).into())
+}
+
+/// Picks `amount` elements at distinct positions of `array`. Unlike `sample` this returns the
+/// whole array in a random order if `amount` is larger than it.
+fn choose_multiple(array: Elements, amount: VmInt) -> IO<RuntimeResult<Elements, RandError>> {
+    IO::Value(if amount < 0 {
+        RuntimeResult::Panic(RandError::NegativeLength(amount))
+    } else {
+        RuntimeResult::Return(with_global_rng(|rng| {
+            array
+                .choose_multiple(rng, amount as usize)
+                .cloned()
+                .collect()
+        }))
+    })
+}
+
+/// Picks `amount` elements at distinct positions of `array`, keeping them in the order they have
+/// in `array`. The whole array is returned if `amount` is larger than it.
+fn sample_without_replacement(
+    array: Elements,
+    amount: VmInt,
+) -> IO<RuntimeResult<Elements, RandError>> {
+    IO::Value(if amount < 0 {
+        RuntimeResult::Panic(RandError::NegativeLength(amount))
+    } else if amount as usize >= array.len() {
+        RuntimeResult::Return(array)
+    } else {
+        let mut indices =
+            with_global_rng(|rng| index::sample(rng, array.len(), amount as usize)).into_vec();
+        indices.sort_unstable();
+        RuntimeResult::Return(indices.into_iter().map(|i| array[i].clone()).collect())
+    })
+}
+
+fn sample_indices(length: VmInt, amount: VmInt) -> IO<RuntimeResult<Vec<VmInt>, RandError>> {
+    IO::Value(
+        with_global_rng(|rng| sample_index_vec(rng, length, amount))
+            .map(|indices| indices.into_iter().map(|i| i as VmInt).collect())
+            .into(),
+    )
+}
+
+fn shuffle_with(gen: &XorShiftRng, mut array: Elements) -> RngNext<Elements, XorShiftRng> {
+    let mut gen = gen.clone();
+    array.shuffle(&mut gen.0);
+    rng_value(array, gen)
+}
+
+fn choose_with(gen: &XorShiftRng, array: Elements) -> RngNext<Option<Generic<A>>, XorShiftRng> {
+    let mut gen = gen.clone();
+    let value = array.choose(&mut gen.0).cloned();
+    rng_value(value, gen)
+}
+
+fn sample_with(
+    gen: &XorShiftRng,
+    array: Elements,
+    amount: VmInt,
+) -> Result<RngNext<Elements, XorShiftRng>, RandError> {
+    let mut gen = gen.clone();
+    let result = sample_elements(&mut gen.0, &array, amount);
+    rng_next(result, gen)
+}
+
+fn choose_weighted_with(
+    gen: &XorShiftRng,
+    array: Elements,
+    weights: &[f64],
+) -> Result<RngNext<Option<Generic<A>>, XorShiftRng>, RandError> {
+    let mut gen = gen.clone();
+    let result = choose_weighted_element(&mut gen.0, &array, weights);
+    rng_next(result, gen)
+}
+
+fn gen_int_range_with(
+    gen: &XorShiftRng,
+    low: VmInt,
+    high: VmInt,
+) -> Result<RngNext<VmInt, XorShiftRng>, RandError> {
+    let mut gen = gen.clone();
+    let result = sample_int_range(&mut gen.0, low, high);
+    rng_next(result, gen)
+}
+
+fn gen_float_range_with(
+    gen: &XorShiftRng,
+    low: f64,
+    high: f64,
+) -> Result<RngNext<f64, XorShiftRng>, RandError> {
+    let mut gen = gen.clone();
+    let result = sample_float_range(&mut gen.0, low, high);
+    rng_next(result, gen)
+}
+
+fn gen_bool_with(
+    gen: &XorShiftRng,
+    probability: f64,
+) -> Result<RngNext<bool, XorShiftRng>, RandError> {
+    let mut gen = gen.clone();
+    let result = sample_bool(&mut gen.0, probability);
+    rng_next(result, gen)
+}
+
+fn next_normal_with(
+    gen: &XorShiftRng,
+    mean: f64,
+    std_dev: f64,
+) -> Result<RngNext<f64, XorShiftRng>, RandError> {
+    let mut gen = gen.clone();
+    let result = normal_distribution(mean, std_dev).map(|normal| normal.sample(&mut gen.0));
+    rng_next(result, gen)
+}
+
+fn next_exponential_with(
+    gen: &XorShiftRng,
+    lambda: f64,
+) -> Result<RngNext<f64, XorShiftRng>, RandError> {
+    let mut gen = gen.clone();
+    let result = exponential_distribution(lambda).map(|exp| exp.sample(&mut gen.0));
+    rng_next(result, gen)
+}
+
+fn xor_shift_new(seed: &[u8]) -> RuntimeResult<XorShiftRng, RandError> {
+    xor_shift_try_new(seed).into()
+}
+
+/// Like `xor_shift_new` but an invalid seed is returned as an `Err` instead of being raised.
+fn xor_shift_try_new(seed: &[u8]) -> Result<XorShiftRng, RandError> {
+    <[u8; 16]>::try_from(seed)
+        .map(|seed| XorShiftRng(self::rand_xorshift::XorShiftRng::from_seed(seed)))
+        .map_err(|_| RandError::BadSeedLength {
+            expected: 16,
+            got: seed.len(),
+        })
+}
+
+/// SplitMix64, used to expand small seeds into a full generator state
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Replaces `gen` with a generator seeded from `seed`, which is checked in the same way as in
+/// `xor_shift_new`
+fn xor_shift_reseed(_gen: &XorShiftRng, seed: &[u8]) -> RuntimeResult<XorShiftRng, RandError> {
+    xor_shift_new(seed)
+}
+
+/// Like `xor_shift_reseed` but an invalid seed is returned as an `Err` instead of being raised.
+fn xor_shift_try_reseed(_gen: &XorShiftRng, seed: &[u8]) -> Result<XorShiftRng, RandError> {
+    xor_shift_try_new(seed)
+}
+
+fn xor_shift_from_state(mut state: u64) -> XorShiftRng {
+    let mut seed = [0; 16];
+    seed[..8].copy_from_slice(&split_mix(&mut state).to_le_bytes());
+    seed[8..].copy_from_slice(&split_mix(&mut state).to_le_bytes());
+    XorShiftRng(self::rand_xorshift::XorShiftRng::from_seed(seed))
+}
+
+fn xor_shift_from_int(seed: VmInt) -> XorShiftRng {
+    xor_shift_from_state(seed as u64)
+}
+
+/// Seeds the generator the same way as `XorShiftRng::seed_from_u64` in Rust
+fn xor_shift_from_u64(seed: VmInt) -> XorShiftRng {
+    XorShiftRng(self::rand_xorshift::XorShiftRng::seed_from_u64(seed as u64))
+}
+
+fn xor_shift_from_ints(seed: &[VmInt]) -> XorShiftRng {
+    // Mix in the length as well so that trailing zeroes still give a distinct seed
+    let state = seed.iter().fold(seed.len() as u64, |mut state, &x| {
+        state ^= x as u64;
+        split_mix(&mut state)
+    });
+    xor_shift_from_state(state)
+}
+
+/// Hashes `seed` into the 16 byte seed used by `xor_shift_from_string`. Only the bytes of the
+/// string are hashed, using SipHash-2-4 with fixed keys and a little endian output, so the seed is
+/// the same on every platform and must never change between versions.
+fn string_seed(seed: &str) -> [u8; 16] {
+    let mut hasher = SipHasher24::new_with_keys(0, 0);
+    hasher.write(seed.as_bytes());
+    let hash = hasher.finish128();
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&hash.h1.to_le_bytes());
+    bytes[8..].copy_from_slice(&hash.h2.to_le_bytes());
+    bytes
+}
+
+fn xor_shift_from_string(seed: &str) -> XorShiftRng {
+    XorShiftRng(self::rand_xorshift::XorShiftRng::from_seed(string_seed(
+        seed,
+    )))
+}
+
+/// Returns the 16 byte state of `gen`. Restoring it with `xor_shift_deserialize` gives a generator
+/// which continues the exact same sequence.
+fn xor_shift_serialize(gen: &XorShiftRng) -> Vec<u8> {
+    bincode::serialize(&gen.0).expect("XorShiftRng can always be serialized")
+}
+
+fn xor_shift_deserialize(state: &[u8]) -> Result<XorShiftRng, RandError> {
+    if state.len() != XOR_SHIFT_STATE_LEN {
+        return Err(RandError::InvalidState(format!(
+            "Expected {} bytes, got {}",
+            XOR_SHIFT_STATE_LEN,
+            state.len()
+        )));
+    }
+    // An all zero state would make the generator return zero forever
+    if state.iter().all(|&b| b == 0) {
+        return Err(RandError::InvalidState(
+            "The state may not be all zeroes".to_string(),
+        ));
+    }
+    bincode::deserialize(state)
+        .map(XorShiftRng)
+        .map_err(|err| RandError::InvalidState(err.to_string()))
+}
+
+/// The version of the format written by `xor_shift_checkpoint`, stored in its first byte so that
+/// a checkpoint written by another version of the format is rejected by `xor_shift_restore`
+/// instead of silently restoring a different position
+const XOR_SHIFT_CHECKPOINT_VERSION: u8 = 1;
+
+/// Captures the position of `gen` in its sequence as the format version followed by the state of
+/// the generator
+fn xor_shift_checkpoint(gen: &XorShiftRng) -> Vec<u8> {
+    let mut checkpoint = vec![XOR_SHIFT_CHECKPOINT_VERSION];
+    checkpoint.extend(xor_shift_serialize(gen));
+    checkpoint
+}
+
+/// Rebuilds the generator captured by `xor_shift_checkpoint`, raising an error if `checkpoint` was
+/// not written by it
+fn xor_shift_restore(checkpoint: &[u8]) -> RuntimeResult<XorShiftRng, RandError> {
+    let result = match checkpoint.split_first() {
+        Some((&XOR_SHIFT_CHECKPOINT_VERSION, state)) => xor_shift_deserialize(state),
+        Some((version, _)) => Err(RandError::InvalidState(format!(
+            "Expected checkpoint version {}, got {}",
+            XOR_SHIFT_CHECKPOINT_VERSION, version
+        ))),
+        None => Err(RandError::InvalidState(
+            "The checkpoint is empty".to_string(),
+        )),
+    };
+    result.into()
+}
+
+fn xor_shift_show(gen: &XorShiftRng) -> String {
+    format!("{:?}", gen)
+}
+
+fn xor_shift_next(gen: &XorShiftRng) -> RngNext<VmInt, XorShiftRng> {
+    let mut gen = gen.clone();
+    rng_value(gen.0.gen(), gen)
+}
+
+fn xor_shift_next_float(gen: &XorShiftRng) -> RngNext<f64, XorShiftRng> {
+    let mut gen = gen.clone();
+    rng_value(gen.0.gen(), gen)
 }
 
-fn xor_shift_next(gen: &XorShiftRng) -> RngNext<XorShiftRng> {
+/// Draws `len` integers from `gen` in one call, like `gen_int_array` does from the thread
+/// generator. The integers are the same as `len` calls to `xor_shift_next` would return.
+fn fill_int_array_with(
+    gen: &XorShiftRng,
+    len: VmInt,
+) -> Result<RngNext<Vec<VmInt>, XorShiftRng>, RandError> {
+    if len < 0 {
+        return Err(RandError::NegativeLength(len));
+    }
     let mut gen = gen.clone();
+    let array = gen.0.sample_iter(&Standard).take(len as usize).collect();
+    Ok(rng_value(array, gen))
+}
+
+type Split<G> = record_type! {
+    left => G,
+    right => G
+};
+
+/// Splits `gen` into two generators which produce independent streams. `left` continues the stream
+/// of `gen` while `right` is seeded from a value drawn from it. The value is expanded with
+/// SplitMix instead of seeding `right` directly from the output of `gen`, which could leave the
+/// streams correlated.
+fn xor_shift_split(gen: &XorShiftRng) -> Split<XorShiftRng> {
+    let mut left = gen.clone();
+    let right = xor_shift_from_state(left.0.gen());
     record_no_decl! {
-        value => gen.0.gen(),
-        gen => gen
+        left => left,
+        right => right
     }
 }
 
+/// Passes each word drawn from a generator through SplitMix. A `XorShiftRng` seeded directly with
+/// the output of another `XorShiftRng` starts out by repeating the draws of its parent.
+struct SplitMixOutput<'a>(&'a mut self::rand_xorshift::XorShiftRng);
+
+impl<'a> RngCore for SplitMixOutput<'a> {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut state = self.0.next_u64();
+        split_mix(&mut state)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), self::rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Seeds a new generator from the output of `gen` with `SeedableRng::from_rng`, returning it as
+/// `value` along with the advanced parent as `gen`
+fn xor_shift_from_rng(gen: &XorShiftRng) -> RngNext<XorShiftRng, XorShiftRng> {
+    let mut gen = gen.clone();
+    let child = self::rand_xorshift::XorShiftRng::from_rng(SplitMixOutput(&mut gen.0))
+        .expect("XorShiftRng never fails to generate a seed");
+    rng_value(XorShiftRng(child), gen)
+}
+
+fn cha_cha_new(seed: &[u8]) -> RuntimeResult<ChaChaRng, RandError> {
+    cha_cha_try_new(seed).into()
+}
+
+/// Like `cha_cha_new` but an invalid seed is returned as an `Err` instead of being raised.
+fn cha_cha_try_new(seed: &[u8]) -> Result<ChaChaRng, RandError> {
+    <[u8; 32]>::try_from(seed)
+        .map(|seed| ChaChaRng(self::rand_chacha::ChaChaRng::from_seed(seed)))
+        .map_err(|_| RandError::BadSeedLength {
+            expected: 32,
+            got: seed.len(),
+        })
+}
+
+fn cha_cha_next(gen: &ChaChaRng) -> RngNext<VmInt, ChaChaRng> {
+    let mut gen = gen.clone();
+    rng_value(gen.0.gen(), gen)
+}
+
+fn cha_cha_next_float(gen: &ChaChaRng) -> RngNext<f64, ChaChaRng> {
+    let mut gen = gen.clone();
+    rng_value(gen.0.gen(), gen)
+}
+
+fn std_rng_from_seed(seed: VmInt) -> StdRng {
+    StdRng(self::rand::rngs::StdRng::seed_from_u64(seed as u64))
+}
+
+fn std_rng_from_entropy(_: ()) -> IO<StdRng> {
+    IO::Value(StdRng(self::rand::rngs::StdRng::from_entropy()))
+}
+
+fn std_rng_next_int(gen: &StdRng) -> RngNext<VmInt, StdRng> {
+    let mut gen = gen.clone();
+    rng_value(gen.0.gen(), gen)
+}
+
+fn std_rng_next_float(gen: &StdRng) -> RngNext<f64, StdRng> {
+    let mut gen = gen.clone();
+    rng_value(gen.0.gen(), gen)
+}
+
+fn std_rng_gen_int_range(
+    gen: &StdRng,
+    low: VmInt,
+    high: VmInt,
+) -> Result<RngNext<VmInt, StdRng>, RandError> {
+    let mut gen = gen.clone();
+    let result = sample_int_range(&mut gen.0, low, high);
+    rng_next(result, gen)
+}
+
+/// Wraps `gen` so that the bytes drawn from it are counted, starting from zero
+fn counting_rng_from_xor_shift(gen: &XorShiftRng) -> CountingRng {
+    CountingRng {
+        rng: gen.0.clone(),
+        bytes: 0,
+    }
+}
+
+fn counting_rng_from_int(seed: VmInt) -> CountingRng {
+    counting_rng_from_xor_shift(&xor_shift_from_int(seed))
+}
+
+fn counting_rng_next(gen: &CountingRng) -> RngNext<VmInt, CountingRng> {
+    let mut gen = gen.clone();
+    rng_value(gen.gen(), gen)
+}
+
+fn counting_rng_next_float(gen: &CountingRng) -> RngNext<f64, CountingRng> {
+    let mut gen = gen.clone();
+    rng_value(gen.gen(), gen)
+}
+
+fn counting_rng_bytes_consumed(gen: &CountingRng) -> VmInt {
+    VmInt::try_from(gen.bytes).unwrap_or(VmInt::MAX)
+}
+
+fn counting_rng_to_xor_shift(gen: &CountingRng) -> XorShiftRng {
+    XorShiftRng(gen.rng.clone())
+}
+
+fn shared_new(seed: VmInt) -> IO<SharedRng> {
+    IO::Value(SharedRng(Arc::new(Mutex::new(
+        self::rand::rngs::StdRng::seed_from_u64(seed as u64),
+    ))))
+}
+
+fn shared_next(gen: &SharedRng) -> IO<VmInt> {
+    IO::Value(gen.0.lock().unwrap().gen())
+}
+
 mod std {
     pub mod random {
         pub use crate::rand_bind as prim;
     }
 }
 
+/// Loads `std.random.prim` with the `thread_rng` functions drawing from a `StdRng` which is seeded
+/// from the operating system
 pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    load_with(vm, RngConfig::default())
+}
+
+/// Loads `std.random.prim` with the `thread_rng` functions drawing from the generator selected by
+/// `config`. A host which wants reproducible draws can replace the default loader before the
+/// module is imported:
+///
+/// ```
+/// # extern crate gluon;
+/// # use gluon::{import::add_extern_module, new_vm, rand_bind::{self, RngConfig}};
+/// # fn main() {
+/// let vm = new_vm();
+/// add_extern_module(&vm, "std.random.prim", |vm| {
+///     rand_bind::load_with(vm, RngConfig::Seed(42))
+/// });
+/// # }
+/// ```
+///
+/// The generators are per OS thread state and the configuration is shared by the whole process,
+/// so the configuration of the module that was loaded last is used by every vm. Each load also
+/// recreates the generators, so every vm that loads the module with the same seed sees the same
+/// draws.
+pub fn load_with(vm: &Thread, config: RngConfig) -> vm::Result<ExternModule> {
     use self::std;
 
+    set_rng_config(config);
+
     vm.register_type::<XorShiftRng>("XorShiftRng", &[])?;
+    vm.register_type::<ChaChaRng>("ChaChaRng", &[])?;
+    vm.register_type::<StdRng>("StdRng", &[])?;
+    vm.register_type::<CountingRng>("CountingRng", &[])?;
+    vm.register_type::<SharedRng>("SharedRng", &[])?;
+    vm.register_type::<UniformInt>("UniformInt", &[])?;
+    vm.register_type::<WeightedSampler>("WeightedSampler", &[])?;
 
     ExternModule::new(
         vm,
         record! {
             type XorShiftRng => XorShiftRng,
+            type ChaChaRng => ChaChaRng,
+            type StdRng => StdRng,
+            type CountingRng => CountingRng,
+            type SharedRng => SharedRng,
+            type UniformInt => UniformInt,
+            type WeightedSampler => WeightedSampler,
+            show_error => primitive!(1, std::random::prim::show_error),
+            seed_global => primitive!(1, std::random::prim::seed_global),
+            with_seed => primitive!(2, async fn std::random::prim::with_seed),
             next_int => primitive!(1, std::random::prim::next_int),
+            next_u64 => primitive!(1, std::random::prim::next_u64),
+            next_int_bits => primitive!(1, std::random::prim::next_int_bits),
             next_float => primitive!(1, std::random::prim::next_float),
+            next_float_open => primitive!(1, std::random::prim::next_float_open),
+            next_float_canonical => primitive!(1, std::random::prim::next_float_canonical),
+            next_float32 => primitive!(1, std::random::prim::next_float32),
+            fill_bytes => primitive!(1, std::random::prim::fill_bytes),
+            gen_alphanumeric => primitive!(1, std::random::prim::gen_alphanumeric),
+            gen_int_array => primitive!(1, std::random::prim::gen_int_array),
             gen_int_range => primitive!(2, std::random::prim::gen_int_range),
+            gen_int_range_saturating =>
+                primitive!(2, std::random::prim::gen_int_range_saturating),
+            uniform_int_new => primitive!(2, std::random::prim::uniform_int_new),
+            uniform_int_sample => primitive!(1, std::random::prim::uniform_int_sample),
+            gen_int_range_inclusive => primitive!(2, std::random::prim::gen_int_range_inclusive),
+            gen_duration_millis => primitive!(2, std::random::prim::gen_duration_millis),
+            gen_char_range => primitive!(2, std::random::prim::gen_char_range),
+            roll => primitive!(2, std::random::prim::roll),
+            gen_float_range => primitive!(2, std::random::prim::gen_float_range),
+            gen_float_range_inclusive =>
+                primitive!(2, std::random::prim::gen_float_range_inclusive),
+            gen_bool => primitive!(1, std::random::prim::gen_bool),
+            bernoulli_trials => primitive!(2, std::random::prim::bernoulli_trials),
+            next_normal => primitive!(2, std::random::prim::next_normal),
+            next_log_normal => primitive!(2, std::random::prim::next_log_normal),
+            sample_normal_array => primitive!(3, std::random::prim::sample_normal_array),
+            sample_simplex => primitive!(1, std::random::prim::sample_simplex),
+            next_exponential => primitive!(1, std::random::prim::next_exponential),
+            next_triangular => primitive!(3, std::random::prim::next_triangular),
+            next_poisson => primitive!(1, std::random::prim::next_poisson),
+            next_geometric => primitive!(1, std::random::prim::next_geometric),
+            shuffle => primitive!(1, std::random::prim::shuffle),
+            shuffle_string => primitive!(1, std::random::prim::shuffle_string),
+            permutation => primitive!(1, std::random::prim::permutation),
+            choose => primitive!(1, std::random::prim::choose),
+            choose_weighted => primitive!(2, std::random::prim::choose_weighted),
+            weighted_alias_new => primitive!(1, std::random::prim::weighted_alias_new),
+            weighted_sample => primitive!(1, std::random::prim::weighted_sample),
+            sample => primitive!(2, std::random::prim::sample),
+            choose_multiple => primitive!(2, std::random::prim::choose_multiple),
+            sample_without_replacement =>
+                primitive!(2, std::random::prim::sample_without_replacement),
+            sample_indices => primitive!(2, std::random::prim::sample_indices),
+            shuffle_with => primitive!(2, std::random::prim::shuffle_with),
+            choose_with => primitive!(2, std::random::prim::choose_with),
+            sample_with => primitive!(3, std::random::prim::sample_with),
+            choose_weighted_with => primitive!(3, std::random::prim::choose_weighted_with),
+            gen_int_range_with => primitive!(3, std::random::prim::gen_int_range_with),
+            gen_float_range_with => primitive!(3, std::random::prim::gen_float_range_with),
+            gen_bool_with => primitive!(2, std::random::prim::gen_bool_with),
+            next_normal_with => primitive!(3, std::random::prim::next_normal_with),
+            next_exponential_with => primitive!(2, std::random::prim::next_exponential_with),
             xor_shift_new => primitive!(1, std::random::prim::xor_shift_new),
-            xor_shift_next => primitive!(1, std::random::prim::xor_shift_next)
+            xor_shift_try_new => primitive!(1, std::random::prim::xor_shift_try_new),
+            xor_shift_from_int => primitive!(1, std::random::prim::xor_shift_from_int),
+            xor_shift_from_u64 => primitive!(1, std::random::prim::xor_shift_from_u64),
+            xor_shift_from_ints => primitive!(1, std::random::prim::xor_shift_from_ints),
+            xor_shift_from_string => primitive!(1, std::random::prim::xor_shift_from_string),
+            xor_shift_reseed => primitive!(2, std::random::prim::xor_shift_reseed),
+            xor_shift_try_reseed => primitive!(2, std::random::prim::xor_shift_try_reseed),
+            xor_shift_serialize => primitive!(1, std::random::prim::xor_shift_serialize),
+            xor_shift_deserialize => primitive!(1, std::random::prim::xor_shift_deserialize),
+            xor_shift_checkpoint => primitive!(1, std::random::prim::xor_shift_checkpoint),
+            xor_shift_restore => primitive!(1, std::random::prim::xor_shift_restore),
+            xor_shift_show => primitive!(1, std::random::prim::xor_shift_show),
+            xor_shift_next => primitive!(1, std::random::prim::xor_shift_next),
+            xor_shift_next_float => primitive!(1, std::random::prim::xor_shift_next_float),
+            fill_int_array_with => primitive!(2, std::random::prim::fill_int_array_with),
+            xor_shift_split => primitive!(1, std::random::prim::xor_shift_split),
+            xor_shift_from_rng => primitive!(1, std::random::prim::xor_shift_from_rng),
+            cha_cha_new => primitive!(1, std::random::prim::cha_cha_new),
+            cha_cha_try_new => primitive!(1, std::random::prim::cha_cha_try_new),
+            cha_cha_next => primitive!(1, std::random::prim::cha_cha_next),
+            cha_cha_next_float => primitive!(1, std::random::prim::cha_cha_next_float),
+            std_rng_from_seed => primitive!(1, std::random::prim::std_rng_from_seed),
+            std_rng_from_entropy => primitive!(1, std::random::prim::std_rng_from_entropy),
+            std_rng_next_int => primitive!(1, std::random::prim::std_rng_next_int),
+            std_rng_next_float => primitive!(1, std::random::prim::std_rng_next_float),
+            std_rng_gen_int_range => primitive!(3, std::random::prim::std_rng_gen_int_range),
+            counting_rng_from_int => primitive!(1, std::random::prim::counting_rng_from_int),
+            counting_rng_from_xor_shift => primitive!(1, std::random::prim::counting_rng_from_xor_shift),
+            counting_rng_next => primitive!(1, std::random::prim::counting_rng_next),
+            counting_rng_next_float => primitive!(1, std::random::prim::counting_rng_next_float),
+            counting_rng_bytes_consumed => primitive!(1, std::random::prim::counting_rng_bytes_consumed),
+            counting_rng_to_xor_shift => primitive!(1, std::random::prim::counting_rng_to_xor_shift),
+            shared_new => primitive!(1, std::random::prim::shared_new),
+            shared_next => primitive!(1, std::random::prim::shared_next)
         },
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{new_vm, Compiler};
+
+    fn std_rng_sequence(seed: VmInt) -> Vec<VmInt> {
+        let vm = new_vm();
+        let expr = format!(
+            r#"
+            let random = import! std.random
+            let {{ next }} = random.std_rng.random_gen
+            let a = next (random.std_rng.new {})
+            let b = next a.gen
+            let c = next b.gen
+            [a.value, b.value, c.value]
+            "#,
+            seed
+        );
+        Compiler::new()
+            .run_expr::<Vec<VmInt>>(&vm, "std_rng_sequence", &expr)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .0
+    }
+
+    #[test]
+    fn std_rng_from_seed_is_deterministic_across_vms() {
+        let _ = ::env_logger::try_init();
+
+        assert_eq!(std_rng_sequence(42), std_rng_sequence(42));
+        assert_ne!(std_rng_sequence(42), std_rng_sequence(43));
+    }
+
+    #[test]
+    fn std_rng_from_entropy_generators_differ() {
+        let _ = ::env_logger::try_init();
+
+        let vm = new_vm();
+        let expr = r#"
+            let { wrap } = import! std.applicative
+            let { ? } = import! std.io
+            let random = import! std.random
+            let { next } = random.std_rng.random_gen
+            do a = random.std_rng.from_entropy ()
+            do b = random.std_rng.from_entropy ()
+            wrap ((next a).value, (next b).value)
+        "#;
+        let (result, _) = Compiler::new()
+            .run_io(true)
+            .run_expr::<IO<(VmInt, VmInt)>>(&vm, "std_rng_from_entropy", expr)
+            .unwrap_or_else(|err| panic!("{}", err));
+        match result {
+            // The chance of two entropy seeded generators agreeing on their first value is 2^-64
+            IO::Value((a, b)) => assert_ne!(a, b),
+            IO::Exception(err) => panic!("{}", err),
+            IO::Error(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn shared_rng_clones_draw_each_value_once() {
+        let _ = ::env_logger::try_init();
+
+        let vm = new_vm();
+        // `SharedRng` must be registered before it can be used as the type of the expression
+        Compiler::new()
+            .run_expr::<()>(&vm, "load", "let _ = import! std.random in ()")
+            .unwrap_or_else(|err| panic!("{}", err));
+        let expr = r#"
+            let random = import! std.random
+            random.shared_rng.new 42
+        "#;
+        let (gen, _) = Compiler::new()
+            .run_io(true)
+            .run_expr::<IO<vm::api::UserdataValue<SharedRng>>>(&vm, "shared_rng", expr)
+            .unwrap_or_else(|err| panic!("{}", err));
+        // `UserdataValue` clones the generator out of the vm, the clone still shares its state
+        let gen = match gen {
+            IO::Value(gen) => gen.0,
+            IO::Exception(err) => panic!("{}", err),
+            IO::Error(err) => panic!("{}", err),
+        };
+
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let gen = gen.clone();
+                ::std::thread::spawn(move || {
+                    (0..100)
+                        .map(|_| match shared_next(&gen) {
+                            IO::Value(value) => value,
+                            IO::Exception(err) => panic!("{}", err),
+                            IO::Error(err) => panic!("{}", err),
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut draws: Vec<VmInt> = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect();
+        draws.sort();
+
+        let mut reference = self::rand::rngs::StdRng::seed_from_u64(42);
+        let mut expected: Vec<VmInt> = (0..200).map(|_| reference.gen()).collect();
+        expected.sort();
+        assert_eq!(draws, expected);
+    }
+
+    #[test]
+    fn geometric_draws_saturate_for_tiny_probabilities() {
+        let geometric = geometric_distribution(1e-300).unwrap_or_else(|err| panic!("{}", err));
+        let mut rng = self::rand::rngs::StdRng::seed_from_u64(4);
+        for _ in 0..100 {
+            assert_eq!(geometric.sample(&mut rng), u64::max_value());
+        }
+    }
+
+    #[test]
+    fn uniform_int_new_checks_the_range() {
+        for &(low, high) in &[(5, 5), (6, 5)] {
+            match uniform_int_new(low, high) {
+                RuntimeResult::Panic(err) => assert_eq!(err, RandError::EmptyRange { low, high }),
+                RuntimeResult::Return(_) => panic!("Accepted the range {}..{}", low, high),
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_alias_new_validates_the_weights() {
+        let cases: &[(&[f64], RandError)] = &[
+            (&[], RandError::AllWeightsZero),
+            (&[0.0, 0.0], RandError::AllWeightsZero),
+            (&[1.0, -0.5], RandError::InvalidWeight(-0.5)),
+            (
+                &[1.0, ::std::f64::INFINITY],
+                RandError::InvalidWeight(::std::f64::INFINITY),
+            ),
+        ];
+        for &(weights, ref expected) in cases {
+            match weighted_alias_new(weights) {
+                RuntimeResult::Panic(err) => assert_eq!(err, *expected),
+                RuntimeResult::Return(_) => panic!("Accepted the weights {:?}", weights),
+            }
+        }
+        match weighted_alias_new(&[1.0, ::std::f64::NAN]) {
+            RuntimeResult::Panic(RandError::InvalidWeight(weight)) => assert!(weight.is_nan()),
+            _ => panic!("Accepted a NaN weight"),
+        }
+    }
+
+    #[test]
+    fn weighted_sample_follows_the_weights() {
+        const DRAWS: usize = 100_000;
+
+        let sampler = match weighted_alias_new(&[1.0, 0.0, 2.0, 7.0]) {
+            RuntimeResult::Return(sampler) => sampler,
+            RuntimeResult::Panic(err) => panic!("{}", err),
+        };
+        seed_global(3);
+        let mut counts = [0; 4];
+        for _ in 0..DRAWS {
+            match weighted_sample(&sampler) {
+                IO::Value(index) => counts[index as usize] += 1,
+                IO::Exception(err) => panic!("{}", err),
+                IO::Error(err) => panic!("{}", err),
+            }
+        }
+        assert_eq!(counts[1], 0);
+        for &(index, expected) in &[(0, 0.1), (2, 0.2), (3, 0.7)] {
+            let frequency = counts[index] as f64 / DRAWS as f64;
+            assert!(
+                (frequency - expected).abs() < 0.01,
+                "Index {} was drawn with frequency {}, expected {}",
+                index,
+                frequency,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn sample_char_range_skips_the_surrogates() {
+        let mut gen = self::rand_xorshift::XorShiftRng::seed_from_u64(1);
+        let mut seen = [false; 2];
+        for _ in 0..100 {
+            match sample_char_range(&mut gen, '\u{D7FF}', '\u{E000}') {
+                Ok('\u{D7FF}') => seen[0] = true,
+                Ok('\u{E000}') => seen[1] = true,
+                result => panic!("Sampled {:?} outside of the range", result),
+            }
+        }
+        assert_eq!(seen, [true, true]);
+
+        assert_eq!(
+            sample_char_range(&mut gen, 'b', 'a'),
+            Err(RandError::EmptyCharRange {
+                low: 'b',
+                high: 'a'
+            })
+        );
+    }
+
+    #[test]
+    fn xor_shift_from_u64_matches_rust() {
+        let _ = ::env_logger::try_init();
+
+        let vm = new_vm();
+        let expr = r#"
+            let random = import! std.random
+            (random.xor_shift_rng.random_gen.next (random.xor_shift_from_u64 7)).value
+        "#;
+        let (value, _) = Compiler::new()
+            .run_expr::<VmInt>(&vm, "xor_shift_from_u64", expr)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let mut gen = self::rand_xorshift::XorShiftRng::seed_from_u64(7);
+        assert_eq!(value, gen.gen::<VmInt>());
+    }
+
+    #[test]
+    fn string_seed_is_stable() {
+        // Changing these breaks the reproducibility of every sequence seeded from a string
+        assert_eq!(
+            string_seed(""),
+            [80, 73, 215, 71, 128, 163, 224, 125, 66, 2, 171, 71, 212, 206, 242, 244]
+        );
+        assert_eq!(
+            string_seed("gluon"),
+            [182, 255, 106, 77, 60, 119, 124, 129, 197, 101, 199, 86, 6, 133, 58, 128]
+        );
+        assert_eq!(string_seed("gluon"), string_seed("gluon"));
+    }
+
+    #[test]
+    fn xor_shift_from_string_is_reproducible() {
+        fn sequence(seed: &str) -> Vec<u64> {
+            let mut gen = xor_shift_from_string(seed).0;
+            (0..8).map(|_| gen.next_u64()).collect()
+        }
+
+        assert_eq!(sequence("level-1"), sequence("level-1"));
+
+        let sequences: Vec<_> = (0..100).map(|i| sequence(&i.to_string())).collect();
+        for (i, l) in sequences.iter().enumerate() {
+            for r in &sequences[i + 1..] {
+                assert_ne!(l, r);
+            }
+        }
+        assert_ne!(sequence("seed"), sequence("seed "));
+        assert_ne!(sequence("seed"), sequence("Seed"));
+    }
+
+    #[test]
+    fn xor_shift_from_string_in_gluon() {
+        let _ = ::env_logger::try_init();
+
+        let vm = new_vm();
+        let expr = r#"
+            let random = import! std.random
+            (random.xor_shift_rng.random_gen.next (random.xor_shift_from_string "gluon")).value
+        "#;
+        let (value, _) = Compiler::new()
+            .run_expr::<VmInt>(&vm, "xor_shift_from_string", expr)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        assert_eq!(value, xor_shift_from_string("gluon").0.gen::<VmInt>());
+    }
+
+    #[test]
+    fn xor_shift_show_is_stable_across_clones() {
+        let _ = ::env_logger::try_init();
+
+        let vm = new_vm();
+        let expr = r#"
+            let { Result } = import! std.result
+            let random = import! std.random
+            let { ? } = random
+            let state = [1b, 2b, 3b, 4b, 5b, 6b, 7b, 8b, 9b, 10b, 11b, 12b, 13b, 14b, 15b, 16b]
+            match random.xor_shift_rng.deserialize state with
+            | Ok gen -> show gen
+            | Err _ -> ""
+        "#;
+        let (shown, _) = Compiler::new()
+            .run_expr::<String>(&vm, "xor_shift_show", expr)
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(shown, "XorShiftRng(0x0102030405060708090a0b0c0d0e0f10)");
+
+        let mut gen = xor_shift_from_int(42);
+        let clone = gen.clone();
+        assert_eq!(xor_shift_show(&gen), xor_shift_show(&clone));
+
+        gen.0.next_u32();
+        assert_ne!(xor_shift_show(&gen), xor_shift_show(&clone));
+    }
+
+    #[test]
+    fn counting_rng_counts_every_byte_drawn() {
+        let mut gen = counting_rng_from_int(5);
+        gen.next_u32();
+        gen.fill_bytes(&mut [0; 5]);
+        assert_eq!(counting_rng_bytes_consumed(&gen), 9);
+
+        gen.gen::<VmInt>();
+        gen.gen::<f64>();
+        assert_eq!(counting_rng_bytes_consumed(&gen), 25);
+    }
+
+    #[test]
+    fn xor_shift_reseed_checks_seed_length() {
+        let gen = xor_shift_from_int(1);
+        match xor_shift_reseed(&gen, &[1; 15]) {
+            RuntimeResult::Panic(err) => assert_eq!(
+                err,
+                RandError::BadSeedLength {
+                    expected: 16,
+                    got: 15
+                }
+            ),
+            RuntimeResult::Return(_) => panic!("Accepted a seed of 15 bytes"),
+        }
+    }
+
+    #[test]
+    fn xor_shift_try_reseed_returns_the_reseed_error() {
+        let gen = xor_shift_from_int(1);
+        match (
+            xor_shift_try_reseed(&gen, &[1; 15]),
+            xor_shift_reseed(&gen, &[1; 15]),
+        ) {
+            (Err(err), RuntimeResult::Panic(panic)) => assert_eq!(err, panic),
+            _ => panic!("Accepted a seed of 15 bytes"),
+        }
+    }
+
+    #[test]
+    fn xor_shift_new_checks_seed_length() {
+        for &len in &[0, 15, 17] {
+            match xor_shift_new(&vec![1; len]) {
+                RuntimeResult::Return(_) => panic!("Accepted a seed of {} bytes", len),
+                RuntimeResult::Panic(err) => assert_eq!(
+                    err,
+                    RandError::BadSeedLength {
+                        expected: 16,
+                        got: len
+                    }
+                ),
+            }
+        }
+        match xor_shift_new(&[1; 16]) {
+            RuntimeResult::Return(_) => (),
+            RuntimeResult::Panic(err) => panic!("{}", err),
+        }
+    }
+}
@@ -4,7 +4,12 @@ extern crate regex;
 
 use crate::real_std::error::Error as StdError;
 
-use crate::vm::{self, api::Collect, thread::Thread, ExternModule};
+use crate::vm::{
+    self,
+    api::{Collect, RuntimeResult},
+    thread::Thread,
+    ExternModule,
+};
 
 #[derive(Debug, Userdata)]
 #[gluon(crate_name = "vm")]
@@ -60,6 +65,75 @@ fn captures<'a>(
         .map(Collect::new)
 }
 
+fn named_captures<'a>(re: &Regex, text: &'a str) -> Option<Vec<(String, Match<'a>)>> {
+    let &Regex(ref re) = re;
+    re.captures(text).map(|c| {
+        re.capture_names()
+            .enumerate()
+            .filter_map(|(i, name)| {
+                let name = name?;
+                c.get(i).map(|m| (name.to_string(), Match::new(m)))
+            })
+            .collect()
+    })
+}
+
+fn find_iter<'a>(re: &Regex, text: &'a str) -> Vec<Match<'a>> {
+    let &Regex(ref re) = re;
+    re.find_iter(text).map(Match::new).collect()
+}
+
+/// Checks that every group referenced by `replacement` exists in `re`. The `regex` crate
+/// replaces references to unknown groups with the empty string which hides mistakes.
+fn check_replacement(re: &regex::Regex, replacement: &str) -> Result<(), String> {
+    let is_name_char = |c: char| c == '_' || c.is_ascii_alphanumeric();
+    let mut rest = replacement;
+    while let Some(i) = rest.find('$') {
+        rest = &rest[i + 1..];
+        let name = if rest.starts_with('$') {
+            rest = &rest[1..];
+            continue;
+        } else if rest.starts_with('{') {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| format!("Unterminated group reference in `{}`", replacement))?;
+            let name = &rest[1..end];
+            rest = &rest[end + 1..];
+            name
+        } else {
+            let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            let name = &rest[..end];
+            rest = &rest[end..];
+            name
+        };
+        if name.is_empty() {
+            return Err(format!(
+                "Expected a group name or index after `$` in `{}`, use `$$` for a literal `$`",
+                replacement
+            ));
+        }
+        let exists = match name.parse::<usize>() {
+            Ok(index) => index < re.captures_len(),
+            Err(_) => re.capture_names().any(|n| n == Some(name)),
+        };
+        if !exists {
+            return Err(format!(
+                "The replacement `{}` refers to the group `{}` which does not exist",
+                replacement, name
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn replace_all(re: &Regex, text: &str, replacement: &str) -> RuntimeResult<String, String> {
+    let &Regex(ref re) = re;
+    match check_replacement(re, replacement) {
+        Ok(()) => RuntimeResult::Return(re.replace_all(text, replacement).into_owned()),
+        Err(err) => RuntimeResult::Panic(err),
+    }
+}
+
 fn error_to_string(err: &Error) -> &str {
     let &Error(ref err) = err;
     err.description()
@@ -88,6 +162,9 @@ pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
             is_match => primitive!(2, std::regex::prim::is_match),
             find => primitive!(2, std::regex::prim::find),
             captures => primitive!(2, std::regex::prim::captures),
+            named_captures => primitive!(2, std::regex::prim::named_captures),
+            find_iter => primitive!(2, std::regex::prim::find_iter),
+            replace_all => primitive!(3, std::regex::prim::replace_all),
             error_to_string => primitive!(1, std::regex::prim::error_to_string)
         },
     )
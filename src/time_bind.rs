@@ -0,0 +1,135 @@
+//! Module containing bindings for measuring time and suspending execution.
+
+extern crate tokio_timer;
+
+use crate::real_std::time::{self, SystemTime, UNIX_EPOCH};
+
+use futures::future::Either;
+use futures::Future;
+
+use crate::vm::{
+    self,
+    api::{RuntimeResult, WithVM, IO},
+    thread::Thread,
+    types::VmInt,
+    ExternModule,
+};
+
+#[derive(Clone, Debug, Userdata)]
+#[gluon(crate_name = "::vm")]
+struct Instant(time::Instant);
+
+#[derive(Clone, Copy, Debug, PartialEq, Getable, Pushable, VmType)]
+#[gluon(vm_type = "std.time.types.Duration")]
+#[gluon(crate_name = "::vm")]
+struct Duration {
+    secs: VmInt,
+    nanos: u32,
+}
+
+impl From<time::Duration> for Duration {
+    fn from(duration: time::Duration) -> Duration {
+        Duration {
+            secs: duration.as_secs() as VmInt,
+            nanos: duration.subsec_nanos(),
+        }
+    }
+}
+
+fn to_duration(name: &str, secs: f64) -> Result<time::Duration, String> {
+    if secs >= 0.0 && secs.is_finite() {
+        Ok(time::Duration::from_secs_f64(secs))
+    } else {
+        Err(format!(
+            "{}: Expected a finite, non-negative number of seconds, got {}",
+            name, secs
+        ))
+    }
+}
+
+fn now() -> IO<Instant> {
+    IO::Value(Instant(time::Instant::now()))
+}
+
+fn elapsed(instant: &Instant) -> IO<f64> {
+    IO::Value(instant.0.elapsed().as_secs_f64())
+}
+
+/// Returns the time from `earlier` to `later` or zero if `earlier` is the later of the two
+fn duration_since(later: &Instant, earlier: &Instant) -> Duration {
+    later
+        .0
+        .checked_duration_since(earlier.0)
+        .unwrap_or_default()
+        .into()
+}
+
+fn system_time() -> IO<f64> {
+    IO::Value(match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs_f64(),
+        Err(err) => -err.duration().as_secs_f64(),
+    })
+}
+
+fn duration_from_secs(secs: f64) -> RuntimeResult<Duration, String> {
+    to_duration("duration.from_secs", secs)
+        .map(Duration::from)
+        .into()
+}
+
+fn duration_as_secs(duration: Duration) -> f64 {
+    duration.secs as f64 + f64::from(duration.nanos) / 1e9
+}
+
+/// Suspends the gluon thread for `secs` seconds. The wait is registered with the timer of the
+/// tokio runtime driving the VM so that other tasks can run in the meantime. If there is no such
+/// timer, as when the VM is run synchronously, the OS thread is blocked instead. Either way the
+/// sleep ends early if the thread is interrupted.
+fn sleep(WithVM { vm, value: secs }: WithVM<f64>) -> impl Future<Item = IO<()>, Error = vm::Error> {
+    let duration = match to_duration("sleep", secs) {
+        Ok(duration) => duration,
+        Err(err) => return Either::B(futures::future::err(vm::Error::Message(err))),
+    };
+    let deadline = time::Instant::now() + duration;
+    let interrupt = vm.interrupt_token();
+    Either::A(
+        tokio_timer::Delay::new(deadline)
+            .or_else(move |err| {
+                if err.is_shutdown() {
+                    if let Some(remaining) = deadline.checked_duration_since(time::Instant::now()) {
+                        interrupt.sleep(remaining);
+                    }
+                    Ok(())
+                } else {
+                    Err(vm::Error::Message(format!("sleep: {}", err)))
+                }
+            })
+            .map(IO::Value),
+    )
+}
+
+mod std {
+    pub mod time {
+        pub use crate::time_bind as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    use self::std;
+
+    vm.register_type::<Instant>("Instant", &[])?;
+
+    ExternModule::new(
+        vm,
+        record! {
+            type Instant => Instant,
+            now => primitive!(0, std::time::prim::now),
+            elapsed => primitive!(1, std::time::prim::elapsed),
+            duration_since => primitive!(2, std::time::prim::duration_since),
+            system_time => primitive!(0, std::time::prim::system_time),
+            duration_from_secs => primitive!(1, std::time::prim::duration_from_secs),
+            duration_as_secs => primitive!(1, std::time::prim::duration_as_secs),
+            sleep => primitive!(1, async fn std::time::prim::sleep)
+        },
+    )
+}
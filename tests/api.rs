@@ -1,5 +1,7 @@
 extern crate env_logger;
 extern crate futures;
+extern crate tempfile;
+extern crate tokio;
 #[macro_use]
 extern crate serde_derive;
 
@@ -11,12 +13,15 @@ use futures::future::lazy;
 use futures::{Future, IntoFuture};
 
 use gluon::base::types::{Alias, ArcType, Type};
-use gluon::import::{add_extern_module, Import};
+use gluon::import::{add_extern_module, add_module_source, Import};
 use gluon::vm::api::de::De;
-use gluon::vm::api::{FunctionRef, FutureResult, OpaqueValue, RuntimeResult, Userdata, VmType, IO};
+use gluon::vm::api::{
+    FunctionRef, FutureResult, OpaqueValue, OwnedFunction, RuntimeResult, Userdata, VmType, WithVM,
+    IO,
+};
 use gluon::vm::thread::{RootedThread, Thread, Traverseable};
 use gluon::vm::types::VmInt;
-use gluon::vm::{Error, ExternModule};
+use gluon::vm::{self, Error, ExternModule};
 use gluon::Compiler;
 
 fn load_script(vm: &Thread, filename: &str, input: &str) -> ::gluon::Result<()> {
@@ -266,6 +271,210 @@ fn io_future() {
     assert_eq!(result.0, IO::Value(124));
 }
 
+/// Resolves to `value` once another OS thread has been able to handle it, forcing the gluon thread
+/// to suspend while waiting
+fn delayed<T>(value: T) -> impl Future<Item = T, Error = Error>
+where
+    T: Send + 'static,
+{
+    use futures::sync::oneshot::channel;
+    use std::thread::spawn;
+
+    let (sender, receiver) = channel();
+    spawn(move || sender.send(value).ok().expect("send"));
+    receiver.map_err(|err| Error::Message(format!("{}", err)))
+}
+
+fn fail_later(msg: String) -> impl Future<Item = IO<String>, Error = Error> {
+    delayed(msg).and_then(|msg| Err(Error::Message(msg)))
+}
+
+fn panic_later(
+    msg: String,
+) -> impl Future<Item = IO<RuntimeResult<String, String>>, Error = Error> {
+    delayed(msg).map(|msg| IO::Value(RuntimeResult::Panic(msg)))
+}
+
+fn collect_later(
+    WithVM { vm, value }: WithVM<String>,
+) -> impl Future<Item = IO<String>, Error = Error> {
+    let vm = vm.root_thread();
+    delayed(value).map(move |value| {
+        vm.collect();
+        IO::Value(value)
+    })
+}
+
+fn never(_: ()) -> impl Future<Item = IO<String>, Error = Error> {
+    futures::future::empty()
+}
+
+fn load_async_test(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            fail_later => primitive!(1, async fn fail_later),
+            panic_later => primitive!(1, async fn panic_later),
+            collect_later => primitive!(1, async fn collect_later),
+            never => primitive!(1, async fn never),
+        },
+    )
+}
+
+#[test]
+fn delayed_future_errors_are_raised() {
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let io @ { ? } = import! std.io
+        let { wrap } = import! std.applicative
+        let { fail_later, panic_later } = import! async_test
+        do failed = io.catch (fail_later "connection refused") wrap
+        do panicked = io.catch (panic_later "timed out") wrap
+        wrap [failed, panicked]
+    "#;
+
+    let vm = make_vm();
+    add_extern_module(&vm, "async_test", load_async_test);
+
+    let (result, _) = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<Vec<String>>>(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    match result {
+        IO::Value(messages) => {
+            assert!(
+                messages[0].starts_with("connection refused"),
+                "{}",
+                messages[0]
+            );
+            assert!(messages[1].starts_with("timed out"), "{}", messages[1]);
+        }
+        IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
+    }
+}
+
+#[test]
+fn delayed_future_keeps_stack_alive_across_collection() {
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let io @ { ? } = import! std.io
+        let { wrap } = import! std.applicative
+        let { (++) } = import! std.string
+        let { collect_later } = import! async_test
+        let before = "live " ++ "value"
+        let record = { before, exclaimed = before ++ "!" }
+        do after = collect_later " after"
+        wrap (record.before ++ record.exclaimed ++ after)
+    "#;
+
+    let vm = make_vm();
+    add_extern_module(&vm, "async_test", load_async_test);
+
+    let (result, _) = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<String>>(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, IO::Value("live valuelive value! after".to_string()));
+}
+
+#[test]
+fn call_async_awaits_async_primitives() {
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+        let { (++) } = import! std.string
+        let { collect_later } = import! async_test
+        \name ->
+            do greeting = collect_later "Hello "
+            wrap (greeting ++ name)
+    "#;
+
+    let vm = make_vm();
+    add_extern_module(&vm, "async_test", load_async_test);
+
+    let (mut greet, _) = Compiler::new()
+        .run_expr::<OwnedFunction<fn(String) -> IO<String>>>(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let (sender, receiver) = futures::sync::oneshot::channel();
+    tokio::run(lazy(move || {
+        tokio::spawn(
+            greet
+                .call_async("world".to_string())
+                .then(|result| sender.send(result).map_err(|_| ())),
+        )
+    }));
+    let result = receiver
+        .wait()
+        .unwrap()
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, IO::Value("Hello world".to_string()));
+}
+
+#[test]
+fn call_async_resolves_to_the_error() {
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let { fail_later } = import! async_test
+        fail_later
+    "#;
+
+    let vm = make_vm();
+    add_extern_module(&vm, "async_test", load_async_test);
+
+    let (mut fail, _) = Compiler::new()
+        .run_expr::<OwnedFunction<fn(String) -> IO<String>>>(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let result = lazy(|| fail.call_async("connection refused".to_string())).wait();
+    match result {
+        Err(err) => assert!(err.to_string().starts_with("connection refused"), "{}", err),
+        Ok(value) => panic!("Expected an error, got {:?}", value),
+    }
+    // The thread can be called again after the error
+    let result = lazy(|| fail.call_async("timed out".to_string())).wait();
+    assert!(result.is_err());
+}
+
+#[test]
+fn dropping_call_async_leaves_the_thread_usable() {
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let { never, collect_later } = import! async_test
+        (never, collect_later)
+    "#;
+
+    let vm = make_vm();
+    add_extern_module(&vm, "async_test", load_async_test);
+
+    let ((mut never, mut collect_later), _) = Compiler::new()
+        .run_expr::<(
+            OwnedFunction<fn(()) -> IO<String>>,
+            OwnedFunction<fn(String) -> IO<String>>,
+        )>(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    lazy(|| {
+        let mut pending = never.call_async(());
+        assert!(pending.poll().unwrap().is_not_ready());
+        Ok::<_, ()>(())
+    })
+    .wait()
+    .unwrap();
+
+    let result = lazy(|| collect_later.call_async("after".to_string()))
+        .wait()
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, IO::Value("after".to_string()));
+}
+
 #[test]
 fn generic_record_type() {
     use gluon::base::types::ArcType;
@@ -296,6 +505,31 @@ fn tuples_start_at_0() {
     );
 }
 
+#[test]
+fn pushed_tuple_fields_are_accessible() {
+    let _ = ::env_logger::try_init();
+
+    fn pair(_: ()) -> (i32, String) {
+        (1, "two".to_string())
+    }
+
+    let expr = r#"
+        let pair = import! pair
+        let p = pair ()
+        p._1
+    "#;
+
+    let vm = make_vm();
+    add_extern_module(&vm, "pair", |thread| {
+        ExternModule::new(thread, primitive!(1, pair))
+    });
+
+    let (result, _) = Compiler::new()
+        .run_expr::<String>(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "two");
+}
+
 #[test]
 fn use_type_from_type_field() {
     let _ = ::env_logger::try_init();
@@ -405,3 +639,287 @@ fn runtime_result_vm_type_forwarding() {
         .run_expr::<IO<()>>(&vm, "test", text)
         .unwrap_or_else(|err| panic!("{}", err));
 }
+
+#[test]
+fn nested_extern_modules() {
+    let _ = ::env_logger::try_init();
+
+    #[derive(Debug)]
+    struct LeftHandle(VmInt);
+    impl Userdata for LeftHandle {}
+    impl Traverseable for LeftHandle {}
+    impl VmType for LeftHandle {
+        type Type = LeftHandle;
+    }
+
+    #[derive(Debug)]
+    struct RightHandle(VmInt);
+    impl Userdata for RightHandle {}
+    impl Traverseable for RightHandle {}
+    impl VmType for RightHandle {
+        type Type = RightHandle;
+    }
+
+    let vm = make_vm();
+
+    add_extern_module(&vm, "parent", |vm| {
+        vm.register_type::<LeftHandle>("parent.left.Handle", &[])?;
+        vm.register_type::<RightHandle>("parent.right.Handle", &[])?;
+        ExternModule::new(
+            vm,
+            record! {
+                value => 1,
+                left => record! {
+                    type Handle => LeftHandle,
+                    new => primitive!(1, |i: VmInt| LeftHandle(i)),
+                    get => primitive!(1, |h: &LeftHandle| h.0)
+                },
+                right => record! {
+                    type Handle => RightHandle,
+                    new => primitive!(1, |i: VmInt| RightHandle(i)),
+                    get => primitive!(1, |h: &RightHandle| h.0 * 10)
+                }
+            },
+        )
+    });
+
+    // Importing a submodule first also loads its parent
+    let text = r#"
+        let left = import! parent.left
+        let right = import! parent.right
+        let parent = import! parent
+        let l : left.Handle = left.new 2
+        let r : right.Handle = right.new 3
+        parent.value + left.get l + right.get r
+    "#;
+    let (result, _) = Compiler::new()
+        .run_expr::<VmInt>(&vm, "test", text)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 33);
+
+    let text = r#"
+        let left = import! parent.left
+        let right = import! parent.right
+        right.get (left.new 1)
+    "#;
+    let err = Compiler::new()
+        .run_expr::<VmInt>(&vm, "mismatch", text)
+        .unwrap_err();
+    assert!(err.to_string().contains("parent.right.Handle"), "{}", err);
+
+    let text = r#"
+        import! parent.value
+    "#;
+    let err = Compiler::new()
+        .run_expr::<VmInt>(&vm, "not_a_module", text)
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Could not find module 'parent.value'"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn import_module_source() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    add_module_source(
+        &vm,
+        "my.virtual.module",
+        r#"
+        let twice x : Int -> Int = x * 2
+        { twice }
+        "#,
+    );
+    let expr = r#"
+        let { twice } = import! my.virtual.module
+        twice 21
+    "#;
+    let (result, _) = Compiler::new()
+        .run_expr::<VmInt>(&vm, "example", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 42);
+    assert!(vm.global_env().global_exists("my.virtual.module"));
+}
+
+#[test]
+fn module_source_errors_use_the_module_name() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    add_module_source(&vm, "my.virtual.broken", r#" 1 + "" "#);
+    let err = Compiler::new()
+        .run_expr::<VmInt>(&vm, "example", " import! my.virtual.broken ")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("my.virtual.broken"), "{}", err);
+}
+
+#[test]
+fn changed_module_source_is_compiled_again() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let expr = " let { value } = import! my.virtual.changing in value ";
+
+    add_module_source(&vm, "my.virtual.changing", " { value = 1 } ");
+    let (result, _) = Compiler::new()
+        .run_expr::<VmInt>(&vm, "example", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 1);
+
+    add_module_source(&vm, "my.virtual.changing", " { value = 2 } ");
+    let (result, _) = Compiler::new()
+        .run_expr::<VmInt>(&vm, "example", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 2);
+}
+
+#[test]
+fn reload_module() {
+    use std::fs;
+
+    let _ = ::env_logger::try_init();
+
+    let dir = tempfile::tempdir().unwrap();
+    let vm = make_vm();
+    vm.get_macros()
+        .get("import")
+        .as_ref()
+        .and_then(|import| import.downcast_ref::<Import>())
+        .expect("Import macro")
+        .add_path(dir.path());
+
+    fs::create_dir(dir.path().join("reload")).unwrap();
+    let module_path = dir.path().join("reload/module.glu");
+    fs::write(&module_path, " let f x : Int -> Int = x + 1 in { f } ").unwrap();
+    fs::write(
+        dir.path().join("reload/dependent.glu"),
+        " let { f } = import! reload.module in { g = \\x -> f (f x) } ",
+    )
+    .unwrap();
+
+    let run = |expr| {
+        Compiler::new()
+            .run_expr::<VmInt>(&vm, "example", expr)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .0
+    };
+    let call_f = " let { f } = import! reload.module in f 10 ";
+    let call_g = " let { g } = import! reload.dependent in g 10 ";
+    assert_eq!(run(call_f), 11);
+    assert_eq!(run(call_g), 12);
+
+    // Importing the module again uses the module that was already loaded
+    fs::write(&module_path, " let f x : Int -> Int = x * 2 in { f } ").unwrap();
+    assert_eq!(run(call_f), 11);
+
+    Compiler::new()
+        .reload_module(&vm, "reload.module")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(run(call_f), 20);
+    // `reload.dependent` was unloaded and is compiled again with the new module
+    assert!(!vm.global_env().global_exists("reload.dependent"));
+    assert_eq!(run(call_g), 40);
+}
+
+#[test]
+fn insert_into_btree_map() {
+    use std::collections::BTreeMap;
+
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let map @ { ? } = import! std.map
+        let { ? } = import! std.string
+        \m -> (map.find "b" m, map.insert "c" 3 m)
+    "#;
+    let vm = make_vm();
+    Compiler::new()
+        .run_expr::<()>(&vm, "load", " let _ = import! std.map in () ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    // Load std.map so that the `Map` type can be found through the `VmType` trait
+    Compiler::new()
+        .run_expr::<()>(&vm, "load", " let _ = import! std.map in () ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let (mut insert, _) = Compiler::new()
+        .run_expr::<OwnedFunction<
+            fn(BTreeMap<String, VmInt>) -> (Option<VmInt>, BTreeMap<String, VmInt>),
+        >>(&vm, "example", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let mut map = BTreeMap::new();
+    for (i, key) in ["a", "b", "d", "e", "f"].iter().enumerate() {
+        map.insert(key.to_string(), i as VmInt);
+    }
+    let (found, result) = insert.call(map.clone()).unwrap();
+    assert_eq!(found, Some(1));
+    map.insert("c".to_string(), 3);
+    assert_eq!(result, map);
+    assert_eq!(
+        result.keys().map(|key| &key[..]).collect::<Vec<_>>(),
+        ["a", "b", "c", "d", "e", "f"]
+    );
+
+    assert_eq!(
+        insert.call(BTreeMap::new()).unwrap(),
+        (None, {
+            let mut map = BTreeMap::new();
+            map.insert("c".to_string(), 3);
+            map
+        })
+    );
+}
+
+#[test]
+fn insert_into_hash_map() {
+    use std::collections::HashMap;
+
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let map @ { Map, ? } = import! std.map
+        let { ? } = import! std.int
+        let { ? } = import! std.array
+        let { (<>) } = import! std.semigroup
+        let keys : Map Int String -> Array Int = map.foldr_with_key (\k _ acc -> [k] <> acc) []
+        \m -> (keys m, map.insert 0 "zero" m)
+    "#;
+    let vm = make_vm();
+    Compiler::new()
+        .run_expr::<()>(&vm, "load", " let _ = import! std.map in () ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let (mut insert, _) = Compiler::new()
+        .run_expr::<OwnedFunction<
+            fn(HashMap<VmInt, String>) -> (Vec<VmInt>, HashMap<VmInt, String>),
+        >>(&vm, "example", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let mut map: HashMap<_, _> = (1..20).map(|i| (i, i.to_string())).collect();
+    let (keys, result) = insert
+        .call(map.clone())
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(keys, (1..20).collect::<Vec<_>>());
+    map.insert(0, "zero".to_string());
+    assert_eq!(result, map);
+}
+
+#[test]
+#[should_panic(expected = "Distinct keys of the map were converted to the same key")]
+fn map_keys_which_collide_on_conversion() {
+    use std::collections::BTreeMap;
+
+    let expr = r#"
+        let map @ { ? } = import! std.map
+        let { ? } = import! std.int
+        map.insert 65537 "b" (map.singleton 1 "a")
+    "#;
+    let vm = make_vm();
+    Compiler::new()
+        .run_expr::<()>(&vm, "load", " let _ = import! std.map in () ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let _ = Compiler::new().run_expr::<BTreeMap<i16, String>>(&vm, "example", expr);
+}
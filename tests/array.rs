@@ -107,3 +107,132 @@ array.foldable.foldl (\x y -> y.x) 0 [{ x = 4 }]
 "#,
 4
 }
+
+test_expr! { array_slice_whole_array,
+r#"
+let array = import! std.array.prim
+let arr = [1, 2, 3]
+let whole = array.slice arr 0 3
+let part = array.slice arr 1 3
+array.len whole #Int== 3 && array.index whole 2 #Int== 3
+    && array.len part #Int== 2 && array.index part 0 #Int== 2
+"#,
+true
+}
+
+test_expr! { array_unsafe_index,
+r#"
+let array = import! std.array
+let arr = [{ x = 1 }, { x = 2 }]
+(array.unsafe_index arr 1).x #Int== 2 && array.unsafe_index [1b, 2b] 0 #Byte== 1b
+"#,
+true
+}
+
+test_expr! { io array_buf,
+r#"
+let { wrap } = import! std.applicative
+let { flat_map } = import! std.monad
+let { ? } = import! std.io
+let { buf } = import! std.array
+do xs = buf.new ()
+seq buf.push 1 xs
+seq buf.push 2 xs
+seq buf.push 3 xs
+seq buf.set 1 20 xs
+do first = buf.get 0 xs
+do len = buf.len xs
+do frozen = buf.freeze xs
+// The frozen array is a copy which is not affected by later updates
+seq buf.set 0 10 xs
+wrap (first, len, frozen)
+"#,
+(1i32, 3i32, vec![1i32, 20, 3])
+}
+
+test_expr! { io array_buf_out_of_range,
+r#"
+let { (*>), wrap } = import! std.applicative
+let { flat_map } = import! std.monad
+let io @ { ? } = import! std.io
+let string = import! std.string
+let { buf } = import! std.array
+do xs = buf.new ()
+seq buf.push "a" xs
+do msg = io.catch (buf.set 1 "b" xs *> wrap "") wrap
+wrap (string.starts_with msg "index 1 is out of range for array buffer of length 1")
+"#,
+true
+}
+
+// Every collection traverses the whole buffer so building it would take quadratic time if the
+// collector did not count the memory of the buffer when deciding when to collect next
+test_expr! { io array_buf_million_elements,
+r#"
+let { wrap } = import! std.applicative
+let { flat_map } = import! std.monad
+let { ? } = import! std.io
+let array @ { buf } = import! std.array
+do xs = buf.new ()
+rec let fill i =
+    if i #Int== 1000000 then wrap ()
+    else
+        seq buf.push i xs
+        fill (i #Int+ 1)
+seq fill 0
+do frozen = buf.freeze xs
+wrap (array.len frozen, array.index frozen 0, array.index frozen 999999)
+"#,
+(1_000_000i32, 0i32, 999_999i32)
+}
+
+#[test]
+fn array_buf_keeps_values_from_child_threads_alive() {
+    use gluon::vm::api::{OpaqueValue, OwnedFunction, IO};
+    use gluon::vm::primitives::ArrayBuf;
+    use gluon::RootedThread;
+
+    let _ = ::env_logger::try_init();
+
+    type Buf = OpaqueValue<RootedThread, ArrayBuf<String>>;
+    type Freeze = OwnedFunction<fn(Buf) -> IO<Vec<String>>>;
+
+    let vm = support::make_vm();
+    // `ArrayBuf` must be registered before it can be used as the type of an expression
+    support::run_expr::<()>(&vm, "let _ = import! std.array in ()");
+    let buf: IO<Buf> = support::run_expr(&vm, "let { buf } = import! std.array in buf.new ()");
+    let buf = match buf {
+        IO::Value(buf) => buf,
+        IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
+    };
+
+    {
+        // Strings created by `++` in the child thread are allocated on the child's heap
+        let child = vm.new_thread().unwrap();
+        let mut fill: OwnedFunction<fn(Buf) -> IO<()>> = support::run_expr(
+            &child,
+            r#"
+            let { (*>) } = import! std.applicative
+            let { ? } = import! std.io
+            let { (++) } = import! std.string
+            let { buf } = import! std.array
+            \xs -> buf.push ("a" ++ "b") xs *> buf.push ("c" ++ "d") xs
+            "#,
+        );
+        match fill.call(buf.clone()).unwrap() {
+            IO::Value(()) => (),
+            IO::Exception(err) => panic!("{}", err),
+            IO::Error(err) => panic!("{}", err),
+        }
+    }
+    vm.collect();
+
+    let mut freeze: Freeze =
+        support::run_expr(&vm, "let { buf } = import! std.array in buf.freeze");
+    match freeze.call(buf).unwrap() {
+        IO::Value(values) => assert_eq!(values, ["ab", "cd"]),
+        IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
+    }
+}
@@ -5,13 +5,15 @@ extern crate futures;
 extern crate gluon;
 
 use std::collections::BTreeMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 
 use futures::{Async, Future};
 
 use gluon::base::pos::Line;
 use gluon::base::types::{ArcType, Type, TypeExt};
 use gluon::vm::compiler::UpvarInfo;
+use gluon::vm::debugger::{DebugAction, DebugSnapshot, SourceLocation};
 use gluon::vm::thread::{HookFlags, ThreadInternal};
 use gluon::{new_vm, Compiler};
 
@@ -64,6 +66,8 @@ fn run_line_hook_test(source: &str) -> Vec<Line> {
     }
     let mut execute = Compiler::new()
         .implicit_prelude(false)
+        // Keep every line of the source, unused bindings would otherwise be removed
+        .fold_constants(false)
         .run_expr_async::<i32>(&thread, "test", source)
         .map(|_| ());
     let mut result = Ok(Async::NotReady);
@@ -244,6 +248,8 @@ fn read_variables() {
     "#;
     Compiler::new()
         .implicit_prelude(false)
+        // Folding removes the unused bindings which are inspected below
+        .fold_constants(false)
         .run_expr::<i32>(&thread, "test", expr)
         .unwrap();
 
@@ -412,6 +418,8 @@ fn upvars() {
     "#;
     Compiler::new()
         .implicit_prelude(false)
+        // The constants would otherwise be inlined into the closures instead of captured
+        .fold_constants(false)
         .run_expr::<i32>(&thread, "test", expr)
         .unwrap();
 
@@ -471,3 +479,154 @@ fn implicit_prelude_variable_names() {
         _ => panic!("{:#?}", f[0]),
     }
 }
+
+fn function_names(snapshot: &DebugSnapshot) -> Vec<&str> {
+    snapshot
+        .call_stack
+        .iter()
+        .map(|entry| &entry.function_name[..])
+        .collect()
+}
+
+#[test]
+fn breakpoint_suspends_the_thread_until_resumed() {
+    let _ = env_logger::try_init();
+
+    let vm = new_vm();
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    vm.set_breakpoint("test", Line::from(3));
+    vm.set_debug_callback(Some(Box::new(move |snapshot| {
+        sender.lock().unwrap().send(snapshot.clone()).unwrap();
+        DebugAction::Suspend
+    })));
+
+    let expr = r#"
+let add x y =
+    let sum = x #Int+ y
+    sum
+let result = add 1 2
+result
+"#;
+    let runner = vm.clone();
+    let execute = thread::spawn(move || {
+        Compiler::new()
+            .implicit_prelude(false)
+            .run_expr_async::<i32>(&runner, "test", expr)
+            .wait()
+            .map(|(value, _)| value)
+    });
+
+    let snapshot = receiver.recv().unwrap();
+    assert_eq!(
+        snapshot.location,
+        SourceLocation {
+            source_name: "test".to_string(),
+            line: Some(Line::from(3)),
+        }
+    );
+    let sum = snapshot
+        .locals
+        .iter()
+        .find(|local| local.name == "sum")
+        .expect("sum");
+    assert_eq!(sum.value, "3");
+    assert_eq!(sum.typ, Type::int());
+    assert_eq!(function_names(&snapshot), ["add", "test"]);
+
+    vm.continue_to_breakpoint();
+    assert_eq!(execute.join().unwrap().unwrap(), 3);
+}
+
+#[test]
+fn step_into_over_and_out() {
+    let _ = env_logger::try_init();
+
+    let vm = new_vm();
+    let stops = Arc::new(Mutex::new(Vec::new()));
+    {
+        let stops = stops.clone();
+        let mut actions = vec![
+            DebugAction::StepInto,
+            DebugAction::StepOut,
+            DebugAction::StepOver,
+            DebugAction::Continue,
+        ]
+        .into_iter();
+        vm.set_breakpoint("test", Line::from(3));
+        vm.set_debug_callback(Some(Box::new(move |snapshot| {
+            stops.lock().unwrap().push((
+                snapshot.call_stack[0].function_name.clone(),
+                snapshot.location.line.unwrap(),
+            ));
+            actions.next().unwrap_or(DebugAction::Continue)
+        })));
+    }
+
+    let expr = r#"
+let inc x = x #Int+ 1
+let twice x =
+    let y = inc x
+    let z = inc y
+    z
+twice 1
+"#;
+    let (result, _) = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&vm, "test", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 3);
+    assert_eq!(
+        *stops.lock().unwrap(),
+        [
+            ("twice".to_string(), Line::from(3)),
+            ("inc".to_string(), Line::from(1)),
+            ("twice".to_string(), Line::from(4)),
+            ("twice".to_string(), Line::from(5)),
+        ]
+    );
+}
+
+#[test]
+fn breakpoint_in_imported_module() {
+    let _ = env_logger::try_init();
+
+    let vm = new_vm();
+    let snapshots = Arc::new(Mutex::new(Vec::new()));
+    {
+        let snapshots = snapshots.clone();
+        vm.set_breakpoint("math", Line::from(1));
+        vm.set_debug_callback(Some(Box::new(move |snapshot| {
+            snapshots.lock().unwrap().push(snapshot.clone());
+            DebugAction::Continue
+        })));
+    }
+
+    Compiler::new()
+        .implicit_prelude(false)
+        .load_script(&vm, "math", "let double x =\n    x #Int* 2\n{ double }")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let expr = r#"
+let { double } = import! math
+let result = double 21
+result
+"#;
+    let (result, _) = Compiler::new()
+        .implicit_prelude(false)
+        // `double` must be called for its breakpoint to be hit
+        .inline_threshold(0)
+        .run_expr::<i32>(&vm, "test", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 42);
+
+    let snapshots = snapshots.lock().unwrap();
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(snapshots[0].location.source_name, "math");
+    let x = snapshots[0]
+        .locals
+        .iter()
+        .find(|local| local.name == "x")
+        .expect("x");
+    assert_eq!(x.value, "21");
+    assert_eq!(function_names(&snapshots[0]), ["double", "test"]);
+}
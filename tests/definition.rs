@@ -0,0 +1,218 @@
+extern crate env_logger;
+extern crate gluon;
+extern crate gluon_completion as completion;
+
+mod support;
+
+use std::fs;
+
+use gluon::base::ast::SpannedExpr;
+use gluon::base::pos::{BytePos, Span};
+use gluon::base::symbol::Symbol;
+use gluon::{import, Compiler, Thread};
+
+use completion::{Location, Modules};
+
+use crate::support::make_vm;
+
+const ARITHMETIC: &str = r#"
+let add x y = x + y
+let double x = add x x
+{ add, double }
+"#;
+
+const REEXPORT: &str = r#"
+let { add } = import! test.arithmetic
+let arithmetic = import! test.arithmetic
+{ add, twice = arithmetic.double }
+"#;
+
+const MAIN: &str = r#"
+let reexport = import! test.reexport
+let { add } = reexport
+let random = import! std.random
+let x = add 1 (reexport.twice 2)
+let gen = random.xor_shift_rng.from_int 1
+add x (reexport.add 3 4)
+"#;
+
+/// A workspace of typechecked modules along with their sources
+struct Workspace {
+    compiler: Compiler,
+    modules: Vec<(&'static str, String, SpannedExpr<Symbol>)>,
+}
+
+impl Workspace {
+    fn new(vm: &Thread) -> Workspace {
+        import::add_module_source(vm, "test.arithmetic", ARITHMETIC);
+        import::add_module_source(vm, "test.reexport", REEXPORT);
+        let sources = vec![
+            ("test.arithmetic", ARITHMETIC.to_string()),
+            ("test.reexport", REEXPORT.to_string()),
+            ("std.random", fs::read_to_string("std/random.glu").unwrap()),
+            ("main", MAIN.to_string()),
+        ];
+
+        let mut compiler = Compiler::new();
+        let modules = sources
+            .into_iter()
+            .map(|(name, source)| {
+                let (expr, _) = compiler
+                    .typecheck_str(vm, name, &source, None)
+                    .unwrap_or_else(|err| panic!("{}", err));
+                (name, source, expr)
+            })
+            .collect();
+        Workspace { compiler, modules }
+    }
+
+    fn modules(&self) -> Modules {
+        let mut modules = Modules::new();
+        for &(name, _, ref expr) in &self.modules {
+            let span = self.compiler.get_filemap(name).expect("file map").span();
+            modules.insert(name, span, expr);
+        }
+        modules
+    }
+
+    /// Returns the span of the `nth` occurrence of the word `needle` in `module`
+    fn span(&self, module: &str, needle: &str, nth: usize) -> Span<BytePos> {
+        let source = &self
+            .modules
+            .iter()
+            .find(|m| m.0 == module)
+            .expect("module")
+            .1;
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let offset = source
+            .match_indices(needle)
+            .map(|(offset, _)| offset)
+            .filter(|&offset| {
+                !source[..offset].ends_with(is_word)
+                    && !source[offset + needle.len()..].starts_with(is_word)
+            })
+            .nth(nth)
+            .unwrap_or_else(|| panic!("`{}` is not in `{}`", needle, module));
+        let start = self.compiler.get_filemap(module).unwrap().span().start();
+        Span::new(
+            BytePos(start.0 + offset as u32),
+            BytePos(start.0 + (offset + needle.len()) as u32),
+        )
+    }
+
+    fn location(&self, module: &str, needle: &str, nth: usize) -> Location {
+        Location {
+            module: module.to_string(),
+            span: self.span(module, needle, nth),
+        }
+    }
+
+    fn definition(&self, module: &str, needle: &str, nth: usize) -> Option<Location> {
+        let pos = self.span(module, needle, nth).start();
+        completion::definition(&self.modules(), module, pos).ok()
+    }
+
+    fn references(&self, module: &str, needle: &str, nth: usize) -> Vec<Location> {
+        let pos = self.span(module, needle, nth).start();
+        completion::references(&self.modules(), module, pos).unwrap_or_else(|()| {
+            panic!("No references for the `{}` at {:?}", needle, pos);
+        })
+    }
+}
+
+#[test]
+fn definition_of_a_local_binding() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let workspace = Workspace::new(&vm);
+    assert_eq!(
+        workspace.definition("main", "x", 1),
+        Some(workspace.location("main", "x", 0))
+    );
+}
+
+#[test]
+fn definition_through_a_re_export() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let workspace = Workspace::new(&vm);
+    let add = workspace.location("test.arithmetic", "add", 0);
+    // `add` is destructured from `test.reexport` which itself imports it from `test.arithmetic`
+    assert_eq!(workspace.definition("main", "add", 2), Some(add.clone()));
+    assert_eq!(workspace.definition("main", "add", 0), Some(add.clone()));
+    assert_eq!(workspace.definition("main", "add", 1), Some(add));
+}
+
+#[test]
+fn definition_of_a_renamed_field() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let workspace = Workspace::new(&vm);
+    assert_eq!(
+        workspace.definition("main", "twice", 0),
+        Some(workspace.location("test.arithmetic", "double", 0))
+    );
+}
+
+#[test]
+fn definition_of_a_primitive_resolves_to_the_wrapper() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let workspace = Workspace::new(&vm);
+    // `from_int` is defined in Rust, so the definition is the field which re-exports it
+    let wrapper = workspace.span("std.random", "from_int = prim.xor_shift_from_int", 0);
+    assert_eq!(
+        workspace.definition("main", "from_int", 0),
+        Some(Location {
+            module: "std.random".to_string(),
+            span: Span::new(wrapper.start(), BytePos(wrapper.start().0 + 8)),
+        })
+    );
+}
+
+#[test]
+fn references_across_modules() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let workspace = Workspace::new(&vm);
+    let expected = vec![
+        // `double x = add x x`
+        workspace.location("test.arithmetic", "add", 1),
+        // `{ add, double }`
+        workspace.location("test.arithmetic", "add", 2),
+        // `let { add } = import! test.arithmetic`
+        workspace.location("test.reexport", "add", 0),
+        // `{ add, twice = ... }`
+        workspace.location("test.reexport", "add", 1),
+        workspace.location("main", "add", 0),
+        workspace.location("main", "add", 1),
+        workspace.location("main", "add", 2),
+        // `reexport.add 3 4`
+        workspace.location("main", "add", 3),
+    ];
+    assert_eq!(workspace.references("test.arithmetic", "add", 0), expected);
+    // The references are the same from any use of the binding
+    assert_eq!(workspace.references("main", "add", 2), expected);
+}
+
+#[test]
+fn references_through_a_renamed_field() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let workspace = Workspace::new(&vm);
+    assert_eq!(
+        workspace.references("test.arithmetic", "double", 0),
+        vec![
+            workspace.location("test.arithmetic", "double", 1),
+            workspace.location("test.reexport", "double", 0),
+            // `reexport.twice` is `double` under another name
+            workspace.location("main", "twice", 0),
+        ]
+    );
+}
@@ -62,6 +62,54 @@ fn panics_contain_stacktrace() {
     }
 }
 
+#[test]
+fn stacktrace_lists_each_call_of_a_call_chain() {
+    let _ = ::env_logger::try_init();
+
+    let vm = support::make_vm();
+    let text = r#"
+let h x = 1 + error "deep"
+let g x = 1 + h x
+let f x = 1 + g x
+f 1
+"#;
+    let error = Compiler::new()
+        .run_expr::<i32>(&vm, "test", text)
+        .unwrap_err();
+    let frames = error
+        .stacktrace()
+        .unwrap_or_else(|| panic!("Expected an error with a stacktrace: {}", error))
+        .frames
+        .iter()
+        .map(|frame| {
+            let frame = frame.as_ref().unwrap();
+            (
+                frame.name.declared_name().to_string(),
+                frame.source_name.clone(),
+                frame.line.map(|line| line.to_usize() + 1),
+            )
+        })
+        .collect::<Vec<_>>();
+    let test = Some("test".to_string());
+    assert_eq!(
+        frames,
+        vec![
+            ("f".to_string(), test.clone(), Some(4)),
+            ("g".to_string(), test.clone(), Some(3)),
+            ("h".to_string(), test.clone(), Some(2)),
+            ("error".to_string(), None, None),
+        ]
+    );
+
+    let message = error.to_string();
+    assert!(message.contains("deep"), "{}", message);
+    assert!(
+        message.contains("0: f (test:Line 4)\n1: g (test:Line 3)\n2: h (test:Line 2)\n3: error"),
+        "{}",
+        message
+    );
+}
+
 #[test]
 fn undefined_infix() {
     let _ = ::env_logger::try_init();
@@ -0,0 +1,257 @@
+extern crate env_logger;
+extern crate gluon;
+#[macro_use]
+extern crate gluon_vm;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use gluon::import::add_extern_module;
+use gluon::vm::api::{OpaqueValue, Userdata, VmType, IO};
+use gluon::vm::thread::{RootedThread, Thread, Traverseable};
+use gluon::vm::ExternModule;
+use gluon::{new_vm, Compiler, VmBuilder};
+
+/// How many `DropCounter` values have been dropped
+static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+struct DropCounter;
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Userdata for DropCounter {}
+impl Traverseable for DropCounter {}
+impl VmType for DropCounter {
+    type Type = DropCounter;
+}
+
+/// How many `Finalized` values have been finalized and how many of those that were dropped
+/// afterwards
+static FINALIZED: AtomicUsize = AtomicUsize::new(0);
+static DROPPED_AFTER_FINALIZE: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+struct Finalized {
+    finalized: bool,
+}
+
+impl Drop for Finalized {
+    fn drop(&mut self) {
+        if self.finalized {
+            DROPPED_AFTER_FINALIZE.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Userdata for Finalized {
+    fn finalize(&mut self, _thread: &Thread) {
+        assert!(!self.finalized, "Finalized twice");
+        self.finalized = true;
+        FINALIZED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+impl Traverseable for Finalized {}
+impl VmType for Finalized {
+    type Type = Finalized;
+}
+
+fn run_io(vm: &Thread, name: &str, expr: &str) {
+    Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<()>>(vm, name, expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+}
+
+#[test]
+fn collect_now_frees_unreachable_userdata() {
+    let _ = ::env_logger::try_init();
+
+    let vm = new_vm();
+    // Prevent the collections that allocating would otherwise run
+    vm.set_collect_threshold(usize::max_value() / 2);
+
+    vm.register_type::<DropCounter>("DropCounter", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+    add_extern_module(&vm, "drop_counter", |thread| {
+        ExternModule::new(thread, primitive!(1, "drop_counter", |_: ()| DropCounter))
+    });
+
+    let expr = r#"
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+        let new_counter = import! drop_counter
+        let allocate n : Int -> IO () =
+            if n == 0 then wrap ()
+            else
+                let _ = new_counter ()
+                allocate (n - 1)
+        allocate 10
+    "#;
+    run_io(&vm, "allocate", expr);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+
+    let before = vm.gc_stats();
+    let expr = r#"
+        let gc = import! std.gc
+        gc.collect_now ()
+    "#;
+    run_io(&vm, "collect", expr);
+    let after = vm.gc_stats();
+
+    assert_eq!(DROPS.load(Ordering::SeqCst), 10);
+    assert!(after.collections > before.collections);
+    assert!(after.live_objects < before.live_objects);
+    assert!(after.allocated_bytes < before.allocated_bytes);
+}
+
+#[test]
+fn finalizer_runs_after_collect_now() {
+    let _ = ::env_logger::try_init();
+
+    let vm = new_vm();
+    vm.set_collect_threshold(usize::max_value() / 2);
+
+    vm.register_type::<Finalized>("Finalized", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+    add_extern_module(&vm, "finalized", |thread| {
+        ExternModule::new(
+            thread,
+            primitive!(1, "finalized", |_: ()| Finalized { finalized: false }),
+        )
+    });
+
+    let expr = r#"
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+        let gc = import! std.gc
+        let new_finalized = import! finalized
+        let kept = new_finalized ()
+        let _ = new_finalized ()
+        let _ = new_finalized ()
+        seq gc.collect_now ()
+        wrap kept
+    "#;
+    let (kept, _) = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<OpaqueValue<RootedThread, Finalized>>>(&vm, "finalize", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(FINALIZED.load(Ordering::SeqCst), 2);
+    assert_eq!(DROPPED_AFTER_FINALIZE.load(Ordering::SeqCst), 2);
+
+    drop(kept);
+    vm.collect();
+    assert_eq!(FINALIZED.load(Ordering::SeqCst), 3);
+    assert_eq!(DROPPED_AFTER_FINALIZE.load(Ordering::SeqCst), 3);
+}
+
+/// The sum which `RunsGluon` computed by running gluon code from its finalizer
+static FINALIZER_RESULT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+struct RunsGluon;
+
+impl Userdata for RunsGluon {
+    fn finalize(&mut self, thread: &Thread) {
+        let (value, _) = Compiler::new()
+            .run_expr::<i32>(thread, "in_finalizer", "1 + 2")
+            .unwrap_or_else(|err| panic!("{}", err));
+        FINALIZER_RESULT.store(value as usize, Ordering::SeqCst);
+    }
+}
+impl Traverseable for RunsGluon {}
+impl VmType for RunsGluon {
+    type Type = RunsGluon;
+}
+
+#[test]
+fn finalizer_can_run_gluon_code() {
+    let _ = ::env_logger::try_init();
+
+    let vm = new_vm();
+    vm.set_collect_threshold(usize::max_value() / 2);
+
+    vm.register_type::<RunsGluon>("RunsGluon", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+    add_extern_module(&vm, "runs_gluon", |thread| {
+        ExternModule::new(thread, primitive!(1, "runs_gluon", |_: ()| RunsGluon))
+    });
+
+    let expr = r#"
+        let { ? } = import! std.io
+        let gc = import! std.gc
+        let runs_gluon = import! runs_gluon
+        let _ = runs_gluon ()
+        gc.collect_now ()
+    "#;
+    run_io(&vm, "finalize", expr);
+    assert_eq!(FINALIZER_RESULT.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn stats_change_after_allocating_and_collecting() {
+    let _ = ::env_logger::try_init();
+
+    let vm = new_vm();
+    vm.set_collect_threshold(usize::max_value() / 2);
+
+    let expr = r#"
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+        let gc = import! std.gc
+        do before = gc.stats ()
+        let values = [[1, 2], [3]]
+        do allocated = gc.stats ()
+        seq gc.collect_now ()
+        do collected = gc.stats ()
+        wrap [
+            allocated.allocated_bytes > before.allocated_bytes,
+            allocated.live_objects > before.live_objects,
+            collected.collections == allocated.collections + 1,
+            collected.last_pause_ms >= 0.0,
+        ]
+    "#;
+    let (checks, _) = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<Vec<bool>>>(&vm, "stats", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    match checks {
+        IO::Value(checks) => assert_eq!(checks, [true, true, true, true]),
+        IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
+    }
+}
+
+#[test]
+fn growth_factor_reduces_the_number_of_collections() {
+    let _ = ::env_logger::try_init();
+
+    let count_collections = |vm: &RootedThread| {
+        let before = vm.gc_stats().collections;
+        let expr = r#"
+            let { wrap } = import! std.applicative
+            let { ? } = import! std.io
+            let allocate n : Int -> IO () =
+                if n == 0 then wrap ()
+                else
+                    let _ = [n, n, n]
+                    allocate (n - 1)
+            allocate 100000
+        "#;
+        run_io(vm, "allocate", expr);
+        vm.gc_stats().collections - before
+    };
+
+    let default = count_collections(&new_vm());
+    let large_factor =
+        count_collections(&VmBuilder::new().collect_growth_factor(Some(8.0)).build());
+    assert!(
+        large_factor < default,
+        "Expected fewer than {} collections with a factor of 8, got {}",
+        default,
+        large_factor
+    );
+}
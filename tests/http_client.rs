@@ -0,0 +1,333 @@
+#![cfg(feature = "web")]
+extern crate env_logger;
+extern crate futures;
+extern crate hyper;
+extern crate tokio;
+extern crate tokio_timer;
+
+extern crate gluon;
+
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::{future, Future, Stream};
+
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response, Server, StatusCode};
+
+use tokio::runtime::Runtime;
+
+use gluon::vm::api::{Getable, VmType, IO};
+use gluon::vm::types::VmInt;
+use gluon::{new_vm, Compiler};
+
+type Headers = Vec<(String, String)>;
+
+fn respond(
+    request: Request<Body>,
+) -> Box<dyn Future<Item = Response<Body>, Error = hyper::Error> + Send> {
+    let redirect = |status, location: &str| {
+        Response::builder()
+            .status(status)
+            .header("Location", location)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = match request.uri().path() {
+        "/hello" => Response::builder()
+            .header("x-greeting", "hello")
+            .body(Body::from("Hello World"))
+            .unwrap(),
+        "/echo" => {
+            let method = request.method().to_string();
+            let echo = request
+                .headers()
+                .get("x-echo")
+                .map(|value| value.to_str().unwrap().to_string())
+                .unwrap_or_default();
+            return Box::new(request.into_body().concat2().map(move |body| {
+                let body = String::from_utf8(body.to_vec()).unwrap();
+                Response::new(Body::from(format!("{} {} {}", method, echo, body)))
+            }));
+        }
+        "/found" => redirect(StatusCode::FOUND, "/hello"),
+        "/nested/see_other" => redirect(StatusCode::SEE_OTHER, "../echo"),
+        "/loop" => redirect(StatusCode::FOUND, "/loop"),
+        "/slow" => {
+            return Box::new(
+                tokio_timer::Delay::new(Instant::now() + Duration::from_secs(2))
+                    .then(|_| Ok(Response::new(Body::from("Too late")))),
+            );
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    };
+    Box::new(future::ok(response))
+}
+
+/// Starts a server on a background thread, returning the address it listens on
+fn start_server() -> SocketAddr {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(|| service_fn(respond));
+        sender.send(server.local_addr()).unwrap();
+        tokio::run(server.map_err(|err| panic!("{}", err)));
+    });
+    receiver.recv().unwrap()
+}
+
+fn run<T>(expr: &str) -> T
+where
+    T: for<'vm, 'value> Getable<'vm, 'value> + VmType + Send + 'static,
+    T::Type: Sized,
+{
+    let _ = env_logger::try_init();
+
+    let vm = new_vm();
+    let (result, _) = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<T>>(&vm, "test", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    match result {
+        IO::Value(value) => value,
+        IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
+    }
+}
+
+/// Runs `send request` and returns the error it fails with, shown as a string
+fn send_error(request: &str) -> String {
+    let expr = format!(
+        r#"
+        let {{ wrap }} = import! std.applicative
+        let {{ ? }} = import! std.io
+        let {{ Result }} = import! std.result
+        let client @ {{ ? }} = import! std.http.client
+        do result = client.send ({})
+        wrap (match result with
+            | Ok response -> "Unexpected response with status " ++ show response.status
+            | Err err -> show err)
+        "#,
+        request
+    );
+    run(&expr)
+}
+
+#[test]
+fn get() {
+    let addr = start_server();
+
+    let expr = format!(
+        r#"
+        let {{ wrap }} = import! std.applicative
+        let {{ ? }} = import! std.io
+        let client = import! std.http.client
+        do response = client.get "http://{}/hello"
+        wrap (response.status, response.body, response.headers)
+        "#,
+        addr
+    );
+    let (status, body, headers): (VmInt, String, Headers) = run(&expr);
+    assert_eq!(status, 200);
+    assert_eq!(body, "Hello World");
+    assert!(
+        headers.contains(&("x-greeting".to_string(), "hello".to_string())),
+        "{:?}",
+        headers
+    );
+}
+
+#[test]
+fn get_keeps_error_statuses() {
+    let addr = start_server();
+
+    let expr = format!(
+        r#"
+        let {{ wrap }} = import! std.applicative
+        let {{ ? }} = import! std.io
+        let client = import! std.http.client
+        do response = client.get "http://{}/missing"
+        wrap response.status
+        "#,
+        addr
+    );
+    assert_eq!(run::<VmInt>(&expr), 404);
+}
+
+#[test]
+fn send_with_method_headers_and_body() {
+    let addr = start_server();
+
+    let expr = format!(
+        r#"
+        let {{ wrap }} = import! std.applicative
+        let {{ ? }} = import! std.io
+        let {{ (|>) }} = import! std.function
+        let {{ Result }} = import! std.result
+        let client @ {{ ? }} = import! std.http.client
+        let request =
+            client.request "http://{}/echo"
+                |> client.method "PUT"
+                |> client.header "X-Echo" "header"
+                |> client.body "body"
+        do result = client.send request
+        match result with
+        | Ok response -> wrap response.body
+        | Err err -> wrap (show err)
+        "#,
+        addr
+    );
+    assert_eq!(run::<String>(&expr), "PUT header body");
+}
+
+#[test]
+fn follow_redirects() {
+    let addr = start_server();
+
+    let expr = format!(
+        r#"
+        let {{ wrap }} = import! std.applicative
+        let {{ ? }} = import! std.io
+        let client = import! std.http.client
+        do response = client.get "http://{}/found"
+        wrap response.body
+        "#,
+        addr
+    );
+    assert_eq!(run::<String>(&expr), "Hello World");
+
+    // `303 See Other` turns the request into a `GET` without a body
+    let expr = format!(
+        r#"
+        let {{ wrap }} = import! std.applicative
+        let {{ ? }} = import! std.io
+        let {{ (|>) }} = import! std.function
+        let {{ Result }} = import! std.result
+        let client @ {{ ? }} = import! std.http.client
+        let request =
+            client.request "http://{}/nested/see_other"
+                |> client.method "POST"
+                |> client.body "body"
+        do result = client.send request
+        match result with
+        | Ok response -> wrap response.body
+        | Err err -> wrap (show err)
+        "#,
+        addr
+    );
+    assert_eq!(run::<String>(&expr), "GET  ");
+}
+
+#[test]
+fn dont_follow_redirects() {
+    let addr = start_server();
+
+    let expr = format!(
+        r#"
+        let {{ wrap }} = import! std.applicative
+        let {{ ? }} = import! std.io
+        let {{ (|>) }} = import! std.function
+        let {{ Result }} = import! std.result
+        let client @ {{ ? }} = import! std.http.client
+        let request = client.request "http://{}/found" |> client.follow_redirects False
+        do result = client.send request
+        match result with
+        | Ok response -> wrap (response.status, response.headers)
+        | Err err -> error (show err)
+        "#,
+        addr
+    );
+    let (status, headers): (VmInt, Headers) = run(&expr);
+    assert_eq!(status, 302);
+    assert!(
+        headers.contains(&("location".to_string(), "/hello".to_string())),
+        "{:?}",
+        headers
+    );
+}
+
+#[test]
+fn too_many_redirects() {
+    let addr = start_server();
+
+    let request = format!(r#"client.request "http://{}/loop""#, addr);
+    assert_eq!(send_error(&request), "TooManyRedirects");
+}
+
+#[test]
+fn timeout() {
+    let addr = start_server();
+
+    let request = format!(
+        r#"client.timeout 0.2 (client.request "http://{}/slow")"#,
+        addr
+    );
+    let start = Instant::now();
+    assert_eq!(send_error(&request), "Timeout");
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn connection_failed() {
+    // Bind and immediately drop a listener to find a port that nothing listens on
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let request = format!(r#"client.request "http://{}/hello""#, addr);
+    let err = send_error(&request);
+    assert!(err.starts_with("ConnectionFailed"), "{}", err);
+}
+
+#[test]
+fn invalid_url() {
+    for uri in &["not a url", "/hello", "https://localhost/"] {
+        let request = format!(r#"client.request "{}""#, uri);
+        let err = send_error(&request);
+        assert!(err.starts_with("InvalidUrl"), "{}: {}", uri, err);
+    }
+}
+
+#[test]
+fn get_throws_errors() {
+    let expr = r#"
+        let io @ { ? } = import! std.io
+        let client = import! std.http.client
+        io.catch (io.functor.map (\_ -> "") (client.get "not a url")) io.applicative.wrap
+    "#;
+    let err = run::<String>(expr);
+    assert!(err.starts_with("InvalidUrl"), "{}", err);
+}
+
+#[test]
+fn send_on_runtime() {
+    let _ = env_logger::try_init();
+
+    let addr = start_server();
+
+    let expr = format!(
+        r#"
+        let {{ wrap }} = import! std.applicative
+        let {{ ? }} = import! std.io
+        let client = import! std.http.client
+        do response = client.get "http://{}/found"
+        wrap response.body
+        "#,
+        addr
+    );
+    let vm = new_vm();
+    let mut runtime = Runtime::new().unwrap();
+    let (result, _) = runtime
+        .block_on(future::lazy(move || {
+            Compiler::new()
+                .run_io(true)
+                .run_expr_async::<IO<String>>(&vm, "test", &expr)
+        }))
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, IO::Value("Hello World".to_string()));
+}
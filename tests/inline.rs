@@ -0,0 +1,162 @@
+extern crate env_logger;
+extern crate gluon;
+
+mod support;
+
+use gluon::vm;
+use gluon::vm::compiler::CompiledFunction;
+use gluon::{Compiler, Error, Thread};
+
+use crate::support::make_vm;
+
+const MODULE: &str = r#"
+let { error } = import! std.prim
+
+let add x y = x #Int+ y
+
+let fail x : Int -> Int =
+    x #Int+ error "fail"
+
+let count_down x =
+    if x #Int== 0 then 0 else count_down (x #Int- 1)
+
+{ add, fail, count_down }
+"#;
+
+fn load_module(vm: &Thread) {
+    Compiler::new()
+        .implicit_prelude(false)
+        .load_script(vm, "test.inline", MODULE)
+        .unwrap_or_else(|err| panic!("{}", err));
+}
+
+fn instruction_count(function: &CompiledFunction) -> usize {
+    function.instructions.len()
+        + function
+            .inner_functions
+            .iter()
+            .map(instruction_count)
+            .sum::<usize>()
+}
+
+/// Returns the number of instructions that `expr` compiles to, including the instructions of the
+/// functions inside of it
+fn compiled_size(vm: &Thread, inline_threshold: usize, expr: &str) -> usize {
+    let mut compiler = Compiler::new()
+        .implicit_prelude(false)
+        .inline_threshold(inline_threshold);
+    let (typed_expr, _) = compiler
+        .typecheck_str(vm, "test", expr, None)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let module = compiler
+        .compile_script(vm, "test", expr, &typed_expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    instruction_count(&module.function)
+}
+
+#[test]
+fn inline_and_fold_an_imported_function() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    load_module(&vm);
+    let expr = r#"
+let { add } = import! test.inline
+add 1 2
+"#;
+    let folded = r#"
+let { add } = import! test.inline
+3
+"#;
+    assert_eq!(compiled_size(&vm, 8, expr), compiled_size(&vm, 8, folded));
+    assert!(compiled_size(&vm, 8, expr) < compiled_size(&vm, 0, expr));
+}
+
+#[test]
+fn do_not_inline_recursive_functions() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    load_module(&vm);
+    let expr = r#"
+let { count_down } = import! test.inline
+count_down 10
+"#;
+    assert_eq!(compiled_size(&vm, 100, expr), compiled_size(&vm, 0, expr));
+}
+
+#[test]
+fn do_not_inline_functions_above_the_threshold() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    load_module(&vm);
+    let expr = r#"
+let { add } = import! test.inline
+add 1 2
+"#;
+    assert_eq!(compiled_size(&vm, 1, expr), compiled_size(&vm, 0, expr));
+}
+
+#[test]
+fn behaviour_is_preserved() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let exprs = [
+        "let { flip } = import! std.function in flip (-) 1 10",
+        "let { not } = import! std.bool in if not (1 == 2) then 1 else 0",
+        "let { unwrap } = import! std.option in unwrap (Some 3)",
+        "let { (|>) } = import! std.function in 3 |> (\\x -> x * 2) |> (\\x -> x + 1)",
+        "let add x y = x + y in add 1 2 * 3",
+    ];
+    for expr in &exprs {
+        let inlined = Compiler::new()
+            .run_expr::<i32>(&vm, "test", expr)
+            .map(|(value, _)| value)
+            .map_err(|err| err.to_string());
+        let not_inlined = Compiler::new()
+            .inline_threshold(0)
+            .run_expr::<i32>(&vm, "test", expr)
+            .map(|(value, _)| value)
+            .map_err(|err| err.to_string());
+        assert_eq!(inlined, not_inlined, "{}", expr);
+    }
+}
+
+#[test]
+fn stacktrace_contains_the_inlined_function() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    load_module(&vm);
+    let expr = r#"
+let { fail } = import! test.inline
+let f x =
+    1 #Int+ fail x
+f 1
+"#;
+    let result = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&vm, "test", expr);
+    match result {
+        Err(Error::VM(vm::Error::Panic(_, Some(stacktrace)))) => {
+            let frames = stacktrace
+                .frames
+                .iter()
+                .map(|frame| {
+                    let frame = frame.as_ref().unwrap();
+                    (frame.name.declared_name().to_string(), frame.line)
+                })
+                .collect::<Vec<_>>();
+            let fail = frames
+                .iter()
+                .position(|frame| frame.0 == "fail")
+                .unwrap_or_else(|| panic!("No frame for `fail` in {:?}", frames));
+            // The caller is at the call site and `fail` at the line of the call to `error`
+            assert_eq!(frames[fail - 1].1, Some(3.into()), "{:?}", frames);
+            assert_eq!(frames[fail].1, Some(6.into()), "{:?}", frames);
+        }
+        _ => panic!("Expected an error with a stacktrace {:?}", result),
+    }
+}
@@ -45,6 +45,7 @@ fn read_file() {
     match result {
         Ok((IO::Value(value), _)) => assert_eq!(value, b']'),
         Ok((IO::Exception(err), _)) => assert!(false, "{}", err),
+        Ok((IO::Error(err), _)) => assert!(false, "{}", err),
         Err(err) => assert!(false, "{}", err),
     }
 }
@@ -116,6 +117,7 @@ fn run_expr_int() {
             assert_eq!(result, expected);
         }
         IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
     }
 }
 
@@ -178,6 +180,7 @@ fn spawn_on_twice() {
             assert_eq!(result, "abc");
         }
         IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
     }
 
     let (result, _) = runtime
@@ -192,6 +195,7 @@ fn spawn_on_twice() {
             assert_eq!(result, "abc");
         }
         IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
     }
 }
 
@@ -224,6 +228,7 @@ fn spawn_on_runexpr() {
             assert_eq!(result, "123");
         }
         IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
     }
 }
 
@@ -328,6 +333,7 @@ fn spawn_on_runexpr_in_catch() {
             assert_eq!(result, "123");
         }
         IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
     }
 
     let (result, _) = runtime
@@ -342,6 +348,7 @@ fn spawn_on_runexpr_in_catch() {
             assert_eq!(result, "123");
         }
         IO::Exception(err) => panic!("{}", err),
+        IO::Error(err) => panic!("{}", err),
     }
 }
 
@@ -410,3 +417,63 @@ fn io_error_in_catch3() {
 
     assert_eq!(result, expected);
 }
+
+#[test]
+fn sleep_yields_to_other_tasks() {
+    use std::time::{Duration, Instant};
+    use tokio::prelude::Future;
+
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let time = import! std.time
+        time.sleep 0.2
+    "#;
+
+    // A single threaded runtime is only able to run the delay if `sleep` suspends the gluon
+    // thread instead of blocking the OS thread
+    let mut runtime = self::tokio::runtime::current_thread::Runtime::new().unwrap();
+    let vm = make_vm();
+    let sleep = Compiler::new()
+        .run_io(true)
+        .run_expr_async::<IO<()>>(&vm, "<top>", expr)
+        .map(|_| Instant::now())
+        .map_err(|err| panic!("{}", err));
+    let delay = self::tokio::timer::Delay::new(Instant::now() + Duration::from_millis(20))
+        .map(|_| Instant::now())
+        .map_err(|err| panic!("{}", err));
+
+    let (slept, delayed) = runtime.block_on(sleep.join(delay)).unwrap();
+    assert!(delayed < slept);
+}
+
+#[cfg(unix)]
+#[test]
+fn kill_child_while_it_is_waited_on() {
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let process = import! std.process
+        let thread = import! std.thread
+        let { (>>=) } = import! std.monad
+        let { id } = import! std.function
+        let io @ { ? } = import! std.io
+
+        do child = process.spawn (process.proc "sleep" ["10"])
+        do t = thread.new_thread ()
+        thread.join (process.wait child) (thread.spawn_on t (\_ -> process.kill child) >>= id)
+    "#;
+
+    // `kill` can only run while `wait` is pending if `wait` releases the child between its checks
+    let mut runtime = self::tokio::runtime::current_thread::Runtime::new().unwrap();
+    let vm = make_vm();
+    let (result, _) = runtime
+        .block_on(
+            Compiler::new()
+                .run_io(true)
+                .run_expr_async::<IO<(Option<i32>, ())>>(&vm, "<top>", expr),
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    // A child which is killed by a signal has no exit code
+    assert_eq!(result, IO::Value((None, ())));
+}
@@ -3,10 +3,13 @@ extern crate gluon;
 
 mod support;
 
-use gluon::vm::api::{Hole, OpaqueValue};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gluon::vm::api::{FunctionRef, Hole, OpaqueValue, IO};
 use gluon::vm::thread::ThreadInternal;
 use gluon::vm::Error as VMError;
-use gluon::{Compiler, Error, Thread};
+use gluon::{Compiler, Error, Thread, VmBuilder};
 
 use crate::support::make_vm;
 
@@ -48,3 +51,205 @@ fn stack_overflow() {
         Ok(_) => panic!("Expected an error"),
     }
 }
+
+#[test]
+fn out_of_fuel() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    vm.set_fuel(Some(10_000));
+
+    let expr = " rec let loop x = loop (x #Int+ 1) in loop 0 ";
+    let result = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&vm, "example", expr);
+    match result {
+        Err(Error::VM(VMError::OutOfFuel)) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+    assert_eq!(vm.fuel(), Some(0));
+
+    // Refueling lets the thread run another bounded expression
+    vm.set_fuel(Some(10_000));
+    let (value, _) = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&vm, "example", " 1 #Int+ 2 ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 3);
+    assert!(vm.fuel().unwrap() < 10_000);
+}
+
+#[test]
+fn out_of_fuel_in_function_call() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let expr = r#"
+        rec let loop x = if x #Int== 0 then 0 else loop (x #Int- 1)
+        loop
+    "#;
+    let (mut count_down, _) = Compiler::new()
+        .run_expr::<FunctionRef<fn(i32) -> i32>>(&vm, "example", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    vm.set_fuel(Some(1_000));
+    match count_down.call(1_000_000) {
+        Err(VMError::OutOfFuel) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+
+    vm.set_fuel(Some(1_000));
+    assert_eq!(count_down.call(10), Ok(0));
+
+    vm.set_fuel(None);
+    assert_eq!(count_down.call(1_000_000), Ok(0));
+}
+
+#[test]
+fn allocation_bomb() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    // Load the modules before setting the limit so that only the bomb counts towards it
+    Compiler::new()
+        .run_expr::<()>(&vm, "example", " let _ = import! std.array in () ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    vm.set_memory_limit(vm.allocated_memory() + 100_000);
+
+    let expr = r#"
+        let array = import! std.array
+        rec let double xs = double (array.append xs xs)
+        double [1]
+    "#;
+    let result = Compiler::new().run_expr::<i32>(&vm, "example", expr);
+    match result {
+        Err(Error::VM(VMError::OutOfMemory { .. })) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+
+    vm.set_memory_limit(usize::max_value());
+    let (value, _) = Compiler::new()
+        .run_expr::<i32>(&vm, "example", " 1 + 2 ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 3);
+}
+
+#[test]
+fn builder_limits() {
+    let _ = ::env_logger::try_init();
+
+    let vm = VmBuilder::new()
+        .fuel(Some(10_000))
+        .max_stack_size(Some(100))
+        .build();
+
+    let expr = " rec let loop x = loop (x #Int+ 1) in loop 0 ";
+    match Compiler::new().run_expr::<i32>(&vm, "example", expr) {
+        Err(Error::VM(VMError::OutOfFuel)) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+
+    vm.set_fuel(None);
+    let expr = " rec let recurse x = 1 + recurse x in recurse 0 ";
+    match Compiler::new().run_expr::<i32>(&vm, "example", expr) {
+        Err(Error::VM(VMError::StackOverflow(100))) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
+
+/// Cancels `vm` after 50 milliseconds from another OS thread
+fn cancel_soon(vm: &Thread) {
+    let token = vm.interrupt_token();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        token.cancel();
+    });
+}
+
+#[test]
+fn interrupt_infinite_loop() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    cancel_soon(&vm);
+
+    let expr = " rec let loop x = loop (x #Int+ 1) in loop 0 ";
+    match Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&vm, "example", expr)
+    {
+        Err(Error::VM(VMError::Interrupted)) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+    assert!(!vm.interrupted());
+
+    let (value, _) = Compiler::new()
+        .run_expr::<i32>(&vm, "example", " 1 + 2 ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 3);
+}
+
+#[test]
+fn interrupt_sleep() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    // Load the modules first so that the thread is sleeping when it gets interrupted
+    Compiler::new()
+        .run_expr::<()>(
+            &vm,
+            "example",
+            " let _ = import! std.io in let _ = import! std.time in () ",
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    cancel_soon(&vm);
+
+    let start = Instant::now();
+    let expr = r#"
+        let io @ { ? } = import! std.io
+        let time = import! std.time
+        io.catch (time.sleep 10.0) (\_ -> io.applicative.wrap ())
+    "#;
+    match Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<()>>(&vm, "example", expr)
+    {
+        Err(Error::VM(VMError::Interrupted)) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let (value, _) = Compiler::new()
+        .run_expr::<i32>(&vm, "example", " 1 + 2 ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 3);
+}
+
+#[test]
+fn cancel_after_the_call_returned_does_not_interrupt_the_next_call() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let token = vm.interrupt_token();
+
+    let (value, _) = Compiler::new()
+        .run_expr::<i32>(&vm, "example", " 1 + 2 ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 3);
+
+    // Like a timeout which fires after the call it guards has already completed
+    token.cancel();
+    assert!(!vm.interrupted());
+
+    let (value, _) = Compiler::new()
+        .run_expr::<i32>(&vm, "example", " 3 + 4 ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 7);
+}
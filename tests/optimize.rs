@@ -0,0 +1,199 @@
+extern crate env_logger;
+extern crate gluon;
+
+mod support;
+
+use std::fmt;
+
+use gluon::vm::api::{Getable, VmType};
+use gluon::vm::compiler::CompiledFunction;
+use gluon::{Compiler, Thread};
+
+use crate::support::make_vm;
+
+fn instruction_count(function: &CompiledFunction) -> usize {
+    function.instructions.len()
+        + function
+            .inner_functions
+            .iter()
+            .map(instruction_count)
+            .sum::<usize>()
+}
+
+/// Returns the number of instructions that `expr` compiles to, including the instructions of the
+/// functions inside of it
+fn compiled_size(vm: &Thread, fold_constants: bool, expr: &str) -> usize {
+    let mut compiler = Compiler::new()
+        .implicit_prelude(false)
+        .fold_constants(fold_constants);
+    let (typed_expr, _) = compiler
+        .typecheck_str(vm, "test", expr, None)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let module = compiler
+        .compile_script(vm, "test", expr, &typed_expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    instruction_count(&module.function)
+}
+
+/// Checks that `expr` compiles to as many instructions as `expected` when constants are folded,
+/// and to more instructions without folding
+fn assert_folds_to(expr: &str, expected: &str) {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let folded = compiled_size(&vm, true, expr);
+    assert_eq!(folded, compiled_size(&vm, true, expected), "{}", expr);
+    assert!(
+        folded < compiled_size(&vm, false, expr),
+        "`{}` was not optimized",
+        expr
+    );
+}
+
+fn run<T>(vm: &Thread, fold_constants: bool, expr: &str) -> Result<T, String>
+where
+    T: for<'vm, 'value> Getable<'vm, 'value> + VmType + Send,
+    T::Type: Sized,
+{
+    Compiler::new()
+        .implicit_prelude(false)
+        .fold_constants(fold_constants)
+        .run_expr::<T>(vm, "test", expr)
+        .map(|(value, _)| value)
+        .map_err(|err| err.to_string())
+}
+
+/// Checks that each expression evaluates to the same value, or fails with the same error, with
+/// and without folding
+fn assert_same_behaviour<T>(exprs: &[&str])
+where
+    T: for<'vm, 'value> Getable<'vm, 'value> + VmType + Send + fmt::Debug,
+    T::Type: Sized,
+{
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    for expr in exprs {
+        let folded = run::<T>(&vm, true, expr);
+        let unfolded = run::<T>(&vm, false, expr);
+        // Compare the debug output as the errors can not be compared directly
+        assert_eq!(
+            format!("{:?}", folded),
+            format!("{:?}", unfolded),
+            "{}",
+            expr
+        );
+    }
+}
+
+#[test]
+fn fold_int_arithmetic() {
+    assert_folds_to("2 #Int+ 3 #Int* 4", "14");
+}
+
+#[test]
+fn fold_float_arithmetic() {
+    assert_folds_to("1.5 #Float* 2.0 #Float- 0.5", "2.5");
+}
+
+#[test]
+fn fold_comparison_and_remove_the_dead_branch() {
+    assert_folds_to(
+        r#"if 1 #Int< 2 then "cheap" else "expensive" "#,
+        r#""cheap""#,
+    );
+    assert_folds_to("if 1 #Int== 2 then 1 #Int/ 7 else 2", "2");
+}
+
+#[test]
+fn remove_unreachable_match_alternatives() {
+    assert_folds_to(
+        r#"
+match "b" with
+| "a" -> 1 #Int+ 1
+| "b" -> 2
+| _ -> 3 #Int* 3
+"#,
+        "2",
+    );
+}
+
+#[test]
+fn remove_unused_pure_bindings() {
+    assert_folds_to(
+        r#"
+let x = 1 #Int+ 2
+let unused = "unused"
+let f y = y
+x #Int* 2
+"#,
+        "6",
+    );
+}
+
+#[test]
+fn do_not_fold_operations_which_fail_at_runtime() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    for expr in &["1 #Int/ 0", "9223372036854775807 #Int+ 1", "255b #Byte+ 1b"] {
+        assert_eq!(
+            compiled_size(&vm, true, expr),
+            compiled_size(&vm, false, expr),
+            "{}",
+            expr
+        );
+    }
+}
+
+#[test]
+fn int_behaviour_is_preserved() {
+    assert_same_behaviour::<i64>(&[
+        "2 #Int+ 3 #Int* 4",
+        "7 #Int/ 2",
+        "-7 #Int/ 2",
+        "1 #Int/ 0",
+        "9223372036854775807 #Int+ 1",
+        "(-9223372036854775807 #Int- 1) #Int/ -1",
+        "let x = 10 in let y = x #Int- 3 in y #Int* y",
+        "if 3 #Int== 3 then 1 else 1 #Int/ 0",
+        "if False && (1 #Int/ 0 #Int== 0) then 1 else 2",
+        "if True || (1 #Int/ 0 #Int== 0) then 1 else 2",
+        "if 'a' #Char< 'b' then 1 else 2",
+        "match 2 with | 1 -> 10 | x -> x #Int+ 1",
+        "let f x = if x #Int< 0 then 0 else x in f (2 #Int- 5)",
+    ]);
+}
+
+#[test]
+fn float_behaviour_is_preserved() {
+    assert_same_behaviour::<f64>(&[
+        "1.5 #Float* 2.0 #Float- 0.5",
+        "1.0 #Float/ 0.0",
+        "(0.0 #Float- 1.0) #Float* 0.0",
+        "0.1 #Float+ 0.2",
+        "1.0e308 #Float* 10.0",
+        "if 0.1 #Float+ 0.2 #Float== 0.3 then 1.0 else 2.0",
+    ]);
+}
+
+#[test]
+fn bool_and_string_behaviour_is_preserved() {
+    assert_same_behaviour::<bool>(&[
+        "1 #Int< 2",
+        "2.0 #Float< 1.0",
+        "255b #Byte== 255b",
+        "True && False",
+        "False || True",
+        "let b = 1 #Int== 1 in b && b",
+        // A `NaN` can't be returned directly as rooting it requires it to be equal to itself
+        "let nan = 0.0 #Float/ 0.0 in nan #Float== nan",
+        "let inf = 1.0e308 #Float* 10.0 in (inf #Float- inf) #Float< 0.0",
+    ]);
+    assert_same_behaviour::<String>(&[
+        r#"if 1 #Int< 2 then "cheap" else "expensive""#,
+        r#"match "b" with | "a" -> "1" | "b" -> "2" | _ -> "3""#,
+        r#"match "c" with | "a" -> "1" | other -> other"#,
+        r#"let unused = 1 #Int/ 0 in "kept""#,
+    ]);
+}
@@ -1,12 +1,19 @@
+extern crate futures;
 extern crate gluon;
 #[macro_use]
 extern crate gluon_vm;
 
 use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use futures::{future, Future};
 
 use gluon::vm::api::FunctionRef;
 use gluon::vm::api::OpaqueValue;
+use gluon::vm::api::{OwnedFunction, IO};
+use gluon::vm::atomic::{AtomicInt, AtomicRef};
 use gluon::vm::channel::{ChannelRecord, Receiver, Sender};
+use gluon::vm::types::VmInt;
 use gluon::RootedThread;
 use gluon::{new_vm, Compiler, Error};
 
@@ -77,3 +84,296 @@ fn parallel_() -> Result<(), Error> {
     handle1.join().unwrap()?;
     handle2.join().unwrap()
 }
+
+fn new_channel(
+    vm: &RootedThread,
+) -> Result<
+    ChannelRecord<OpaqueValue<RootedThread, Sender<i32>>, OpaqueValue<RootedThread, Receiver<i32>>>,
+    Error,
+> {
+    // Load the module so that the `Sender` and `Receiver` types are defined
+    Compiler::new().run_expr::<()>(vm, "<top>", " let _ = import! std.channel in () ")?;
+    let (value, _) = Compiler::new().run_expr(
+        vm,
+        "<top>",
+        " let { channel } = import! std.channel in channel 0 ",
+    )?;
+    Ok(value)
+}
+
+#[test]
+fn select_racing_producers() {
+    if let Err(err) = select_racing_producers_() {
+        assert!(false, "{}", err);
+    }
+}
+
+fn select_racing_producers_() -> Result<(), Error> {
+    let vm = new_vm();
+
+    let record_p! { sender, receiver } = new_channel(&vm)?;
+    let (left_sender, left) = (sender, receiver);
+    let record_p! { sender, receiver } = new_channel(&vm)?;
+    let (right_sender, right) = (sender, receiver);
+
+    let child = vm.new_thread()?;
+    let consumer = spawn(move || -> Result<(Vec<(VmInt, i32)>, Vec<VmInt>), Error> {
+        // Selects until both channels are disconnected, removing each receiver from the selection
+        // once it has been closed. `ids` maps indices into `receivers` back to the original order.
+        let expr = r#"
+        let { Select, select } = import! std.channel
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+        let array = import! std.array
+
+        let remove arr i = array.append (array.slice arr 0 i) (array.slice arr (i + 1) (array.len arr))
+
+        \left right ->
+            rec let loop receivers ids received disconnected =
+                if array.is_empty receivers then wrap (received, disconnected)
+                else
+                    do selected = select receivers
+                    match selected with
+                    | Ready { index, value } ->
+                        loop receivers ids (array.append received [(array.index ids index, value)]) disconnected
+                    | Disconnected index ->
+                        let disconnected = array.append disconnected [array.index ids index]
+                        loop (remove receivers index) (remove ids index) received disconnected
+            loop [left, right] [0, 1] [] []
+        "#;
+        type Consume = fn(
+            OpaqueValue<RootedThread, Receiver<i32>>,
+            OpaqueValue<RootedThread, Receiver<i32>>,
+        ) -> IO<(Vec<(VmInt, i32)>, Vec<VmInt>)>;
+        let mut f: OwnedFunction<Consume> = Compiler::new()
+            .run_io(true)
+            .run_expr(&child, "<top>", expr)?
+            .0;
+        match future::lazy(|| f.call_async(left, right)).wait()? {
+            IO::Value(value) => Ok(value),
+            IO::Exception(err) => Err(gluon::vm::Error::Message(err).into()),
+            IO::Error(err) => Err(gluon::vm::Error::IO(err, None).into()),
+        }
+    });
+
+    let produce = |vm: RootedThread, sender, start| {
+        spawn(move || -> Result<(), Error> {
+            let expr = r#"
+            let { send, close } = import! std.channel
+            let { sleep } = import! std.thread
+            let { error } = import! std.prim
+            let { ? } = import! std.io
+            let { Result } = import! std.result
+
+            \sender start ->
+                rec let loop i =
+                    if i == 10 then close sender
+                    else
+                        match send sender (start + i) with
+                        | Ok _ ->
+                            do _ = sleep 1
+                            loop (i + 1)
+                        | Err _ -> error "Unable to send"
+                loop 0
+            "#;
+            let mut f: OwnedFunction<fn(OpaqueValue<RootedThread, Sender<i32>>, i32) -> IO<()>> =
+                Compiler::new().run_io(true).run_expr(&vm, "<top>", expr)?.0;
+            future::lazy(|| f.call_async(sender, start)).wait()?;
+            Ok(())
+        })
+    };
+    let left_producer = produce(vm.new_thread()?, left_sender, 0);
+    let right_producer = produce(vm.new_thread()?, right_sender, 100);
+
+    left_producer.join().unwrap()?;
+    right_producer.join().unwrap()?;
+    let (received, mut disconnected) = consumer.join().unwrap()?;
+
+    let from = |index| {
+        received
+            .iter()
+            .filter(|&&(i, _)| i == index)
+            .map(|&(_, value)| value)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(from(0), (0..10).collect::<Vec<_>>());
+    assert_eq!(from(1), (100..110).collect::<Vec<_>>());
+    disconnected.sort();
+    assert_eq!(disconnected, [0, 1]);
+    Ok(())
+}
+
+#[test]
+fn recv_timeout() {
+    if let Err(err) = recv_timeout_() {
+        assert!(false, "{}", err);
+    }
+}
+
+fn recv_timeout_() -> Result<(), Error> {
+    let vm = new_vm();
+
+    let record_p! { sender, receiver } = new_channel(&vm)?;
+
+    let expr = r#"
+        let { recv_timeout } = import! std.channel
+        let { Option } = import! std.option
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+
+        \receiver ->
+            do value = recv_timeout 0.2 receiver
+            match value with
+            | Some x -> wrap x
+            | None -> wrap (-1)
+    "#;
+    let (mut f, _) = Compiler::new().run_io(true).run_expr::<OwnedFunction<
+        fn(OpaqueValue<RootedThread, Receiver<i32>>) -> IO<i32>,
+    >>(&vm, "<top>", expr)?;
+
+    // Nothing is sent so the timeout fires
+    let start = Instant::now();
+    assert_eq!(
+        future::lazy(|| f.call_async(receiver.clone())).wait()?,
+        IO::Value(-1)
+    );
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+
+    // A value sent while waiting wakes the receiver before the timeout
+    let child = vm.new_thread()?;
+    let producer = spawn(move || -> Result<(), Error> {
+        let mut send: OwnedFunction<fn(OpaqueValue<RootedThread, Sender<i32>>, i32) -> IO<()>> =
+            Compiler::new()
+                .run_io(true)
+                .run_expr(
+                    &child,
+                    "<top>",
+                    r#"
+                    let { send } = import! std.channel
+                    let { sleep } = import! std.thread
+                    let { ? } = import! std.io
+                    \sender x ->
+                        do _ = sleep 50
+                        let _ = send sender x
+                        sleep 0
+                    "#,
+                )?
+                .0;
+        future::lazy(|| send.call_async(sender, 7)).wait()?;
+        Ok(())
+    });
+    assert_eq!(
+        future::lazy(|| f.call_async(receiver)).wait()?,
+        IO::Value(7)
+    );
+    producer.join().unwrap()
+}
+
+#[test]
+fn atomic_counter() {
+    if let Err(err) = atomic_counter_() {
+        assert!(false, "{}", err);
+    }
+}
+
+fn atomic_counter_() -> Result<(), Error> {
+    const THREADS: usize = 4;
+    const INCREMENTS: VmInt = 500;
+
+    let vm = new_vm();
+    // Load the module so that the `AtomicInt` and `AtomicRef` types are defined
+    Compiler::new().run_expr::<()>(&vm, "<top>", " let _ = import! std.atomic in () ")?;
+
+    type Counters = (
+        OpaqueValue<RootedThread, AtomicInt>,
+        OpaqueValue<RootedThread, AtomicRef<Vec<VmInt>>>,
+    );
+    let (counters, _) = Compiler::new().run_io(true).run_expr::<IO<Counters>>(
+        &vm,
+        "<top>",
+        r#"
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+        let atomic = import! std.atomic
+        do int = atomic.int.new 0
+        do reference = atomic.ref.new [0]
+        wrap (int, reference)
+        "#,
+    )?;
+    let (int, reference) = match counters {
+        IO::Value(counters) => counters,
+        IO::Exception(err) => return Err(gluon::vm::Error::Message(err).into()),
+        IO::Error(err) => return Err(gluon::vm::Error::IO(err, None).into()),
+    };
+
+    // Each thread increments the integer directly and the count stored in an array behind the
+    // reference, the array being a heap value that has to survive the garbage collections
+    // triggered by the appends of the other threads
+    let expr = r#"
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+        let array = import! std.array
+        let atomic = import! std.atomic
+
+        \int reference n ->
+            rec let loop i =
+                if i == n then wrap ()
+                else
+                    do _ = atomic.int.fetch_add 1 int
+                    do _ = atomic.ref.modify (\xs -> [array.index xs 0 + 1]) reference
+                    loop (i + 1)
+            loop 0
+    "#;
+    type Increment = fn(
+        OpaqueValue<RootedThread, AtomicInt>,
+        OpaqueValue<RootedThread, AtomicRef<Vec<VmInt>>>,
+        VmInt,
+    ) -> IO<()>;
+
+    let handles = (0..THREADS)
+        .map(|_| {
+            let child = vm.new_thread()?;
+            let (int, reference) = (int.clone(), reference.clone());
+            Ok(spawn(move || -> Result<(), Error> {
+                let mut f: OwnedFunction<Increment> = Compiler::new()
+                    .run_io(true)
+                    .run_expr(&child, "<top>", expr)?
+                    .0;
+                match future::lazy(|| f.call_async(int, reference, INCREMENTS)).wait()? {
+                    IO::Value(()) => Ok(()),
+                    IO::Exception(err) => Err(gluon::vm::Error::Message(err).into()),
+                    IO::Error(err) => Err(gluon::vm::Error::IO(err, None).into()),
+                }
+            }))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+
+    let expr = r#"
+        let { wrap } = import! std.applicative
+        let { ? } = import! std.io
+        let atomic = import! std.atomic
+
+        \int reference ->
+            do count = atomic.int.load int
+            do counts = atomic.ref.load reference
+            wrap (count, counts)
+    "#;
+    type Load = fn(
+        OpaqueValue<RootedThread, AtomicInt>,
+        OpaqueValue<RootedThread, AtomicRef<Vec<VmInt>>>,
+    ) -> IO<(VmInt, Vec<VmInt>)>;
+    let mut load: OwnedFunction<Load> =
+        Compiler::new().run_io(true).run_expr(&vm, "<top>", expr)?.0;
+    let expected = THREADS as VmInt * INCREMENTS;
+    match load.call(int, reference)? {
+        IO::Value(counts) => assert_eq!(counts, (expected, vec![expected])),
+        IO::Exception(err) => return Err(gluon::vm::Error::Message(err).into()),
+        IO::Error(err) => return Err(gluon::vm::Error::IO(err, None).into()),
+    }
+    Ok(())
+}
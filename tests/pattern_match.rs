@@ -188,3 +188,73 @@ match (Some 10, 1) with
 "#,
 10
 }
+
+test_expr! { match_on_dense_int_literals,
+r#"
+let f x =
+    match x with
+    | -1 -> 10
+    | 0 -> 11
+    | 1 -> 12
+    | 3 -> 13
+    | 4 -> 14
+    | _ -> 0
+[f (-2), f (-1), f 0, f 1, f 2, f 3, f 4, f 5]
+"#,
+vec![0i32, 10, 11, 12, 0, 13, 14, 0]
+}
+
+test_expr! { match_on_dense_int_literals_with_min_int,
+r#"
+let f x =
+    match x with
+    | 1 -> 10
+    | 2 -> 11
+    | 3 -> 12
+    | 4 -> 13
+    | _ -> 0
+[f (-9223372036854775807 #Int- 1), f 2]
+"#,
+vec![0i32, 11]
+}
+
+test_expr! { match_on_dense_int_literals_with_max_int,
+r#"
+let f x =
+    match x with
+    | -1 -> 10
+    | 0 -> 11
+    | 1 -> 12
+    | 2 -> 13
+    | _ -> 0
+[f 9223372036854775807, f (-1)]
+"#,
+vec![0i32, 10]
+}
+
+test_expr! { match_on_sparse_int_literals,
+r#"
+let f x =
+    match x with
+    | 1 -> 10
+    | 100 -> 11
+    | 10000 -> 12
+    | 1000000 -> 13
+    | _ -> 0
+[f 1, f 2, f 100, f 10000, f 1000000, f 1000001]
+"#,
+vec![10i32, 0, 11, 12, 13, 0]
+}
+
+test_expr! { match_on_string_literals,
+r#"
+let f x =
+    match x with
+    | "" -> 10
+    | "a" -> 11
+    | "abc" -> 12
+    | _ -> 0
+[f "", f "a", f "ab", f "abc"]
+"#,
+vec![10i32, 11, 0, 12]
+}
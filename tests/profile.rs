@@ -0,0 +1,117 @@
+extern crate env_logger;
+extern crate gluon;
+
+use std::time::Duration;
+
+use gluon::vm::profiler::ProfileRecord;
+use gluon::{new_vm, Compiler};
+
+fn find<'a>(report: &'a [ProfileRecord], name: &str) -> &'a ProfileRecord {
+    report
+        .iter()
+        .find(|record| record.function_name == name)
+        .unwrap_or_else(|| panic!("No record for `{}` in {:#?}", name, report))
+}
+
+#[test]
+fn hot_function_dominates_the_report() {
+    let _ = env_logger::try_init();
+
+    let vm = new_vm();
+    vm.set_profiling(true);
+    let expr = r#"
+        let cheap x = x #Int+ 1
+        rec let spin n acc = if n #Int== 0 then acc else spin (n #Int- 1) (acc #Int+ n)
+        in
+        let a = cheap 1
+        let b = spin 100000 0
+        cheap (a #Int+ b)
+    "#;
+    let (result, _) = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i64>(&vm, "test", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 5000050003);
+
+    let report = vm.profile_report();
+    assert_eq!(report[0].function_name, "spin");
+    assert_eq!(report[0].source_name, Some("test".to_string()));
+    // Each tail call is counted as a call of its own
+    assert_eq!(report[0].calls, 100001);
+    assert_eq!(find(&report, "cheap").calls, 2);
+
+    let total: Duration = report.iter().map(|record| record.exclusive).sum();
+    assert!(report[0].exclusive * 2 > total, "{:#?}", report);
+    // The tail calls replace each other so their time is only counted once
+    assert!(report[0].inclusive <= find(&report, "test").inclusive);
+}
+
+#[test]
+fn recursive_calls_are_not_counted_twice() {
+    let _ = env_logger::try_init();
+
+    let vm = new_vm();
+    vm.set_profiling(true);
+    let expr = r#"
+        rec let fib n = if n #Int< 2 then n else fib (n #Int- 1) #Int+ fib (n #Int- 2)
+        in
+        let x = fib 15
+        x
+    "#;
+    let (result, _) = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&vm, "test", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 610);
+
+    let report = vm.profile_report();
+    let fib = find(&report, "fib");
+    assert_eq!(fib.calls, 1973);
+    assert!(fib.exclusive <= fib.inclusive);
+    assert!(fib.inclusive <= find(&report, "test").inclusive);
+
+    // Every nanosecond of exclusive time shows up exactly once in the folded stacks
+    let folded = vm.profile_folded();
+    let folded_total: u64 = folded
+        .lines()
+        .map(|line| {
+            let (_, nanos) = line.split_at(line.rfind(' ').unwrap());
+            nanos.trim().parse::<u64>().unwrap()
+        })
+        .sum();
+    let total: Duration = report.iter().map(|record| record.exclusive).sum();
+    assert_eq!(
+        folded_total,
+        total.as_secs() * 1_000_000_000 + u64::from(total.subsec_nanos())
+    );
+    assert!(
+        folded
+            .lines()
+            .any(|line| line.starts_with("test:test;test:fib;test:fib;test:fib ")),
+        "{}",
+        folded
+    );
+}
+
+#[test]
+fn profiling_is_disabled_by_default() {
+    let _ = env_logger::try_init();
+
+    let vm = new_vm();
+    let run = || {
+        Compiler::new()
+            .implicit_prelude(false)
+            .run_expr::<i32>(&vm, "test", "let f x = x in f 1")
+            .unwrap_or_else(|err| panic!("{}", err))
+    };
+    run();
+    assert_eq!(vm.profile_report(), []);
+    assert_eq!(vm.profile_folded(), "");
+
+    vm.set_profiling(true);
+    run();
+    assert_eq!(find(&vm.profile_report(), "f").calls, 1);
+
+    vm.set_profiling(false);
+    assert_eq!(vm.profile_report(), []);
+}
@@ -0,0 +1,123 @@
+extern crate env_logger;
+#[macro_use]
+extern crate gluon_vm;
+extern crate gluon;
+
+mod support;
+
+use gluon::import::add_extern_module;
+use gluon::vm::api::{Hole, OpaqueValue, Userdata, VmType};
+use gluon::vm::thread::{RenderOptions, RootedThread, Thread, Traverseable};
+use gluon::vm::ExternModule;
+use gluon::Compiler;
+
+use crate::support::make_vm;
+
+fn render(vm: &Thread, expr: &str, options: &RenderOptions) -> String {
+    let (value, typ) = Compiler::new()
+        .run_expr::<OpaqueValue<RootedThread, Hole>>(vm, "test", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    vm.render_value(&typ, value.get_variant(), options)
+}
+
+#[test]
+fn cyclic_reference() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let expr = r#"
+        let { (<-), ref } = import! std.reference
+        type Node = { value : Int, next : Ref (Option Node) }
+        let next : Ref (Option Node) = ref None
+        let node : Node = { value = 1, next }
+        next <- Some node
+        node
+    "#;
+    // `node` is stored in the reference which it contains
+    assert_eq!(
+        render(&vm, expr, &RenderOptions::default()),
+        "{ value: 1, next: ref (Some <cycle>), }"
+    );
+}
+
+#[test]
+fn nested_record_is_cut_off_at_max_depth() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let expr = r#"{ a = { b = { c = { d = 1 } } } }"#;
+    let options = RenderOptions {
+        max_depth: 3,
+        ..RenderOptions::default()
+    };
+    assert_eq!(render(&vm, expr, &options), "{ a: { b: { c: .., }, }, }");
+    assert_eq!(
+        render(&vm, expr, &RenderOptions::default()),
+        "{ a: { b: { c: { d: 1, }, }, }, }"
+    );
+}
+
+#[test]
+fn long_arrays_and_strings_are_truncated() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let options = RenderOptions {
+        max_len: 3,
+        ..RenderOptions::default()
+    };
+    assert_eq!(render(&vm, "[1, 2, 3, 4, 5]", &options), "[1, 2, 3, ...]");
+    assert_eq!(render(&vm, "[1, 2, 3]", &options), "[1, 2, 3]");
+    assert_eq!(render(&vm, r#""abcdef""#, &options), r#""abc"..."#);
+    assert_eq!(render(&vm, r#""abc""#, &options), r#""abc""#);
+}
+
+#[test]
+fn show_types_annotates_records_and_variants() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let options = RenderOptions {
+        show_types: true,
+        ..RenderOptions::default()
+    };
+    assert_eq!(
+        render(&vm, "{ x = Some 1 }", &options),
+        "{ x: Some 1 : std.types.Option Int, } : { x : std.types.Option Int }"
+    );
+}
+
+#[test]
+fn userdata_is_rendered_with_its_debug_impl() {
+    let _ = env_logger::try_init();
+
+    #[derive(Debug)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+    impl Userdata for Point {}
+    impl Traverseable for Point {}
+    impl VmType for Point {
+        type Type = Point;
+    }
+
+    fn origin(_: ()) -> Point {
+        Point { x: 0, y: 0 }
+    }
+
+    let vm = make_vm();
+    vm.register_type::<Point>("Point", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+    add_extern_module(&vm, "point", |thread| {
+        ExternModule::new(thread, primitive!(1, origin))
+    });
+    let expr = r#"
+        let origin = import! point
+        [origin ()]
+    "#;
+    assert_eq!(
+        render(&vm, expr, &RenderOptions::default()),
+        "[Point { x: 0, y: 0 }]"
+    );
+}
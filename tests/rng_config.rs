@@ -0,0 +1,56 @@
+#![cfg(feature = "random")]
+extern crate env_logger;
+extern crate gluon;
+
+mod support;
+
+use gluon::import::add_extern_module;
+use gluon::rand_bind::{self, RngConfig};
+use gluon::vm::api::IO;
+use gluon::vm::types::VmInt;
+use gluon::Compiler;
+
+use crate::support::make_vm;
+
+const DRAW_INTS: &str = r#"
+let { wrap } = import! std.applicative
+let { ? } = import! std.io
+let random = import! std.random
+do a = random.thread_rng.next_int ()
+do b = random.thread_rng.next_int ()
+do c = random.thread_rng.next_int ()
+wrap [a, b, c]
+"#;
+
+fn draw_ints(config: Option<RngConfig>) -> Vec<VmInt> {
+    let vm = make_vm();
+    if let Some(config) = config {
+        add_extern_module(&vm, "std.random.prim", move |vm| {
+            rand_bind::load_with(vm, config.clone())
+        });
+    }
+    let (result, _) = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<Vec<VmInt>>>(&vm, "draw_ints", DRAW_INTS)
+        .unwrap_or_else(|err| panic!("{}", err));
+    match result {
+        IO::Value(ints) => ints,
+        _ => panic!("Expected a value, got {:?}", result),
+    }
+}
+
+// The configuration is shared by the whole process so every configuration is tested in a single
+// test, in a test binary of its own
+#[test]
+fn configured_generator_is_used_by_thread_rng() {
+    let _ = env_logger::try_init();
+
+    let seeded = draw_ints(Some(RngConfig::Seed(42)));
+    assert_eq!(seeded, draw_ints(Some(RngConfig::Seed(42))));
+    assert_ne!(seeded, draw_ints(Some(RngConfig::Seed(43))));
+
+    // The chance of the other generators reproducing the seeded draws is 2^-192
+    assert_ne!(seeded, draw_ints(Some(RngConfig::ThreadRng)));
+    assert_ne!(seeded, draw_ints(Some(RngConfig::Entropy)));
+    assert_ne!(seeded, draw_ints(None));
+}
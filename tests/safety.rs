@@ -4,8 +4,9 @@ extern crate gluon;
 
 mod support;
 
-use gluon::vm::api::{FunctionRef, OpaqueValue};
+use gluon::vm::api::{FunctionRef, Hole, OpaqueValue};
 use gluon::vm::reference::Reference;
+use gluon::vm::Error;
 use gluon::{Compiler, RootedThread, Thread};
 
 use crate::support::*;
@@ -66,3 +67,74 @@ fn cant_transfer_opaque_value_between_disjoint_threads() {
     let vm2 = make_vm();
     verify_value_cloned(&vm1, &vm2);
 }
+
+const NESTED_RECORD: &str = r#"
+    type Shape = | Circle Float | Rect Int Int
+    {
+        name = "value",
+        inner = { values = [1, 2, 3], names = ["a", "b"], shape = Rect 2 3 },
+        tag = Some 1,
+    }
+    "#;
+
+/// Describes a record like `NESTED_RECORD`. The argument is row polymorphic which makes the
+/// fields be looked up by name in the vm that runs the function.
+const DESCRIBE: &str = r#"
+    let array = import! std.array
+    let string = import! std.string
+    let describe r : { name : String, inner : { names : Array String | s } | r } -> String =
+        r.name ++ ":" ++ string.concat_array r.inner.names
+    describe
+    "#;
+
+fn describe(vm: &Thread, value: OpaqueValue<RootedThread, Hole>) -> String {
+    type Describe<'t> = FunctionRef<'t, fn(OpaqueValue<RootedThread, Hole>) -> String>;
+    let (mut describe, _) = Compiler::new()
+        .run_expr::<Describe>(vm, "describe", DESCRIBE)
+        .unwrap_or_else(|err| panic!("{}", err));
+    describe.call(value).unwrap_or_else(|err| panic!("{}", err))
+}
+
+#[test]
+fn migrate_nested_record_between_disjoint_threads() {
+    let _ = ::env_logger::try_init();
+
+    let vm1 = make_vm();
+    let vm2 = make_vm();
+    let (value, _) = Compiler::new()
+        .run_expr::<OpaqueValue<RootedThread, Hole>>(&vm1, "example", NESTED_RECORD)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let migrated = value.migrate(&vm2).unwrap_or_else(|err| panic!("{}", err));
+    drop(value);
+    assert_eq!(describe(&vm2, migrated.clone()), "value:ab");
+
+    let round_tripped = migrated
+        .migrate(&vm1)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(describe(&vm1, round_tripped), "value:ab");
+}
+
+#[test]
+fn migrating_a_closure_is_an_error() {
+    let _ = ::env_logger::try_init();
+
+    let vm1 = make_vm();
+    let vm2 = make_vm();
+    let (value, _) = Compiler::new()
+        .run_expr::<OpaqueValue<RootedThread, Hole>>(
+            &vm1,
+            "example",
+            r#" let x = 1 in { f = \y -> x #Int+ y } "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    match value.migrate(&vm2) {
+        Err(Error::Message(message)) => assert!(
+            message.contains("Functions can't be migrated"),
+            "{}",
+            message
+        ),
+        result => panic!("Expected an error, got {:?}", result),
+    }
+}
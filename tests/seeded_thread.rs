@@ -0,0 +1,71 @@
+#![cfg(feature = "random")]
+extern crate env_logger;
+extern crate gluon;
+
+mod support;
+
+use gluon::vm::api::IO;
+use gluon::{Compiler, Thread};
+
+use crate::support::make_vm;
+
+const DRAW: &str = r#"
+let { wrap } = import! std.applicative
+let { ? } = import! std.io
+let int = import! std.int
+let float = import! std.float
+let random = import! std.random
+do a = random.thread_rng.next_int ()
+do b = random.thread_rng.next_float ()
+do c = random.thread_rng.gen_int_range 0 1000000
+wrap (int.show.show a ++ " " ++ float.show.show b ++ " " ++ int.show.show c)
+"#;
+
+fn draw(thread: &Thread) -> String {
+    let (result, _) = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<String>>(thread, "draw", DRAW)
+        .unwrap_or_else(|err| panic!("{}", err));
+    match result {
+        IO::Value(output) => output,
+        _ => panic!("Expected a value, got {:?}", result),
+    }
+}
+
+/// Returns the output of `DRAW` on a vm seeded with `seed` and on a thread spawned from it
+fn run_seeded(seed: u64) -> (String, String) {
+    let vm = make_vm();
+    vm.set_seed(Some(seed));
+    let parent = draw(&vm);
+    let child = vm.new_thread().unwrap();
+    (parent, draw(&child))
+}
+
+#[test]
+fn same_seed_gives_the_same_output() {
+    let _ = env_logger::try_init();
+
+    assert_eq!(run_seeded(42), run_seeded(42));
+}
+
+#[test]
+fn different_seeds_give_different_output() {
+    let _ = env_logger::try_init();
+
+    let (parent, child) = run_seeded(42);
+    let (other_parent, other_child) = run_seeded(43);
+    assert_ne!(parent, other_parent);
+    assert_ne!(child, other_child);
+    // The spawned thread is seeded from the parent but does not repeat its draws
+    assert_ne!(parent, child);
+}
+
+#[test]
+fn unseeded_threads_use_the_global_generator() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    vm.set_seed(Some(42));
+    vm.set_seed(None);
+    assert_ne!(draw(&vm), run_seeded(42).0);
+}
@@ -0,0 +1,104 @@
+#![cfg(feature = "serialization")]
+extern crate env_logger;
+#[macro_use]
+extern crate serde_json;
+
+extern crate gluon;
+
+use gluon::vm::api::json::SerdeValue;
+use gluon::vm::api::FunctionRef;
+use gluon::{new_vm, Compiler};
+
+#[test]
+fn roundtrip_nested_document() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let (mut id, _) = Compiler::new()
+        .run_expr::<FunctionRef<fn(SerdeValue) -> SerdeValue>>(&thread, "test", "\\x -> x")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let document = json!({
+        "name": "gluon",
+        "version": 11,
+        "ratio": 0.5,
+        "enabled": true,
+        "tags": ["a", "b"],
+        "empty_array": [],
+        "empty_object": {},
+        "not an identifier!": 1,
+        "nested": {
+            "points": [
+                { "x": 1, "y": -2 },
+                { "x": 3, "y": 4 }
+            ],
+            "deeper": {
+                "matrix": [[1, 2], [3, 4]],
+                "deepest": { "flags": [false, true], "value": -1.25 }
+            }
+        }
+    });
+    let result = id
+        .call(SerdeValue(document.clone()))
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, SerdeValue(document));
+}
+
+#[test]
+fn access_pushed_document() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let (mut f, _) = Compiler::new()
+        .run_expr::<FunctionRef<fn(SerdeValue) -> SerdeValue>>(
+            &thread,
+            "test",
+            r#"
+            let { Option } = import! std.option
+            \x ->
+                let name =
+                    match x.nested.name with
+                    | Some name -> name
+                    | None -> "unnamed"
+                { name, count = x.nested.count + 1 }
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let result = f
+        .call(SerdeValue(
+            json!({ "nested": { "name": null, "count": 1 } }),
+        ))
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, SerdeValue(json!({ "name": "unnamed", "count": 2 })));
+}
+
+#[test]
+fn numbers_outside_int_range_are_floats() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let (mut id, _) = Compiler::new()
+        .run_expr::<FunctionRef<fn(SerdeValue) -> SerdeValue>>(&thread, "test", "\\x -> x")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let result = id
+        .call(SerdeValue(json!([1, 2.5, ::std::u64::MAX])))
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(
+        result,
+        SerdeValue(json!([1.0, 2.5, ::std::u64::MAX as f64]))
+    );
+}
+
+#[test]
+fn mixed_array_is_an_error() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let (mut id, _) = Compiler::new()
+        .run_expr::<FunctionRef<fn(SerdeValue) -> SerdeValue>>(&thread, "test", "\\x -> x")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    assert!(id.call(SerdeValue(json!([1, "a"]))).is_err());
+}
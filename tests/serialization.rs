@@ -207,3 +207,56 @@ fn roundtrip_thread() {
         .unwrap_or_else(|err| panic!("{}", err));
     roundtrip(&thread, &value);
 }
+
+#[test]
+fn compile_to_bytes_and_load_bytes() {
+    use gluon::vm::api::FunctionRef;
+
+    let _ = env_logger::try_init();
+
+    let expr = r#"
+        let array = import! std.array
+
+        let sum xs : Array Int -> Int = array.foldable.foldl (+) 0 xs
+        let greet name : String -> String = "Hello " ++ name ++ "!"
+        { sum, greet }
+        "#;
+    let bytes = Compiler::new()
+        .compile_to_bytes(&new_vm(), "precompiled", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    // The dependencies are loaded into the new VM as needed
+    let thread = new_vm();
+    Compiler::new()
+        .load_bytes(&thread, "precompiled", &bytes)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let mut sum: FunctionRef<fn(Vec<i32>) -> i32> = thread
+        .get_global("precompiled.sum")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(sum.call(vec![1, 2, 3]), Ok(6));
+
+    let (value, _) = Compiler::new()
+        .run_expr::<String>(
+            &thread,
+            "test",
+            r#" let { greet } = import! precompiled in greet "gluon" "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, "Hello gluon!");
+}
+
+#[test]
+fn load_bytes_rejects_other_versions() {
+    let mut bytes = Compiler::new()
+        .compile_to_bytes(&new_vm(), "precompiled", " 1 ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    // The version string follows its 8 byte length
+    bytes[8] = b'G';
+
+    let err = Compiler::new()
+        .load_bytes(&new_vm(), "precompiled", &bytes)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Gluon-"), "{}", err);
+}
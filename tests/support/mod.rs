@@ -85,6 +85,7 @@ macro_rules! test_expr {
                     equiv(&value, &$value);
                 }
                 IO::Exception(err) => panic!("{}", err),
+                IO::Error(err) => panic!("{}", err),
             }
         }
     };
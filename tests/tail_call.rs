@@ -0,0 +1,203 @@
+extern crate env_logger;
+extern crate gluon;
+
+mod support;
+
+use gluon::vm::thread::ThreadInternal;
+use gluon::vm::Error as VMError;
+use gluon::{Compiler, Error, RootedThread};
+
+use crate::support::make_vm;
+
+// Each test runs with a stack that is far smaller than the recursion depth so any call which is
+// not compiled as a tail call overflows it
+const STACK_LIMIT: u32 = 10_000;
+
+fn make_limited_vm() -> RootedThread {
+    let vm = make_vm();
+    vm.context().set_max_stack_size(STACK_LIMIT);
+    vm
+}
+
+fn run_bool(vm: &RootedThread, name: &str, expr: &str) -> Result<bool, Error> {
+    Compiler::new()
+        .run_expr::<bool>(vm, name, expr)
+        .map(|(value, _)| value)
+}
+
+#[test]
+fn mutual_recursion() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_limited_vm();
+    // Uses the primitive operators directly as going through the `Eq` and `Num` implicits makes
+    // ten million iterations take minutes in debug builds
+    let expr = r#"
+        rec
+        #[tail]
+        let even n = if n #Int== 0 then True else odd (n #Int- 1)
+        #[tail]
+        let odd n = if n #Int== 0 then False else even (n #Int- 1)
+        even 10000000
+    "#;
+    assert_eq!(run_bool(&vm, "mutual_recursion", expr).unwrap(), true);
+}
+
+#[test]
+fn mutual_recursion_across_modules() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_limited_vm();
+    Compiler::new()
+        .load_script(
+            &vm,
+            "odd",
+            "let odd even n : (Int -> Bool) -> Int -> Bool = if n == 0 then False else even (n - 1) in { odd }",
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    let expr = r#"
+        let { odd } = import! odd
+        rec let even n = if n == 0 then True else odd even (n - 1)
+        even 1000001
+    "#;
+    assert_eq!(
+        run_bool(&vm, "mutual_recursion_across_modules", expr).unwrap(),
+        false
+    );
+}
+
+#[test]
+fn recursion_through_record_field() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_limited_vm();
+    let expr = r#"
+        rec let counter = {
+            down = \n -> if n == 0 then True else counter.step n,
+            step = \n -> counter.down (n - 1),
+        }
+        counter.down 1000000
+    "#;
+    assert_eq!(
+        run_bool(&vm, "recursion_through_record_field", expr).unwrap(),
+        true
+    );
+}
+
+#[test]
+fn tail_calls_in_match_arms_and_let_bodies() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_limited_vm();
+    let expr = r#"
+        type Step = | Done | Next Int
+        let step n = if n == 0 then Done else Next (n - 1)
+        #[tail]
+        rec let loop n =
+            match step n with
+            | Done -> True
+            | Next m ->
+                let continue x = loop x
+                continue m
+        loop 1000000
+    "#;
+    assert_eq!(
+        run_bool(&vm, "tail_calls_in_match_arms_and_let_bodies", expr).unwrap(),
+        true
+    );
+}
+
+#[test]
+fn continuation_passing_loop() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_limited_vm();
+    let expr = r#"
+        rec
+        #[tail]
+        let loop n k = if n == 0 then k True else step n (\m -> loop m k)
+        let step n k = k (n - 1)
+        loop 1000000 (\done -> done)
+    "#;
+    assert_eq!(
+        run_bool(&vm, "continuation_passing_loop", expr).unwrap(),
+        true
+    );
+}
+
+#[test]
+fn non_tail_recursion_overflows_the_stack() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_limited_vm();
+    let expr = r#"
+        rec let count n = if n == 0 then 0 else 1 + count (n - 1)
+        count 1000000 == 1000000
+    "#;
+    match run_bool(&vm, "non_tail_recursion_overflows_the_stack", expr) {
+        Err(Error::VM(VMError::StackOverflow(STACK_LIMIT))) => (),
+        Err(err) => panic!("Unexpected error `{}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
+
+#[test]
+fn tail_attribute_rejects_calls_outside_tail_position() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let expr = r#"
+        #[tail]
+        rec let count n = if n == 0 then 0 else 1 + count (n - 1)
+        count 10 == 10
+    "#;
+    match run_bool(&vm, "tail_attribute", expr) {
+        Err(Error::VM(VMError::Message(msg))) => assert_eq!(
+            msg,
+            "tail_attribute:3: The call to `count` is not a tail call but `count` is marked with \
+             `#[tail]`"
+        ),
+        Err(err) => panic!("Unexpected error `{}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
+
+#[test]
+fn tail_attribute_checks_mutually_recursive_calls() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let expr = r#"
+        rec
+        #[tail]
+        let even n = if n == 0 then True else not (odd (n - 1))
+        let odd n = if n == 0 then False else not (even (n - 1))
+        even 10
+    "#;
+    match run_bool(&vm, "tail_attribute", expr) {
+        Err(Error::VM(VMError::Message(msg))) => assert_eq!(
+            msg,
+            "tail_attribute:4: The call to `odd` is not a tail call but `even` is marked with \
+             `#[tail]`"
+        ),
+        Err(err) => panic!("Unexpected error `{}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
+
+#[test]
+fn tail_attribute_allows_partial_application() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_limited_vm();
+    let expr = r#"
+        #[tail]
+        rec let sum n acc =
+            if n == 0 then acc
+            else
+                let next = sum (n - 1)
+                next (acc + n)
+        sum 1000000 0 == 500000500000
+    "#;
+    assert_eq!(run_bool(&vm, "tail_attribute", expr).unwrap(), true);
+}
@@ -0,0 +1,79 @@
+extern crate env_logger;
+extern crate gluon;
+
+mod support;
+
+use std::fs;
+
+use gluon::{Compiler, TestReport};
+
+use crate::support::make_vm;
+
+const FAILING_TESTS: &str = r#"
+let { assert_eq, assert_neq, test, group, ? } = import! std.test
+let { (<|) } = import! std.function
+let { (*>) } = import! std.applicative
+let { ? } = import! std.effect
+
+group "failing" [
+    test "passes" <| \_ -> assert_eq 1 1,
+    test "assertion" <| \_ ->
+        assert_eq (1 + 1) 3 *> assert_neq 2 2 *> assert_eq 1 1,
+    test "error" <| \_ ->
+        error "an error",
+    group "nested" [
+        test "passes" <| \_ -> assert_neq 1 2,
+    ],
+]
+"#;
+
+#[test]
+fn run_passing_test_file() {
+    let _ = env_logger::try_init();
+
+    let vm = make_vm();
+    let report = Compiler::new()
+        .run_test_file(&vm, "tests/pass/reference.glu")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(
+        report,
+        TestReport {
+            passed: 1,
+            failed: 0,
+            report: "1 passed, 0 failed".to_string(),
+        }
+    );
+}
+
+#[test]
+fn run_failing_test_file() {
+    let _ = env_logger::try_init();
+
+    let dir = std::env::temp_dir().join("gluon_test_runner");
+    fs::create_dir_all(&dir).unwrap();
+    let filename = dir.join("failing.glu");
+    fs::write(&filename, FAILING_TESTS).unwrap();
+    let filename = filename.to_str().unwrap();
+    let module = gluon::base::filename_to_module(filename);
+
+    let vm = make_vm();
+    let err = Compiler::new()
+        .run_test_file(&vm, filename)
+        .expect_err("Expected the test file to fail");
+    let report = err.to_string();
+
+    // Every test runs even though some of the earlier tests fail
+    assert!(report.ends_with("2 passed, 2 failed"), "{}", report);
+    let assertions = format!(
+        "failing/assertion:\n    {0}:10: {1}\n    {0}:10: {2}\n",
+        module, "Assertion failed: 2 != 3", "Assertion failed: 2 == 2",
+    );
+    assert!(report.starts_with(&assertions), "{}", report);
+    assert!(
+        report.contains("failing/error:\n    an error"),
+        "{}",
+        report
+    );
+    assert!(!report.contains("failing/passes"), "{}", report);
+    assert!(!report.contains("nested"), "{}", report);
+}
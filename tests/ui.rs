@@ -1,5 +1,6 @@
 extern crate gluon;
 
+use gluon::base::error::Severity;
 use gluon::{new_vm, Compiler};
 
 #[test]
@@ -19,3 +20,155 @@ fn macro_error_with_line_column_info() {
 "#
     );
 }
+
+#[test]
+fn int_literal_overflow_points_at_the_literal() {
+    let thread = new_vm();
+    let mut compiler = Compiler::new();
+    let result =
+        compiler.run_expr::<i32>(&thread, "test", "1 + 0b1_0000_0000 + 9223372036854775808");
+    assert_eq!(
+        result
+            .unwrap_err()
+            .emit_string(&compiler.code_map())
+            .unwrap(),
+        r#"error: cannot parse integer, probable overflow
+- <test>:1:21
+1 | 1 + 0b1_0000_0000 + 9223372036854775808
+  |                     ^^^^^^^^^^^^^^^^^^^
+"#
+    );
+}
+
+#[test]
+fn check_expr_returns_every_type_error_with_its_span() {
+    let thread = new_vm();
+    let mut compiler = Compiler::new();
+    let text = r#"
+let x : Int = "abc"
+let y : String = 1
+()
+"#;
+    let diagnostics = compiler.check_expr(&thread, "test", text);
+    let labels = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let primary = diagnostic.primary.as_ref().unwrap();
+            (
+                diagnostic.severity,
+                primary.source_name.clone(),
+                primary.byte_range.clone(),
+                (
+                    primary.start.line.to_usize(),
+                    primary.start.column.to_usize(),
+                ),
+                (primary.end.line.to_usize(), primary.end.column.to_usize()),
+            )
+        })
+        .collect::<Vec<_>>();
+    // Lines and columns are zero-indexed
+    assert_eq!(
+        labels,
+        [
+            (
+                Severity::Error,
+                "test".to_string(),
+                15..20,
+                (1, 14),
+                (1, 19)
+            ),
+            (
+                Severity::Error,
+                "test".to_string(),
+                38..39,
+                (2, 17),
+                (2, 18)
+            ),
+        ]
+    );
+    assert_eq!(
+        diagnostics[1].rendered,
+        r#"error: Expected the following types to be equal
+Expected: String
+Found: Int
+1 errors were found during unification:
+Types do not match:
+    Expected: String
+    Found: Int
+- <test>:3:18
+3 | let y : String = 1
+  |                  ^
+"#
+    );
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|diagnostic| &diagnostic.rendered[..])
+            .collect::<Vec<_>>()
+            .join("\n"),
+        compiler
+            .typecheck_str(&thread, "test", text, None)
+            .unwrap_err()
+            .emit_string(&compiler.code_map())
+            .unwrap()
+    );
+}
+
+#[test]
+fn deprecated_function_warns_at_its_call_sites() {
+    let thread = new_vm();
+    let mut compiler = Compiler::new();
+    compiler
+        .load_script(
+            &thread,
+            "deprecated_module",
+            r#"
+/// Returns its argument
+#[deprecated("use `id` instead")]
+let identity x = x
+let id x = x
+{ identity, id }
+"#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let text = r#"
+let { identity, id } = import! deprecated_module
+let m = import! deprecated_module
+identity (m.identity (id 1))
+"#;
+    let diagnostics = compiler.check_expr(&thread, "test", text);
+    let labels = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.severity,
+                &diagnostic.message[..],
+                diagnostic.primary.as_ref().unwrap().byte_range.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        [
+            (
+                Severity::Warning,
+                "`identity` is deprecated: use `id` instead",
+                84..92
+            ),
+            (
+                Severity::Warning,
+                "`identity` is deprecated: use `id` instead",
+                94..104
+            ),
+        ]
+    );
+    assert_eq!(
+        diagnostics[0].rendered,
+        r#"warning: `identity` is deprecated: use `id` instead
+- <test>:4:1
+4 | identity (m.identity (id 1))
+  | ^^^^^^^^
+"#
+    );
+}
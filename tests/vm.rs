@@ -11,8 +11,11 @@ mod support;
 
 use crate::support::*;
 
+use either::Either;
+
 use gluon::base::pos::BytePos;
 use gluon::base::types::Type;
+use gluon::check;
 use gluon::vm::api::{FunctionRef, Hole, OpaqueValue, ValueRef, IO};
 use gluon::vm::channel::Sender;
 use gluon::vm::thread::{RootedThread, Thread, ThreadInternal};
@@ -454,6 +457,15 @@ r#"
 String::from("x")
 }
 
+test_expr! { record_update_does_not_change_the_base,
+r#"
+let r = { x = 1, y = "y" }
+let r2 = { r with x = 2 }
+r.x #Int+ r2.x
+"#,
+3
+}
+
 test_expr! { load_option,
 r#"
 let _ = import! std.option
@@ -764,34 +776,42 @@ g 10
                     // Some(StacktraceFrame { name: f.clone(), line: 9 }),
                     Some(StacktraceFrame {
                         name: g.clone(),
+                        source_name: Some("<top>".to_string()),
                         line: Some(8.into()),
                     }),
                     Some(StacktraceFrame {
                         name: f.clone(),
+                        source_name: Some("<top>".to_string()),
                         line: Some(7.into()),
                     }),
                     Some(StacktraceFrame {
                         name: g.clone(),
+                        source_name: Some("<top>".to_string()),
                         line: Some(8.into()),
                     }),
                     Some(StacktraceFrame {
                         name: f.clone(),
+                        source_name: Some("<top>".to_string()),
                         line: Some(7.into()),
                     }),
                     Some(StacktraceFrame {
                         name: g.clone(),
+                        source_name: Some("<top>".to_string()),
                         line: Some(8.into()),
                     }),
                     Some(StacktraceFrame {
                         name: f.clone(),
+                        source_name: Some("<top>".to_string()),
                         line: Some(5.into()),
                     }),
                     Some(StacktraceFrame {
                         name: end.clone(),
+                        source_name: Some("<top>".to_string()),
                         line: Some(1.into()),
                     }),
                     Some(StacktraceFrame {
                         name: error.clone(),
+                        source_name: None,
                         line: None,
                     }),
                 ]
@@ -887,6 +907,107 @@ fn suggestion_from_implicit_prelude() {
     assert!(!result.is_empty());
 }
 
+#[test]
+fn suggest_record_fields_in_front_of_an_error() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    let expr = r#"
+let record = { aa = 1, ab = "" }
+let x = record.
+let y : Int = ""
+y
+"#;
+
+    let mut compiler = Compiler::new();
+    let (expr, result) = compiler.typecheck_partial_str(&vm, "example", expr, None);
+    assert!(result.is_err());
+    let expr = expr.expect("Partial expression");
+
+    let lines = compiler.get_filemap("example").expect("file_map");
+    let result = completion::suggest(
+        &*vm.get_env(),
+        lines.span(),
+        &expr,
+        lines.byte_index(2.into(), 15.into()).unwrap(),
+    );
+    assert_eq!(
+        result,
+        vec![
+            completion::Suggestion {
+                name: "aa".into(),
+                typ: Either::Right(Type::int()),
+            },
+            completion::Suggestion {
+                name: "ab".into(),
+                typ: Either::Right(Type::string()),
+            },
+        ]
+    );
+}
+
+#[test]
+fn suggest_module_members_with_documentation() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    let expr = r#"
+let map = import! std.map
+let x = map.si
+1 #Int+ 1.0
+"#;
+
+    let mut compiler = Compiler::new();
+    let (expr, _) = compiler.typecheck_partial_str(&vm, "example", expr, None);
+    let expr = expr.expect("Partial expression");
+    let (_, metadata) = check::metadata::metadata(&*vm.get_env(), &expr);
+
+    let lines = compiler.get_filemap("example").expect("file_map");
+    let pos = lines.byte_index(2.into(), 14.into()).unwrap();
+    let result = completion::suggest(&*vm.get_env(), lines.span(), &expr, pos);
+    let names = result.iter().map(|s| &s.name[..]).collect::<Vec<_>>();
+    assert_eq!(names, ["singleton", "size"]);
+
+    let documentation = completion::suggest_metadata(
+        &metadata,
+        &*vm.get_env(),
+        lines.span(),
+        &expr,
+        pos,
+        "singleton",
+    )
+    .and_then(|metadata| metadata.comment.as_ref())
+    .map(|comment| &comment.content[..]);
+    assert_eq!(documentation, Some("Creates a map with a single entry."));
+}
+
+#[test]
+fn suggest_module_path_of_an_import_which_does_not_exist_yet() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    for &(source, column) in &[("import! std.ma", 14), ("import! \"std/ma\"", 15)] {
+        let mut compiler = Compiler::new();
+        let (expr, result) = compiler.typecheck_partial_str(&vm, "example", source, None);
+        assert!(result.is_err());
+        let expr = expr.expect("Partial expression");
+
+        let lines = compiler.get_filemap("example").expect("file_map");
+        let query = completion::SuggestionQuery {
+            paths: vec![".".into()],
+            ..completion::SuggestionQuery::default()
+        };
+        let result = query.suggest(
+            &*vm.get_env(),
+            lines.span(),
+            &expr,
+            lines.byte_index(0.into(), column.into()).unwrap(),
+        );
+        let names = result.iter().map(|s| &s.name[..]).collect::<Vec<_>>();
+        assert_eq!(names, ["map"], "{}", source);
+    }
+}
+
 /// Would cause panics in `Source` as the spans from the implicit prelude were used with the
 /// `Source` from the normal expression
 #[test]
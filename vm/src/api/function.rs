@@ -14,7 +14,7 @@ use crate::api::{ActiveThread, AsyncPushable, Getable, Pushable, RootedValue, Vm
 use crate::compiler::{CompiledFunction, CompiledModule};
 use crate::gc::Move;
 use crate::stack::{ExternState, StackFrame};
-use crate::thread::{RootedThread, Status, Thread, ThreadInternal, VmRoot};
+use crate::thread::{ExecuteCall, RootedThread, Status, Thread, ThreadInternal, VmRoot};
 use crate::types::{Instruction, VmIndex};
 use crate::value::{ExternFunction, ValueRepr};
 use crate::{Error, Result, Variants};
@@ -220,6 +220,12 @@ where
             _marker: self._marker,
         })
     }
+
+    /// Returns the number of frames and values on the stack of the thread
+    fn stack_size(&self) -> (usize, VmIndex) {
+        let context = self.vm().context();
+        (context.stack.get_frames().len(), context.stack.len())
+    }
 }
 
 impl<T, F> VmType for Function<T, F>
@@ -434,22 +440,25 @@ impl<T, $($args,)* R> Function<T, fn($($args),*) -> R>
           T: Deref<Target = Thread> + Clone + Send,
           R: VmType + for<'x, 'value> Getable<'x, 'value> + Send + Sync + 'static,
 {
+    /// Calls the function without blocking on any futures returned by async primitives, resolving
+    /// to the result once the call completes. If the future is dropped before that, the call is
+    /// abandoned, which leaves the thread ready for other calls.
     #[allow(non_snake_case)]
     pub fn call_async(
         &mut self
         $(, $args: $args)*
         ) -> Box<Future<Item = R, Error = Error> + Send + Sync + 'static>
     {
-        use crate::thread::Execute;
         use futures::IntoFuture;
 
+        let (frames, values) = self.stack_size();
         match self.call_first($($args),*) {
             Ok(ok) => {
                 match ok {
                     Async::Ready(value) => Box::new(Ok(value).into_future()),
                     Async::NotReady => {
                         Box::new(
-                            Execute::new(self.value.vm().root_thread())
+                            ExecuteCall::new(self.value.vm().root_thread(), frames, values)
                                 .and_then(|value| Self::return_value(value.vm(), value.get_variant()))
                         )
                     }
@@ -467,15 +476,14 @@ impl<T, $($args,)* R> Function<T, fn($($args),*) -> R>
         $(, $args: $args)*
         ) -> Box<Future<Item = R, Error = Error> + Send + Sync + 'static>
     {
-        use crate::thread::Execute;
-
+        let (frames, values) = self.stack_size();
         match self.call_first($($args),*) {
             Ok(ok) => {
                 match ok {
                     Async::Ready(value) => Box::new(future::ok(value)),
                     Async::NotReady => {
                         Box::new(
-                            Execute::new(self.value.vm().root_thread())
+                            ExecuteCall::new(self.value.vm().root_thread(), frames, values)
                                 .and_then(|value| Self::return_value(value.vm(), value.get_variant()))
                         )
                     }
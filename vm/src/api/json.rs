@@ -1,14 +1,20 @@
 extern crate serde_json;
 
+pub mod stream;
+
 use std::borrow::Borrow;
 use std::fmt;
+use std::io;
 use std::result::Result as StdResult;
 
 use crate::base::types::{ArcType, Type};
 
-use crate::api::{Getable, OpaqueValue, VmType};
-use crate::thread::{ActiveThread, RootedThread, Thread, ThreadInternal};
-use crate::{ExternModule, Result, Variants};
+use crate::api::{Getable, Hole, OpaqueValue, ValueRef, VmType};
+use crate::interner::InternedStr;
+use crate::thread::{self, ActiveThread, RootedThread, Thread, ThreadInternal};
+use crate::types::{VmIndex, VmInt};
+use crate::value::{RecordDef, ValueRepr};
+use crate::{Error, ExternModule, Result, Variants};
 
 use crate::serde::de::{self, DeserializeState, MapAccess, SeqAccess, Visitor};
 
@@ -52,14 +58,209 @@ pub fn load(vm: &Thread) -> Result<ExternModule> {
                 |v| serialize(v, serde_json::ser::CompactFormatter)
             ),
             serialize_pretty => primitive!(
-                1,
+                2,
                 "std.json.prim.serialize_pretty",
-                |v| serialize(v, serde_json::ser::PrettyFormatter::new())
+                serialize_pretty
             ),
         },
     )
 }
 
+/// The options of `std.json.ser.to_string_pretty_with`
+#[derive(Getable, VmType)]
+#[gluon(gluon_vm)]
+struct PrettyOptions {
+    indent: VmInt,
+    sort_keys: bool,
+    ascii_only: bool,
+}
+
+fn serialize_pretty(
+    options: PrettyOptions,
+    value: crate::api::WithVM<Value>,
+) -> StdResult<String, String> {
+    let crate::api::WithVM { vm, value } = value;
+
+    if options.indent < 0 {
+        return Err(format!(
+            "Expected a non-negative indent, got {}",
+            options.indent
+        ));
+    }
+    let indent = vec![b' '; options.indent as usize];
+    let formatter = PrettyFormatter {
+        formatter: serde_json::ser::PrettyFormatter::with_indent(&indent),
+        ascii_only: options.ascii_only,
+    };
+
+    let mut output = Vec::new();
+    crate::serde::ser::Serialize::serialize(
+        &PrettyValue {
+            vm,
+            value,
+            sort_keys: options.sort_keys,
+        },
+        &mut serde_json::Serializer::with_formatter(&mut output, formatter),
+    )
+    .map_err(|err| err.to_string())?;
+
+    // serde_json outputs valid UTF-8
+    unsafe { Ok(String::from_utf8_unchecked(output)) }
+}
+
+/// Serializes a `Value` like its `SerializeState` implementation but errors on non-finite floats
+/// instead of writing them as `null` and optionally sorts the fields of objects.
+struct PrettyValue<'a> {
+    vm: &'a Thread,
+    value: Value,
+    sort_keys: bool,
+}
+
+impl<'a> PrettyValue<'a> {
+    fn nested(&self, value: &JsonValue) -> PrettyValue<'a> {
+        PrettyValue {
+            vm: self.vm,
+            value: Value::from_value(self.vm, value.0.get_variant()),
+            sort_keys: self.sort_keys,
+        }
+    }
+}
+
+impl<'a> crate::serde::ser::Serialize for PrettyValue<'a> {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        use crate::serde::ser::{Error, SerializeMap, SerializeSeq};
+
+        match self.value {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(b),
+            Value::Int(i) => serializer.serialize_i64(i),
+            Value::Float(f) => {
+                if f.is_finite() {
+                    serializer.serialize_f64(f)
+                } else {
+                    Err(S::Error::custom(format!(
+                        "Unable to serialize `{}`, JSON numbers must be finite",
+                        f
+                    )))
+                }
+            }
+            Value::String(ref s) => serializer.serialize_str(s),
+            Value::Array(ref elems) => {
+                let mut seq = serializer.serialize_seq(Some(elems.len()))?;
+                for elem in elems {
+                    seq.serialize_element(&self.nested(elem))?;
+                }
+                seq.end()
+            }
+            Value::Object(ref fields) => {
+                let mut fields = fields.iter().collect::<Vec<_>>();
+                if self.sort_keys {
+                    fields.sort_by(|l, r| l.0.cmp(r.0));
+                }
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (key, value) in fields {
+                    map.serialize_entry(&**key, &self.nested(value))?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// `serde_json::ser::PrettyFormatter` which can also escape every non-ASCII character
+struct PrettyFormatter<'a> {
+    formatter: serde_json::ser::PrettyFormatter<'a>,
+    ascii_only: bool,
+}
+
+impl<'a> serde_json::ser::Formatter for PrettyFormatter<'a> {
+    fn begin_array<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.formatter.begin_array(writer)
+    }
+
+    fn end_array<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.formatter.end_array(writer)
+    }
+
+    fn begin_array_value<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.formatter.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.formatter.end_array_value(writer)
+    }
+
+    fn begin_object<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.formatter.begin_object(writer)
+    }
+
+    fn end_object<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.formatter.end_object(writer)
+    }
+
+    fn begin_object_key<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.formatter.begin_object_key(writer, first)
+    }
+
+    fn begin_object_value<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.formatter.begin_object_value(writer)
+    }
+
+    fn end_object_value<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.formatter.end_object_value(writer)
+    }
+
+    fn write_string_fragment<W>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        if !self.ascii_only {
+            return writer.write_all(fragment.as_bytes());
+        }
+        let mut start = 0;
+        for (i, c) in fragment.char_indices() {
+            if !c.is_ascii() {
+                writer.write_all(&fragment.as_bytes()[start..i])?;
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    write!(writer, "\\u{:04x}", unit)?;
+                }
+                start = i + c.len_utf8();
+            }
+        }
+        writer.write_all(&fragment.as_bytes()[start..])
+    }
+}
+
 #[derive(Pushable, Getable, SerializeState)]
 #[serde(serialize_state = "Thread")]
 #[gluon(gluon_vm)]
@@ -286,3 +487,189 @@ impl<'de> de::DeserializeState<'de, ActiveThread<'de>> for JsonValue {
         deserializer.deserialize_any(ValueVisitor(thread))
     }
 }
+
+/// `Pushable` and `Getable` wrapper for `serde_json::Value`s whose shape is only known at runtime.
+///
+/// Where `JsonValue` marshals to the `std.json.value.Value` type, `SerdeValue` converts each JSON
+/// value to its plain gluon counterpart so that gluon code can use it directly. Since no type can
+/// be given up front the value has the type `_` and it is up to the gluon code receiving or
+/// returning it to use it consistently.
+///
+/// | JSON | gluon |
+/// |------|-------|
+/// | `null` | `None` |
+/// | `true`, `false` | `True`, `False` |
+/// | integers | `Int` |
+/// | other numbers | `Float` |
+/// | strings | `String` |
+/// | arrays | `Array` |
+/// | objects | records |
+///
+/// The keys of an object are used verbatim as the field names of the record, even if they are not
+/// valid gluon identifiers. Such fields can't be accessed by name from gluon code but they are kept
+/// and converted back unchanged.
+///
+/// Integers which do not fit in an `Int` (unsigned integers above `i64::MAX`) are pushed as
+/// `Float`s and may lose precision. Gluon arrays can only contain elements of one type, so if an
+/// array contains both integers and other numbers all of its numbers are pushed as `Float`s. Other
+/// arrays with elements of different kinds (e.g. both strings and numbers) fail to be pushed.
+///
+/// Converting back only looks at the runtime representation of the value:
+///
+/// * `Int`s (and `Byte`s) become integers and `Float`s become numbers, or `null` if not finite.
+/// * Records become objects.
+/// * Tags without any fields become booleans. `None` and `False` have the same representation so
+///   `null` is read back as `false`.
+/// * Variants with a single field, such as `Some x`, become the value of that field and other
+///   variants become an array of their fields.
+///
+/// Functions, userdata and threads have no JSON counterpart and cause a panic.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerdeValue(pub serde_json::Value);
+
+impl VmType for SerdeValue {
+    type Type = Hole;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        Hole::make_type(vm)
+    }
+}
+
+impl<'vm> crate::api::Pushable<'vm> for SerdeValue {
+    fn push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        match self.0 {
+            serde_json::Value::Null => context.push(ValueRepr::Tag(0)),
+            serde_json::Value::Bool(b) => crate::api::Pushable::push(b, context)?,
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => crate::api::Pushable::push(i, context)?,
+                None => {
+                    let f = n.as_f64().unwrap_or(f64::NAN);
+                    crate::api::Pushable::push(f, context)?
+                }
+            },
+            serde_json::Value::String(s) => crate::api::Pushable::push(s, context)?,
+            serde_json::Value::Array(elems) => push_array(elems, context)?,
+            serde_json::Value::Object(map) => {
+                let thread = context.thread();
+                let len = map.len() as VmIndex;
+                let mut field_names: Vec<InternedStr> = Vec::with_capacity(map.len());
+                for (key, value) in map {
+                    field_names.push(thread.global_env().intern(&key)?);
+                    crate::api::Pushable::push(SerdeValue(value), context)?;
+                }
+
+                let context = context.context();
+                let offset = context.stack.len() - len;
+                let value = thread::alloc(
+                    &mut context.gc,
+                    thread,
+                    &context.stack,
+                    RecordDef {
+                        fields: &field_names,
+                        elems: &context.stack[offset..],
+                    },
+                )?;
+                for _ in 0..len {
+                    context.stack.pop();
+                }
+                context.stack.push(ValueRepr::Data(value));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn push_array(mut elems: Vec<serde_json::Value>, context: &mut ActiveThread) -> Result<()> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Kind {
+        Number,
+        String,
+        Array,
+        // Represented as tags or data
+        Data,
+    }
+
+    fn kind(value: &serde_json::Value) -> Kind {
+        match *value {
+            serde_json::Value::Number(_) => Kind::Number,
+            serde_json::Value::String(_) => Kind::String,
+            serde_json::Value::Array(_) => Kind::Array,
+            serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::Object(_) => {
+                Kind::Data
+            }
+        }
+    }
+
+    if let Some(first) = elems.first().map(kind) {
+        if elems.iter().any(|elem| kind(elem) != first) {
+            return Err(Error::Message(
+                "Unable to push a JSON array with elements of different types".to_string(),
+            ));
+        }
+    }
+
+    let has_non_int = elems.iter().any(|elem| match *elem {
+        serde_json::Value::Number(ref n) => n.as_i64().is_none(),
+        _ => false,
+    });
+    if has_non_int {
+        for elem in &mut elems {
+            let float = match *elem {
+                serde_json::Value::Number(ref n) => n.as_f64(),
+                _ => None,
+            };
+            if let Some(number) = float.and_then(serde_json::Number::from_f64) {
+                *elem = serde_json::Value::Number(number);
+            }
+        }
+    }
+
+    let elems = elems.into_iter().map(SerdeValue).collect::<Vec<_>>();
+    crate::api::Pushable::push(elems, context)
+}
+
+impl<'vm, 'value> Getable<'vm, 'value> for SerdeValue {
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        SerdeValue(match value.as_ref() {
+            ValueRef::Byte(b) => serde_json::Value::from(b),
+            ValueRef::Int(i) => serde_json::Value::from(i),
+            ValueRef::Float(f) => serde_json::Number::from_f64(f)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            ValueRef::String(s) => serde_json::Value::from(s),
+            ValueRef::Array(array) => serde_json::Value::Array(
+                array
+                    .iter()
+                    .map(|elem| SerdeValue::from_value(vm, elem).0)
+                    .collect(),
+            ),
+            ValueRef::Data(data) => {
+                if data.is_record() {
+                    serde_json::Value::Object(
+                        data.field_names()
+                            .iter()
+                            .map(|name| {
+                                let field = data.lookup_field(vm, name).unwrap();
+                                (name.to_string(), SerdeValue::from_value(vm, field).0)
+                            })
+                            .collect(),
+                    )
+                } else {
+                    match data.len() {
+                        0 => serde_json::Value::Bool(data.tag() != 0),
+                        1 => SerdeValue::from_value(vm, data.get_variant(0).unwrap()).0,
+                        _ => serde_json::Value::Array(
+                            data.iter()
+                                .map(|field| SerdeValue::from_value(vm, field).0)
+                                .collect(),
+                        ),
+                    }
+                }
+            }
+            ValueRef::Closure(_)
+            | ValueRef::Userdata(_)
+            | ValueRef::Thread(_)
+            | ValueRef::Internal => ice!("ValueRef can't be converted to a JSON value"),
+        })
+    }
+}
@@ -0,0 +1,525 @@
+//! Pull parser which reads JSON documents one event at a time, letting gluon process documents
+//! which are too large to be deserialized into a single value.
+
+use ::std::fmt;
+use ::std::fs::File;
+use ::std::io::{BufRead, BufReader, Cursor};
+use ::std::path::Path;
+use ::std::sync::Mutex;
+
+use super::serde_json;
+
+use crate::api::IO;
+use crate::thread::Thread;
+use crate::types::VmInt;
+use crate::{ExternModule, Result};
+
+#[derive(Clone, Debug, PartialEq, Pushable, VmType)]
+#[gluon(vm_type = "std.json.stream.types.JsonEvent")]
+#[gluon(gluon_vm)]
+pub enum JsonEvent {
+    ObjectStart,
+    Key(String),
+    ArrayStart,
+    End,
+    NullValue,
+    BoolValue(bool),
+    IntValue(VmInt),
+    FloatValue(f64),
+    StringValue(String),
+    Eof,
+    Error(VmInt, String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Container {
+    Object,
+    Array,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Expect {
+    /// A value (or the end of input at the top level)
+    Value,
+    /// A value or `]` directly after `[`
+    FirstElement,
+    /// A key or `}` directly after `{`
+    FirstKey,
+    /// A key after `,` in an object
+    Key,
+    /// `,` or the end of the enclosing container (or another value at the top level)
+    Separator,
+}
+
+#[derive(Debug)]
+struct ParseError {
+    offset: u64,
+    message: String,
+}
+
+/// Parses JSON from `R`, only keeping the token being parsed and the nesting of the containers it
+/// is in, in memory. Multiple whitespace separated documents may follow each other in the input.
+pub struct Parser<R> {
+    reader: R,
+    offset: u64,
+    containers: Vec<Container>,
+    expect: Expect,
+    error: Option<(u64, String)>,
+}
+
+impl<R> Parser<R>
+where
+    R: BufRead,
+{
+    pub fn new(reader: R) -> Self {
+        Parser {
+            reader,
+            offset: 0,
+            containers: Vec::new(),
+            expect: Expect::Value,
+            error: None,
+        }
+    }
+
+    /// Returns the next event of the input. Once an `Error` or `Eof` event has been returned the
+    /// same event is returned for all later calls.
+    pub fn next_event(&mut self) -> JsonEvent {
+        if let Some((offset, ref message)) = self.error {
+            return JsonEvent::Error(offset as VmInt, message.clone());
+        }
+        match self.parse_event() {
+            Ok(event) => event,
+            Err(ParseError { offset, message }) => {
+                self.error = Some((offset, message.clone()));
+                JsonEvent::Error(offset as VmInt, message)
+            }
+        }
+    }
+
+    fn parse_event(&mut self) -> StdResult<JsonEvent> {
+        loop {
+            let expect = self.expect;
+            let c = match self.peek_non_whitespace()? {
+                Some(c) => c,
+                None if self.containers.is_empty()
+                    && (expect == Expect::Value || expect == Expect::Separator) =>
+                {
+                    self.expect = Expect::Value;
+                    return Ok(JsonEvent::Eof);
+                }
+                None => return Err(self.error_at("Unexpected end of input")),
+            };
+            match expect {
+                Expect::Value => return self.parse_value(c),
+                Expect::FirstElement if c == b']' => return Ok(self.end_container()),
+                Expect::FirstElement => return self.parse_value(c),
+                Expect::FirstKey if c == b'}' => return Ok(self.end_container()),
+                Expect::FirstKey | Expect::Key => return self.parse_key(c),
+                Expect::Separator => match (self.containers.last().cloned(), c) {
+                    (None, _) => self.expect = Expect::Value,
+                    (Some(Container::Array), b',') => {
+                        self.bump();
+                        self.expect = Expect::Value;
+                    }
+                    (Some(Container::Object), b',') => {
+                        self.bump();
+                        self.expect = Expect::Key;
+                    }
+                    (Some(Container::Array), b']') | (Some(Container::Object), b'}') => {
+                        return Ok(self.end_container());
+                    }
+                    (Some(Container::Array), _) => {
+                        return Err(self.error_at("Expected `,` or `]`"));
+                    }
+                    (Some(Container::Object), _) => {
+                        return Err(self.error_at("Expected `,` or `}`"));
+                    }
+                },
+            }
+        }
+    }
+
+    fn parse_value(&mut self, c: u8) -> StdResult<JsonEvent> {
+        let event = match c {
+            b'{' => {
+                self.bump();
+                self.containers.push(Container::Object);
+                self.expect = Expect::FirstKey;
+                return Ok(JsonEvent::ObjectStart);
+            }
+            b'[' => {
+                self.bump();
+                self.containers.push(Container::Array);
+                self.expect = Expect::FirstElement;
+                return Ok(JsonEvent::ArrayStart);
+            }
+            b'"' => JsonEvent::StringValue(self.parse_string()?),
+            b't' => {
+                self.parse_literal("true")?;
+                JsonEvent::BoolValue(true)
+            }
+            b'f' => {
+                self.parse_literal("false")?;
+                JsonEvent::BoolValue(false)
+            }
+            b'n' => {
+                self.parse_literal("null")?;
+                JsonEvent::NullValue
+            }
+            b'-' | b'0'..=b'9' => self.parse_number()?,
+            _ => return Err(self.error_at("Expected a value")),
+        };
+        self.expect = Expect::Separator;
+        Ok(event)
+    }
+
+    fn parse_key(&mut self, c: u8) -> StdResult<JsonEvent> {
+        if c != b'"' {
+            return Err(self.error_at("Expected a key"));
+        }
+        let key = self.parse_string()?;
+        if self.peek_non_whitespace()? != Some(b':') {
+            return Err(self.error_at("Expected `:` after the key"));
+        }
+        self.bump();
+        self.expect = Expect::Value;
+        Ok(JsonEvent::Key(key))
+    }
+
+    fn end_container(&mut self) -> JsonEvent {
+        self.bump();
+        self.containers.pop();
+        self.expect = Expect::Separator;
+        JsonEvent::End
+    }
+
+    fn parse_literal(&mut self, literal: &str) -> StdResult<()> {
+        for &expected in literal.as_bytes() {
+            if self.peek()? != Some(expected) {
+                return Err(self.error_at(format!("Expected `{}`", literal)));
+            }
+            self.bump();
+        }
+        Ok(())
+    }
+
+    fn parse_number(&mut self) -> StdResult<JsonEvent> {
+        let start = self.offset;
+        let mut text = Vec::new();
+        while let Some(c) = self.peek()? {
+            match c {
+                b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9' => {
+                    text.push(c);
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+        // The characters above are all ASCII
+        let text = String::from_utf8(text).unwrap();
+        let number = serde_json::from_str::<serde_json::Number>(&text).map_err(|_| ParseError {
+            offset: start,
+            message: format!("Invalid number `{}`", text),
+        })?;
+        Ok(match number.as_i64() {
+            Some(i) => JsonEvent::IntValue(i),
+            None => JsonEvent::FloatValue(number.as_f64().unwrap_or(f64::NAN)),
+        })
+    }
+
+    fn parse_string(&mut self) -> StdResult<String> {
+        let start = self.offset;
+        self.bump();
+        let mut bytes = Vec::new();
+        loop {
+            let c = match self.peek()? {
+                Some(c) => c,
+                None => return Err(self.error_at("Unterminated string")),
+            };
+            match c {
+                b'"' => {
+                    self.bump();
+                    break;
+                }
+                b'\\' => {
+                    self.bump();
+                    let escaped = match self.peek()? {
+                        Some(c) => c,
+                        None => return Err(self.error_at("Unterminated string")),
+                    };
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            self.bump();
+                            self.parse_unicode_escape()?
+                        }
+                        _ => return Err(self.error_at("Invalid escape")),
+                    };
+                    if escaped != b'u' {
+                        self.bump();
+                    }
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                0..=0x1f => return Err(self.error_at("Control character in string")),
+                _ => {
+                    bytes.push(c);
+                    self.bump();
+                }
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| ParseError {
+            offset: start,
+            message: "Invalid UTF-8 in string".to_string(),
+        })
+    }
+
+    /// Parses the digits of a `\u` escape, including the second half of a surrogate pair
+    fn parse_unicode_escape(&mut self) -> StdResult<char> {
+        let start = self.offset;
+        let high = self.parse_hex4()?;
+        let code = match high {
+            0xD800..=0xDBFF => {
+                self.parse_literal("\\u")
+                    .map_err(|_| self.error_at("Expected the second half of a surrogate pair"))?;
+                let low = self.parse_hex4()?;
+                if !(0xDC00..=0xDFFF).contains(&low) {
+                    return Err(ParseError {
+                        offset: start,
+                        message: "Invalid surrogate pair".to_string(),
+                    });
+                }
+                0x10000 + ((u32::from(high) - 0xD800) << 10) + (u32::from(low) - 0xDC00)
+            }
+            0xDC00..=0xDFFF => {
+                return Err(ParseError {
+                    offset: start,
+                    message: "Unpaired surrogate".to_string(),
+                });
+            }
+            _ => u32::from(high),
+        };
+        // Surrogates are handled above so every remaining code point is a valid `char`
+        Ok(::std::char::from_u32(code).unwrap())
+    }
+
+    fn parse_hex4(&mut self) -> StdResult<u16> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = match self.peek()? {
+                Some(c) => (c as char).to_digit(16),
+                None => None,
+            };
+            match digit {
+                Some(digit) => {
+                    value = value * 16 + digit as u16;
+                    self.bump();
+                }
+                None => return Err(self.error_at("Expected a hexadecimal digit")),
+            }
+        }
+        Ok(value)
+    }
+
+    fn peek(&mut self) -> StdResult<Option<u8>> {
+        match self.reader.fill_buf() {
+            Ok(buf) => Ok(buf.first().cloned()),
+            Err(err) => Err(ParseError {
+                offset: self.offset,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    fn peek_non_whitespace(&mut self) -> StdResult<Option<u8>> {
+        loop {
+            match self.peek()? {
+                Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => self.bump(),
+                c => return Ok(c),
+            }
+        }
+    }
+
+    /// Consumes the byte returned by the last call to `peek`
+    fn bump(&mut self) {
+        self.reader.consume(1);
+        self.offset += 1;
+    }
+
+    fn error_at(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            offset: self.offset,
+            message: message.into(),
+        }
+    }
+}
+
+type StdResult<T> = ::std::result::Result<T, ParseError>;
+
+#[derive(Userdata)]
+#[gluon(gluon_vm)]
+pub struct JsonParser(Mutex<Parser<Box<dyn BufRead + Send + Sync>>>);
+
+impl fmt::Debug for JsonParser {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parser = self.0.lock().unwrap();
+        f.debug_struct("JsonParser")
+            .field("offset", &parser.offset)
+            .field("depth", &parser.containers.len())
+            .finish()
+    }
+}
+
+fn parser_from_file(path: &Path) -> IO<JsonParser> {
+    match File::open(path) {
+        Ok(file) => IO::Value(JsonParser(Mutex::new(Parser::new(Box::new(
+            BufReader::new(file),
+        ))))),
+        Err(err) => IO::Exception(format!("Could not open `{}`: {}", path.display(), err)),
+    }
+}
+
+fn parser_from_string(input: &str) -> JsonParser {
+    let reader: Box<dyn BufRead + Send + Sync> = Box::new(Cursor::new(input.to_string()));
+    JsonParser(Mutex::new(Parser::new(reader)))
+}
+
+fn next_event(parser: &JsonParser) -> IO<JsonEvent> {
+    IO::Value(parser.0.lock().unwrap().next_event())
+}
+
+mod std {
+    pub mod json {
+        pub mod stream {
+            pub use crate::api::json::stream as prim;
+        }
+    }
+}
+
+pub fn load(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
+    vm.register_type::<JsonParser>("JsonParser", &[])?;
+
+    ExternModule::new(
+        vm,
+        record! {
+            type JsonParser => JsonParser,
+            from_file => primitive!(1, std::json::stream::prim::parser_from_file),
+            from_string => primitive!(1, std::json::stream::prim::parser_from_string),
+            next_event => primitive!(1, std::json::stream::prim::next_event)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(input: &str) -> Vec<JsonEvent> {
+        let mut parser = Parser::new(input.as_bytes());
+        let mut events = Vec::new();
+        loop {
+            let event = parser.next_event();
+            events.push(event.clone());
+            match event {
+                JsonEvent::Eof | JsonEvent::Error(..) => return events,
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn nested() {
+        use self::JsonEvent::*;
+        assert_eq!(
+            events(r#" { "a" : [1, -2.5, "x\nå😀"], "b": {}, "c": [true, false, null] } "#),
+            vec![
+                ObjectStart,
+                Key("a".to_string()),
+                ArrayStart,
+                IntValue(1),
+                FloatValue(-2.5),
+                StringValue("x\nå😀".to_string()),
+                End,
+                Key("b".to_string()),
+                ObjectStart,
+                End,
+                Key("c".to_string()),
+                ArrayStart,
+                BoolValue(true),
+                BoolValue(false),
+                NullValue,
+                End,
+                End,
+                Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn multiple_documents() {
+        use self::JsonEvent::*;
+        assert_eq!(
+            events("1 [] \"a\""),
+            vec![
+                IntValue(1),
+                ArrayStart,
+                End,
+                StringValue("a".to_string()),
+                Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        use self::JsonEvent::*;
+        assert_eq!(
+            events("[1 2]"),
+            vec![
+                ArrayStart,
+                IntValue(1),
+                Error(3, "Expected `,` or `]`".to_string())
+            ]
+        );
+        assert_eq!(
+            events(r#"{"a" 1}"#),
+            vec![
+                ObjectStart,
+                Error(5, "Expected `:` after the key".to_string())
+            ]
+        );
+        assert_eq!(
+            events("[tru]"),
+            vec![ArrayStart, Error(4, "Expected `true`".to_string())]
+        );
+        assert_eq!(
+            events("[1,"),
+            vec![
+                ArrayStart,
+                IntValue(1),
+                Error(3, "Unexpected end of input".to_string())
+            ]
+        );
+        assert_eq!(
+            events("01"),
+            vec![Error(0, "Invalid number `01`".to_string())]
+        );
+    }
+
+    #[test]
+    fn error_is_repeated() {
+        let mut parser = Parser::new(&b"]"[..]);
+        let error = JsonEvent::Error(0, "Expected a value".to_string());
+        assert_eq!(parser.next_event(), error);
+        assert_eq!(parser.next_event(), error);
+    }
+}
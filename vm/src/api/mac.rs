@@ -69,6 +69,29 @@ macro_rules! closure_wrapper {
 ///     primitive!(2, test);
 /// }
 /// ```
+///
+/// Functions which need to wait on something, such as a network request, should return a `Future`
+/// instead of blocking. Prefixing the function with `async fn` tells the virtual machine to
+/// suspend the calling gluon thread until the future resolves, after which it is resumed with the
+/// resolved value. Functions which already return a `FutureResult` do not need the prefix.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate gluon_vm;
+/// extern crate futures;
+///
+/// use futures::{Future, IntoFuture};
+/// use gluon_vm::api::IO;
+/// use gluon_vm::Error;
+///
+/// fn fetch(url: String) -> impl Future<Item = IO<String>, Error = Error> {
+///     Ok(IO::Value(url)).into_future()
+/// }
+///
+/// fn main() {
+///     primitive!(1, async fn fetch);
+/// }
+/// ```
 #[macro_export(local_inner_macros)]
 macro_rules! primitive {
     ($arg_count:tt, async fn $name:expr) => {
@@ -7,11 +7,12 @@ use crate::base::{
 use crate::{
     forget_lifetime,
     gc::{DataDef, Gc, GcPtr, Move, Traverseable},
+    stack::Stacktrace,
     thread::{self, Context, RootedThread, ThreadInternal, VmRoot},
     types::{VmIndex, VmInt, VmTag},
     value::{
-        ArrayDef, ArrayRepr, Cloner, ClosureData, DataStruct, Def, GcStr, Value, ValueArray,
-        ValueRepr,
+        ArrayDef, ArrayRepr, Cloner, ClosureData, DataStruct, Def, GcStr, RecordDef, Value,
+        ValueArray, ValueRepr,
     },
     vm::{self, RootedValue, Status, Thread},
     Error, Result, Variants,
@@ -19,12 +20,13 @@ use crate::{
 
 use std::{
     any::Any,
-    borrow::Borrow,
     cell::Ref,
     cmp::Ordering,
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap},
     ffi::{OsStr, OsString},
     fmt,
+    hash::{BuildHasher, Hash},
+    io,
     marker::PhantomData,
     ops::Deref,
     path::{Path, PathBuf},
@@ -229,6 +231,14 @@ impl<'a> Data<'a> {
         }
     }
 
+    #[doc(hidden)]
+    pub fn is_record(&self) -> bool {
+        match self.0 {
+            DataInner::Tag(_) => false,
+            DataInner::Data(data) => data.is_record(),
+        }
+    }
+
     #[doc(hidden)]
     pub fn field_names(&self) -> Vec<crate::interner::InternedStr> {
         match self.0 {
@@ -256,6 +266,47 @@ impl VmType for Hole {
 pub enum IO<T> {
     Value(T),
     Exception(String),
+    /// An error which can be matched on with `std.io.catch_error`
+    Error(IOError),
+}
+
+/// The structured errors of IO actions, mapped to `std.io.types.IOError`
+#[derive(Clone, Debug, PartialEq, Getable, Pushable, VmType)]
+#[gluon(vm_type = "std.io.types.IOError")]
+#[gluon(gluon_vm)]
+pub enum IOError {
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    Interrupted,
+    TimedOut,
+    Other(String),
+}
+
+impl fmt::Display for IOError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IOError::NotFound => write!(f, "entity not found"),
+            IOError::PermissionDenied => write!(f, "permission denied"),
+            IOError::AlreadyExists => write!(f, "entity already exists"),
+            IOError::Interrupted => write!(f, "operation interrupted"),
+            IOError::TimedOut => write!(f, "timed out"),
+            IOError::Other(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<io::Error> for IOError {
+    fn from(err: io::Error) -> IOError {
+        match err.kind() {
+            io::ErrorKind::NotFound => IOError::NotFound,
+            io::ErrorKind::PermissionDenied => IOError::PermissionDenied,
+            io::ErrorKind::AlreadyExists => IOError::AlreadyExists,
+            io::ErrorKind::Interrupted => IOError::Interrupted,
+            io::ErrorKind::TimedOut => IOError::TimedOut,
+            _ => IOError::Other(err.to_string()),
+        }
+    }
 }
 
 impl<T, E> From<StdResult<T, E>> for IO<T>
@@ -380,9 +431,10 @@ fn insert_forall_walker(
         )
         .map(|t| ArcType::from(Type::Variant(t))),
 
-        Type::Record(ref typ) => match **typ {
+        // The fields of a tuple share the variables of the type it is part of
+        Type::Record(ref row) if !types::is_tuple(typ) => match **row {
             Type::ExtendRow { .. } => types::walk_move_type_opt(
-                typ,
+                row,
                 &mut types::ControlVisitation(|typ: &ArcType| insert_forall(variables, typ)),
             )
             .map(|typ| ArcType::from(Type::Record(typ))),
@@ -442,18 +494,58 @@ pub trait AsyncPushable<'vm> {
         match self.async_push(context, frame_index) {
             Ok(Async::Ready(())) => Status::Ok,
             Ok(Async::NotReady) => Status::Yield,
-            Err(err) => {
-                let msg = unsafe {
-                    GcStr::from_utf8_unchecked(
-                        context
-                            .context()
-                            .alloc_ignore_limit(format!("{}", err).as_bytes()),
-                    )
-                };
-                context.push(ValueRepr::String(msg));
-                Status::Error
+            Err(err) => push_error(context, err),
+        }
+    }
+}
+
+/// Pushes `err` so that the thread can retrieve it once `Status::Error` is returned. `IOError`s
+/// are pushed as values so they keep their structure, `Error::OutOfMemory` is stored on the
+/// context as is and every other error is pushed as its message
+fn push_error(context: &mut ActiveThread, err: Error) -> Status {
+    match err {
+        Error::IO(err, _) => {
+            if let Err(err) = err.push(context) {
+                return push_error(context, err);
             }
         }
+        err @ Error::OutOfMemory { .. } => {
+            context.context().error = Some(err);
+            context.push(ValueRepr::Int(0));
+        }
+        err => {
+            let msg = unsafe {
+                GcStr::from_utf8_unchecked(
+                    context
+                        .context()
+                        .alloc_ignore_limit(format!("{}", err).as_bytes()),
+                )
+            };
+            context.push(ValueRepr::String(msg));
+        }
+    }
+    Status::Error
+}
+
+/// Retrieves the error pushed by `push_error` once a function has returned `Status::Error`
+pub(crate) fn pop_error(
+    thread: &Thread,
+    error: Option<Error>,
+    value: &Value,
+    stacktrace: Stacktrace,
+) -> Option<Error> {
+    if error.is_some() {
+        return error;
+    }
+    // The value was just popped from the stack and nothing is allocated before it is read
+    let value = unsafe { Variants::new(value) };
+    match value.as_ref() {
+        ValueRef::String(s) => Some(Error::Panic(s.to_string(), Some(stacktrace))),
+        ValueRef::Data(_) => Some(Error::IO(
+            IOError::from_value(thread, value),
+            Some(stacktrace),
+        )),
+        _ => None,
     }
 }
 
@@ -479,17 +571,7 @@ pub trait Pushable<'vm>: AsyncPushable<'vm> {
     {
         match self.push(context) {
             Ok(()) => Status::Ok,
-            Err(err) => {
-                let msg = unsafe {
-                    GcStr::from_utf8_unchecked(
-                        context
-                            .context()
-                            .alloc_ignore_limit(format!("{}", err).as_bytes()),
-                    )
-                };
-                context.push(ValueRepr::String(msg));
-                Status::Error
-            }
+            Err(err) => push_error(context, err),
         }
     }
 
@@ -1077,6 +1159,78 @@ impl<'vm, 'value, T: Getable<'vm, 'value>> Getable<'vm, 'value> for Box<T> {
     }
 }
 
+fn make_map_type<K, V>(vm: &Thread) -> ArcType
+where
+    K: VmType,
+    V: VmType,
+{
+    let map_alias = vm
+        .find_type_info("std.map.types.Map")
+        .unwrap()
+        .clone()
+        .into_type();
+    Type::app(map_alias, collect![K::make_type(vm), V::make_type(vm)])
+}
+
+/// Pushes `entries`, which must be sorted by their keys, as a balanced `std.map.Map`.
+///
+/// The tree is built directly instead of through `std.map.insert` as that would need the `Ord`
+/// instance of the keys, so the order of the keys in Rust must agree with their order in gluon.
+pub(crate) fn push_map_entries<'vm, K, V>(
+    mut entries: Vec<(K, V)>,
+    context: &mut ActiveThread<'vm>,
+) -> Result<()>
+where
+    K: Pushable<'vm>,
+    V: Pushable<'vm>,
+{
+    if entries.is_empty() {
+        // Tip
+        context.push(ValueRepr::Tag(0));
+        return Ok(());
+    }
+    let right = entries.split_off(entries.len() - entries.len() / 2);
+    let (key, value) = entries.pop().unwrap();
+    key.push(context)?;
+    value.push(context)?;
+    push_map_entries(entries, context)?;
+    push_map_entries(right, context)?;
+    // Bin key value left right
+    let thread = context.thread();
+    context.context().push_new_data(thread, 1, 4)?;
+    context.context().slide(4);
+    Ok(())
+}
+
+/// Calls `insert` with every entry of the `std.map.Map` in `value`, in ascending order of the keys.
+/// `insert` returns `false` if the key was already inserted, which happens if distinct gluon keys
+/// convert to the same Rust key.
+pub(crate) fn walk_map<'vm, 'value, K, V>(
+    vm: &'vm Thread,
+    value: Variants<'value>,
+    insert: &mut dyn FnMut(K, V) -> bool,
+) where
+    K: Getable<'vm, 'value>,
+    V: Getable<'vm, 'value>,
+{
+    match value.as_ref() {
+        ValueRef::Data(data) => {
+            if data.tag() == 1 {
+                walk_map(vm, data.get_variant(2).expect("left"), insert);
+
+                let key = K::from_value(vm, data.get_variant(0).expect("key"));
+                let value = V::from_value(vm, data.get_variant(1).expect("value"));
+                if !insert(key, value) {
+                    panic!("Distinct keys of the map were converted to the same key");
+                }
+
+                walk_map(vm, data.get_variant(3).expect("right"), insert);
+            }
+        }
+        _ => ice!("ValueRef is not a Map"),
+    }
+}
+
 impl<K, V> VmType for BTreeMap<K, V>
 where
     K: VmType,
@@ -1087,73 +1241,85 @@ where
     type Type = BTreeMap<K::Type, V::Type>;
 
     fn make_type(vm: &Thread) -> ArcType {
-        let map_alias = vm
-            .find_type_info("std.map.Map")
-            .unwrap()
-            .clone()
-            .into_type();
-        Type::app(map_alias, collect![K::make_type(vm), V::make_type(vm)])
+        make_map_type::<K, V>(vm)
     }
 }
 
+/// Pushes the map as a `std.map.Map`. The gluon `Ord` instance of `K` must order the keys the same
+/// way as the `Ord` implementation of `K` does.
 impl<'vm, K, V> Pushable<'vm> for BTreeMap<K, V>
 where
-    K: Borrow<str> + VmType,
-    K::Type: Sized,
-    V: for<'vm2> Pushable<'vm2> + VmType,
-    V::Type: Sized,
+    K: Pushable<'vm> + Ord,
+    V: Pushable<'vm>,
 {
     fn push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
-        let thread = context.thread();
-        type Map<V2> = OpaqueValue<RootedThread, BTreeMap<String, V2>>;
-        let mut map: Map<V> = thread.get_global("std.map.empty")?;
-        let mut insert: OwnedFunction<fn(String, V, Map<V>) -> Map<V>> =
-            thread.get_global("std.json.de.insert_string")?;
-
-        context.drop();
-        for (key, value) in self {
-            map = insert.call(key.borrow().to_string(), value, map)?;
-        }
-        context.restore();
-
-        map.push(context)
+        push_map_entries(self.into_iter().collect(), context)
     }
 }
 
+/// Reads a `std.map.Map`.
+///
+/// # Panics
+///
+/// If two keys of the gluon map convert to the same key in Rust.
 impl<'vm, 'value, K, V> Getable<'vm, 'value> for BTreeMap<K, V>
 where
     K: Getable<'vm, 'value> + Ord,
     V: Getable<'vm, 'value>,
 {
     fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
-        fn build_map<'vm2, 'value2, K2, V2>(
-            map: &mut BTreeMap<K2, V2>,
-            vm: &'vm2 Thread,
-            value: Variants<'value2>,
-        ) where
-            K2: Getable<'vm2, 'value2> + Ord,
-            V2: Getable<'vm2, 'value2>,
-        {
-            match value.as_ref() {
-                ValueRef::Data(data) => {
-                    if data.tag() == 1 {
-                        let key = K2::from_value(vm, data.get_variant(0).expect("key"));
-                        let value = V2::from_value(vm, data.get_variant(1).expect("value"));
-                        map.insert(key, value);
-
-                        let left = data.get_variant(2).expect("left");
-                        build_map(map, vm, left);
-
-                        let right = data.get_variant(3).expect("right");
-                        build_map(map, vm, right);
-                    }
-                }
-                _ => ice!("ValueRef is not a Map"),
-            }
-        }
-
         let mut map = BTreeMap::new();
-        build_map(&mut map, vm, value);
+        walk_map(vm, value, &mut |key, value| {
+            map.insert(key, value).is_none()
+        });
+        map
+    }
+}
+
+impl<K, V, S> VmType for HashMap<K, V, S>
+where
+    K: VmType,
+    K::Type: Sized,
+    V: VmType,
+    V::Type: Sized,
+{
+    type Type = HashMap<K::Type, V::Type>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        make_map_type::<K, V>(vm)
+    }
+}
+
+/// Pushes the map as a `std.map.Map`. The gluon `Ord` instance of `K` must order the keys the same
+/// way as the `Ord` implementation of `K` does.
+impl<'vm, K, V, S> Pushable<'vm> for HashMap<K, V, S>
+where
+    K: Pushable<'vm> + Ord,
+    V: Pushable<'vm>,
+{
+    fn push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        let mut entries: Vec<_> = self.into_iter().collect();
+        entries.sort_by(|l, r| l.0.cmp(&r.0));
+        push_map_entries(entries, context)
+    }
+}
+
+/// Reads a `std.map.Map`.
+///
+/// # Panics
+///
+/// If two keys of the gluon map convert to the same key in Rust.
+impl<'vm, 'value, K, V, S> Getable<'vm, 'value> for HashMap<K, V, S>
+where
+    K: Getable<'vm, 'value> + Eq + Hash,
+    V: Getable<'vm, 'value>,
+    S: BuildHasher + Default,
+{
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        let mut map = HashMap::default();
+        walk_map(vm, value, &mut |key, value| {
+            map.insert(key, value).is_none()
+        });
         map
     }
 }
@@ -1266,6 +1432,13 @@ impl<'vm, 'value, T: Getable<'vm, 'value>, E: Getable<'vm, 'value>> Getable<'vm,
 
 /// Wrapper around a `Future` which can be used as a return value to let the virtual machine know
 /// that it must resolve the `Future` to receive the value.
+///
+/// The gluon thread which called the function is suspended until the `Future` resolves, leaving
+/// other threads free to run. Values on its stack stay rooted while it waits but the `Future`
+/// itself is not traversed by the garbage collector so it must only hold values which own or root
+/// their data. If the `Future` resolves to an error, or to a value which fails to be pushed such
+/// as a `RuntimeResult::Panic`, the error is raised in the gluon thread where it can be caught
+/// with `io.catch`.
 pub struct FutureResult<F>(pub F);
 
 impl<F> FutureResult<F> {
@@ -1324,7 +1497,7 @@ impl<T, E> From<StdResult<T, E>> for RuntimeResult<T, E> {
     }
 }
 
-impl<T: VmType, E> VmType for RuntimeResult<T, E> {
+impl<T: VmType, E: fmt::Display> VmType for RuntimeResult<T, E> {
     type Type = T::Type;
 
     fn make_forall_type(vm: &Thread) -> ArcType {
@@ -1348,6 +1521,78 @@ impl<'vm, T: Pushable<'vm>, E: fmt::Display> Pushable<'vm> for RuntimeResult<T,
     }
 }
 
+/// Wrapper for VM errors which should abort the running computation as they are instead of being
+/// formatted into a panic message. Returning a `RuntimeResult<T, Propagate>` lets errors such as
+/// `Error::OutOfMemory` reach the caller of the VM as their own variant.
+pub struct Propagate(pub Error);
+
+impl<T: VmType> VmType for RuntimeResult<T, Propagate> {
+    type Type = T::Type;
+
+    fn make_forall_type(vm: &Thread) -> ArcType {
+        T::make_forall_type(vm)
+    }
+
+    fn make_type(vm: &Thread) -> ArcType {
+        T::make_type(vm)
+    }
+
+    fn extra_args() -> VmIndex {
+        T::extra_args()
+    }
+}
+
+impl<'vm, T: Pushable<'vm>> Pushable<'vm> for RuntimeResult<T, Propagate> {
+    fn push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        match self {
+            RuntimeResult::Return(value) => value.push(context),
+            RuntimeResult::Panic(Propagate(err)) => Err(err),
+        }
+    }
+}
+
+impl From<Error> for Propagate {
+    fn from(err: Error) -> Propagate {
+        Propagate(err)
+    }
+}
+
+/// Wrapper for errors which should be passed to gluon as values instead of aborting the running
+/// computation with a message. A `RuntimeResult<T, Structured<E>>` is seen as `Result e t` from
+/// gluon so the error can be matched on, or rethrown into `std.effect.error` with `ok_or_throw`.
+///
+/// Since `io.catch` only receives the message of a panic, use a plain `RuntimeResult<T, E>`
+/// instead if the error is only meant to be displayed.
+pub struct Structured<E>(pub E);
+
+impl<T, E> VmType for RuntimeResult<T, Structured<E>>
+where
+    T: VmType,
+    T::Type: Sized,
+    E: VmType,
+    E::Type: Sized,
+{
+    type Type = StdResult<T::Type, E::Type>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        StdResult::<T, E>::make_type(vm)
+    }
+}
+
+impl<'vm, T, E> Pushable<'vm> for RuntimeResult<T, Structured<E>>
+where
+    T: Pushable<'vm>,
+    E: Pushable<'vm>,
+{
+    fn push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        let result: StdResult<T, E> = match self {
+            RuntimeResult::Return(value) => Ok(value),
+            RuntimeResult::Panic(Structured(err)) => Err(err),
+        };
+        result.push(context)
+    }
+}
+
 impl<T> VmType for IO<T>
 where
     T: VmType,
@@ -1375,6 +1620,7 @@ impl<'vm, T: Pushable<'vm>> Pushable<'vm> for IO<T> {
         match self {
             IO::Value(value) => value.push(context),
             IO::Exception(exc) => Err(Error::Message(exc)),
+            IO::Error(err) => Err(Error::IO(err, None)),
         }
     }
 }
@@ -1393,9 +1639,12 @@ where
     fn push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
         let value = {
             let thread = context.thread();
+            let migrate = !thread.shares_global_state_with(self.vm());
             let full_clone = !thread.can_share_values_with(context.gc(), self.vm());
             let mut cloner = Cloner::new(thread, context.gc());
-            if full_clone {
+            if migrate {
+                cloner.migrate();
+            } else if full_clone {
                 cloner.force_full_clone();
             }
             cloner.deep_clone(&self.get_value())?
@@ -1521,13 +1770,18 @@ macro_rules! define_tuple {
                 )+
                 let len = count!($($id),+);
                 let thread = context.thread();
+                // Tuples are records with the fields `_0`, `_1`, ... in gluon so the fields
+                // must be named for them to be accessible
+                let field_names = (0..len)
+                    .map(|i| thread.global_env().intern(&format!("_{}", i)))
+                    .collect::<Result<Vec<_>>>()?;
                 let context = context.context();
                 let offset = context.stack.len() - len;
                 let value = thread::alloc(&mut context.gc,
                                           thread,
                                           &context.stack,
-                                          Def {
-                                              tag: 0,
+                                          RecordDef {
+                                              fields: &field_names,
                                               elems: &context.stack[offset..],
                                           })?;
                 for _ in 0..len {
@@ -1559,12 +1813,7 @@ where
     type Type = Map<K::Type, V::Type>;
 
     fn make_type(vm: &Thread) -> ArcType {
-        let map_alias = vm
-            .find_type_info("std.map.Map")
-            .unwrap()
-            .clone()
-            .into_type();
-        Type::app(map_alias, collect![K::make_type(vm), V::make_type(vm)])
+        make_map_type::<K, V>(vm)
     }
 }
 
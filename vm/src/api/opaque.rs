@@ -7,7 +7,8 @@ use std::ops::Deref;
 use crate::base::types::ArcType;
 
 use crate::api::{ArrayRef, Getable, Pushable, ValueRef, VmType};
-use crate::thread::{ActiveThread, RootedValue, Thread, ThreadInternal, VmRoot};
+use crate::gc::{Gc, Traverseable};
+use crate::thread::{ActiveThread, RootedThread, RootedValue, Thread, ThreadInternal, VmRoot};
 use crate::types::{VmIndex, VmInt};
 use crate::value::{ArrayRepr, Value, ValueArray};
 use crate::vm;
@@ -15,8 +16,6 @@ use crate::{Result, Variants};
 
 #[cfg(feature = "serde")]
 use std::result::Result as StdResult;
-#[cfg(feature = "serde")]
-use crate::thread::RootedThread;
 
 #[cfg(feature = "serde")]
 use crate::serde::de::{Deserialize, Deserializer};
@@ -249,6 +248,16 @@ where
     }
 }
 
+impl<T, V> Traverseable for Opaque<T, V>
+where
+    T: Traverseable,
+    V: ?Sized,
+{
+    fn traverse(&self, gc: &mut Gc) {
+        self.0.traverse(gc)
+    }
+}
+
 impl<T, V> fmt::Debug for Opaque<T, V>
 where
     T: fmt::Debug,
@@ -292,6 +301,20 @@ where
     {
         V::from_value(self.vm(), self.get_variant())
     }
+
+    /// Deep copies the value into the heap of `target`, which may be a thread of another vm than
+    /// the one that the value was created in.
+    ///
+    /// Values can always be copied between threads of the same vm but only plain data (numbers,
+    /// strings, arrays, records and variants) can be copied to another vm. Closures and threads
+    /// return an error, as does userdata whose type is not registered in the vm of `target` or
+    /// which does not implement `Userdata::deep_clone`.
+    pub fn migrate(&self, target: &Thread) -> Result<OpaqueValue<RootedThread, V>> {
+        let value = target.migrate_value(self.vm(), self.get_variant())?;
+        Ok(OpaqueValue::from_value(
+            target.root_value(unsafe { Variants::new(&value) }),
+        ))
+    }
 }
 
 impl<T, V> Opaque<T, V>
@@ -394,6 +417,13 @@ where
         self.get_array().get(index as usize).map(Opaque::from_value)
     }
 
+    /// Returns the element at `index` without checking that it is in bounds.
+    ///
+    /// Calling this with an `index` outside of `0..self.len()` is undefined behavior.
+    pub(crate) unsafe fn get_unchecked(&'s self, index: VmInt) -> OpaqueRef<'value, V> {
+        Opaque::from_value(self.get_value_array().get_unchecked(index as usize))
+    }
+
     pub fn iter(&'s self) -> Iter<'s, 'value, T, V> {
         Iter {
             index: 0,
@@ -0,0 +1,246 @@
+//! Mutable cells which can be shared between gluon threads running on different OS threads.
+use crate::real_std::{
+    any::Any,
+    fmt,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::api::generic::A;
+use crate::api::{Generic, OwnedFunction, Unrooted, Userdata, VmType, WithVM, IO};
+use crate::base::types::{ArcType, Type};
+use crate::gc::{Gc, GcPtr, Move, Traverseable};
+use crate::thread::ThreadInternal;
+use crate::types::VmInt;
+use crate::value::{Cloner, Value};
+use crate::vm::Thread;
+use crate::{ExternModule, Result, Variants};
+
+/// An integer which is updated atomically
+pub struct AtomicInt(Arc<AtomicI64>);
+
+impl Userdata for AtomicInt {
+    fn deep_clone(&self, deep_cloner: &mut Cloner) -> Result<GcPtr<Box<Userdata>>> {
+        // The integer is not part of any gc heap so the clone can keep referring to the same one
+        let data: Box<Userdata> = Box::new(AtomicInt(self.0.clone()));
+        deep_cloner.gc().alloc(Move(data))
+    }
+}
+
+impl fmt::Debug for AtomicInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AtomicInt({:?})", self.0)
+    }
+}
+
+impl Traverseable for AtomicInt {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+impl VmType for AtomicInt {
+    type Type = AtomicInt;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let env = vm.global_env().get_env();
+        let symbol = env.find_type_info("AtomicInt").unwrap().name.clone();
+        Type::ident(symbol)
+    }
+}
+
+struct Cell {
+    value: Value,
+    /// Incremented on every store so that `modify` can detect that it raced with another update
+    version: u64,
+}
+
+/// A reference to a gluon value which can be loaded and replaced from any thread
+pub struct AtomicRef<T> {
+    cell: Mutex<Cell>,
+    // Values are cloned into the heap of the thread that created the reference before they are
+    // stored, any thread which can reach the reference can also reach values in that heap
+    thread: GcPtr<Thread>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Userdata for AtomicRef<T>
+where
+    T: Any + Send + Sync,
+{
+    fn deep_clone(&self, deep_cloner: &mut Cloner) -> Result<GcPtr<Box<Userdata>>> {
+        let cell = self.cell.lock().unwrap();
+        let cloned_value = deep_cloner.deep_clone(&cell.value)?;
+        let data: Box<Userdata> = Box::new(AtomicRef {
+            cell: Mutex::new(Cell {
+                value: cloned_value,
+                version: 0,
+            }),
+            thread: unsafe { GcPtr::from_raw(deep_cloner.thread()) },
+            _marker: PhantomData::<A>,
+        });
+        deep_cloner.gc().alloc(Move(data))
+    }
+}
+
+impl<T> fmt::Debug for AtomicRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AtomicRef({:?})", self.cell.lock().unwrap().value)
+    }
+}
+
+impl<T> Traverseable for AtomicRef<T> {
+    fn traverse(&self, gc: &mut Gc) {
+        // Every value stored in the reference lives in the heap of `self.thread`, which is also the
+        // heap containing the reference itself, so marking it here keeps it alive no matter which
+        // thread performed the store
+        self.cell.lock().unwrap().value.traverse(gc)
+    }
+}
+
+impl<T> VmType for AtomicRef<T>
+where
+    T: VmType,
+    T::Type: Sized,
+{
+    type Type = AtomicRef<T::Type>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let env = vm.global_env().get_env();
+        let symbol = env.find_type_info("AtomicRef").unwrap().name.clone();
+        let ctor = Type::ident(symbol);
+        Type::app(ctor, collect![T::make_type(vm)])
+    }
+}
+
+impl<T> AtomicRef<T> {
+    /// Clones `value` into the heap of the reference. This must be done before `cell` is locked
+    /// as the clone may need to wait for a garbage collection of that heap, which in turn locks
+    /// `cell` to traverse the stored value.
+    fn clone_value(&self, value: Variants) -> Result<Value> {
+        self.thread.deep_clone_value(&self.thread, value)
+    }
+
+    fn load(&self) -> (Value, u64) {
+        let cell = self.cell.lock().unwrap();
+        (cell.value.clone(), cell.version)
+    }
+}
+
+fn int_new(value: VmInt) -> IO<AtomicInt> {
+    IO::Value(AtomicInt(Arc::new(AtomicI64::new(value))))
+}
+
+fn int_load(atomic: &AtomicInt) -> IO<VmInt> {
+    IO::Value(atomic.0.load(Ordering::SeqCst))
+}
+
+fn int_store(value: VmInt, atomic: &AtomicInt) -> IO<()> {
+    atomic.0.store(value, Ordering::SeqCst);
+    IO::Value(())
+}
+
+fn int_fetch_add(value: VmInt, atomic: &AtomicInt) -> IO<VmInt> {
+    IO::Value(atomic.0.fetch_add(value, Ordering::SeqCst))
+}
+
+fn int_fetch_sub(value: VmInt, atomic: &AtomicInt) -> IO<VmInt> {
+    IO::Value(atomic.0.fetch_sub(value, Ordering::SeqCst))
+}
+
+fn int_compare_and_swap(current: VmInt, new: VmInt, atomic: &AtomicInt) -> IO<VmInt> {
+    IO::Value(
+        match atomic
+            .0
+            .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(previous) | Err(previous) => previous,
+        },
+    )
+}
+
+fn ref_new(a: WithVM<Generic<A>>) -> IO<AtomicRef<A>> {
+    unsafe {
+        IO::Value(AtomicRef {
+            cell: Mutex::new(Cell {
+                value: a.value.get_variant().get_value(),
+                version: 0,
+            }),
+            thread: GcPtr::from_raw(a.vm),
+            _marker: PhantomData,
+        })
+    }
+}
+
+fn ref_load(r: &AtomicRef<A>) -> IO<Unrooted<A>> {
+    IO::Value(Unrooted::from(r.load().0))
+}
+
+fn ref_store(a: Generic<A>, r: &AtomicRef<A>) -> IO<()> {
+    match r.clone_value(a.get_variant()) {
+        Ok(value) => {
+            let mut cell = r.cell.lock().unwrap();
+            cell.value = value;
+            cell.version = cell.version.wrapping_add(1);
+            IO::Value(())
+        }
+        Err(err) => IO::Exception(err.to_string()),
+    }
+}
+
+/// Replaces the value of `r` with the result of applying `f` to it and returns the new value. The
+/// reference is not locked while `f` runs, if another thread stores a value in the meantime `f` is
+/// called again with that value instead.
+fn ref_modify(
+    mut f: OwnedFunction<fn(Unrooted<A>) -> Generic<A>>,
+    r: &AtomicRef<A>,
+) -> IO<Generic<A>> {
+    loop {
+        let (previous, version) = r.load();
+        let result = match f.call(Unrooted::from(previous)) {
+            Ok(result) => result,
+            Err(err) => return IO::Exception(err.to_string()),
+        };
+        let value = match r.clone_value(result.get_variant()) {
+            Ok(value) => value,
+            Err(err) => return IO::Exception(err.to_string()),
+        };
+        let mut cell = r.cell.lock().unwrap();
+        if cell.version == version {
+            cell.value = value;
+            cell.version = cell.version.wrapping_add(1);
+            return IO::Value(result);
+        }
+    }
+}
+
+mod std {
+    pub mod atomic {
+        pub use crate::atomic as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
+    let _ = vm.register_type::<AtomicInt>("AtomicInt", &[]);
+    let _ = vm.register_type::<AtomicRef<A>>("AtomicRef", &["a"]);
+    ExternModule::new(
+        vm,
+        record! {
+            type AtomicInt => AtomicInt,
+            type AtomicRef a => AtomicRef<A>,
+            int_new => primitive!(1, std::atomic::prim::int_new),
+            int_load => primitive!(1, std::atomic::prim::int_load),
+            int_store => primitive!(2, std::atomic::prim::int_store),
+            int_fetch_add => primitive!(2, std::atomic::prim::int_fetch_add),
+            int_fetch_sub => primitive!(2, std::atomic::prim::int_fetch_sub),
+            int_compare_and_swap => primitive!(3, std::atomic::prim::int_compare_and_swap),
+            ref_new => primitive!(1, std::atomic::prim::ref_new),
+            ref_load => primitive!(1, std::atomic::prim::ref_load),
+            ref_store => primitive!(2, std::atomic::prim::ref_store),
+            ref_modify => primitive!(2, std::atomic::prim::ref_modify)
+        },
+    )
+}
@@ -4,12 +4,14 @@ use crate::real_std::{
     fmt,
     marker::PhantomData,
     sync::{Arc, Mutex},
+    thread,
     time::Duration,
 };
 
 use futures::{
     future::{self, Either},
-    Future,
+    task::{self, Task},
+    Async, Future, Poll,
 };
 
 use crate::base::types::{ArcType, Type};
@@ -17,7 +19,8 @@ use crate::base::types::{ArcType, Type};
 use crate::api::generic::{A, B};
 use crate::api::{
     primitive, AsyncPushable, Function, FunctionRef, FutureResult, Generic, Getable, OpaqueRef,
-    OpaqueValue, OwnedFunction, Pushable, Pushed, RuntimeResult, Unrooted, VmType, WithVM, IO,
+    OpaqueValue, OwnedFunction, Propagate, Pushable, Pushed, RuntimeResult, Unrooted, VmType,
+    WithVM, IO,
 };
 use crate::gc::{Gc, GcPtr, Traverseable};
 use crate::stack::{ClosureState, ExternState, StackFrame, State};
@@ -27,11 +30,42 @@ use crate::value::{Callable, GcStr, Userdata, Value, ValueRepr};
 use crate::vm::{RootedThread, Status, Thread};
 use crate::{Error, ExternModule, Result as VmResult};
 
+/// The state shared between the `Sender` and `Receiver` of a channel
+#[derive(Default)]
+struct Queue {
+    values: VecDeque<Value>,
+    /// Tasks which are suspended in `recv_timeout` or `select` until a value is sent
+    waiting: Vec<Task>,
+    disconnected: bool,
+}
+
+impl fmt::Debug for Queue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.values)
+    }
+}
+
+impl Queue {
+    fn notify_waiting(&mut self) {
+        for task in self.waiting.drain(..) {
+            task.notify();
+        }
+    }
+
+    /// Registers the current task to be notified when the state of the queue changes, unless it
+    /// is already registered
+    fn wait(&mut self) {
+        if !self.waiting.iter().any(|task| task.will_notify_current()) {
+            self.waiting.push(task::current());
+        }
+    }
+}
+
 pub struct Sender<T> {
     // No need to traverse this thread reference as any thread having a reference to this `Sender`
     // would also directly own a reference to the `Thread`
     thread: GcPtr<Thread>,
-    queue: Arc<Mutex<VecDeque<Value>>>,
+    queue: Arc<Mutex<Queue>>,
     _element_type: PhantomData<T>,
 }
 
@@ -53,19 +87,37 @@ impl<T> Traverseable for Sender<T> {
 }
 
 impl<T> Sender<T> {
-    fn send(&self, value: Value) {
-        self.queue.lock().unwrap().push_back(value);
+    fn send(&self, value: Value) -> Result<(), ()> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.disconnected {
+            return Err(());
+        }
+        queue.values.push_back(value);
+        queue.notify_waiting();
+        Ok(())
+    }
+
+    fn close(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.disconnected = true;
+        queue.notify_waiting();
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.close();
     }
 }
 
 impl<T> Traverseable for Receiver<T> {
     fn traverse(&self, gc: &mut Gc) {
-        self.queue.lock().unwrap().traverse(gc);
+        self.queue.lock().unwrap().values.traverse(gc);
     }
 }
 
 pub struct Receiver<T> {
-    queue: Arc<Mutex<VecDeque<Value>>>,
+    queue: Arc<Mutex<Queue>>,
     _element_type: PhantomData<T>,
 }
 
@@ -82,7 +134,7 @@ where
 
 impl<T> Receiver<T> {
     fn try_recv(&self) -> Result<Value, ()> {
-        self.queue.lock().unwrap().pop_front().ok_or(())
+        self.queue.lock().unwrap().values.pop_front().ok_or(())
     }
 }
 
@@ -129,7 +181,7 @@ pub type ChannelRecord<S, R> = record_type!(sender => S, receiver => R);
 fn channel(WithVM { vm, .. }: WithVM<Generic<A>>) -> ChannelRecord<Sender<A>, Receiver<A>> {
     let sender = Sender {
         thread: unsafe { GcPtr::from_raw(vm) },
-        queue: Arc::new(Mutex::new(VecDeque::new())),
+        queue: Arc::new(Mutex::new(Queue::default())),
         _element_type: PhantomData,
     };
     let receiver = Receiver {
@@ -148,7 +200,160 @@ fn send(sender: &Sender<A>, value: Generic<A>) -> Result<(), ()> {
         .thread
         .deep_clone_value(&sender.thread, value.get_variant())
         .map_err(|_| ())?;
-    Ok(sender.send(value))
+    sender.send(value)
+}
+
+/// Closes the channel. Values which have already been sent can still be received but any later
+/// `send` fails and `select` reports the channel as `Disconnected` once it is empty. A channel is
+/// also closed when its `Sender` is garbage collected.
+fn close(sender: &Sender<A>) -> IO<()> {
+    sender.close();
+    IO::Value(())
+}
+
+#[derive(Default)]
+struct TimerState {
+    fired: bool,
+    task: Option<Task>,
+}
+
+/// A timer which does not rely on an event loop. The OS thread it spawns sleeps for the duration
+/// and then wakes the task waiting on it.
+struct Timer(Arc<Mutex<TimerState>>);
+
+impl Timer {
+    fn new(duration: Duration) -> Timer {
+        let state = Arc::new(Mutex::new(TimerState::default()));
+        let thread_state = state.clone();
+        thread::spawn(move || {
+            thread::sleep(duration);
+            let mut state = thread_state.lock().unwrap();
+            state.fired = true;
+            if let Some(task) = state.task.take() {
+                task.notify();
+            }
+        });
+        Timer(state)
+    }
+
+    fn poll(&self) -> Async<()> {
+        let mut state = self.0.lock().unwrap();
+        if state.fired {
+            Async::Ready(())
+        } else {
+            state.task = Some(task::current());
+            Async::NotReady
+        }
+    }
+}
+
+enum Received {
+    Value(usize, Value),
+    Disconnected(usize),
+    Timeout,
+}
+
+/// Resolves once one of `queues` has a value or, if none do, once one of them is disconnected.
+/// Queues earlier in the list are preferred when several are ready.
+struct Receive {
+    queues: Vec<Arc<Mutex<Queue>>>,
+    timer: Option<Timer>,
+}
+
+impl Future for Receive {
+    type Item = Received;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Received, Error> {
+        let mut disconnected = None;
+        for (index, queue) in self.queues.iter().enumerate() {
+            // Checking and registering under the same lock ensures no `send` is missed
+            let mut queue = queue.lock().unwrap();
+            if let Some(value) = queue.values.pop_front() {
+                return Ok(Async::Ready(Received::Value(index, value)));
+            }
+            if queue.disconnected {
+                disconnected = disconnected.or(Some(index));
+            } else {
+                queue.wait();
+            }
+        }
+        if let Some(index) = disconnected {
+            return Ok(Async::Ready(Received::Disconnected(index)));
+        }
+        match self.timer {
+            Some(ref timer) => Ok(timer.poll().map(|()| Received::Timeout)),
+            None => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// Waits at most `secs` seconds for a value to be sent on `receiver`. Returns `None` if the
+/// wait timed out or if the channel is closed and empty.
+fn recv_timeout(
+    secs: f64,
+    receiver: &Receiver<A>,
+) -> impl Future<Item = IO<Option<Unrooted<A>>>, Error = Error> {
+    if !(secs >= 0.0 && secs.is_finite()) {
+        return Either::B(future::ok(IO::Exception(format!(
+            "recv_timeout: Expected a finite, non-negative number of seconds, got {}",
+            secs
+        ))));
+    }
+    let receive = Receive {
+        queues: vec![receiver.queue.clone()],
+        timer: Some(Timer::new(Duration::from_secs_f64(secs))),
+    };
+    Either::A(receive.map(|received| {
+        IO::Value(match received {
+            Received::Value(_, value) => Some(Unrooted::from(value)),
+            Received::Disconnected(_) | Received::Timeout => None,
+        })
+    }))
+}
+
+#[derive(Pushable, VmType)]
+#[gluon(gluon_vm)]
+struct Selected<T> {
+    index: VmInt,
+    value: T,
+}
+
+#[derive(Pushable, VmType)]
+#[gluon(gluon_vm)]
+#[gluon(vm_type = "std.channel.types.Select")]
+enum Select<T> {
+    Ready(Selected<T>),
+    Disconnected(VmInt),
+}
+
+/// Waits until one of `receivers` has a value, preferring the earliest receiver if several do.
+/// If all of them are empty and one is closed that receiver is returned as `Disconnected`.
+fn select(
+    receivers: Vec<&Receiver<A>>,
+) -> impl Future<Item = IO<Select<Unrooted<A>>>, Error = Error> {
+    if receivers.is_empty() {
+        return Either::B(future::ok(IO::Exception(
+            "select: Expected at least one receiver".to_string(),
+        )));
+    }
+    let receive = Receive {
+        queues: receivers
+            .iter()
+            .map(|receiver| receiver.queue.clone())
+            .collect(),
+        timer: None,
+    };
+    Either::A(receive.map(|received| {
+        IO::Value(match received {
+            Received::Value(index, value) => Select::Ready(Selected {
+                index: index as VmInt,
+                value: Unrooted::from(value),
+            }),
+            Received::Disconnected(index) => Select::Disconnected(index as VmInt),
+            Received::Timeout => unreachable!("select has no timeout"),
+        })
+    }))
 }
 
 extern "C" fn resume(vm: &Thread) -> Status {
@@ -195,8 +400,8 @@ extern "C" fn yield_(_vm: &Thread) -> Status {
 
 fn spawn<'vm>(
     value: WithVM<'vm, Function<&'vm Thread, fn(())>>,
-) -> RuntimeResult<RootedThread, Error> {
-    spawn_(value).into()
+) -> RuntimeResult<RootedThread, Propagate> {
+    spawn_(value).map_err(Propagate).into()
 }
 fn spawn_<'vm>(value: WithVM<'vm, Function<&'vm Thread, fn(())>>) -> VmResult<RootedThread> {
     let thread = value.vm.new_thread()?;
@@ -361,8 +566,10 @@ fn new_thread(WithVM { vm, .. }: WithVM<()>) -> IO<RootedThread> {
     }
 }
 
-fn sleep(ms: VmInt) -> IO<()> {
-    ::std::thread::sleep(Duration::from_millis(ms as u64));
+fn sleep(WithVM { vm, value: ms }: WithVM<VmInt>) -> IO<()> {
+    // Wakes up early if the thread is interrupted, the interpreter then raises the interrupt
+    // once this function returns
+    vm.interrupt_token().sleep(Duration::from_millis(ms as u64));
     IO::Value(())
 }
 
@@ -390,6 +597,9 @@ pub fn load_channel<'vm>(vm: &'vm Thread) -> VmResult<ExternModule> {
             channel => primitive!(1, std::channel::channel),
             recv => primitive!(1, std::channel::recv),
             send => primitive!(2, std::channel::send),
+            close => primitive!(1, std::channel::close),
+            recv_timeout => primitive!(2, async fn std::channel::recv_timeout),
+            select => primitive!(1, async fn std::channel::select),
         },
     )
 }
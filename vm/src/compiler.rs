@@ -12,9 +12,9 @@ use crate::base::{
 };
 
 use crate::{
-    core::{self, CExpr, Expr, Pattern},
+    core::{self, inline::InlinedSource, CExpr, Expr, Pattern},
     interner::InternedStr,
-    source_map::{LocalMap, SourceMap},
+    source_map::{InlineMap, Inlined, LocalMap, SourceMap},
     types::*,
     vm::GlobalVmState,
     Error, Result,
@@ -73,6 +73,8 @@ pub struct DebugInfo {
     #[cfg_attr(feature = "serde_derive", serde(state))]
     pub upvars: Vec<UpvarInfo>,
     pub source_name: String,
+    /// Maps instruction indexes to the functions they were inlined from
+    pub inline_map: InlineMap,
 }
 
 #[derive(Debug)]
@@ -168,6 +170,7 @@ impl CompiledFunction {
                 local_map: LocalMap::new(),
                 upvars: Vec::new(),
                 source_name: source_name,
+                inline_map: InlineMap::new(),
             },
         }
     }
@@ -183,6 +186,8 @@ struct FunctionEnv {
     free_vars: Vec<(Symbol, ArcType)>,
     /// The line where instructions are currently being emitted
     current_line: Line,
+    /// The function that the instructions currently being emitted were inlined from
+    current_inlined: Option<Inlined>,
     emit_debug_info: bool,
     function: CompiledFunction,
 }
@@ -267,6 +272,7 @@ impl FunctionEnv {
             stack_size: 0,
             function: CompiledFunction::new(args, id, typ, source_name),
             current_line: Line::from(0),
+            current_inlined: None,
             emit_debug_info: emit_debug_info,
         }
     }
@@ -291,6 +297,10 @@ impl FunctionEnv {
                 .debug_info
                 .source_map
                 .emit(self.function.instructions.len() - 1, self.current_line);
+            self.function.debug_info.inline_map.emit(
+                self.function.instructions.len() - 1,
+                self.current_inlined.as_ref(),
+            );
         }
     }
 
@@ -431,6 +441,14 @@ impl CompilerEnv for TypeInfos {
     }
 }
 
+/// A function which may only be called in tail position while compiling the body of a function
+/// marked with `#[tail]`
+struct TailCallTarget {
+    caller: Symbol,
+    callee: Symbol,
+    arity: usize,
+}
+
 pub struct Compiler<'a> {
     globals: &'a (CompilerEnv<Type = ArcType> + 'a),
     vm: &'a GlobalVmState,
@@ -440,6 +458,8 @@ pub struct Compiler<'a> {
     source_name: String,
     emit_debug_info: bool,
     empty_symbol: Symbol,
+    tail_call_targets: Vec<TailCallTarget>,
+    inlined: Vec<InlinedSource>,
 }
 
 impl<'a> KindEnv for Compiler<'a> {
@@ -484,9 +504,16 @@ impl<'a> Compiler<'a> {
             source: source,
             source_name: source_name,
             emit_debug_info: emit_debug_info,
+            tail_call_targets: Vec::new(),
+            inlined: Vec::new(),
         }
     }
 
+    /// Sets the origins of the functions which were inlined into the expression being compiled
+    pub fn set_inlined(&mut self, inlined: Vec<InlinedSource>) {
+        self.inlined = inlined;
+    }
+
     fn intern(&mut self, s: &str) -> Result<InternedStr> {
         self.vm.intern(s)
     }
@@ -605,10 +632,24 @@ impl<'a> Compiler<'a> {
     }
 
     fn update_line(&mut self, function: &mut FunctionEnvs, expr: CExpr) {
+        let pos = expr.span().start();
+        // Inlined code keeps the line of the call in this module so that the function is still
+        // found at the call site
+        if let Some(inlined) = self.inlined.iter().find(|inlined| inlined.contains(pos)) {
+            if let Some(line) = inlined.line(pos) {
+                function.current_inlined = Some(Inlined {
+                    name: inlined.name.declared_name().to_string(),
+                    source_name: inlined.source_name.clone(),
+                    line,
+                });
+            }
+            return;
+        }
         // Don't update the current_line for macro expanded code as the lines in that code do not
         // come from this module
-        if let Some(current_line) = self.source.line_number_at_byte(expr.span().start()) {
+        if let Some(current_line) = self.source.line_number_at_byte(pos) {
             function.current_line = current_line;
+            function.current_inlined = None;
         }
     }
 
@@ -622,12 +663,14 @@ impl<'a> Compiler<'a> {
         // done
         function.stack.enter_scope();
 
+        let inlined = function.current_inlined.clone();
         self.update_line(function, expr);
 
         while let Some(next) = self.compile_(expr, function, tail_position)? {
             expr = next;
             self.update_line(function, expr);
         }
+        function.current_inlined = inlined;
         let count = function.exit_scope(self);
         function.emit(Slide(count));
         Ok(())
@@ -723,12 +766,26 @@ impl<'a> Compiler<'a> {
                                     ),
                                 }
                             } else {
+                                let outer_targets = self.tail_call_targets.len();
+                                if closure.tail {
+                                    self.tail_call_targets.extend(
+                                        closures
+                                            .iter()
+                                            .filter(|callee| !callee.args.is_empty())
+                                            .map(|callee| TailCallTarget {
+                                                caller: closure.name.name.clone(),
+                                                callee: callee.name.name.clone(),
+                                                arity: callee.args.len(),
+                                            }),
+                                    );
+                                }
                                 let (function_index, vars, cf) = self.compile_lambda(
                                     &closure.name,
                                     &closure.args,
                                     &closure.expr,
                                     function,
                                 )?;
+                                self.tail_call_targets.truncate(outer_targets);
                                 function.function.instructions[offset] = NewClosure {
                                     function_index: function_index,
                                     upvars: vars,
@@ -755,6 +812,10 @@ impl<'a> Compiler<'a> {
                         return Ok(None);
                     }
 
+                    if !tail_position {
+                        self.check_tail_call(&id.name, args.len(), function.current_line)?;
+                    }
+
                     if let Some(Constructor(tag, num_args)) = self.find(&id.name, function) {
                         for arg in args {
                             self.compile(arg, function, false)?;
@@ -777,13 +838,48 @@ impl<'a> Compiler<'a> {
                 // Indexes for each alternative for a successful match to the alternatives code
                 let mut start_jumps = Vec::new();
                 let typ = expr.env_type_of(self);
-                // Emit a TestTag + Jump instuction for each alternative which jumps to the
-                // alternatives code if TestTag is sucessesful
-                for alt in alts.iter() {
-                    match alt.pattern {
-                        Pattern::Constructor(ref id, _) => {
-                            let tag =
-                                self.find_tag(typ.remove_forall(), &id.name)
+                // Where the jump table jumps to the last alternative for the values which are not
+                // matched by any other alternative
+                let mut default_jumps = Vec::new();
+                let jump_table = dense_int_alternatives(alts);
+                if let Some((min, ref table)) = jump_table {
+                    let lhs_i = function.stack_size() - 1;
+                    let max = min + (table.len() as VmInt - 1);
+                    // Values outside of `min..=max` jump to the catch all alternative before `min`
+                    // is subtracted as the subtraction could overflow for them
+                    function.emit(Push(lhs_i));
+                    function.emit(PushInt(min));
+                    function.emit(IntLT);
+                    default_jumps.push(function.function.instructions.len());
+                    function.emit(CJump(0));
+                    function.emit(PushInt(max));
+                    function.emit(Push(lhs_i));
+                    function.emit(IntLT);
+                    default_jumps.push(function.function.instructions.len());
+                    function.emit(CJump(0));
+                    function.emit(Push(lhs_i));
+                    function.emit(PushInt(min));
+                    function.emit(SubtractInt);
+                    function.emit(Switch(table.len() as VmIndex));
+                    start_jumps.resize(alts.len(), 0);
+                    for &alt in table {
+                        let index = function.function.instructions.len();
+                        match alt {
+                            Some(alt) => start_jumps[alt] = index,
+                            None => default_jumps.push(index),
+                        }
+                        function.emit(Jump(0));
+                    }
+                    start_jumps[alts.len() - 1] = function.function.instructions.len();
+                    function.emit(Jump(0));
+                } else {
+                    // Emit a TestTag + Jump instuction for each alternative which jumps to the
+                    // alternatives code if TestTag is sucessesful
+                    for alt in alts.iter() {
+                        match alt.pattern {
+                            Pattern::Constructor(ref id, _) => {
+                                let tag = self
+                                    .find_tag(typ.remove_forall(), &id.name)
                                     .unwrap_or_else(|| {
                                         ice!(
                                             "ICE: Could not find tag for {}::{} when matching on \
@@ -793,58 +889,62 @@ impl<'a> Compiler<'a> {
                                         )
                                     });
 
-                            match tag {
-                                FieldAccess::Index(tag) => function.emit(TestTag(tag)),
-                                FieldAccess::Name => {
-                                    let interned = self.intern(id.name.as_ref())?;
-                                    let index = function.add_string_constant(interned);
-                                    function.emit(TestPolyTag(index));
+                                match tag {
+                                    FieldAccess::Index(tag) => function.emit(TestTag(tag)),
+                                    FieldAccess::Name => {
+                                        let interned = self.intern(id.name.as_ref())?;
+                                        let index = function.add_string_constant(interned);
+                                        function.emit(TestPolyTag(index));
+                                    }
                                 }
-                            }
 
-                            start_jumps.push(function.function.instructions.len());
-                            function.emit(CJump(0));
-                        }
-                        Pattern::Record { .. } => {
-                            start_jumps.push(function.function.instructions.len());
-                        }
-                        Pattern::Ident(_) => {
-                            start_jumps.push(function.function.instructions.len());
-                            function.emit(Jump(0));
-                        }
-                        Pattern::Literal(ref l) => {
-                            let lhs_i = function.stack_size() - 1;
-                            match *l {
-                                ast::Literal::Byte(b) => {
-                                    function.emit(Push(lhs_i));
-                                    function.emit(PushByte(b));
-                                    function.emit(ByteEQ);
-                                }
-                                ast::Literal::Int(i) => {
-                                    function.emit(Push(lhs_i));
-                                    function.emit(PushInt(i));
-                                    function.emit(IntEQ);
-                                }
-                                ast::Literal::Char(ch) => {
-                                    function.emit(Push(lhs_i));
-                                    function.emit(PushInt(u32::from(ch).into()));
-                                    function.emit(IntEQ);
-                                }
-                                ast::Literal::Float(f) => {
-                                    function.emit(Push(lhs_i));
-                                    function.emit(PushFloat(f.into_inner()));
-                                    function.emit(FloatEQ);
-                                }
-                                ast::Literal::String(ref s) => {
-                                    self.load_identifier(&Symbol::from("@string_eq"), function)?;
-                                    let lhs_i = function.stack_size() - 2;
-                                    function.emit(Push(lhs_i));
-                                    function.emit_string(self.intern(&s)?);
-                                    function.emit(Call(2));
-                                }
-                            };
-                            start_jumps.push(function.function.instructions.len());
-                            function.emit(CJump(0));
+                                start_jumps.push(function.function.instructions.len());
+                                function.emit(CJump(0));
+                            }
+                            Pattern::Record { .. } => {
+                                start_jumps.push(function.function.instructions.len());
+                            }
+                            Pattern::Ident(_) => {
+                                start_jumps.push(function.function.instructions.len());
+                                function.emit(Jump(0));
+                            }
+                            Pattern::Literal(ref l) => {
+                                let lhs_i = function.stack_size() - 1;
+                                match *l {
+                                    ast::Literal::Byte(b) => {
+                                        function.emit(Push(lhs_i));
+                                        function.emit(PushByte(b));
+                                        function.emit(ByteEQ);
+                                    }
+                                    ast::Literal::Int(i) => {
+                                        function.emit(Push(lhs_i));
+                                        function.emit(PushInt(i));
+                                        function.emit(IntEQ);
+                                    }
+                                    ast::Literal::Char(ch) => {
+                                        function.emit(Push(lhs_i));
+                                        function.emit(PushInt(u32::from(ch).into()));
+                                        function.emit(IntEQ);
+                                    }
+                                    ast::Literal::Float(f) => {
+                                        function.emit(Push(lhs_i));
+                                        function.emit(PushFloat(f.into_inner()));
+                                        function.emit(FloatEQ);
+                                    }
+                                    ast::Literal::String(ref s) => {
+                                        self.load_identifier(
+                                            &Symbol::from("@string_eq"),
+                                            function,
+                                        )?;
+                                        let lhs_i = function.stack_size() - 2;
+                                        function.emit(Push(lhs_i));
+                                        function.emit_string(self.intern(&s)?);
+                                        function.emit(Call(2));
+                                    }
+                                };
+                                start_jumps.push(function.function.instructions.len());
+                                function.emit(CJump(0));
+                            }
                         }
                     }
                 }
@@ -867,13 +967,24 @@ impl<'a> Compiler<'a> {
                             self.compile_let_pattern(&alt.pattern, typ, function)?;
                         }
                         Pattern::Ident(ref id) => {
-                            function.function.instructions[start_index] =
-                                Jump(function.function.instructions.len() as VmIndex);
+                            let target = function.function.instructions.len() as VmIndex;
+                            function.function.instructions[start_index] = Jump(target);
+                            for &index in &default_jumps {
+                                let instruction = &mut function.function.instructions[index];
+                                *instruction = match *instruction {
+                                    CJump(_) => CJump(target),
+                                    _ => Jump(target),
+                                };
+                            }
                             function.new_stack_var(self, id.name.clone(), id.typ.clone());
                         }
                         Pattern::Literal(_) => {
-                            function.function.instructions[start_index] =
-                                CJump(function.function.instructions.len() as VmIndex);
+                            let target = function.function.instructions.len() as VmIndex;
+                            function.function.instructions[start_index] = if jump_table.is_some() {
+                                Jump(target)
+                            } else {
+                                CJump(target)
+                            };
                             // Add a dummy variable to mark where the literal itself is stored
                             function.new_stack_var(self, self.empty_symbol.clone(), Type::hole());
                         }
@@ -933,6 +1044,25 @@ impl<'a> Compiler<'a> {
         Ok(None)
     }
 
+    /// Errors if `callee` is called with all of its arguments from the body of a function marked
+    /// with `#[tail]` that it must be tail called from
+    fn check_tail_call(&self, callee: &Symbol, args: usize, line: Line) -> Result<()> {
+        match self
+            .tail_call_targets
+            .iter()
+            .find(|target| target.callee == *callee && args >= target.arity)
+        {
+            Some(target) => Err(Error::Message(format!(
+                "{}:{}: The call to `{}` is not a tail call but `{}` is marked with `#[tail]`",
+                self.source_name,
+                line.number(),
+                callee.declared_name(),
+                target.caller.declared_name(),
+            ))),
+            None => Ok(()),
+        }
+    }
+
     fn compile_primitive(
         &mut self,
         op: &Symbol,
@@ -1121,6 +1251,42 @@ impl<'a> Compiler<'a> {
     }
 }
 
+/// The smallest number of integer alternatives which are compiled to a jump table
+const JUMP_TABLE_MIN_ALTERNATIVES: usize = 4;
+
+/// If `alts` matches on integer literals which are close enough together to be compiled to a jump
+/// table, returns the smallest literal and the index of the alternative that each value from it
+/// and upwards jumps to (`None` for values which jump to the last, catch all, alternative).
+fn dense_int_alternatives(alts: &[core::Alternative]) -> Option<(VmInt, Vec<Option<usize>>)> {
+    let (default, literal_alts) = alts.split_last()?;
+    match default.pattern {
+        Pattern::Ident(_) => (),
+        _ => return None,
+    }
+    if literal_alts.len() < JUMP_TABLE_MIN_ALTERNATIVES {
+        return None;
+    }
+    let mut values = Vec::with_capacity(literal_alts.len());
+    for alt in literal_alts {
+        match alt.pattern {
+            Pattern::Literal(Literal::Int(i)) => values.push(i),
+            _ => return None,
+        }
+    }
+    let min = *values.iter().min()?;
+    let max = *values.iter().max()?;
+    // Only use a table if at least half of its entries jump to a literal alternative
+    let len = max.checked_sub(min)?.checked_add(1)?;
+    if len as u64 > 2 * values.len() as u64 {
+        return None;
+    }
+    let mut table = vec![None; len as usize];
+    for (alt, value) in values.into_iter().enumerate() {
+        table[(value - min) as usize] = Some(alt);
+    }
+    Some((min, table))
+}
+
 #[cfg(all(test, feature = "test"))]
 mod tests {
     use super::*;
@@ -1239,4 +1405,60 @@ mod tests {
             ],
         )
     }
+
+    #[test]
+    fn dense_int_match_is_compiled_to_a_jump_table() {
+        let _ = ::env_logger::try_init();
+
+        assert_instructions(
+            "match 2 with
+             | 1 -> 10
+             | 2 -> 20
+             | 3 -> 30
+             | 5 -> 50
+             | _ -> 0
+             end",
+            &[&[
+                PushInt(2),
+                // Values outside of 1..=5 skip the table
+                Push(0),
+                PushInt(1),
+                IntLT,
+                CJump(31),
+                PushInt(5),
+                Push(0),
+                IntLT,
+                CJump(31),
+                Push(0),
+                PushInt(1),
+                SubtractInt,
+                Switch(5),
+                // 1, 2, 3
+                Jump(19),
+                Jump(22),
+                Jump(25),
+                // 4 is not matched so it jumps to the catch all alternative
+                Jump(31),
+                // 5
+                Jump(28),
+                // Values outside of the table
+                Jump(31),
+                PushInt(10),
+                Slide(1),
+                Jump(34),
+                PushInt(20),
+                Slide(1),
+                Jump(34),
+                PushInt(30),
+                Slide(1),
+                Jump(34),
+                PushInt(50),
+                Slide(1),
+                Jump(34),
+                PushInt(0),
+                Slide(1),
+                Jump(34),
+            ]],
+        )
+    }
 }
@@ -0,0 +1,710 @@
+//! Inlining of the small functions that the modules of the vm export.
+//!
+//! When a module is compiled the functions it exports which are small, do not call themselves and
+//! only refer to their arguments, globals and primitives are stored as `InlineFunctions`. Calls
+//! to them in the modules which import the module are then replaced by their body.
+//!
+//! The inlined expressions keep the spans of the function they were copied from, moved past the
+//! end of the module they are inlined into so that they can't be mistaken for its own code. The
+//! returned `InlinedSource`s map these spans back to the source of the function.
+use std::{mem, sync::Arc};
+
+use codespan::FileMap;
+
+use crate::base::{
+    ast::TypedIdent,
+    fnv::{FnvMap, FnvSet},
+    pos::{BytePos, Line, Span},
+    scoped_map::ScopedMap,
+    source::Source,
+    symbol::Symbol,
+    types::TypeExt,
+};
+
+use crate::core::{
+    optimize::{is_variable_in_expression, walk_expr_alloc, SameLifetime, Visitor},
+    Allocator, Alternative, CExpr, Closure, Expr, LetBinding, Named, Pattern,
+};
+
+/// The number of expressions which may be inlined into a single module
+const MODULE_BUDGET: usize = 2048;
+
+/// A field of a global module which is bound by the module of an inlined function
+#[derive(Clone)]
+struct Import {
+    module: TypedIdent<Symbol>,
+    field: TypedIdent<Symbol>,
+    name: Symbol,
+}
+
+struct InlineFunction<'a> {
+    name: Symbol,
+    args: Vec<TypedIdent<Symbol>>,
+    /// The fields of other modules that `body` uses, these are bound again where the function is
+    /// inlined
+    imports: Vec<Import>,
+    body: CExpr<'a>,
+    /// The span which contains every expression of `body`
+    span: Span<BytePos>,
+    /// The number of expressions in `body`
+    size: usize,
+}
+
+/// The functions exported by a module which may be inlined, keyed by the name of the field they
+/// are exported as
+pub struct InlineFunctions {
+    // `functions` refer to expressions in `allocator` and must therefore be dropped before it
+    functions: FnvMap<String, InlineFunction<'static>>,
+    allocator: Allocator<'static>,
+    source_name: String,
+    source: Arc<FileMap>,
+}
+
+// The arena of `allocator` is only allocated into while `InlineFunctions::new` runs, after which
+// the functions are only ever read.
+unsafe impl Sync for InlineFunctions {}
+
+impl InlineFunctions {
+    /// Collects the functions with at most `threshold` expressions from the record returned by
+    /// the module `expr`. `source` must be the file that the spans of `expr` point into.
+    pub fn new(
+        source_name: &str,
+        source: Arc<FileMap>,
+        expr: CExpr,
+        threshold: usize,
+    ) -> Option<InlineFunctions> {
+        let allocator = Allocator::new();
+        let functions = {
+            // Forget the lifetime of `allocator` until it is stored next to the functions
+            let allocator: &'static Allocator<'static> =
+                unsafe { mem::transmute::<&Allocator, &'static Allocator<'static>>(&allocator) };
+            collect_functions(allocator, &source, expr, threshold)
+        };
+        if functions.is_empty() {
+            return None;
+        }
+        Some(InlineFunctions {
+            functions,
+            allocator: unsafe { mem::transmute::<Allocator, Allocator<'static>>(allocator) },
+            source_name: source_name.to_string(),
+            source,
+        })
+    }
+
+    fn get<'s>(&'s self, name: &str) -> Option<&'s InlineFunction<'s>> {
+        self.functions.get(name)
+    }
+
+    /// Returns the names of the fields which hold functions that can be inlined
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(|name| &name[..])
+    }
+}
+
+/// The counts and spans of the expressions of a function
+struct Measure {
+    size: usize,
+    start: u32,
+    end: u32,
+}
+
+impl Measure {
+    fn add_pos(&mut self, pos: BytePos) {
+        // Position 0 is used by expressions which are created by the compiler
+        if pos.0 != 0 {
+            self.start = self.start.min(pos.0);
+            self.end = self.end.max(pos.0);
+        }
+    }
+
+    fn add_span(&mut self, span: Span<BytePos>) {
+        self.add_pos(span.start());
+        self.add_pos(span.end());
+    }
+}
+
+/// Measures `body`, collecting the variables it refers to which are neither bound in it (or by
+/// `args`), globals nor primitives into `free`.
+fn measure<'a>(
+    args: &'a [TypedIdent<Symbol>],
+    body: CExpr<'a>,
+    free: &mut FnvSet<&'a Symbol>,
+) -> Measure {
+    fn measure_<'a>(
+        bound: &mut FnvSet<&'a Symbol>,
+        free: &mut FnvSet<&'a Symbol>,
+        measure: &mut Measure,
+        expr: CExpr<'a>,
+    ) {
+        measure.size += 1;
+        measure.add_span(expr.span());
+        match *expr {
+            Expr::Const(..) => (),
+            Expr::Ident(ref id, _) => {
+                let name = &id.name;
+                if !name.is_global() && !name.as_ref().starts_with('#') && !bound.contains(name) {
+                    free.insert(name);
+                }
+            }
+            Expr::Call(f, args) => {
+                measure_(bound, free, measure, f);
+                for arg in args {
+                    measure_(bound, free, measure, arg);
+                }
+            }
+            Expr::Data(_, args, pos) => {
+                measure.add_pos(pos);
+                for arg in args {
+                    measure_(bound, free, measure, arg);
+                }
+            }
+            Expr::Let(ref bind, body) => {
+                measure.add_pos(bind.span_start);
+                match bind.expr {
+                    Named::Expr(bind_expr) => {
+                        measure_(bound, free, measure, bind_expr);
+                        bound.insert(&bind.name.name);
+                    }
+                    Named::Recursive(ref closures) => {
+                        for closure in closures {
+                            bound.insert(&closure.name.name);
+                        }
+                        for closure in closures {
+                            measure.add_pos(closure.pos);
+                            bound.extend(closure.args.iter().map(|arg| &arg.name));
+                            measure_(bound, free, measure, closure.expr);
+                        }
+                    }
+                }
+                measure_(bound, free, measure, body);
+            }
+            Expr::Match(scrutinee, alts) => {
+                measure_(bound, free, measure, scrutinee);
+                for alt in alts {
+                    match alt.pattern {
+                        Pattern::Constructor(_, ref args) => {
+                            bound.extend(args.iter().map(|arg| &arg.name))
+                        }
+                        Pattern::Record(ref fields) => bound.extend(
+                            fields
+                                .iter()
+                                .map(|field| field.1.as_ref().unwrap_or(&field.0.name)),
+                        ),
+                        Pattern::Ident(ref id) => {
+                            bound.insert(&id.name);
+                        }
+                        Pattern::Literal(_) => (),
+                    }
+                    measure_(bound, free, measure, alt.expr);
+                }
+            }
+        }
+    }
+
+    // Since bindings are only added to `bound` a variable which is used before it is bound is not
+    // seen as free. That is fine as each binding in a module has a symbol of its own.
+    let mut bound = args.iter().map(|arg| &arg.name).collect();
+    let mut result = Measure {
+        size: 0,
+        start: u32::max_value(),
+        end: 0,
+    };
+    measure_(&mut bound, free, &mut result, body);
+    result
+}
+
+fn collect_functions<'a>(
+    allocator: &'a Allocator<'a>,
+    source: &FileMap,
+    mut expr: CExpr,
+    threshold: usize,
+) -> FnvMap<String, InlineFunction<'a>> {
+    let file_span = Source::span(source);
+    let mut imports = FnvMap::default();
+    let mut candidates = FnvMap::default();
+    let mut functions = FnvMap::default();
+    loop {
+        match *expr {
+            Expr::Let(ref bind, body) => {
+                if let Named::Recursive(ref closures) = bind.expr {
+                    for closure in closures {
+                        let mut free = FnvSet::default();
+                        let measure = measure(&closure.args, closure.expr, &mut free);
+                        // Only the variables of other modules can be bound again in the module the
+                        // function is inlined into
+                        let closure_imports = match free
+                            .iter()
+                            .map(|name| imports.get(name).cloned())
+                            .collect::<Option<Vec<Import>>>()
+                        {
+                            Some(closure_imports) => closure_imports,
+                            None => continue,
+                        };
+                        // Functions which contain code from another file (from a macro for
+                        // instance) can't be mapped back to their source
+                        let in_file = measure.start > measure.end
+                            || (file_span.start().0 <= measure.start
+                                && measure.end <= file_span.end().0);
+                        if measure.size <= threshold && in_file {
+                            candidates
+                                .insert(&closure.name.name, (closure, closure_imports, measure));
+                        }
+                    }
+                }
+                expr = body;
+            }
+            // The modules imported by destructuring them
+            Expr::Match(scrutinee, alts) if alts.len() == 1 => {
+                match (scrutinee, &alts[0].pattern) {
+                    (Expr::Ident(ref module, _), Pattern::Record(ref fields))
+                        if module.name.is_global() =>
+                    {
+                        for (field, name) in fields {
+                            let name = name.as_ref().unwrap_or(&field.name);
+                            imports.insert(
+                                name,
+                                Import {
+                                    module: module.clone(),
+                                    field: field.clone(),
+                                    name: name.clone(),
+                                },
+                            );
+                        }
+                    }
+                    _ => (),
+                }
+                expr = alts[0].expr
+            }
+            Expr::Data(ref id, args, _) => {
+                for (field, arg) in id.typ.remove_forall().row_iter().zip(args) {
+                    let (closure, closure_imports, measure) = match *arg {
+                        Expr::Ident(ref arg_id, _) => match candidates.get(&arg_id.name) {
+                            Some(candidate) => candidate,
+                            None => continue,
+                        },
+                        _ => continue,
+                    };
+                    let span = if measure.start > measure.end {
+                        Span::new(closure.pos, closure.pos)
+                    } else {
+                        Span::new(BytePos(measure.start), BytePos(measure.end))
+                    };
+                    functions.insert(
+                        field.name.declared_name().to_string(),
+                        InlineFunction {
+                            name: closure.name.name.clone(),
+                            args: closure.args.clone(),
+                            imports: closure_imports.clone(),
+                            body: relocate(allocator, 0, closure.expr),
+                            span,
+                            size: measure.size,
+                        },
+                    );
+                }
+                break;
+            }
+            _ => break,
+        }
+    }
+    functions
+}
+
+/// Copies `expr` into `allocator`, moving each of its positions by `offset`
+fn relocate<'a>(allocator: &'a Allocator<'a>, offset: i64, expr: CExpr) -> CExpr<'a> {
+    allocator.arena.alloc(relocate_(allocator, offset, expr))
+}
+
+fn relocate_<'a>(allocator: &'a Allocator<'a>, offset: i64, expr: CExpr) -> Expr<'a> {
+    let pos = |pos: BytePos| {
+        if pos.0 == 0 {
+            pos
+        } else {
+            BytePos((i64::from(pos.0) + offset) as u32)
+        }
+    };
+    let span = |span: Span<BytePos>| Span::new(pos(span.start()), pos(span.end()));
+    let exprs = |exprs: &[Expr]| -> &'a [Expr<'a>] {
+        let exprs = exprs
+            .iter()
+            .map(|expr| relocate_(allocator, offset, expr))
+            .collect::<Vec<_>>();
+        allocator.arena.alloc_extend(exprs)
+    };
+    match *expr {
+        Expr::Const(ref literal, s) => Expr::Const(literal.clone(), span(s)),
+        Expr::Ident(ref id, s) => Expr::Ident(id.clone(), span(s)),
+        Expr::Call(f, args) => Expr::Call(relocate(allocator, offset, f), exprs(args)),
+        Expr::Data(ref id, args, p) => Expr::Data(id.clone(), exprs(args), pos(p)),
+        Expr::Let(ref bind, body) => {
+            let named = match bind.expr {
+                Named::Expr(bind_expr) => Named::Expr(relocate(allocator, offset, bind_expr)),
+                Named::Recursive(ref closures) => Named::Recursive(
+                    closures
+                        .iter()
+                        .map(|closure| Closure {
+                            pos: pos(closure.pos),
+                            name: closure.name.clone(),
+                            args: closure.args.clone(),
+                            expr: relocate(allocator, offset, closure.expr),
+                            tail: closure.tail,
+                        })
+                        .collect(),
+                ),
+            };
+            Expr::Let(
+                LetBinding {
+                    name: bind.name.clone(),
+                    expr: named,
+                    span_start: pos(bind.span_start),
+                },
+                relocate(allocator, offset, body),
+            )
+        }
+        Expr::Match(scrutinee, alts) => {
+            let alts = alts
+                .iter()
+                .map(|alt| Alternative {
+                    pattern: alt.pattern.clone(),
+                    expr: relocate(allocator, offset, alt.expr),
+                })
+                .collect::<Vec<_>>();
+            Expr::Match(
+                relocate(allocator, offset, scrutinee),
+                allocator.alternative_arena.alloc_extend(alts),
+            )
+        }
+    }
+}
+
+/// The origin of a function which was inlined
+#[derive(Clone)]
+pub struct InlinedSource {
+    /// The span which the expressions of the function were moved to
+    pub span: Span<BytePos>,
+    /// The amount the positions of the function were moved by
+    offset: i64,
+    pub name: Symbol,
+    pub source_name: String,
+    source: Arc<FileMap>,
+}
+
+impl InlinedSource {
+    /// Returns true if `pos` is in one of the expressions of the function
+    pub fn contains(&self, pos: BytePos) -> bool {
+        self.span.start() <= pos && pos <= self.span.end()
+    }
+
+    /// Returns the line in the source of the function that the moved position `pos` refers to
+    pub fn line(&self, pos: BytePos) -> Option<Line> {
+        let original = BytePos((i64::from(pos.0) - self.offset) as u32);
+        self.source.line_number_at_byte(original)
+    }
+}
+
+#[derive(Clone)]
+enum Known {
+    Module(Arc<InlineFunctions>),
+    Function(Arc<InlineFunctions>, String),
+    Unknown,
+}
+
+struct Inliner<'a, 'f> {
+    allocator: &'a Allocator<'a>,
+    modules: &'f Fn(&Symbol) -> Option<Arc<InlineFunctions>>,
+    known: ScopedMap<Symbol, Known>,
+    threshold: usize,
+    budget: usize,
+    sources: Vec<InlinedSource>,
+    /// The index in `sources` of each function which has been inlined
+    source_indexes: FnvMap<(String, String), usize>,
+    next_pos: u32,
+}
+
+impl<'a, 'f> Inliner<'a, 'f> {
+    fn find(&self, expr: CExpr) -> Known {
+        match *expr {
+            Expr::Ident(ref id, _) => match self.known.get(&id.name) {
+                Some(known) => known.clone(),
+                None if id.name.is_global() => (self.modules)(&id.name)
+                    .map(Known::Module)
+                    .unwrap_or(Known::Unknown),
+                None => Known::Unknown,
+            },
+            _ => Known::Unknown,
+        }
+    }
+
+    fn shadow(&mut self, id: &Symbol) {
+        self.known.insert(id.clone(), Known::Unknown);
+    }
+
+    fn relocation_offset(
+        &mut self,
+        functions: &InlineFunctions,
+        field: &str,
+        function: &InlineFunction,
+    ) -> i64 {
+        let key = (functions.source_name.clone(), field.to_string());
+        if let Some(&index) = self.source_indexes.get(&key) {
+            return self.sources[index].offset;
+        }
+        let start = self.next_pos;
+        let len = function.span.end().0 - function.span.start().0;
+        self.next_pos += len + 1;
+        let offset = i64::from(start) - i64::from(function.span.start().0);
+        self.source_indexes.insert(key, self.sources.len());
+        self.sources.push(InlinedSource {
+            span: Span::new(BytePos(start), BytePos(start + len)),
+            offset,
+            name: function.name.clone(),
+            source_name: functions.source_name.clone(),
+            source: functions.source.clone(),
+        });
+        offset
+    }
+
+    fn inline_call(
+        &mut self,
+        functions: &InlineFunctions,
+        field: &str,
+        args: &'a [Expr<'a>],
+    ) -> Option<CExpr<'a>> {
+        let function = functions.get(field)?;
+        if function.args.len() > args.len()
+            || function.size > self.threshold
+            || function.size > self.budget
+        {
+            return None;
+        }
+        // The arguments are bound to the parameters one at a time so an argument which refers to
+        // a variable with the same name as a parameter would see the parameter instead
+        let params = || function.args.iter().map(|arg| &arg.name);
+        if args
+            .iter()
+            .any(|arg| is_variable_in_expression(params(), arg))
+        {
+            return None;
+        }
+        debug!("Inlining `{}` from `{}`", field, functions.source_name);
+        self.budget -= function.size;
+
+        let offset = self.relocation_offset(functions, field, function);
+        let body = relocate(self.allocator, offset, function.body);
+        let body_start = Span::new(body.span().start(), body.span().start());
+        let body = function.imports.iter().fold(body, |body, import| {
+            let module = self
+                .allocator
+                .arena
+                .alloc(Expr::Ident(import.module.clone(), body_start));
+            let alt = Alternative {
+                pattern: Pattern::Record(vec![(import.field.clone(), Some(import.name.clone()))]),
+                expr: body,
+            };
+            &*self.allocator.arena.alloc(Expr::Match(
+                module,
+                self.allocator.alternative_arena.alloc_extend(Some(alt)),
+            ))
+        });
+        let (args, rest) = args.split_at(function.args.len());
+        let expr = function
+            .args
+            .iter()
+            .zip(args)
+            .rev()
+            .fold(body, |body, (param, arg)| {
+                &*self.allocator.arena.alloc(Expr::Let(
+                    LetBinding {
+                        name: param.clone(),
+                        expr: Named::Expr(arg),
+                        span_start: arg.span().start(),
+                    },
+                    body,
+                ))
+            });
+        Some(if rest.is_empty() {
+            expr
+        } else {
+            self.allocator.arena.alloc(Expr::Call(expr, rest))
+        })
+    }
+
+    fn visit_let(&mut self, bind: &LetBinding<'a>, body: CExpr<'a>) -> Option<CExpr<'a>> {
+        let new_named = match bind.expr {
+            Named::Expr(bind_expr) => self.visit_expr(bind_expr).map(Named::Expr),
+            Named::Recursive(ref closures) => {
+                self.known.enter_scope();
+                for closure in closures {
+                    self.shadow(&closure.name.name);
+                }
+                let new_closures = closures
+                    .iter()
+                    .map(|closure| {
+                        self.known.enter_scope();
+                        for arg in &closure.args {
+                            self.shadow(&arg.name);
+                        }
+                        let new_expr = self.visit_expr(closure.expr);
+                        self.known.exit_scope();
+                        new_expr
+                    })
+                    .collect::<Vec<_>>();
+                self.known.exit_scope();
+                if new_closures.iter().any(Option::is_some) {
+                    Some(Named::Recursive(
+                        closures
+                            .iter()
+                            .zip(new_closures)
+                            .map(|(closure, new_expr)| Closure {
+                                expr: new_expr.unwrap_or(closure.expr),
+                                ..closure.clone()
+                            })
+                            .collect(),
+                    ))
+                } else {
+                    None
+                }
+            }
+        };
+
+        self.known.enter_scope();
+        match new_named.as_ref().unwrap_or(&bind.expr) {
+            // Modules which are bound to a variable before they are destructured
+            Named::Expr(bind_expr) => {
+                let known = self.find(bind_expr);
+                self.known.insert(bind.name.name.clone(), known);
+            }
+            Named::Recursive(closures) => {
+                for closure in closures {
+                    self.shadow(&closure.name.name);
+                }
+            }
+        }
+        let new_body = self.visit_expr(body);
+        self.known.exit_scope();
+
+        if new_named.is_none() && new_body.is_none() {
+            return None;
+        }
+        Some(self.allocator.arena.alloc(Expr::Let(
+            LetBinding {
+                name: bind.name.clone(),
+                expr: new_named.unwrap_or_else(|| bind.expr.clone()),
+                span_start: bind.span_start,
+            },
+            new_body.unwrap_or(body),
+        )))
+    }
+
+    fn visit_match(
+        &mut self,
+        scrutinee: CExpr<'a>,
+        alts: &'a [Alternative<'a>],
+    ) -> Option<CExpr<'a>> {
+        let new_scrutinee = self.visit_expr(scrutinee);
+        let module = match self.find(new_scrutinee.unwrap_or(scrutinee)) {
+            Known::Module(module) => Some(module),
+            _ => None,
+        };
+
+        let new_alts = alts
+            .iter()
+            .map(|alt| {
+                self.known.enter_scope();
+                match alt.pattern {
+                    Pattern::Constructor(_, ref args) => {
+                        for arg in args {
+                            self.shadow(&arg.name);
+                        }
+                    }
+                    Pattern::Record(ref fields) => {
+                        for field in fields {
+                            let name = field.1.as_ref().unwrap_or(&field.0.name);
+                            let field_name = field.0.name.declared_name();
+                            let known = match module {
+                                Some(ref module) if module.get(field_name).is_some() => {
+                                    Known::Function(module.clone(), field_name.to_string())
+                                }
+                                _ => Known::Unknown,
+                            };
+                            self.known.insert(name.clone(), known);
+                        }
+                    }
+                    Pattern::Ident(ref id) => self.shadow(&id.name),
+                    Pattern::Literal(_) => (),
+                }
+                let new_expr = self.visit_expr(alt.expr);
+                self.known.exit_scope();
+                new_expr
+            })
+            .collect::<Vec<_>>();
+
+        if new_scrutinee.is_none() && new_alts.iter().all(Option::is_none) {
+            return None;
+        }
+        let alts = alts
+            .iter()
+            .zip(new_alts)
+            .map(|(alt, new_expr)| Alternative {
+                pattern: alt.pattern.clone(),
+                expr: new_expr.unwrap_or(alt.expr),
+            })
+            .collect::<Vec<_>>();
+        Some(self.allocator.arena.alloc(Expr::Match(
+            new_scrutinee.unwrap_or(scrutinee),
+            self.allocator.alternative_arena.alloc_extend(alts),
+        )))
+    }
+}
+
+impl<'a, 'f> Visitor<'a, 'a> for Inliner<'a, 'f> {
+    type Producer = SameLifetime<'a>;
+
+    fn visit_expr(&mut self, expr: CExpr<'a>) -> Option<CExpr<'a>> {
+        match *expr {
+            Expr::Let(ref bind, body) => self.visit_let(bind, body),
+            Expr::Match(scrutinee, alts) => self.visit_match(scrutinee, alts),
+            Expr::Call(..) => {
+                let new_expr = walk_expr_alloc(self, expr);
+                match *new_expr.unwrap_or(expr) {
+                    Expr::Call(f, args) => match self.find(f) {
+                        Known::Function(functions, field) => {
+                            self.inline_call(&functions, &field, args).or(new_expr)
+                        }
+                        _ => new_expr,
+                    },
+                    _ => new_expr,
+                }
+            }
+            _ => walk_expr_alloc(self, expr),
+        }
+    }
+
+    fn detach_allocator(&self) -> Option<&'a Allocator<'a>> {
+        Some(self.allocator)
+    }
+}
+
+/// Replaces the calls in `expr` to the functions of other modules with at most `threshold`
+/// expressions by the body of the function. `modules` returns the functions of each global module.
+///
+/// `end` must be past every position in the module of `expr`.
+pub fn inline<'a>(
+    allocator: &'a Allocator<'a>,
+    modules: &Fn(&Symbol) -> Option<Arc<InlineFunctions>>,
+    threshold: usize,
+    end: BytePos,
+    expr: CExpr<'a>,
+) -> (CExpr<'a>, Vec<InlinedSource>) {
+    let mut inliner = Inliner {
+        allocator,
+        modules,
+        known: ScopedMap::new(),
+        threshold,
+        budget: MODULE_BUDGET,
+        sources: Vec::new(),
+        source_indexes: FnvMap::default(),
+        next_pos: end.0.max(expr.span().end().0) + 1,
+    };
+    let expr = inliner.visit_expr(expr).unwrap_or(expr);
+    (expr, inliner.sources)
+}
@@ -1,12 +1,12 @@
 use crate::base::ast::{Literal, TypedIdent};
-use crate::base::fnv::FnvSet;
 use crate::base::kind::{ArcKind, KindEnv};
 use crate::base::merge::merge_iter;
 use crate::base::scoped_map::ScopedMap;
 use crate::base::symbol::{Symbol, SymbolRef};
 use crate::base::types::{Alias, ArcType, TypeEnv, TypeExt};
 use crate::core::optimize::{
-    walk_expr_alloc, DifferentLifetime, ExprProducer, SameLifetime, Visitor,
+    is_variable_in_expression, walk_expr_alloc, DifferentLifetime, ExprProducer, SameLifetime,
+    Visitor,
 };
 use crate::core::{self, Allocator, CExpr, Closure, Expr, LetBinding, Named, Pattern};
 use crate::types::*;
@@ -14,32 +14,6 @@ use std::ops::{Deref, DerefMut};
 
 use crate::{Error, Result};
 
-fn is_variable_in_expression<'a, I>(iter: I, expr: CExpr) -> bool
-where
-    I: IntoIterator<Item = &'a Symbol>,
-{
-    struct FreeVars(FnvSet<Symbol>);
-    impl<'e> Visitor<'e, 'e> for FreeVars {
-        type Producer = SameLifetime<'e>;
-
-        fn visit_expr(&mut self, expr: CExpr<'e>) -> Option<CExpr<'e>> {
-            match *expr {
-                Expr::Ident(ref id, ..) => {
-                    self.0.insert(id.name.clone());
-                    None
-                }
-                _ => walk_expr_alloc(self, expr),
-            }
-        }
-        fn detach_allocator(&self) -> Option<&'e Allocator<'e>> {
-            None
-        }
-    }
-    let mut free_vars = FreeVars(FnvSet::default());
-    free_vars.visit_expr(expr);
-    iter.into_iter().any(|field| free_vars.0.contains(field))
-}
-
 #[derive(Copy, Clone, Debug)]
 enum Reduced<L, G> {
     Local(L),
@@ -502,6 +476,7 @@ impl<'a, 'e> Compiler<'a, 'e> {
                                     name: closure.name.clone(),
                                     args: closure.args.clone(),
                                     expr: expr.into_local(self.allocator),
+                                    tail: closure.tail,
                                 });
 
                                 function.exit_scope();
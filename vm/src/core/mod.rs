@@ -33,6 +33,7 @@ lalrpop_mod!(
     pub grammar,
     "/core/grammar.rs"
 );
+pub mod inline;
 pub mod interpreter;
 pub mod optimize;
 #[cfg(feature = "test")]
@@ -63,6 +64,8 @@ pub struct Closure<'a> {
     pub name: TypedIdent<Symbol>,
     pub args: Vec<TypedIdent<Symbol>>,
     pub expr: &'a Expr<'a>,
+    /// Set for functions marked with `#[tail]` whose recursive calls must all be tail calls
+    pub tail: bool,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -765,6 +768,7 @@ impl<'a, 'e> Translator<'a, 'e> {
                     },
                     args: bind.args.iter().map(|arg| arg.name.value.clone()).collect(),
                     expr: self.translate_alloc(&bind.expr),
+                    tail: bind.metadata.get_attribute("tail").is_some(),
                 })
                 .collect();
             Expr::Let(
@@ -800,6 +804,7 @@ impl<'a, 'e> Translator<'a, 'e> {
                         name: name.clone(),
                         args: bind.args.iter().map(|arg| arg.name.value.clone()).collect(),
                         expr: self.translate_alloc(&bind.expr),
+                        tail: bind.metadata.get_attribute("tail").is_some(),
                     }])
                 };
                 Expr::Let(
@@ -905,6 +910,7 @@ impl<'a, 'e> Translator<'a, 'e> {
                     name: name.clone(),
                     args: args,
                     expr: body,
+                    tail: false,
                 }]),
                 span_start: span.start(),
             },
@@ -1,11 +1,15 @@
 use std::marker::PhantomData;
 
+use ordered_float::NotNan;
+
 use crate::base::{
-    ast::TypedIdent,
+    ast::{Literal, TypedIdent},
+    fnv::FnvSet,
     merge::{merge_fn, merge_iter},
-    pos,
+    pos::{self, Span},
+    scoped_map::ScopedMap,
     symbol::Symbol,
-    types::{ArcType, Field, TypeExt},
+    types::{ArcType, Field, NullInterner, Type, TypeExt},
 };
 
 use crate::core::{Allocator, Alternative, CExpr, Closure, Expr, LetBinding, Named, Pattern};
@@ -168,6 +172,382 @@ pub fn optimize<'a>(allocator: &'a Allocator<'a>, expr: &'a Expr<'a>) -> &'a Exp
     optimizer.visit_expr(expr).unwrap_or(expr)
 }
 
+pub(crate) fn is_variable_in_expression<'a, I>(iter: I, expr: CExpr) -> bool
+where
+    I: IntoIterator<Item = &'a Symbol>,
+{
+    struct FreeVars(FnvSet<Symbol>);
+    impl<'e> Visitor<'e, 'e> for FreeVars {
+        type Producer = SameLifetime<'e>;
+
+        fn visit_expr(&mut self, expr: CExpr<'e>) -> Option<CExpr<'e>> {
+            match *expr {
+                Expr::Ident(ref id, ..) => {
+                    self.0.insert(id.name.clone());
+                    None
+                }
+                _ => walk_expr_alloc(self, expr),
+            }
+        }
+        fn detach_allocator(&self) -> Option<&'e Allocator<'e>> {
+            None
+        }
+    }
+    let mut free_vars = FreeVars(FnvSet::default());
+    free_vars.visit_expr(expr);
+    iter.into_iter().any(|field| free_vars.0.contains(field))
+}
+
+/// Constants are literals and constructors without arguments
+fn is_constant(expr: CExpr) -> bool {
+    match *expr {
+        Expr::Const(..) => true,
+        Expr::Data(_, args, _) => args.is_empty(),
+        _ => false,
+    }
+}
+
+/// Returns true if evaluating `expr` can't have any side effects or fail, and can therefore be
+/// removed if its value is unused
+fn is_pure(expr: CExpr) -> bool {
+    match *expr {
+        Expr::Const(..) | Expr::Ident(..) => true,
+        Expr::Data(_, args, _) => args.iter().all(is_pure),
+        Expr::Call(..) | Expr::Let(..) | Expr::Match(..) => false,
+    }
+}
+
+fn bool_value(expr: CExpr) -> Option<bool> {
+    match *expr {
+        Expr::Data(ref id, args, _) if args.is_empty() => match id.name.declared_name() {
+            "True" => Some(true),
+            "False" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Finds the `True` or `False` constructor of the `Bool` type that `op_type` returns
+fn bool_constructor(op_type: &ArcType, value: bool) -> Option<TypedIdent<Symbol>> {
+    let mut typ = op_type.remove_forall();
+    while let Some((_, ret)) = typ.as_function() {
+        typ = ret;
+    }
+    match **typ {
+        Type::Alias(ref alias) => match **alias.typ(&mut NullInterner) {
+            Type::Variant(ref variants) => {
+                variants
+                    .row_iter()
+                    .nth(value as usize)
+                    .map(|variant| TypedIdent {
+                        name: variant.name.clone(),
+                        typ: typ.clone(),
+                    })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+enum Folded {
+    Literal(Literal),
+    Bool(bool),
+}
+
+/// Evaluates the primitive operation `op` if doing so gives the same result as the vm. Operations
+/// which fail at runtime, such as integer overflow or division by zero, are left for the vm to
+/// report.
+fn fold_primitive(op: &str, l: &Literal, r: &Literal) -> Option<Folded> {
+    fn float(x: f64) -> Option<Folded> {
+        NotNan::new(x)
+            .ok()
+            .map(|x| Folded::Literal(Literal::Float(x)))
+    }
+    Some(match (l, r) {
+        (&Literal::Int(l), &Literal::Int(r)) => match op {
+            "#Int+" => Folded::Literal(Literal::Int(l.checked_add(r)?)),
+            "#Int-" => Folded::Literal(Literal::Int(l.checked_sub(r)?)),
+            "#Int*" => Folded::Literal(Literal::Int(l.checked_mul(r)?)),
+            "#Int/" => Folded::Literal(Literal::Int(l.checked_div(r)?)),
+            "#Int<" => Folded::Bool(l < r),
+            "#Int==" => Folded::Bool(l == r),
+            _ => return None,
+        },
+        (&Literal::Byte(l), &Literal::Byte(r)) => match op {
+            "#Byte+" => Folded::Literal(Literal::Byte(l.checked_add(r)?)),
+            "#Byte-" => Folded::Literal(Literal::Byte(l.checked_sub(r)?)),
+            "#Byte*" => Folded::Literal(Literal::Byte(l.checked_mul(r)?)),
+            "#Byte/" => Folded::Literal(Literal::Byte(l.checked_div(r)?)),
+            "#Byte<" => Folded::Bool(l < r),
+            "#Byte==" => Folded::Bool(l == r),
+            _ => return None,
+        },
+        (&Literal::Float(l), &Literal::Float(r)) => {
+            let (l, r) = (l.into_inner(), r.into_inner());
+            match op {
+                "#Float+" => float(l + r)?,
+                "#Float-" => float(l - r)?,
+                "#Float*" => float(l * r)?,
+                "#Float/" => float(l / r)?,
+                "#Float<" => Folded::Bool(l < r),
+                "#Float==" => Folded::Bool(l == r),
+                _ => return None,
+            }
+        }
+        (&Literal::Char(l), &Literal::Char(r)) => match op {
+            "#Char<" => Folded::Bool(l < r),
+            "#Char==" => Folded::Bool(l == r),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+/// Returns the alternative which is always selected when matching on `scrutinee`, if it is a
+/// constant
+fn select_alternative<'a>(
+    scrutinee: CExpr,
+    alts: &'a [Alternative<'a>],
+) -> Option<&'a Alternative<'a>> {
+    for alt in alts {
+        match alt.pattern {
+            Pattern::Ident(_) => return Some(alt),
+            Pattern::Constructor(ref id, ref args) => match *scrutinee {
+                Expr::Data(ref data_id, data_args, _)
+                    if data_args.is_empty() && args.is_empty() =>
+                {
+                    if id.name.declared_name() == data_id.name.declared_name() {
+                        return Some(alt);
+                    }
+                }
+                _ => return None,
+            },
+            Pattern::Literal(ref literal) => match *scrutinee {
+                Expr::Const(ref value, _) => {
+                    if value == literal {
+                        return Some(alt);
+                    }
+                }
+                _ => return None,
+            },
+            Pattern::Record(_) => return None,
+        }
+    }
+    None
+}
+
+/// Evaluates the primitive operations on literals at compile time, removes the `match`
+/// alternatives (and `if` branches) which can never be selected and the `let` bindings whose
+/// values are pure and unused.
+struct FoldConstants<'a> {
+    allocator: &'a Allocator<'a>,
+    /// The constant value of each variable in scope, `None` if it is not a constant
+    constants: ScopedMap<Symbol, Option<CExpr<'a>>>,
+}
+
+impl<'a> FoldConstants<'a> {
+    fn shadow(&mut self, id: &Symbol) {
+        self.constants.insert(id.clone(), None);
+    }
+
+    fn shadow_pattern(&mut self, pattern: &Pattern) {
+        match *pattern {
+            Pattern::Constructor(_, ref args) => {
+                for arg in args {
+                    self.shadow(&arg.name);
+                }
+            }
+            Pattern::Record(ref fields) => {
+                for field in fields {
+                    self.shadow(field.1.as_ref().unwrap_or(&field.0.name));
+                }
+            }
+            Pattern::Ident(ref id) => self.shadow(&id.name),
+            Pattern::Literal(_) => (),
+        }
+    }
+
+    fn fold_call(&self, f: CExpr<'a>, args: &'a [Expr<'a>]) -> Option<CExpr<'a>> {
+        let op = match *f {
+            Expr::Ident(ref id, _) => id,
+            _ => return None,
+        };
+        let (l, r) = match *args {
+            [ref l, ref r] => (l, r),
+            _ => return None,
+        };
+        match op.name.as_ref() {
+            "&&" => return bool_value(l).map(|l_value| if l_value { r } else { l }),
+            "||" => return bool_value(l).map(|l_value| if l_value { l } else { r }),
+            _ => (),
+        }
+        let folded = match (l, r) {
+            (&Expr::Const(ref l, _), &Expr::Const(ref r, _)) => {
+                fold_primitive(op.name.as_ref(), l, r)?
+            }
+            _ => return None,
+        };
+        let span = Span::new(l.span().start(), r.span().end());
+        Some(self.allocator.arena.alloc(match folded {
+            Folded::Literal(literal) => Expr::Const(literal, span),
+            Folded::Bool(value) => Expr::Data(bool_constructor(&op.typ, value)?, &[], span.start()),
+        }))
+    }
+
+    fn fold_let(&mut self, bind: &LetBinding<'a>, body: CExpr<'a>) -> Option<CExpr<'a>> {
+        let new_named = match bind.expr {
+            Named::Expr(bind_expr) => self.visit_expr(bind_expr).map(Named::Expr),
+            Named::Recursive(ref closures) => {
+                self.constants.enter_scope();
+                for closure in closures {
+                    self.shadow(&closure.name.name);
+                    for arg in &closure.args {
+                        self.shadow(&arg.name);
+                    }
+                }
+                let new_bind = walk_bind(self, bind);
+                self.constants.exit_scope();
+                new_bind.map(|bind| bind.expr)
+            }
+        };
+        let changed_bind = new_named.is_some();
+        let named = new_named.unwrap_or_else(|| bind.expr.clone());
+
+        self.constants.enter_scope();
+        match named {
+            Named::Expr(bind_expr) => {
+                let constant = if is_constant(bind_expr) {
+                    Some(bind_expr)
+                } else {
+                    None
+                };
+                self.constants.insert(bind.name.name.clone(), constant);
+            }
+            Named::Recursive(ref closures) => {
+                for closure in closures {
+                    self.shadow(&closure.name.name);
+                }
+            }
+        }
+        let new_body = self.visit_expr(body);
+        self.constants.exit_scope();
+
+        let changed_body = new_body.is_some();
+        let body = new_body.unwrap_or(body);
+        let unused = match named {
+            Named::Expr(bind_expr) => {
+                is_pure(bind_expr) && !is_variable_in_expression(Some(&bind.name.name), body)
+            }
+            Named::Recursive(ref closures) => {
+                !is_variable_in_expression(closures.iter().map(|closure| &closure.name.name), body)
+            }
+        };
+        if unused {
+            Some(body)
+        } else if changed_bind || changed_body {
+            Some(self.allocator.arena.alloc(Expr::Let(
+                LetBinding {
+                    name: bind.name.clone(),
+                    expr: named,
+                    span_start: bind.span_start,
+                },
+                body,
+            )))
+        } else {
+            None
+        }
+    }
+
+    fn fold_match(
+        &mut self,
+        scrutinee: CExpr<'a>,
+        alts: &'a [Alternative<'a>],
+    ) -> Option<CExpr<'a>> {
+        let new_scrutinee = self.visit_expr(scrutinee);
+        let scrutinee = new_scrutinee.unwrap_or(scrutinee);
+
+        if let Some(alt) = select_alternative(scrutinee, alts) {
+            return Some(match alt.pattern {
+                // Keep the binding so that the variable can be folded like any other `let`
+                Pattern::Ident(ref id) => {
+                    let bind = LetBinding {
+                        name: id.clone(),
+                        expr: Named::Expr(scrutinee),
+                        span_start: scrutinee.span().start(),
+                    };
+                    match self.fold_let(&bind, alt.expr) {
+                        Some(expr) => expr,
+                        None => self.allocator.arena.alloc(Expr::Let(bind, alt.expr)),
+                    }
+                }
+                _ => self.visit_expr(alt.expr).unwrap_or(alt.expr),
+            });
+        }
+
+        let new_alts = merge_iter(
+            alts,
+            |alt| {
+                self.constants.enter_scope();
+                self.shadow_pattern(&alt.pattern);
+                let new_alt = walk_alt(self, alt);
+                self.constants.exit_scope();
+                new_alt
+            },
+            Clone::clone,
+        )
+        .map(|alts: Vec<_>| {
+            &*self
+                .allocator
+                .alternative_arena
+                .alloc_extend(alts.into_iter())
+        });
+        if new_scrutinee.is_none() && new_alts.is_none() {
+            return None;
+        }
+        Some(
+            self.allocator
+                .arena
+                .alloc(Expr::Match(scrutinee, new_alts.unwrap_or(alts))),
+        )
+    }
+}
+
+impl<'a> Visitor<'a, 'a> for FoldConstants<'a> {
+    type Producer = SameLifetime<'a>;
+
+    fn visit_expr(&mut self, expr: CExpr<'a>) -> Option<CExpr<'a>> {
+        match *expr {
+            Expr::Ident(ref id, _) => self.constants.get(&id.name).cloned().and_then(|c| c),
+            Expr::Let(ref bind, body) => self.fold_let(bind, body),
+            Expr::Match(scrutinee, alts) => self.fold_match(scrutinee, alts),
+            Expr::Call(..) => {
+                let new_expr = walk_expr_alloc(self, expr);
+                match *new_expr.unwrap_or(expr) {
+                    Expr::Call(f, args) => self.fold_call(f, args).or(new_expr),
+                    _ => new_expr,
+                }
+            }
+            _ => walk_expr_alloc(self, expr),
+        }
+    }
+
+    fn detach_allocator(&self) -> Option<&'a Allocator<'a>> {
+        Some(self.allocator)
+    }
+}
+
+/// Evaluates the parts of `expr` which only depend on constants, removing branches which can
+/// never be selected and bindings which become unused.
+pub fn fold_constants<'a>(allocator: &'a Allocator<'a>, expr: &'a Expr<'a>) -> &'a Expr<'a> {
+    let mut folder = FoldConstants {
+        allocator,
+        constants: ScopedMap::new(),
+    };
+    folder.visit_expr(expr).unwrap_or(expr)
+}
+
 pub fn walk_expr_alloc<'a, 'b, V>(visitor: &mut V, expr: CExpr<'b>) -> Option<CExpr<'a>>
 where
     V: ?Sized + Visitor<'a, 'b>,
@@ -293,6 +673,7 @@ where
                     name: closure.name.clone(),
                     args: closure.args.clone(),
                     expr: new_expr,
+                    tail: closure.tail,
                 })
             },
             |closure| Closure {
@@ -300,6 +681,7 @@ where
                 name: closure.name.clone(),
                 args: closure.args.clone(),
                 expr: V::Producer::new(allocator.expect("Allocator")).produce(closure.expr),
+                tail: closure.tail,
             },
         )
         .map(Named::Recursive),
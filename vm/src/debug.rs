@@ -1,6 +1,6 @@
 use crate::{
-    api::{generic::A, Generic, OpaqueRef},
-    thread::Thread,
+    api::{generic::A, Generic, OpaqueRef, WithVM},
+    thread::{Thread, ThreadInternal},
     value::ValueRepr,
     ExternModule, Result,
 };
@@ -22,6 +22,21 @@ fn tag(a: OpaqueRef<A>) -> Option<String> {
     }
 }
 
+/// Returns `module:line` of the call to the function that called `caller_location`
+fn caller_location(WithVM { vm, .. }: WithVM<()>) -> Option<String> {
+    let stacktrace = vm.context().stacktrace(0);
+    let frames = &stacktrace.frames;
+    // The innermost frames are `caller_location` itself and the function which called it
+    let frame = frames
+        .len()
+        .checked_sub(3)
+        .and_then(|i| frames[i].as_ref())?;
+    match (&frame.source_name, frame.line) {
+        (Some(source_name), Some(line)) => Some(format!("{}:{}", source_name, line.number())),
+        _ => None,
+    }
+}
+
 mod std {
     pub use crate::debug;
 }
@@ -34,7 +49,8 @@ pub fn load(vm: &Thread) -> Result<ExternModule> {
         record! {
             trace => primitive!(1, std::debug::trace),
             show => primitive!(1, std::debug::show),
-            tag => primitive!(1, std::debug::tag)
+            tag => primitive!(1, std::debug::tag),
+            caller_location => primitive!(1, std::debug::caller_location)
         },
     )
 }
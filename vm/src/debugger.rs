@@ -0,0 +1,239 @@
+//! A source level debugger built on top of the line hook of a thread's `Context`.
+//!
+//! Breakpoints are set with `Thread::set_breakpoint` and each time a thread stops, either at a
+//! breakpoint or after a step, the callback set with `Thread::set_debug_callback` receives a
+//! `DebugSnapshot` of the current line, the locals in scope and the call stack. The callback then
+//! decides how execution continues through the `DebugAction` it returns.
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use futures::Async;
+
+use crate::base::fnv::FnvMap;
+use crate::base::pos::Line;
+use crate::base::types::ArcType;
+
+use crate::thread::{DebugInfo, HookFn, RenderOptions, StackInfo, Thread};
+use crate::Result;
+
+pub type DebugCallback = Box<FnMut(&DebugSnapshot) -> DebugAction + Send + Sync>;
+
+/// Decides how a thread continues after it has stopped
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DebugAction {
+    /// Runs until the next breakpoint
+    Continue,
+    /// Stops at the next line which is executed, including lines in functions which are called
+    StepInto,
+    /// Stops at the next line of the current function, or of its caller if the function returns
+    StepOver,
+    /// Stops at the next line executed after the current function has returned
+    StepOut,
+    /// Suspends the thread until one of `Thread::continue_to_breakpoint`, `Thread::step_into`,
+    /// `Thread::step_over` or `Thread::step_out` is called from another OS thread. Only the
+    /// suspended thread stops, other gluon threads keep running. The OS thread which runs the
+    /// suspended thread is blocked until then, even if it is driving the thread as a future.
+    Suspend,
+}
+
+/// A line in the source of a module
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceLocation {
+    /// The name of the module which defined the code, `test` for `run_expr(.., "test", ..)`
+    pub source_name: String,
+    /// The (zero-indexed) line, `None` for extern functions
+    pub line: Option<Line>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LocalVariable {
+    pub name: String,
+    pub typ: ArcType,
+    /// The value rendered in the same way as the repl prints values
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StackEntry {
+    pub function_name: String,
+    pub location: SourceLocation,
+}
+
+/// The state of a thread at the point where it stopped
+#[derive(Clone, Debug)]
+pub struct DebugSnapshot {
+    pub location: SourceLocation,
+    /// The local variables which are in scope at `location`
+    pub locals: Vec<LocalVariable>,
+    /// The functions on the call stack, starting with the function which is currently executing.
+    /// Functions which were left through a tail call are no longer on the stack.
+    pub call_stack: Vec<StackEntry>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum StepMode {
+    Run,
+    Into,
+    Over(usize),
+    Out(usize),
+}
+
+struct State {
+    breakpoints: FnvMap<String, Vec<Line>>,
+    callback: Option<DebugCallback>,
+    mode: StepMode,
+    /// The number of frames on the stack the last time the thread stopped
+    depth: usize,
+    suspended: bool,
+}
+
+impl State {
+    fn should_stop(&self, location: &SourceLocation, depth: usize) -> bool {
+        let at_breakpoint = match (self.breakpoints.get(&location.source_name), location.line) {
+            (Some(lines), Some(line)) => lines.contains(&line),
+            _ => false,
+        };
+        at_breakpoint
+            || match self.mode {
+                StepMode::Run => false,
+                StepMode::Into => true,
+                StepMode::Over(start) => depth <= start,
+                StepMode::Out(start) => depth < start,
+            }
+    }
+
+    fn set_mode(&mut self, action: DebugAction) {
+        self.mode = match action {
+            DebugAction::Continue | DebugAction::Suspend => StepMode::Run,
+            DebugAction::StepInto => StepMode::Into,
+            DebugAction::StepOver => StepMode::Over(self.depth),
+            DebugAction::StepOut => StepMode::Out(self.depth),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Debugger(Arc<(Mutex<State>, Condvar)>);
+
+impl Debugger {
+    pub(crate) fn new() -> Debugger {
+        let state = State {
+            breakpoints: FnvMap::default(),
+            callback: None,
+            mode: StepMode::Run,
+            depth: 0,
+            suspended: false,
+        };
+        Debugger(Arc::new((Mutex::new(state), Condvar::new())))
+    }
+
+    fn state(&self) -> MutexGuard<State> {
+        (self.0).0.lock().unwrap()
+    }
+
+    /// Returns the line hook which runs the debugger
+    pub(crate) fn hook(&self) -> HookFn {
+        let debugger = self.clone();
+        Box::new(move |thread, info| debugger.on_line(thread, info))
+    }
+
+    pub(crate) fn set_breakpoint(&self, module: &str, line: Line) {
+        let mut state = self.state();
+        let lines = state.breakpoints.entry(module.to_string()).or_default();
+        if !lines.contains(&line) {
+            lines.push(line);
+        }
+    }
+
+    pub(crate) fn remove_breakpoint(&self, module: &str, line: Line) -> bool {
+        let mut state = self.state();
+        match state.breakpoints.get_mut(module) {
+            Some(lines) => {
+                let len = lines.len();
+                lines.retain(|&l| l != line);
+                len != lines.len()
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn set_callback(&self, callback: Option<DebugCallback>) {
+        self.state().callback = callback;
+    }
+
+    /// Sets how execution continues and wakes the thread if it is suspended
+    pub(crate) fn resume(&self, action: DebugAction) {
+        let mut state = self.state();
+        state.set_mode(action);
+        state.suspended = false;
+        (self.0).1.notify_all();
+    }
+
+    fn on_line(&self, thread: &Thread, info: DebugInfo) -> Result<Async<()>> {
+        let mut state = self.state();
+        let depth = info.stack_info_len();
+        let top = match info.stack_info(0) {
+            Some(top) => top,
+            None => return Ok(Async::Ready(())),
+        };
+        let location = location(&top);
+        if !state.should_stop(&location, depth) {
+            return Ok(Async::Ready(()));
+        }
+
+        let snapshot = DebugSnapshot {
+            location,
+            locals: locals(thread, &top),
+            call_stack: (0..depth)
+                .filter_map(|level| info.stack_info(level))
+                .filter_map(|frame| {
+                    Some(StackEntry {
+                        function_name: frame.function_name()?.to_string(),
+                        location: self::location(&frame),
+                    })
+                })
+                .collect(),
+        };
+        state.depth = depth;
+        let action = match state.callback {
+            Some(ref mut callback) => callback(&snapshot),
+            None => DebugAction::Suspend,
+        };
+        match action {
+            DebugAction::Suspend => {
+                // The lock has been held since the callback ran so a `resume` from another
+                // thread can not happen before the thread is marked as suspended
+                state.suspended = true;
+                while state.suspended {
+                    state = (self.0).1.wait(state).unwrap();
+                }
+            }
+            action => state.set_mode(action),
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+fn location(frame: &StackInfo) -> SourceLocation {
+    SourceLocation {
+        source_name: frame.source_name().to_string(),
+        line: frame.line(),
+    }
+}
+
+fn locals(thread: &Thread, frame: &StackInfo) -> Vec<LocalVariable> {
+    let options = RenderOptions {
+        max_depth: 5,
+        ..RenderOptions::default()
+    };
+    frame
+        .locals()
+        .filter_map(|local| {
+            let value = frame.local_value(local)?;
+            Some(LocalVariable {
+                name: local.name.declared_name().to_string(),
+                typ: local.typ.clone(),
+                value: thread.render_value(&local.typ, value, &options),
+            })
+        })
+        .collect()
+}
@@ -55,6 +55,7 @@ pub fn generate(
                         types: Vec::new(),
                         typ: Type::hole(),
                         base: None,
+                        update: false,
                     },
                 ),
                 &mut |field| {
@@ -123,6 +124,7 @@ pub fn generate(
                 value: Some(ident(span, deserializer_fn.name.clone())),
             }],
             base: None,
+            update: false,
         },
     );
 
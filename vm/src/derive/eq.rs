@@ -202,6 +202,7 @@ pub fn generate(
                     value: Some(ident(span, eq.name.clone())),
                 }],
                 base: None,
+                update: false,
             },
         ),
     );
@@ -188,6 +188,7 @@ pub fn generate(
                 value: Some(ident(span, serialize_.name.clone())),
             }],
             base: None,
+            update: false,
         },
     );
 
@@ -172,6 +172,7 @@ pub fn generate(
                     value: Some(ident(span, show_fn.name.clone())),
                 }],
                 base: None,
+                update: false,
             },
         ),
     );
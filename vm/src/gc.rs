@@ -10,10 +10,13 @@ use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::ptr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::base::fnv::FnvMap;
 use crate::interner::InternedStr;
+use crate::thread::Thread;
 use crate::types::VmIndex;
+use crate::value::Userdata;
 use crate::{Error, Result};
 
 #[inline]
@@ -131,6 +134,44 @@ impl Generation {
     }
 }
 
+/// Statistics about the heap of a garbage collector and the collections which have been run on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GcStats {
+    /// How many bytes are currently allocated
+    pub allocated_bytes: usize,
+    /// How many collections have been run
+    pub collections: usize,
+    /// How long the last collection took, zero if no collection has been run
+    pub last_pause: Duration,
+    /// How many objects have been allocated and not yet freed. Unreachable objects are only freed
+    /// by a collection so this includes those allocated since the last one.
+    pub live_objects: usize,
+}
+
+/// Unreachable values which have been taken out of a garbage collector with
+/// `Gc::take_finalizers` and which are freed when they have been finalized.
+#[derive(Debug)]
+pub struct Finalizers(Vec<AllocPtr>);
+
+impl Finalizers {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Finalizes and frees each value.
+    ///
+    /// `thread` must be the thread which owns the garbage collector that the values were taken
+    /// from. Since the values are no longer owned by the garbage collector it may be locked and
+    /// used by the finalizers.
+    pub unsafe fn run(self, thread: &Thread) {
+        for mut header in self.0 {
+            let finalize = header.finalizer().expect("Finalizer");
+            finalize(header.value(), thread);
+            debug!("FREE: {:?}", header);
+        }
+    }
+}
+
 /// A mark and sweep garbage collector.
 #[derive(Debug)]
 #[cfg_attr(feature = "serde_derive", derive(DeserializeState, SerializeState))]
@@ -150,6 +191,24 @@ pub struct Gc {
     allocated_memory: usize,
     /// How many bytes this garbage collector can allocate before a collection is run
     collect_limit: usize,
+    /// How many times larger than the memory left after a collection `collect_limit` is set to
+    collect_growth_factor: f64,
+    /// The lowest value `collect_limit` is set to
+    collect_threshold: usize,
+    /// How many objects are currently allocated
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    object_count: usize,
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    collections: usize,
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    last_pause: Duration,
+    /// Unreachable values found by `sweep` which must be finalized before they are freed
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    pending_finalizers: Vec<AllocPtr>,
+    /// How many bytes the values reached during the current collection own outside of the heap,
+    /// as reported through `add_external_memory`
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    external_memory: usize,
     /// The maximum number of bytes this garbage collector may contain
     memory_limit: usize,
     #[cfg_attr(feature = "serde_derive", serde(skip))]
@@ -235,6 +294,8 @@ unsafe impl<T> DataDef for Move<T> {
 #[derive(Debug)]
 struct TypeInfo {
     drop: unsafe fn(*mut ()),
+    /// Set for values which must be finalized before they are dropped
+    finalize: Option<unsafe fn(*mut (), &Thread)>,
     generation: Generation,
     tag: Option<InternedStr>,
     fields: FnvMap<InternedStr, VmIndex>,
@@ -335,6 +396,10 @@ impl GcHeader {
     fn generation(&self) -> Generation {
         unsafe { (*self.type_info).generation }
     }
+
+    fn finalizer(&self) -> Option<unsafe fn(*mut (), &Thread)> {
+        unsafe { (*self.type_info).finalize }
+    }
 }
 
 /// A pointer to a garbage collected value.
@@ -617,13 +682,25 @@ where
     }
 }
 
+/// The default for `Gc::set_collect_growth_factor`
+const DEFAULT_COLLECT_GROWTH_FACTOR: f64 = 2.0;
+/// The default for `Gc::set_collect_threshold`
+const DEFAULT_COLLECT_THRESHOLD: usize = 100;
+
 impl Gc {
     /// Constructs a new garbage collector
     pub fn new(generation: Generation, memory_limit: usize) -> Gc {
         Gc {
             values: None,
             allocated_memory: 0,
-            collect_limit: 100,
+            collect_limit: DEFAULT_COLLECT_THRESHOLD,
+            collect_growth_factor: DEFAULT_COLLECT_GROWTH_FACTOR,
+            collect_threshold: DEFAULT_COLLECT_THRESHOLD,
+            object_count: 0,
+            collections: 0,
+            last_pause: Duration::default(),
+            pending_finalizers: Vec::new(),
+            external_memory: 0,
             memory_limit: memory_limit,
             type_infos: FnvMap::default(),
             record_infos: FnvMap::default(),
@@ -640,12 +717,66 @@ impl Gc {
         self.memory_limit = memory_limit;
     }
 
+    pub fn stats(&self) -> GcStats {
+        GcStats {
+            allocated_bytes: self.allocated_memory,
+            collections: self.collections,
+            last_pause: self.last_pause,
+            live_objects: self.object_count,
+        }
+    }
+
+    /// Sets how much the heap may grow between collections. A collection which leaves `n` bytes
+    /// allocated schedules the next collection for when `n * factor` bytes are allocated.
+    /// (default: 2.0)
+    ///
+    /// A larger factor runs fewer collections at the cost of keeping more unreachable values
+    /// around.
+    ///
+    /// Panics if `factor` is not a finite number of at least 1.0.
+    pub fn set_collect_growth_factor(&mut self, factor: f64) {
+        assert!(
+            factor.is_finite() && factor >= 1.0,
+            "The growth factor must be a finite number >= 1.0, got {}",
+            factor
+        );
+        self.collect_growth_factor = factor;
+        self.collect_limit = self.next_collect_limit();
+    }
+
+    /// Sets how many bytes must be allocated before any collection is run. (default: 100)
+    pub fn set_collect_threshold(&mut self, threshold: usize) {
+        self.collect_threshold = threshold;
+        self.collect_limit = self.next_collect_limit();
+    }
+
+    fn next_collect_limit(&self) -> usize {
+        let limit = (self.allocated_memory as f64 * self.collect_growth_factor) as usize;
+        limit
+            .max(self.collect_threshold)
+            .saturating_add(self.external_memory)
+    }
+
+    /// Reports that a value which is being traversed owns `bytes` of memory outside of the heap.
+    ///
+    /// Traversing a value is proportional to its total size so counting that memory, when
+    /// deciding when to run the next collection, keeps the time spent collecting proportional to
+    /// the amount allocated even when large values, such as growable buffers, live outside of the
+    /// heap.
+    pub fn add_external_memory(&mut self, bytes: usize) {
+        self.external_memory = self.external_memory.saturating_add(bytes);
+    }
+
     pub fn generation(&self) -> Generation {
         self.generation
     }
 
     pub fn new_child_gc(&self) -> Gc {
-        Gc::new(self.generation.next(), self.memory_limit)
+        let mut gc = Gc::new(self.generation.next(), self.memory_limit);
+        gc.collect_growth_factor = self.collect_growth_factor;
+        gc.collect_threshold = self.collect_threshold;
+        gc.collect_limit = self.collect_threshold;
+        gc
     }
 
     /// Allocates a new object. If the garbage collector has hit the collection limit a collection
@@ -717,6 +848,7 @@ impl Gc {
         fields: Option<&[InternedStr]>,
         type_id: TypeId,
         drop: unsafe fn(*mut ()),
+        finalize: Option<unsafe fn(*mut (), &Thread)>,
     ) -> *const TypeInfo {
         match fields {
             Some(fields) => match self
@@ -730,6 +862,7 @@ impl Gc {
                     .entry(fields.to_owned())
                     .or_insert(Box::new(TypeInfo {
                         drop,
+                        finalize: None,
                         generation: self.generation,
                         tag,
                         fields: fields
@@ -745,6 +878,7 @@ impl Gc {
                     Entry::Occupied(entry) => &**entry.get(),
                     Entry::Vacant(entry) => &**entry.insert(Box::new(TypeInfo {
                         drop,
+                        finalize: None,
                         generation: self.generation,
                         tag: Some(tag),
                         fields: FnvMap::default(),
@@ -755,6 +889,7 @@ impl Gc {
                     Entry::Occupied(entry) => &**entry.get(),
                     Entry::Vacant(entry) => &**entry.insert(Box::new(TypeInfo {
                         drop,
+                        finalize,
                         generation: self.generation,
                         tag,
                         fields: FnvMap::default(),
@@ -774,16 +909,23 @@ impl Gc {
             ptr::drop_in_place(t as *mut T);
         }
 
-        let type_info = self.get_type_info(
-            def.tag(),
-            def.fields(),
-            TypeId::of::<D::Value>(),
-            drop::<D::Value>,
-        );
+        unsafe fn finalize_userdata(t: *mut (), thread: &Thread) {
+            (*(t as *mut Box<dyn Userdata>)).finalize(thread);
+        }
+
+        let type_id = TypeId::of::<D::Value>();
+        let finalize = if type_id == TypeId::of::<Box<dyn Userdata>>() {
+            Some(finalize_userdata as unsafe fn(*mut (), &Thread))
+        } else {
+            None
+        };
+        let type_info =
+            self.get_type_info(def.tag(), def.fields(), type_id, drop::<D::Value>, finalize);
 
         let mut ptr = AllocPtr::new::<D::Value>(type_info, size);
         ptr.next = self.values.take();
         self.allocated_memory += ptr.size();
+        self.object_count += 1;
         unsafe {
             let p: *mut D::Value = D::Value::make_ptr(&def, ptr.value());
             let ret: *const D::Value = &*def.initialize(WriteOnly::new(p));
@@ -814,11 +956,15 @@ impl Gc {
         R: Traverseable + CollectScope,
     {
         info!("Start collect {:?}", self.generation);
+        let start = Instant::now();
         roots.scope(self, |self_| {
+            self_.external_memory = 0;
             roots.traverse(self_);
             self_.sweep();
-            self_.collect_limit = 2 * self_.allocated_memory;
-        })
+            self_.collect_limit = self_.next_collect_limit();
+        });
+        self.collections += 1;
+        self.last_pause = start.elapsed();
     }
 
     /// Marks the GcPtr
@@ -869,8 +1015,13 @@ impl Gc {
                 count += 1;
                 if free {
                     free_count += 1;
-                    // Free the current pointer
-                    self.free(maybe_header.take());
+                    // Free the current pointer, unless it must be finalized first
+                    match maybe_header.take() {
+                        Some(header) if header.finalizer().is_some() => {
+                            self.pending_finalizers.push(header)
+                        }
+                        header => self.free(header),
+                    }
                     *maybe_header = replaced_next;
                 } else {
                     // Just move to the next pointer
@@ -882,9 +1033,21 @@ impl Gc {
         self.values = first;
     }
 
+    /// Takes the values which `sweep` found to be unreachable and which needs to be finalized
+    /// before they are freed, see `Userdata::finalize`. The values no longer count towards the
+    /// memory used by this garbage collector.
+    pub fn take_finalizers(&mut self) -> Finalizers {
+        for ptr in &self.pending_finalizers {
+            self.allocated_memory -= ptr.size();
+            self.object_count -= 1;
+        }
+        Finalizers(mem::replace(&mut self.pending_finalizers, Vec::new()))
+    }
+
     fn free(&mut self, header: Option<AllocPtr>) {
         if let Some(ref ptr) = header {
             self.allocated_memory -= ptr.size();
+            self.object_count -= 1;
         }
         debug!("FREE: {:?}", header);
         drop(header);
@@ -1052,4 +1215,49 @@ mod tests {
         }
         assert_eq!(true, dropped.get());
     }
+
+    #[test]
+    fn stats() {
+        let mut gc: Gc = Gc::new(Generation::default(), usize::MAX);
+        let mut stack: Vec<Value> = Vec::new();
+        stack.push(new_data(gc.alloc(Def { elems: &[Int(1)] }).unwrap()));
+        gc.alloc(Def { elems: &[Int(2)] }).unwrap();
+        let stats = gc.stats();
+        assert_eq!(stats.live_objects, 2);
+        assert_eq!(stats.collections, 0);
+        assert_eq!(stats.allocated_bytes, gc.allocated_memory());
+
+        unsafe {
+            gc.collect(&mut *stack);
+        }
+        let stats = gc.stats();
+        assert_eq!(stats.live_objects, object_count(&gc));
+        assert_eq!(stats.live_objects, 1);
+        assert_eq!(stats.collections, 1);
+        assert!(stats.allocated_bytes < gc.collect_limit);
+    }
+
+    #[test]
+    fn collect_limit_follows_the_growth_factor_and_threshold() {
+        let mut gc: Gc = Gc::new(Generation::default(), usize::MAX);
+        let mut stack: Vec<Value> = Vec::new();
+        stack.push(new_data(gc.alloc(Def { elems: &[Int(1)] }).unwrap()));
+
+        gc.set_collect_growth_factor(1.0);
+        unsafe {
+            gc.collect(&mut *stack);
+        }
+        assert!(gc.allocated_memory() < DEFAULT_COLLECT_THRESHOLD);
+        assert_eq!(gc.collect_limit, DEFAULT_COLLECT_THRESHOLD);
+
+        gc.set_collect_growth_factor(4.0);
+        assert_eq!(gc.collect_limit, 4 * gc.allocated_memory());
+
+        gc.set_collect_threshold(1000);
+        assert_eq!(gc.collect_limit, 1000);
+
+        let child = gc.new_child_gc();
+        assert_eq!(child.collect_growth_factor, 4.0);
+        assert_eq!(child.collect_limit, 1000);
+    }
 }
@@ -16,6 +16,7 @@ extern crate itertools;
 extern crate log;
 #[macro_use]
 extern crate mopa;
+extern crate ordered_float;
 extern crate pretty;
 #[macro_use]
 extern crate quick_error;
@@ -67,15 +68,19 @@ pub mod serialization;
 
 #[macro_use]
 pub mod api;
+pub mod atomic;
 pub mod channel;
 pub mod compiler;
 pub mod core;
 pub mod debug;
+pub mod debugger;
 pub mod dynamic;
 pub mod gc;
 pub mod lazy;
 pub mod macros;
+pub mod map;
 pub mod primitives;
+pub mod profiler;
 pub mod reference;
 pub mod stack;
 pub mod thread;
@@ -93,7 +98,7 @@ use std::{self as real_std, fmt, marker::PhantomData};
 use crate::api::{ValueRef, VmType};
 use crate::base::metadata::Metadata;
 use crate::base::symbol::Symbol;
-use crate::base::types::ArcType;
+use crate::base::types::{ArcType, Type, TypeExt};
 use crate::stack::Stacktrace;
 use crate::thread::{RootedThread, RootedValue, Thread};
 use crate::types::{VmIndex, VmInt};
@@ -178,6 +183,9 @@ quick_error! {
         StackOverflow(limit: VmIndex) {
             display("The stack has overflowed: Limit `{}`", limit)
         }
+        OutOfFuel {
+            display("Thread ran out of fuel")
+        }
         Message(err: String) {
             display("{}", err)
             from()
@@ -188,11 +196,24 @@ quick_error! {
         Panic(err: String, stacktrace: Option<Stacktrace>) {
             display("{}", Panic { err, stacktrace })
         }
+        IO(err: api::IOError, stacktrace: Option<Stacktrace>) {
+            display("{}", Panic { err, stacktrace })
+        }
+    }
+}
+
+impl Error {
+    /// Returns the stacktrace captured when the error was raised, if there is one
+    pub fn stacktrace(&self) -> Option<&Stacktrace> {
+        match *self {
+            Error::Panic(_, ref stacktrace) | Error::IO(_, ref stacktrace) => stacktrace.as_ref(),
+            _ => None,
+        }
     }
 }
 
 struct Panic<'a> {
-    err: &'a String,
+    err: &'a fmt::Display,
     stacktrace: &'a Option<Stacktrace>,
 }
 
@@ -237,11 +258,46 @@ impl ExternModule {
             metadata,
         })
     }
+
+    /// Returns the fields of this module which are records themselves. The importer exposes these
+    /// as submodules so that `import! parent.field` loads the nested record.
+    ///
+    /// Types declared in a submodule are only reachable through the submodule so two submodules
+    /// can both declare a type with the same short name as long as each is registered with its
+    /// module path (`register_type::<T>("parent.field.Name", &[])`).
+    pub fn submodules(&self) -> Vec<(String, ExternModule)> {
+        self.typ
+            .remove_forall()
+            .row_iter()
+            .filter(|field| match **field.typ.remove_forall() {
+                Type::Record(_) => true,
+                _ => false,
+            })
+            .filter_map(|field| {
+                let name = field.name.declared_name();
+                let value = self.value.get_field(name)?;
+                let metadata = self
+                    .metadata
+                    .module
+                    .get(name)
+                    .map(|metadata| (**metadata).clone())
+                    .unwrap_or_default();
+                Some((
+                    name.to_string(),
+                    ExternModule {
+                        metadata,
+                        value,
+                        typ: field.typ.clone(),
+                    },
+                ))
+            })
+            .collect()
+    }
 }
 
 /// Internal types and functions exposed to the main `gluon` crate
 pub mod internal {
     pub use crate::interner::InternedStr;
-    pub use crate::value::{Value, ValuePrinter};
+    pub use crate::value::{Cloner, Value, ValuePrinter};
     pub use crate::vm::Global;
 }
@@ -0,0 +1,210 @@
+//! Primitives operating on whole `std.map.Map` values.
+//!
+//! The keys are always compared with the `compare` function passed from gluon so that the maps
+//! built here are ordered in the same way as when they are built through `std.map.insert`.
+use crate::real_std::cmp::Ordering;
+
+use crate::api::generic::{A, B, C, K};
+use crate::api::{
+    self, ActiveThread, Generic, Getable, OpaqueRef, OwnedFunction, RuntimeResult, ValueRef, VmType,
+};
+use crate::base::types::ArcType;
+use crate::types::VmInt;
+use crate::vm::Thread;
+use crate::{ExternModule, Result, Variants};
+
+type Compare = OwnedFunction<fn(Generic<K>, Generic<K>) -> Ordering>;
+type Combine = OwnedFunction<fn(Generic<A>, Generic<B>) -> Generic<C>>;
+
+/// The entries of a `std.map.Map` in ascending order of their keys
+struct Entries<K, V>(Vec<(Generic<K>, Generic<V>)>);
+
+impl<K, V> VmType for Entries<K, V>
+where
+    K: VmType,
+    K::Type: Sized,
+    V: VmType,
+    V::Type: Sized,
+{
+    type Type = api::Map<K::Type, V::Type>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        api::Map::<K, V>::make_type(vm)
+    }
+}
+
+impl<'vm, K, V> api::Pushable<'vm> for Entries<K, V>
+where
+    K: VmType,
+    K::Type: Sized,
+    V: VmType,
+    V::Type: Sized,
+{
+    fn push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        api::push_map_entries(self.0, context)
+    }
+}
+
+impl<'vm, 'value, K, V> Getable<'vm, 'value> for Entries<K, V> {
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        let mut entries: Vec<(Generic<K>, Generic<V>)> = Vec::new();
+        api::walk_map(vm, value, &mut |key, value| {
+            entries.push((key, value));
+            true
+        });
+        Entries(entries)
+    }
+}
+
+/// Calls `compare`, storing the first error in `error` and treating the keys as equal once an error
+/// has occurred so that it can be called from `sort_by`
+fn compare_keys(
+    compare: &mut Compare,
+    error: &mut Option<String>,
+    l: &Generic<K>,
+    r: &Generic<K>,
+) -> Ordering {
+    if error.is_some() {
+        return Ordering::Equal;
+    }
+    match compare.call(l.clone(), r.clone()) {
+        Ok(ordering) => ordering,
+        Err(err) => {
+            *error = Some(err.to_string());
+            Ordering::Equal
+        }
+    }
+}
+
+fn from_array(
+    mut compare: Compare,
+    mut entries: Vec<(Generic<K>, Generic<A>)>,
+) -> RuntimeResult<Entries<K, A>, String> {
+    let mut error = None;
+    // The sort is stable so the last of several equal keys is also the last one in `entries`
+    entries.sort_by(|l, r| compare_keys(&mut compare, &mut error, &l.0, &r.0));
+
+    let mut unique: Vec<(Generic<K>, Generic<A>)> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match unique.last_mut() {
+            Some(last)
+                if compare_keys(&mut compare, &mut error, &last.0, &entry.0) == Ordering::Equal =>
+            {
+                *last = entry
+            }
+            _ => unique.push(entry),
+        }
+    }
+
+    match error {
+        Some(err) => RuntimeResult::Panic(err),
+        None => RuntimeResult::Return(Entries(unique)),
+    }
+}
+
+fn to_array(map: Entries<K, A>) -> Vec<(Generic<K>, Generic<A>)> {
+    map.0
+}
+
+/// An entry of the maps passed to `merge`
+enum Merged<V, W> {
+    Left((Generic<K>, Generic<V>)),
+    Right((Generic<K>, Generic<W>)),
+    Both((Generic<K>, Generic<V>), (Generic<K>, Generic<W>)),
+}
+
+/// Walks the entries of `l` and `r` in order, keeping the entries returned by `f`
+fn merge<V, W, X>(
+    compare: &mut Compare,
+    l: Entries<K, V>,
+    r: Entries<K, W>,
+    mut f: impl FnMut(Merged<V, W>) -> Result<Option<(Generic<K>, Generic<X>)>>,
+) -> RuntimeResult<Entries<K, X>, String> {
+    let mut merged = Vec::with_capacity(l.0.len() + r.0.len());
+    let mut error = None;
+    let mut l = l.0.into_iter().peekable();
+    let mut r = r.0.into_iter().peekable();
+    loop {
+        let entry = match (l.peek(), r.peek()) {
+            (Some(l_entry), Some(r_entry)) => {
+                match compare_keys(compare, &mut error, &l_entry.0, &r_entry.0) {
+                    Ordering::Less => Merged::Left(l.next().unwrap()),
+                    Ordering::Greater => Merged::Right(r.next().unwrap()),
+                    Ordering::Equal => Merged::Both(l.next().unwrap(), r.next().unwrap()),
+                }
+            }
+            (Some(_), None) => Merged::Left(l.next().unwrap()),
+            (None, Some(_)) => Merged::Right(r.next().unwrap()),
+            (None, None) => break,
+        };
+        if let Some(err) = error {
+            return RuntimeResult::Panic(err);
+        }
+        match f(entry) {
+            Ok(Some(entry)) => merged.push(entry),
+            Ok(None) => (),
+            Err(err) => return RuntimeResult::Panic(err.to_string()),
+        }
+    }
+    RuntimeResult::Return(Entries(merged))
+}
+
+fn union(
+    mut compare: Compare,
+    l: Entries<K, A>,
+    r: Entries<K, A>,
+) -> RuntimeResult<Entries<K, A>, String> {
+    merge(&mut compare, l, r, |entry| {
+        Ok(Some(match entry {
+            Merged::Left(entry) | Merged::Right(entry) | Merged::Both(_, entry) => entry,
+        }))
+    })
+}
+
+fn intersection_with(
+    mut compare: Compare,
+    mut f: Combine,
+    l: Entries<K, A>,
+    r: Entries<K, B>,
+) -> RuntimeResult<Entries<K, C>, String> {
+    merge(&mut compare, l, r, |entry| match entry {
+        Merged::Both((key, l), (_, r)) => Ok(Some((key, f.call(l, r)?))),
+        Merged::Left(_) | Merged::Right(_) => Ok(None),
+    })
+}
+
+fn size(map: OpaqueRef<api::Map<K, A>>) -> VmInt {
+    let mut size = 0;
+    let mut stack = vec![map.get_variant()];
+    while let Some(map) = stack.pop() {
+        if let ValueRef::Data(data) = map.as_ref() {
+            if data.tag() == 1 {
+                size += 1;
+                stack.push(data.get_variant(2).expect("left"));
+                stack.push(data.get_variant(3).expect("right"));
+            }
+        }
+    }
+    size
+}
+
+mod std {
+    pub mod map {
+        pub use crate::map as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
+    ExternModule::new(
+        vm,
+        record! {
+            from_array => primitive!(2, std::map::prim::from_array),
+            to_array => primitive!(1, std::map::prim::to_array),
+            union => primitive!(3, std::map::prim::union),
+            intersection_with => primitive!(4, std::map::prim::intersection_with),
+            size => primitive!(1, std::map::prim::size)
+        },
+    )
+}
@@ -1,24 +1,28 @@
 //! Module containing functions for interacting with gluon's primitive types.
 use crate::real_std::{
+    any::Any,
     ffi::OsStr,
-    fs, io,
+    fmt, fs, io,
+    marker::PhantomData,
     path::{self, Path},
     result::Result as StdResult,
     str::FromStr,
     string::String as StdString,
+    sync::Mutex,
 };
 
-use crate::base::types::ArcType;
+use crate::base::types::{ArcType, Type};
 
 use crate::api::{
     generic::{self, A},
-    primitive, Array, Getable, OpaqueRef, Pushable, Pushed, RuntimeResult, ValueRef, VmType,
-    WithVM, IO,
+    primitive, ActiveThread, Array, Generic, Getable, IOError, OpaqueRef, Propagate, Pushable,
+    Pushed, RuntimeResult, Unrooted, Userdata, ValueRef, VmType, WithVM, IO,
 };
-use crate::gc::{DataDef, Gc, Traverseable, WriteOnly};
+use crate::gc::{DataDef, Gc, GcPtr, Move, Traverseable, WriteOnly};
 use crate::stack::{ExternState, StackFrame};
+use crate::thread::ThreadInternal;
 use crate::types::VmInt;
-use crate::value::{Def, GcStr, Repr, ValueArray, ValueRepr};
+use crate::value::{ArrayDef, Cloner, Def, GcStr, Repr, Value, ValueArray, ValueRepr};
 use crate::vm::{Status, Thread};
 use crate::{Error, ExternModule, Result, Variants};
 
@@ -41,24 +45,94 @@ pub mod array {
         }
     }
 
+    /// Returns the element at `index` without checking that it lies inside of `array`. Meant for
+    /// loops which already ensure that `index` is in bounds, any other `index` reads arbitrary
+    /// memory.
+    pub(crate) fn unsafe_index<'vm>(
+        array: OpaqueRef<'vm, [generic::A]>,
+        index: VmInt,
+    ) -> OpaqueRef<'vm, generic::A> {
+        debug_assert!(
+            index >= 0 && (index as usize) < array.len(),
+            "Index {} is out of range",
+            index
+        );
+        unsafe { array.get_unchecked(index) }
+    }
+
+    pub fn buf_new(WithVM { vm, .. }: WithVM<()>) -> IO<ArrayBuf<generic::A>> {
+        IO::Value(ArrayBuf {
+            values: Mutex::new(Vec::new()),
+            thread: unsafe { GcPtr::from_raw(vm) },
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn buf_len(buf: &ArrayBuf<generic::A>) -> IO<VmInt> {
+        IO::Value(buf.values.lock().unwrap().len() as VmInt)
+    }
+
+    pub fn buf_get(index: VmInt, buf: &ArrayBuf<generic::A>) -> IO<Unrooted<generic::A>> {
+        let values = buf.values.lock().unwrap();
+        match values.get(index as usize) {
+            Some(value) if index >= 0 => IO::Value(Unrooted::from(value.clone())),
+            _ => IO::Exception(buf.out_of_range(index, values.len())),
+        }
+    }
+
+    pub fn buf_push(value: Generic<generic::A>, buf: &ArrayBuf<generic::A>) -> IO<()> {
+        match buf.clone_value(value.get_variant()) {
+            Ok(value) => {
+                buf.values.lock().unwrap().push(value);
+                IO::Value(())
+            }
+            Err(err) => IO::Exception(err.to_string()),
+        }
+    }
+
+    pub fn buf_set(index: VmInt, value: Generic<generic::A>, buf: &ArrayBuf<generic::A>) -> IO<()> {
+        let value = match buf.clone_value(value.get_variant()) {
+            Ok(value) => value,
+            Err(err) => return IO::Exception(err.to_string()),
+        };
+        let mut values = buf.values.lock().unwrap();
+        let len = values.len();
+        match values.get_mut(index as usize) {
+            Some(slot) if index >= 0 => {
+                *slot = value;
+                IO::Value(())
+            }
+            _ => IO::Exception(buf.out_of_range(index, len)),
+        }
+    }
+
+    pub fn buf_freeze(buf: &ArrayBuf<generic::A>) -> IO<Frozen<generic::A>> {
+        IO::Value(Frozen(buf.values.lock().unwrap().clone(), PhantomData))
+    }
+
     pub(crate) fn slice<'vm>(
         array: Array<'vm, generic::A>,
         start: usize,
         end: usize,
-    ) -> RuntimeResult<Array<'vm, generic::A>, Error> {
+    ) -> RuntimeResult<Array<'vm, generic::A>, Propagate> {
         if start > end {
-            return RuntimeResult::Panic(Error::Message(format!(
+            return RuntimeResult::Panic(Propagate(Error::Message(format!(
                 "slice index starts at {} but ends at {}",
                 start, end
-            )));
+            ))));
         }
 
         if end > array.len() {
-            return RuntimeResult::Panic(Error::Message(format!(
+            return RuntimeResult::Panic(Propagate(Error::Message(format!(
                 "index {} is out of range for array of length {}",
                 end,
                 array.len()
-            )));
+            ))));
+        }
+
+        // Arrays are immutable so a slice of the whole array can share its storage
+        if start == 0 && end == array.len() {
+            return RuntimeResult::Return(array);
         }
 
         struct Slice<'a> {
@@ -104,7 +178,7 @@ pub mod array {
 
         let value = match result {
             Ok(value) => value,
-            Err(err) => return RuntimeResult::Panic(err),
+            Err(err) => return RuntimeResult::Panic(Propagate(err)),
         };
 
         unsafe {
@@ -118,7 +192,7 @@ pub mod array {
     pub(crate) fn append<'vm>(
         lhs: Array<'vm, generic::A>,
         rhs: Array<'vm, generic::A>,
-    ) -> RuntimeResult<Array<'vm, generic::A>, Error> {
+    ) -> RuntimeResult<Array<'vm, generic::A>, Propagate> {
         struct Append<'b> {
             lhs: &'b ValueArray,
             rhs: &'b ValueArray,
@@ -166,7 +240,7 @@ pub mod array {
             });
             match result {
                 Ok(x) => x,
-                Err(err) => return RuntimeResult::Panic(err),
+                Err(err) => return RuntimeResult::Panic(Propagate(err)),
             }
         };
         unsafe {
@@ -183,7 +257,7 @@ mod string {
     use crate::api::Pushable;
     use crate::thread::ThreadInternal;
 
-    pub fn append(lhs: WithVM<&str>, rhs: &str) -> RuntimeResult<String, Error> {
+    pub fn append(lhs: WithVM<&str>, rhs: &str) -> RuntimeResult<String, Propagate> {
         struct StrAppend<'b> {
             lhs: &'b str,
             rhs: &'b str,
@@ -221,7 +295,7 @@ mod string {
             let result = context.alloc(StrAppend { lhs: lhs, rhs: rhs });
             match result {
                 Ok(x) => GcStr::from_utf8_unchecked(x),
-                Err(err) => return RuntimeResult::Panic(err),
+                Err(err) => return RuntimeResult::Panic(Propagate(err)),
             }
         };
         unsafe {
@@ -283,6 +357,37 @@ mod string {
         }
     }
 
+    /// Concatenates `strings`, allocating the result only once
+    pub fn concat_array(strings: Vec<&str>) -> StdString {
+        let mut result = StdString::with_capacity(strings.iter().map(|s| s.len()).sum());
+        for s in strings {
+            result.push_str(s);
+        }
+        result
+    }
+
+    pub fn builder_new(_: ()) -> IO<StringBuilder> {
+        IO::Value(StringBuilder(Mutex::new(StdString::new())))
+    }
+
+    pub fn builder_push(s: &str, builder: &StringBuilder) -> IO<()> {
+        builder.0.lock().unwrap().push_str(s);
+        IO::Value(())
+    }
+
+    pub fn builder_push_char(c: char, builder: &StringBuilder) -> IO<()> {
+        StdString::push(&mut builder.0.lock().unwrap(), c);
+        IO::Value(())
+    }
+
+    pub fn builder_len(builder: &StringBuilder) -> IO<VmInt> {
+        IO::Value(builder.0.lock().unwrap().len() as VmInt)
+    }
+
+    pub fn builder_build(builder: &StringBuilder) -> IO<StdString> {
+        IO::Value(builder.0.lock().unwrap().clone())
+    }
+
     pub fn char_at(s: &str, index: usize) -> RuntimeResult<char, String> {
         if s.is_char_boundary(index) {
             if let Some(c) = s[index..].chars().next() {
@@ -367,6 +472,12 @@ mod std {
     pub mod path {
         pub type prim = ::std::path::Path;
     }
+    pub mod fs {
+        pub use crate::primitives::fs_prim as prim;
+    }
+    pub mod gc {
+        pub use crate::primitives::gc_prim as prim;
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -530,25 +641,150 @@ pub fn load_int(vm: &Thread) -> Result<ExternModule> {
     )
 }
 
+/// A growable array which is updated in place.
+///
+/// Values are cloned into the heap of the thread which created the buffer before they are stored,
+/// the same as for `std.reference`. The buffer is traversed together with that heap so a value
+/// from a younger heap, such as the heap of a thread spawned after the buffer was created, can
+/// not be collected while the buffer still refers to it.
+pub struct ArrayBuf<T> {
+    values: Mutex<Vec<Value>>,
+    thread: GcPtr<Thread>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Userdata for ArrayBuf<T>
+where
+    T: Any + Send + Sync,
+{
+    fn deep_clone(&self, deep_cloner: &mut Cloner) -> Result<GcPtr<Box<Userdata>>> {
+        let values = self.values.lock().unwrap();
+        let cloned_values = values
+            .iter()
+            .map(|value| deep_cloner.deep_clone(value))
+            .collect::<Result<Vec<_>>>()?;
+        let data: Box<Userdata> = Box::new(ArrayBuf {
+            values: Mutex::new(cloned_values),
+            thread: unsafe { GcPtr::from_raw(deep_cloner.thread()) },
+            _marker: PhantomData::<A>,
+        });
+        deep_cloner.gc().alloc(Move(data))
+    }
+}
+
+impl<T> fmt::Debug for ArrayBuf<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ArrayBuf({:?})", *self.values.lock().unwrap())
+    }
+}
+
+impl<T> Traverseable for ArrayBuf<T> {
+    fn traverse(&self, gc: &mut Gc) {
+        let values = self.values.lock().unwrap();
+        // The values are not part of the heap, without reporting them every collection would
+        // traverse the entire buffer no matter how little had been allocated since the last one
+        gc.add_external_memory(values.capacity() * crate::real_std::mem::size_of::<Value>());
+        values.traverse(gc)
+    }
+}
+
+impl<T> VmType for ArrayBuf<T>
+where
+    T: VmType,
+    T::Type: Sized,
+{
+    type Type = ArrayBuf<T::Type>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let env = vm.global_env().get_env();
+        let symbol = env.find_type_info("ArrayBuf").unwrap().name.clone();
+        let ctor = Type::ident(symbol);
+        Type::app(ctor, collect![T::make_type(vm)])
+    }
+}
+
+impl<T> ArrayBuf<T> {
+    /// Clones `value` into the heap of the buffer. This must be done before `values` is locked as
+    /// the clone may need to wait for a garbage collection of that heap, which in turn locks
+    /// `values` to traverse them.
+    fn clone_value(&self, value: Variants) -> Result<Value> {
+        self.thread.deep_clone_value(&self.thread, value)
+    }
+
+    fn out_of_range(&self, index: VmInt, len: usize) -> StdString {
+        format!(
+            "index {} is out of range for array buffer of length {}",
+            index, len
+        )
+    }
+}
+
+/// A copy of the values of an `ArrayBuf` which is pushed as an array
+pub struct Frozen<T>(Vec<Value>, PhantomData<T>);
+
+impl<T> VmType for Frozen<T>
+where
+    T: VmType,
+    T::Type: Sized,
+{
+    type Type = Vec<T::Type>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        Vec::<T>::make_type(vm)
+    }
+}
+
+impl<'vm, T> Pushable<'vm> for Frozen<T> {
+    fn push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        let thread = context.thread();
+        // `ArrayDef` traverses the values while it is allocated so they are kept alive even if
+        // the allocation triggers a collection
+        let array = context.context().alloc_with(thread, ArrayDef(&self.0))?;
+        context.push(ValueRepr::Array(array));
+        Ok(())
+    }
+}
+
 #[allow(non_camel_case_types)]
 pub fn load_array(vm: &Thread) -> Result<ExternModule> {
     use self::std;
+
+    vm.register_type::<ArrayBuf<A>>("ArrayBuf", &["a"])?;
+
     ExternModule::new(
         vm,
         record! {
+            type ArrayBuf a => ArrayBuf<A>,
             len => primitive!(1, std::array::prim::len),
             index => primitive!(2, std::array::prim::index),
+            unsafe_index => primitive!(2, std::array::prim::unsafe_index),
             append => primitive!(2, std::array::prim::append),
-            slice => primitive!(3, std::array::prim::slice)
+            slice => primitive!(3, std::array::prim::slice),
+            buf_new => primitive!(1, std::array::prim::buf_new),
+            buf_len => primitive!(1, std::array::prim::buf_len),
+            buf_get => primitive!(2, std::array::prim::buf_get),
+            buf_push => primitive!(2, std::array::prim::buf_push),
+            buf_set => primitive!(3, std::array::prim::buf_set),
+            buf_freeze => primitive!(1, std::array::prim::buf_freeze)
         },
     )
 }
 
+/// A mutable buffer which strings can be appended to in amortized constant time, unlike `++`
+/// which copies both of its arguments
+#[derive(Userdata, Debug)]
+#[gluon(gluon_vm)]
+pub struct StringBuilder(Mutex<StdString>);
+
 pub fn load_string(vm: &Thread) -> Result<ExternModule> {
     use self::string;
+
+    vm.register_type::<StringBuilder>("StringBuilder", &[])?;
+
     ExternModule::new(
         vm,
         record! {
+            type StringBuilder => StringBuilder,
             len => primitive!(1, std::string::prim::len),
             is_empty => primitive!(1, std::string::prim::is_empty),
             is_char_boundary => primitive!(2, std::string::prim::is_char_boundary),
@@ -570,7 +806,17 @@ pub fn load_string(vm: &Thread) -> Result<ExternModule> {
                 "std.string.prim.from_utf8",
                 string::from_utf8
             ),
-            char_at => primitive!(2, "std.string.prim.char_at", string::char_at)
+            char_at => primitive!(2, "std.string.prim.char_at", string::char_at),
+            concat_array => primitive!(1, "std.string.prim.concat_array", string::concat_array),
+            builder_new => primitive!(1, "std.string.prim.builder_new", string::builder_new),
+            builder_push => primitive!(2, "std.string.prim.builder_push", string::builder_push),
+            builder_push_char => primitive!(
+                2,
+                "std.string.prim.builder_push_char",
+                string::builder_push_char
+            ),
+            builder_len => primitive!(1, "std.string.prim.builder_len", string::builder_len),
+            builder_build => primitive!(1, "std.string.prim.builder_build", string::builder_build)
         },
     )
 }
@@ -601,7 +847,120 @@ pub struct Metadata(fs::Metadata);
 #[gluon(gluon_vm)]
 pub struct DirEntry(fs::DirEntry);
 
+/// The entries returned by `std.fs.prim.list_dir`. Symbolic links are not followed so `is_dir`
+/// and `len` describe the link itself when `is_symlink` is set.
+#[derive(Pushable, VmType)]
+#[gluon(gluon_vm)]
+pub struct Entry {
+    name: StdString,
+    is_dir: bool,
+    is_symlink: bool,
+    len: u64,
+}
+
+#[doc(hidden)]
+pub mod fs_prim {
+    use super::*;
+
+    /// Converts `result` into an `IO`. Errors of a kind which `IOError` can represent are raised as
+    /// that kind, the rest are raised as `Other` with a message mentioning `path` since the OS
+    /// error message does not include it
+    pub(crate) fn with_path<T>(result: io::Result<T>, action: &str, path: &Path) -> IO<T> {
+        match result {
+            Ok(value) => IO::Value(value),
+            Err(err) => IO::Error(match IOError::from(err) {
+                IOError::Other(msg) => IOError::Other(format!(
+                    "Could not {} `{}`: {}",
+                    action,
+                    path.display(),
+                    msg
+                )),
+                err => err,
+            }),
+        }
+    }
+
+    pub fn read_dir(path: &Path) -> IO<Vec<DirEntry>> {
+        with_path(
+            fs::read_dir(path).and_then(|iter| {
+                iter.map(|result| result.map(DirEntry))
+                    .collect::<io::Result<Vec<_>>>()
+            }),
+            "read the directory",
+            path,
+        )
+    }
+
+    pub fn list_dir(path: &Path) -> IO<Vec<Entry>> {
+        let entries = fs::read_dir(path).and_then(|iter| {
+            iter.map(|result| {
+                let entry = result?;
+                let metadata = entry.metadata()?;
+                let file_type = metadata.file_type();
+                Ok(Entry {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    is_dir: file_type.is_dir(),
+                    is_symlink: file_type.is_symlink(),
+                    len: metadata.len(),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()
+        });
+        with_path(entries, "read the directory", path)
+    }
+
+    pub fn exists(path: &Path) -> IO<bool> {
+        IO::Value(path.exists())
+    }
+
+    pub fn metadata(path: &Path) -> IO<Metadata> {
+        with_path(
+            fs::metadata(path).map(Metadata),
+            "read the metadata of",
+            path,
+        )
+    }
+
+    pub fn symlink_metadata(path: &Path) -> IO<Metadata> {
+        with_path(
+            fs::symlink_metadata(path).map(Metadata),
+            "read the metadata of",
+            path,
+        )
+    }
+
+    pub fn create_dir_all(path: &Path) -> IO<()> {
+        with_path(fs::create_dir_all(path), "create the directory", path)
+    }
+
+    pub fn remove_file(path: &Path) -> IO<()> {
+        with_path(fs::remove_file(path), "remove the file", path)
+    }
+
+    pub fn remove_dir_all(path: &Path) -> IO<()> {
+        with_path(fs::remove_dir_all(path), "remove the directory", path)
+    }
+
+    pub fn rename(from: &Path, to: &Path) -> IO<()> {
+        with_path(
+            fs::rename(from, to),
+            &format!("rename `{}` to", from.display()),
+            to,
+        )
+    }
+
+    pub fn copy(from: &Path, to: &Path) -> IO<u64> {
+        with_path(
+            fs::copy(from, to),
+            &format!("copy `{}` to", from.display()),
+            to,
+        )
+    }
+}
+
 pub fn load_fs(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
     vm.register_type::<Metadata>("Metadata", &[])?;
     vm.register_type::<DirEntry>("DirEntry", &[])?;
 
@@ -611,25 +970,82 @@ pub fn load_fs(vm: &Thread) -> Result<ExternModule> {
             type Metadata => Metadata,
             type DirEntry => DirEntry,
 
-            read_dir => primitive!(1, "std.fs.prim.read_dir", |p: &Path| {
-                IO::from(fs::read_dir(p).and_then(|iter| iter.map(|result| result.map(DirEntry)).collect::<io::Result<Vec<_>>>()))
-            }),
+            read_dir => primitive!(1, std::fs::prim::read_dir),
+            list_dir => primitive!(1, std::fs::prim::list_dir),
+            exists => primitive!(1, std::fs::prim::exists),
+            read_metadata => primitive!(1, std::fs::prim::metadata),
+            symlink_metadata => primitive!(1, std::fs::prim::symlink_metadata),
+            create_dir_all => primitive!(1, std::fs::prim::create_dir_all),
+            remove_file => primitive!(1, std::fs::prim::remove_file),
+            remove_dir_all => primitive!(1, std::fs::prim::remove_dir_all),
+            rename => primitive!(2, std::fs::prim::rename),
+            copy => primitive!(2, std::fs::prim::copy),
 
             dir_entry => record! {
                 path => primitive!(1, "std.fs.prim.dir_entry.path", |m: &DirEntry| m.0.path()),
-                metadata => primitive!(1, "std.fs.prim.dir_entry.metadata", |m: &DirEntry| IO::from(m.0.metadata().map(Metadata))),
+                metadata => primitive!(1, "std.fs.prim.dir_entry.metadata", |m: &DirEntry| match m.0.metadata() {
+                Ok(metadata) => IO::Value(Metadata(metadata)),
+                Err(err) => IO::Error(err.into()),
+            }),
                 file_name => primitive!(1, "std.fs.prim.dir_entry.file_name", |m: &DirEntry| m.0.file_name())
             },
 
             metadata => record! {
                 is_dir => primitive!(1, "std.fs.prim.metadata.is_dir", |m: &Metadata| m.0.is_dir()),
                 is_file => primitive!(1, "std.fs.prim.metadata.is_file", |m: &Metadata| m.0.is_file()),
+                is_symlink => primitive!(1, "std.fs.prim.metadata.is_symlink", |m: &Metadata| m.0.file_type().is_symlink()),
                 len => primitive!(1, "std.fs.prim.metadata.len", |m: &Metadata| m.0.len())
             }
         },
     )
 }
 
+/// The statistics returned by `std.gc.prim.stats`, see `GcStats`
+#[derive(Pushable, VmType)]
+#[gluon(gluon_vm)]
+pub struct Stats {
+    allocated_bytes: VmInt,
+    collections: VmInt,
+    last_pause_ms: f64,
+    live_objects: VmInt,
+}
+
+#[doc(hidden)]
+pub mod gc_prim {
+    use super::*;
+
+    /// Collects the heap of the calling thread. Like the collections which run when the heap
+    /// grows this does not collect the heaps of the threads it has spawned.
+    pub fn collect_now(WithVM { vm, .. }: WithVM<()>) -> IO<()> {
+        vm.collect();
+        IO::Value(())
+    }
+
+    pub fn stats(WithVM { vm, .. }: WithVM<()>) -> IO<Stats> {
+        let stats = vm.gc_stats();
+        let pause = stats.last_pause;
+        IO::Value(Stats {
+            allocated_bytes: stats.allocated_bytes as VmInt,
+            collections: stats.collections as VmInt,
+            last_pause_ms: pause.as_secs() as f64 * 1000.0
+                + f64::from(pause.subsec_nanos()) / 1_000_000.0,
+            live_objects: stats.live_objects as VmInt,
+        })
+    }
+}
+
+pub fn load_gc(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
+    ExternModule::new(
+        vm,
+        record! {
+            collect_now => primitive!(1, std::gc::prim::collect_now),
+            stats => primitive!(1, std::gc::prim::stats)
+        },
+    )
+}
+
 pub fn load_path(vm: &Thread) -> Result<ExternModule> {
     ExternModule::new(
         vm,
@@ -659,10 +1075,10 @@ pub fn load_path(vm: &Thread) -> Result<ExternModule> {
                     })
                     .collect::<Vec<_>>()
             }),
-            metadata => primitive!(1, "std.path.prim.metadata", |p: &Path| IO::from(p.metadata().map(Metadata))),
-            symlink_metadata => primitive!(1, "std.path.prim.symlink_metadata", |p: &Path| IO::from(p.symlink_metadata().map(Metadata))),
-            canonicalize => primitive!(1, "std.path.prim.canonicalize", |p: &Path| IO::from(p.canonicalize())),
-            read_link => primitive!(1, "std.path.prim.read_link", |p: &Path| IO::from(p.read_link())),
+            metadata => primitive!(1, "std.path.prim.metadata", |p: &Path| fs_prim::with_path(p.metadata().map(Metadata), "read the metadata of", p)),
+            symlink_metadata => primitive!(1, "std.path.prim.symlink_metadata", |p: &Path| fs_prim::with_path(p.symlink_metadata().map(Metadata), "read the metadata of", p)),
+            canonicalize => primitive!(1, "std.path.prim.canonicalize", |p: &Path| fs_prim::with_path(p.canonicalize(), "canonicalize", p)),
+            read_link => primitive!(1, "std.path.prim.read_link", |p: &Path| fs_prim::with_path(p.read_link(), "read the link", p)),
             read_dir => primitive!(
                 1,
                 "std.path.prim.read_dir",
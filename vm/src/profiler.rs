@@ -0,0 +1,167 @@
+//! Attributes the time spent running a thread to the functions it calls, see
+//! `Thread::set_profiling`.
+use std::cmp::Reverse;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::base::fnv::FnvMap;
+use crate::base::symbol::Symbol;
+
+/// The number of calls to a function and the time spent in it
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileRecord {
+    pub function_name: String,
+    /// The module which defined the function, `None` for extern functions
+    pub source_name: Option<String>,
+    pub calls: u64,
+    /// The time from entering the function until it returned, including the functions it called.
+    /// A recursive function only counts the time of its outermost call so no time is counted
+    /// twice.
+    pub inclusive: Duration,
+    /// The time spent in the function itself, excluding the functions it called
+    pub exclusive: Duration,
+}
+
+impl ProfileRecord {
+    fn frame_name(&self) -> String {
+        match self.source_name {
+            Some(ref source_name) => format!("{}:{}", source_name, self.function_name),
+            None => self.function_name.clone(),
+        }
+    }
+}
+
+struct Activation {
+    record: usize,
+    path: usize,
+    /// The number of frames on the stack while the function runs
+    depth: usize,
+    start: Instant,
+    /// The inclusive time of the functions called by this activation
+    children: Duration,
+}
+
+/// A call stack, identified by the path of its caller and the function at the top of it
+struct Path {
+    parent: Option<usize>,
+    record: usize,
+    exclusive: Duration,
+}
+
+#[derive(Default)]
+pub(crate) struct Profiler {
+    records: Vec<ProfileRecord>,
+    record_indexes: FnvMap<Symbol, usize>,
+    /// How many activations of each record are on the stack
+    active: Vec<usize>,
+    paths: Vec<Path>,
+    path_indexes: FnvMap<(Option<usize>, usize), usize>,
+    stack: Vec<Activation>,
+}
+
+impl Profiler {
+    /// Records a call to `name`, `depth` is the number of frames on the stack once the function
+    /// has been entered
+    pub(crate) fn enter(&mut self, name: &Symbol, source_name: Option<&str>, depth: usize) {
+        let now = Instant::now();
+        // A tail call replaces the frame of the caller and errors remove frames without
+        // returning through them so whatever ran at this depth before has ended
+        self.exit_at(depth.saturating_sub(1), now);
+
+        let record = match self.record_indexes.get(name) {
+            Some(&record) => record,
+            None => {
+                self.records.push(ProfileRecord {
+                    function_name: name.declared_name().to_string(),
+                    source_name: source_name.map(|s| s.to_string()),
+                    calls: 0,
+                    inclusive: Duration::default(),
+                    exclusive: Duration::default(),
+                });
+                self.active.push(0);
+                self.record_indexes
+                    .insert(name.clone(), self.records.len() - 1);
+                self.records.len() - 1
+            }
+        };
+        self.records[record].calls += 1;
+        self.active[record] += 1;
+
+        let parent = self.stack.last().map(|activation| activation.path);
+        let paths = &mut self.paths;
+        let path = *self
+            .path_indexes
+            .entry((parent, record))
+            .or_insert_with(|| {
+                paths.push(Path {
+                    parent,
+                    record,
+                    exclusive: Duration::default(),
+                });
+                paths.len() - 1
+            });
+        self.stack.push(Activation {
+            record,
+            path,
+            depth,
+            start: now,
+            children: Duration::default(),
+        });
+    }
+
+    /// Ends the calls which have frames above the first `depth` frames of the stack
+    pub(crate) fn exit(&mut self, depth: usize) {
+        self.exit_at(depth, Instant::now())
+    }
+
+    fn exit_at(&mut self, depth: usize, now: Instant) {
+        while self
+            .stack
+            .last()
+            .map_or(false, |activation| activation.depth > depth)
+        {
+            let activation = self.stack.pop().unwrap();
+            let elapsed = now.duration_since(activation.start);
+            let exclusive = elapsed.checked_sub(activation.children).unwrap_or_default();
+
+            self.active[activation.record] -= 1;
+            let record = &mut self.records[activation.record];
+            record.exclusive += exclusive;
+            if self.active[activation.record] == 0 {
+                record.inclusive += elapsed;
+            }
+            self.paths[activation.path].exclusive += exclusive;
+            if let Some(parent) = self.stack.last_mut() {
+                parent.children += elapsed;
+            }
+        }
+    }
+
+    /// Returns the records of every function which has been called, the function with the most
+    /// exclusive time first
+    pub(crate) fn report(&self) -> Vec<ProfileRecord> {
+        let mut records = self.records.clone();
+        records.sort_by_key(|record| Reverse(record.exclusive));
+        records
+    }
+
+    /// Writes one `caller;callee <nanoseconds>` line for each call stack, which is the folded
+    /// stack format read by flamegraph tools
+    pub(crate) fn folded(&self) -> String {
+        let mut folded = String::new();
+        for (index, path) in self.paths.iter().enumerate() {
+            let mut frames = Vec::new();
+            let mut current = Some(index);
+            while let Some(path) = current {
+                let path = &self.paths[path];
+                frames.push(self.records[path.record].frame_name());
+                current = path.parent;
+            }
+            frames.reverse();
+            let nanos =
+                path.exclusive.as_secs() * 1_000_000_000 + u64::from(path.exclusive.subsec_nanos());
+            writeln!(folded, "{} {}", frames.join(";"), nanos).unwrap();
+        }
+        folded
+    }
+}
@@ -1,4 +1,4 @@
-use crate::real_std::{any::Any, fmt, marker::PhantomData, sync::Mutex};
+use crate::real_std::{any::Any, cell::RefCell, fmt, marker::PhantomData, sync::Mutex};
 
 use crate::api::generic::A;
 use crate::api::{Generic, RuntimeResult, Unrooted, Userdata, VmType, WithVM};
@@ -31,9 +31,29 @@ where
     }
 }
 
+impl<T> Reference<T> {
+    /// Returns the value currently stored in the reference
+    pub(crate) fn value(&self) -> Value {
+        self.value.lock().unwrap().clone()
+    }
+}
+
 impl<T> fmt::Debug for Reference<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Ref({:?})", *self.value.lock().unwrap())
+        thread_local! {
+            // The references which are currently being formatted on this thread
+            static FORMATTING: RefCell<Vec<usize>> = RefCell::new(Vec::new());
+        }
+        let id = self as *const Self as usize;
+        // A reference which (indirectly) contains itself would otherwise recurse forever
+        if FORMATTING.with(|formatting| formatting.borrow().contains(&id)) {
+            return write!(f, "Ref(<cycle>)");
+        }
+        FORMATTING.with(|formatting| formatting.borrow_mut().push(id));
+        let value = self.value();
+        let result = write!(f, "Ref({:?})", value);
+        FORMATTING.with(|formatting| formatting.borrow_mut().pop());
+        result
     }
 }
 
@@ -55,6 +55,55 @@ impl SourceMap {
     }
 }
 
+/// A function which was inlined into another
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde_derive", derive(Deserialize, Serialize))]
+pub struct Inlined {
+    /// The name of the inlined function
+    pub name: String,
+    /// The module which defines the inlined function
+    pub source_name: String,
+    /// The line in `source_name` that the instructions came from
+    pub line: Line,
+}
+
+#[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde_derive", derive(Deserialize, Serialize))]
+pub struct InlineMap {
+    /// The index of the first instruction for each change of the function being inlined
+    map: Vec<(usize, Option<Inlined>)>,
+}
+
+impl InlineMap {
+    pub fn new() -> InlineMap {
+        InlineMap { map: Vec::new() }
+    }
+
+    /// Defines the instruction at `instruction_index` to come from `inlined` (or from the function
+    /// itself if it is `None`).
+    /// This function must be called with indexes in increasing order
+    pub fn emit(&mut self, instruction_index: usize, inlined: Option<&Inlined>) {
+        let last_emitted = self.map.last().and_then(|t| t.1.as_ref());
+        if last_emitted != inlined {
+            self.map.push((instruction_index, inlined.cloned()));
+        }
+    }
+
+    /// Returns the function that the instruction at `instruction_index` were inlined from
+    pub fn inlined(&self, instruction_index: usize) -> Option<&Inlined> {
+        let p = self
+            .map
+            .iter()
+            .position(|&(index, _)| index > instruction_index)
+            .unwrap_or(self.map.len());
+        if p == 0 {
+            None
+        } else {
+            self.map[p - 1].1.as_ref()
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "serde_derive", derive(DeserializeState, SerializeState))]
 #[cfg_attr(
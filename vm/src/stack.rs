@@ -320,6 +320,13 @@ impl Stack {
         self.values.clear();
     }
 
+    /// Removes all frames except the first `frames` and all values except the first `values`.
+    /// Used to discard the state left behind by a call which returned an error.
+    pub(crate) fn unwind(&mut self, frames: usize, values: VmIndex) {
+        self.frames.truncate(frames);
+        self.values.truncate(values as usize);
+    }
+
     pub fn slide(&mut self, count: VmIndex) {
         let i = self.len() - 1 - count;
         self[i] = self.last().unwrap().get_value();
@@ -410,30 +417,36 @@ impl Stack {
 
     /// Creates a stackrace starting from `frame_level`
     pub fn stacktrace(&self, frame_level: usize) -> Stacktrace {
-        let frames = self.get_frames()[frame_level..]
-            .iter()
-            .filter_map(|frame| match frame.state {
+        let mut frames = Vec::new();
+        for frame in &self.get_frames()[frame_level..] {
+            match frame.state {
                 State::Closure(ClosureState {
                     ref closure,
                     instruction_index,
                 }) => {
-                    let line = closure
-                        .function
-                        .debug_info
-                        .source_map
-                        .line(instruction_index);
-                    Some(Some(StacktraceFrame {
+                    let debug_info = &closure.function.debug_info;
+                    frames.push(Some(StacktraceFrame {
                         name: closure.function.name.clone(),
-                        line,
-                    }))
+                        source_name: Some(debug_info.source_name.clone()),
+                        line: debug_info.source_map.line(instruction_index),
+                    }));
+                    // Inlined functions get a frame of their own as if they had been called
+                    if let Some(inlined) = debug_info.inline_map.inlined(instruction_index) {
+                        frames.push(Some(StacktraceFrame {
+                            name: Symbol::from(&inlined.name[..]),
+                            source_name: Some(inlined.source_name.clone()),
+                            line: Some(inlined.line),
+                        }));
+                    }
                 }
-                State::Extern(ref ext) => Some(Some(StacktraceFrame {
+                State::Extern(ref ext) => frames.push(Some(StacktraceFrame {
                     name: ext.function.id.clone(),
+                    source_name: None,
                     line: None,
                 })),
-                State::Unknown => Some(None),
-            })
-            .collect();
+                State::Unknown => frames.push(None),
+            }
+        }
         Stacktrace { frames }
     }
 }
@@ -599,18 +612,10 @@ where
     }
 
     pub fn insert_slice(&mut self, index: VmIndex, values: &[Value]) {
-        self.stack.values.reserve(values.len());
-        unsafe {
-            let old_len = self.len();
-            for i in (index..old_len).rev() {
-                *self.get_unchecked_mut(i as usize + values.len()) = self[i].clone();
-            }
-            for (i, val) in (index..).zip(values) {
-                *self.get_unchecked_mut(i as usize) = val.clone();
-            }
-            let new_len = self.stack.values.len() + values.len();
-            self.stack.values.set_len(new_len);
-        }
+        let index = (self.frame.offset + index) as usize;
+        self.stack
+            .values
+            .splice(index..index, values.iter().cloned());
     }
 
     pub fn remove_range(&mut self, from: VmIndex, to: VmIndex) {
@@ -870,11 +875,14 @@ where
 #[derive(Debug, PartialEq, Clone)]
 pub struct StacktraceFrame {
     pub name: Symbol,
+    /// The module that the function was defined in, `None` for extern functions
+    pub source_name: Option<String>,
     pub line: Option<Line>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Stacktrace {
+    /// The frames of the stack, starting with the outermost call
     pub frames: Vec<Option<StacktraceFrame>>,
 }
 
@@ -885,8 +893,13 @@ impl fmt::Display for Stacktrace {
             match *frame {
                 Some(ref frame) => {
                     write!(f, "{}: {}", i, frame.name.declared_name())?;
-                    if let Some(line) = frame.line {
-                        write!(f, ":Line {}", line.number())?;
+                    match (&frame.source_name, frame.line) {
+                        (Some(source_name), Some(line)) => {
+                            write!(f, " ({}:Line {})", source_name, line.number())?
+                        }
+                        (Some(source_name), None) => write!(f, " ({})", source_name)?,
+                        (None, Some(line)) => write!(f, ":Line {}", line.number())?,
+                        (None, None) => (),
                     }
                     writeln!(f)
                 }
@@ -1040,4 +1053,30 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn insert_slice_grows_the_stack() {
+        let _ = ::env_logger::try_init();
+
+        let mut stack = Stack::new();
+        StackFrame::frame(&mut stack, 0, State::Unknown);
+        let mut stack = StackFrame::<State>::current(&mut stack);
+        stack.push(Int(0));
+        stack.push(Int(1));
+        stack = stack.enter_scope(1, State::Unknown);
+        stack.push(Int(2));
+
+        // More values than the stack has room for, inserted before values of the frame
+        let values = (10..110).map(|i| Int(i).into()).collect::<Vec<Value>>();
+        stack.insert_slice(0, &values);
+
+        let mut expected = values.clone();
+        expected.push(Int(1).into());
+        expected.push(Int(2).into());
+        assert_eq!(&stack[..], &expected[..]);
+
+        stack = stack.exit_scope().unwrap();
+        assert_eq!(stack[0], Int(0).into());
+        assert_eq!(stack.len(), 103);
+    }
 }
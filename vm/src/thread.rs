@@ -8,10 +8,12 @@ use std::result::Result as StdResult;
 use std::string::String as StdString;
 use std::sync::atomic::{self, AtomicBool};
 use std::sync::Arc;
-use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::{Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
 use std::usize;
 
 use futures::future::{self, Either, FutureResult};
+use futures::task::{self, Task};
 use futures::{Async, Future, Poll};
 
 use crate::base::metadata::Metadata;
@@ -21,10 +23,12 @@ use crate::base::types::{self, Alias, ArcType};
 
 use crate::api::{Getable, Pushable, ValueRef, VmType};
 use crate::compiler::UpvarInfo;
-use crate::gc::{DataDef, Gc, GcPtr, Generation, Move};
+use crate::debugger::{DebugAction, DebugCallback, Debugger};
+use crate::gc::{DataDef, Gc, GcPtr, GcStats, Generation, Move};
 use crate::interner::InternedStr;
 use crate::macros::MacroEnv;
-use crate::source_map::LocalIter;
+use crate::profiler::{ProfileRecord, Profiler};
+use crate::source_map::{Local, LocalIter};
 use crate::stack::{
     ClosureState, ExternCallState, ExternState, Frame, Stack, StackFrame, StackState, State,
 };
@@ -32,7 +36,7 @@ use crate::types::*;
 use crate::value::{
     BytecodeFunction, Callable, ClosureData, ClosureDataDef, ClosureInitDef, Def, ExternFunction,
     PartialApplicationDataDef, RecordDef, UninitializedRecord, UninitializedVariantDef, Userdata,
-    Value, ValueRepr, VariantDef,
+    Value, ValuePrinter, ValueRepr, VariantDef,
 };
 use crate::vm::{GlobalVmState, GlobalVmStateBuilder, VmEnv};
 use crate::{BoxFuture, Error, Result, Variants};
@@ -40,6 +44,7 @@ use crate::{BoxFuture, Error, Result, Variants};
 use crate::value::ValueRepr::{Closure, Data, Float, Function, Int, PartialApplication, String};
 
 pub use crate::gc::Traverseable;
+pub use crate::value::RenderOptions;
 
 pub type FutureValue<F> = Either<FutureResult<<F as Future>::Item, <F as Future>::Error>, F>;
 
@@ -94,6 +99,118 @@ where
     }
 }
 
+/// The generator of a thread in deterministic mode, see `Thread::set_seed`. This is SplitMix64
+/// which only needs a `u64` of state, so seeding a spawned thread is a single draw.
+#[derive(Clone, Copy, Debug)]
+struct SeededState(u64);
+
+impl SeededState {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[derive(Default)]
+struct InterruptState {
+    interrupted: AtomicBool,
+    /// Set once a top level call has finished and cleared as soon as the thread starts executing
+    /// again. Cancelling an idle thread does nothing so that a cancel which arrives too late for
+    /// the call it was meant for does not abort the next call.
+    idle: AtomicBool,
+    /// The task which last found the thread waiting on a future
+    task: Mutex<Option<Task>>,
+    cancelled: Condvar,
+}
+
+/// A handle which can interrupt a thread from any OS thread, see `Thread::interrupt_token`.
+#[derive(Clone, Default)]
+pub struct InterruptToken(Arc<InterruptState>);
+
+impl fmt::Debug for InterruptToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InterruptToken({})", self.is_cancelled())
+    }
+}
+
+impl InterruptToken {
+    /// Interrupts the thread, making it return `Error::Interrupted` at its next function call. If
+    /// the thread is waiting on a future it is woken up so that it stops without waiting for the
+    /// future to complete. Cancelling a thread whose last top level call has already returned has
+    /// no effect.
+    pub fn cancel(&self) {
+        let task = {
+            let mut task = self.0.task.lock().unwrap();
+            if self.0.idle.load(atomic::Ordering::SeqCst) {
+                return;
+            }
+            self.0.interrupted.store(true, atomic::Ordering::SeqCst);
+            task.take()
+        };
+        self.0.cancelled.notify_all();
+        if let Some(task) = task {
+            task.notify();
+        }
+    }
+
+    /// Returns `true` if the thread has been interrupted but has not stopped because of it yet
+    pub fn is_cancelled(&self) -> bool {
+        self.0.interrupted.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Blocks the current OS thread for `duration` or until the token is cancelled, whichever
+    /// comes first. Returns `true` if the token was cancelled.
+    ///
+    /// Primitives which need to block should use this instead of `std::thread::sleep` so that
+    /// they do not delay interrupts.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut task = self.0.task.lock().unwrap();
+        while !self.is_cancelled() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            task = self
+                .0
+                .cancelled
+                .wait_timeout(task, deadline - now)
+                .unwrap()
+                .0;
+        }
+        true
+    }
+
+    /// Makes `cancel` wake up the current task, must only be called from inside a task
+    fn register(&self) {
+        *self.0.task.lock().unwrap() = Some(task::current());
+        // `cancel` may have been called before the task was stored
+        if self.is_cancelled() {
+            task::current().notify();
+        }
+    }
+
+    /// Resets the token, returning whether it had been cancelled
+    fn take_cancelled(&self) -> bool {
+        self.0.interrupted.swap(false, atomic::Ordering::SeqCst)
+    }
+
+    /// Marks the thread as running, called whenever the thread starts executing
+    fn start(&self) {
+        self.0.idle.store(false, atomic::Ordering::SeqCst);
+    }
+
+    /// Marks the thread as idle and drops any cancel which the finished call did not see
+    fn finish(&self) {
+        let _task = self.0.task.lock().unwrap();
+        self.0.idle.store(true, atomic::Ordering::SeqCst);
+        self.0.interrupted.store(false, atomic::Ordering::SeqCst);
+    }
+}
+
 pub struct ExecuteTop<T>(pub Execute<T>);
 
 impl<'vm, T> Future for ExecuteTop<T>
@@ -119,8 +236,12 @@ where
                 let mut context = thread.context();
                 let stack = StackFrame::<State>::current(&mut context.stack);
                 let new_trace = reset_stack(stack, 1)?;
-                if let Error::Panic(_, ref mut trace) = err {
-                    *trace = Some(new_trace);
+                context.poll_fns.clear();
+                match err {
+                    Error::Panic(_, ref mut trace) | Error::IO(_, ref mut trace) => {
+                        *trace = Some(new_trace)
+                    }
+                    _ => (),
                 }
                 Err(err)
             }
@@ -128,6 +249,69 @@ where
     }
 }
 
+/// Drives a function call which did not complete immediately. If the call fails or the future is
+/// dropped before it completes, the frames and values of the call are removed from the stack so
+/// that the thread can be used for other calls instead of resuming the abandoned one.
+pub(crate) struct ExecuteCall<T>
+where
+    T: Deref<Target = Thread>,
+{
+    execute: Execute<T>,
+    frames: usize,
+    values: VmIndex,
+}
+
+impl<T> ExecuteCall<T>
+where
+    T: Deref<Target = Thread>,
+{
+    /// `frames` and `values` are the sizes of the stack before the function and its arguments
+    /// were pushed
+    pub(crate) fn new(thread: T, frames: usize, values: VmIndex) -> Self {
+        ExecuteCall {
+            execute: Execute::new(thread),
+            frames,
+            values,
+        }
+    }
+
+    fn unwind(&mut self) {
+        if let Some(thread) = self.execute.thread.take() {
+            let mut context = thread.context();
+            context.stack.unwind(self.frames, self.values);
+            let frames = self.frames;
+            context
+                .poll_fns
+                .retain(|poll_fn| (poll_fn.frame_index as usize) < frames);
+        }
+    }
+}
+
+impl<'vm, T> Future for ExecuteCall<T>
+where
+    T: Deref<Target = Thread>,
+    T: VmRoot<'vm>,
+{
+    type Item = RootedValue<T>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Error> {
+        self.execute.poll().map_err(|err| {
+            self.unwind();
+            err
+        })
+    }
+}
+
+impl<T> Drop for ExecuteCall<T>
+where
+    T: Deref<Target = Thread>,
+{
+    fn drop(&mut self) {
+        self.unwind();
+    }
+}
+
 /// Enum signaling a successful or unsuccess ful call to an extern function.
 /// If an error occured the error message is expected to be on the top of the stack.
 #[derive(Eq, PartialEq)]
@@ -164,6 +348,14 @@ where
     }
 }
 
+/// Rooted values are already kept alive by the thread they are rooted in
+impl<T> Traverseable for RootedValue<T>
+where
+    T: Deref<Target = Thread>,
+{
+    fn traverse(&self, _: &mut Gc) {}
+}
+
 impl<T, U> PartialEq<RootedValue<U>> for RootedValue<T>
 where
     T: Deref<Target = Thread>,
@@ -379,7 +571,13 @@ pub struct Thread {
     #[cfg_attr(feature = "serde_derive", serde(state))]
     context: Mutex<Context>,
     #[cfg_attr(feature = "serde_derive", serde(skip))]
-    interrupt: AtomicBool,
+    interrupt: InterruptToken,
+    /// Kept outside of `context` so that a thread suspended by the debugger, which holds the
+    /// lock of its context, can be resumed
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    debugger: Mutex<Option<Debugger>>,
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    seed: Mutex<Option<SeededState>>,
 }
 
 impl fmt::Debug for Thread {
@@ -500,7 +698,9 @@ impl RootedThread {
             roots: RwLock::new(Vec::new()),
             rooted_values: RwLock::new(Vec::new()),
             child_threads: RwLock::new(Vec::new()),
-            interrupt: AtomicBool::new(false),
+            interrupt: InterruptToken::default(),
+            debugger: Mutex::new(None),
+            seed: Mutex::new(None),
         };
         let mut gc = Gc::new(Generation::default(), usize::MAX);
         let vm = gc
@@ -543,7 +743,11 @@ impl Thread {
             roots: RwLock::new(Vec::new()),
             rooted_values: RwLock::new(Vec::new()),
             child_threads: RwLock::new(Vec::new()),
-            interrupt: AtomicBool::new(false),
+            interrupt: InterruptToken::default(),
+            debugger: Mutex::new(None),
+            // Drawing the seed of the new thread keeps the threads spawned by a seeded thread
+            // deterministic as long as they are spawned in the same order
+            seed: Mutex::new(self.next_seeded_u64().map(SeededState)),
         };
         // Enter the top level scope
         {
@@ -715,10 +919,14 @@ impl Thread {
 
     /// Runs a garbage collection.
     pub fn collect(&self) {
-        let mut context = self.owned_context();
-        self.with_roots(&mut context, |gc, roots| unsafe {
-            gc.collect(roots);
-        })
+        let finalizers = {
+            let mut context = self.owned_context();
+            self.with_roots(&mut context, |gc, roots| unsafe {
+                gc.collect(roots);
+            });
+            context.gc.take_finalizers()
+        };
+        unsafe { finalizers.run(self) }
     }
 
     /// Pushes a value to the top of the stack
@@ -743,12 +951,175 @@ impl Thread {
         self.owned_context().gc.set_memory_limit(memory_limit)
     }
 
+    /// Returns statistics about the heap of this thread and the collections run on it
+    pub fn gc_stats(&self) -> GcStats {
+        self.owned_context().gc.stats()
+    }
+
+    /// Sets how much the heap of this thread may grow between collections, see
+    /// `Gc::set_collect_growth_factor`. Threads spawned from this thread afterwards use the same
+    /// factor.
+    pub fn set_collect_growth_factor(&self, factor: f64) {
+        self.owned_context().gc.set_collect_growth_factor(factor)
+    }
+
+    /// Sets how many bytes this thread must allocate before any collection is run, see
+    /// `Gc::set_collect_threshold`. Threads spawned from this thread afterwards use the same
+    /// threshold.
+    pub fn set_collect_threshold(&self, threshold: usize) {
+        self.owned_context().gc.set_collect_threshold(threshold)
+    }
+
+    pub fn set_max_stack_size(&self, limit: VmIndex) {
+        self.owned_context().set_max_stack_size(limit)
+    }
+
+    /// Limits the number of instructions this thread may execute, see `Context::set_fuel`.
+    /// Setting the fuel again before each call lets a single thread run many bounded calls.
+    pub fn set_fuel(&self, fuel: Option<u64>) {
+        self.owned_context().set_fuel(fuel)
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.owned_context().fuel()
+    }
+
+    /// Returns a handle which can interrupt this thread from another OS thread while it is running.
+    /// The interrupt is consumed when the thread stops with `Error::Interrupted`, or dropped if
+    /// the call returns before noticing it, so the thread can be used again afterwards.
+    pub fn interrupt_token(&self) -> InterruptToken {
+        self.interrupt.clone()
+    }
+
     pub fn interrupt(&self) {
-        self.interrupt.store(true, atomic::Ordering::Relaxed)
+        self.interrupt.cancel()
     }
 
     pub fn interrupted(&self) -> bool {
-        self.interrupt.load(atomic::Ordering::Relaxed)
+        self.interrupt.is_cancelled()
+    }
+
+    /// Puts the thread in deterministic mode where the random number primitives which draw from
+    /// the thread (such as `next_int` in `std.random`) use a generator seeded with `seed` instead
+    /// of the generator of the OS thread. Threads spawned from a seeded thread are seeded from its
+    /// generator. `None` turns deterministic mode off again.
+    pub fn set_seed(&self, seed: Option<u64>) {
+        *self.seed.lock().unwrap() = seed.map(SeededState);
+    }
+
+    /// Draws the next `u64` from the generator installed by `set_seed`, returning `None` if the
+    /// thread is not in deterministic mode
+    pub fn next_seeded_u64(&self) -> Option<u64> {
+        self.seed
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(SeededState::next_u64)
+    }
+
+    /// Renders `value` of type `typ` the way the REPL prints values. Values which contain
+    /// themselves through a reference are printed as `<cycle>` where they repeat.
+    pub fn render_value(
+        &self,
+        typ: &ArcType,
+        value: Variants,
+        options: &RenderOptions,
+    ) -> StdString {
+        let env = self.global_env().get_env();
+        let debug_level = self.global_env().get_debug_level();
+        ValuePrinter::new(&*env, typ, value, &debug_level)
+            .width(80)
+            .options(options)
+            .to_string()
+    }
+
+    /// Returns the debugger of this thread, installing its hook the first time it is used
+    fn debugger(&self) -> Debugger {
+        let mut debugger = self.debugger.lock().unwrap();
+        debugger
+            .get_or_insert_with(|| {
+                let debugger = Debugger::new();
+                let mut context = self.owned_context();
+                context.set_hook(Some(debugger.hook()));
+                context.set_hook_mask(HookFlags::LINE_FLAG);
+                debugger
+            })
+            .clone()
+    }
+
+    /// Starts recording the calls made by this thread and the time spent in each function, or
+    /// stops and discards the recording. While profiling is disabled function calls only check
+    /// that it is disabled.
+    pub fn set_profiling(&self, enabled: bool) {
+        let mut context = self.owned_context();
+        if !enabled {
+            context.hook.profiler = None;
+        } else if context.hook.profiler.is_none() {
+            context.hook.profiler = Some(Profiler::default());
+        }
+    }
+
+    /// Returns a record for each function called since profiling was enabled, the function
+    /// which spent the most time executing itself first. Calls which have not returned yet are
+    /// counted but their time is not.
+    pub fn profile_report(&self) -> Vec<ProfileRecord> {
+        self.owned_context()
+            .hook
+            .profiler
+            .as_ref()
+            .map_or_else(Vec::new, |profiler| profiler.report())
+    }
+
+    /// Returns the time spent in each call stack since profiling was enabled in the folded stack
+    /// format read by flamegraph tools. Each line is a call stack of `module:function` frames
+    /// separated by `;`, followed by the nanoseconds spent at the top of that stack.
+    pub fn profile_folded(&self) -> StdString {
+        self.owned_context()
+            .hook
+            .profiler
+            .as_ref()
+            .map_or_else(StdString::new, |profiler| profiler.folded())
+    }
+
+    /// Stops this thread whenever it reaches the (zero-indexed) `line` of `module`, passing a
+    /// `DebugSnapshot` to the callback set with `set_debug_callback`. Functions are matched on
+    /// the module which defined them so breakpoints work in imported modules as well.
+    ///
+    /// The debugger replaces any hook set with `Context::set_hook`.
+    pub fn set_breakpoint(&self, module: &str, line: Line) {
+        self.debugger().set_breakpoint(module, line)
+    }
+
+    /// Removes a breakpoint set with `set_breakpoint`, returning `false` if it did not exist
+    pub fn remove_breakpoint(&self, module: &str, line: Line) -> bool {
+        self.debugger().remove_breakpoint(module, line)
+    }
+
+    /// Sets the function which is called each time the thread stops at a breakpoint or after a
+    /// step. Without a callback the thread is suspended as if `DebugAction::Suspend` were
+    /// returned.
+    pub fn set_debug_callback(&self, callback: Option<DebugCallback>) {
+        self.debugger().set_callback(callback)
+    }
+
+    /// Resumes a thread suspended by the debugger, stopping at the next breakpoint
+    pub fn continue_to_breakpoint(&self) {
+        self.debugger().resume(DebugAction::Continue)
+    }
+
+    /// Resumes a thread suspended by the debugger, see `DebugAction::StepInto`
+    pub fn step_into(&self) {
+        self.debugger().resume(DebugAction::StepInto)
+    }
+
+    /// Resumes a thread suspended by the debugger, see `DebugAction::StepOver`
+    pub fn step_over(&self) {
+        self.debugger().resume(DebugAction::StepOver)
+    }
+
+    /// Resumes a thread suspended by the debugger, see `DebugAction::StepOut`
+    pub fn step_out(&self) {
+        self.debugger().resume(DebugAction::StepOut)
     }
 
     #[doc(hidden)]
@@ -756,6 +1127,11 @@ impl Thread {
         &self.global_state
     }
 
+    /// Returns `true` if `self` and `other` are threads of the same vm
+    pub(crate) fn shares_global_state_with(&self, other: &Thread) -> bool {
+        Arc::ptr_eq(&self.global_state, &other.global_state)
+    }
+
     pub fn current_context(&self) -> ActiveThread {
         ActiveThread {
             thread: self,
@@ -861,15 +1237,26 @@ where
     {
         let self_ = RootedThread::root(self.borrow());
         let level = self_.context().stack.get_frames().len();
+        let interrupt = self_.interrupt.clone();
 
-        Box::new(self.call_thunk(closure).or_else(move |mut err| {
+        let call = self.call_thunk(closure).or_else(move |mut err| {
             let mut context = self_.context();
             let stack = StackFrame::<State>::current(&mut context.stack);
             let new_trace = reset_stack(stack, level)?;
-            if let Error::Panic(_, ref mut trace) = err {
-                *trace = Some(new_trace);
+            context
+                .poll_fns
+                .retain(|poll_fn| (poll_fn.frame_index as usize) < level);
+            match err {
+                Error::Panic(_, ref mut trace) | Error::IO(_, ref mut trace) => {
+                    *trace = Some(new_trace)
+                }
+                _ => (),
             }
             Err(err)
+        });
+        Box::new(call.then(move |result| {
+            finish_top_call(&interrupt, level);
+            result
         }))
     }
 
@@ -885,14 +1272,25 @@ where
     {
         let self_ = RootedThread::root(self.borrow());
         let level = self_.context().stack.get_frames().len();
-        Box::new(self.execute_io(value).or_else(move |mut err| {
+        let interrupt = self_.interrupt.clone();
+        let call = self.execute_io(value).or_else(move |mut err| {
             let mut context = self_.context();
             let stack = StackFrame::<State>::current(&mut context.stack);
             let new_trace = reset_stack(stack, level)?;
-            if let Error::Panic(_, ref mut trace) = err {
-                *trace = Some(new_trace);
+            context
+                .poll_fns
+                .retain(|poll_fn| (poll_fn.frame_index as usize) < level);
+            match err {
+                Error::Panic(_, ref mut trace) | Error::IO(_, ref mut trace) => {
+                    *trace = Some(new_trace)
+                }
+                _ => (),
             }
             Err(err)
+        });
+        Box::new(call.then(move |result| {
+            finish_top_call(&interrupt, level);
+            result
         }))
     }
 
@@ -918,6 +1316,10 @@ where
     /// `owner` is theread that owns `value` which is not necessarily the same as `self`
     fn deep_clone_value(&self, owner: &Thread, value: Variants) -> Result<Value>;
 
+    /// Like `deep_clone_value` but `owner` may also be a thread of another vm, in which case only
+    /// plain data can be cloned (see `Cloner::migrate`)
+    fn migrate_value(&self, owner: &Thread, value: Variants) -> Result<Value>;
+
     fn can_share_values_with(&self, gc: &mut Gc, other: &Thread) -> bool;
 }
 
@@ -955,6 +1357,16 @@ impl ThreadInternal for Thread {
                 instruction_index: 0,
             },
         );
+        {
+            let context = &mut *context;
+            if let Some(ref mut profiler) = context.hook.profiler {
+                profiler.enter(
+                    &closure.function.name,
+                    Some(&closure.function.debug_info.source_name),
+                    context.stack.get_frames().len(),
+                );
+            }
+        }
         match try_future!(context.execute(), Either::A) {
             Async::Ready(context) => {
                 let mut context = context.unwrap();
@@ -998,8 +1410,26 @@ impl ThreadInternal for Thread {
         mut context: OwnedContext<'b>,
         args: VmIndex,
     ) -> Result<Async<Option<OwnedContext<'b>>>> {
-        context.borrow_mut().do_call(args)?;
-        context.execute()
+        // The function and its arguments are consumed by the call
+        let frames = context.stack.get_frames().len();
+        let values = context.stack.len() - args - 1;
+        let result = match context.borrow_mut().do_call(args) {
+            Ok(()) => context.execute(),
+            Err(err) => Err(err),
+        };
+        result.map_err(|err| {
+            // Discard the frames of the failed call so that the thread can be called again
+            // instead of resuming them once the next call returns
+            let mut context = self.owned_context();
+            context.stack.unwind(frames, values);
+            context
+                .poll_fns
+                .retain(|poll_fn| (poll_fn.frame_index as usize) < frames);
+            if let Some(ref mut profiler) = context.hook.profiler {
+                profiler.exit(frames);
+            }
+            err
+        })
     }
 
     fn resume(&self) -> Result<Async<OwnedContext>> {
@@ -1008,7 +1438,17 @@ impl ThreadInternal for Thread {
             // Only the top level frame left means that the thread has finished
             return Err(Error::Dead);
         }
-        context = try_ready!(context.execute()).unwrap();
+        context = match context.execute()? {
+            Async::Ready(context) => context.unwrap(),
+            Async::NotReady => {
+                // Only a future that is being waited on guarantees that we are inside a task,
+                // threads can also be suspended by hooks or by yielding
+                if !self.owned_context().poll_fns.is_empty() {
+                    self.interrupt.register();
+                }
+                return Ok(Async::NotReady);
+            }
+        };
         Ok(Async::Ready(context))
     }
 
@@ -1034,15 +1474,23 @@ impl ThreadInternal for Thread {
         cloner.deep_clone(&value.get_value())
     }
 
+    fn migrate_value(&self, owner: &Thread, value: Variants) -> Result<Value> {
+        if self.shares_global_state_with(owner) {
+            return self.deep_clone_value(owner, value);
+        }
+        let mut context = self.owned_context();
+        crate::value::Cloner::new(self, &mut context.gc)
+            .migrate()
+            .deep_clone(&value.get_value())
+    }
+
     fn can_share_values_with(&self, gc: &mut Gc, other: &Thread) -> bool {
         if self as *const Thread == other as *const Thread {
             return true;
         }
         // If the threads do not share the same global state then they are disjoint and can't share
         // values
-        if &*self.global_state as *const GlobalVmState
-            != &*other.global_state as *const GlobalVmState
-        {
+        if !self.shares_global_state_with(other) {
             return false;
         }
         // Otherwise the threads might be able to share values but only if they are on the same
@@ -1170,6 +1618,13 @@ impl<'a> StackInfo<'a> {
         }
     }
 
+    /// Returns the value of `local`, which must be one of the locals returned by `locals`
+    pub(crate) fn local_value(&self, local: &Local) -> Option<Variants> {
+        self.info
+            .stack
+            .get_variant(self.frame().offset + local.index)
+    }
+
     /// Returns a slice with information about the values bound to this closure
     pub fn upvars(&self) -> &[UpvarInfo] {
         match self.frame().state {
@@ -1195,6 +1650,7 @@ struct Hook {
     flags: HookFlags,
     // The index of the last executed instruction
     previous_instruction_index: usize,
+    profiler: Option<Profiler>,
 }
 
 struct PollFn {
@@ -1220,10 +1676,18 @@ pub struct Context {
     #[cfg_attr(feature = "serde_derive", serde(skip))]
     hook: Hook,
     max_stack_size: VmIndex,
+    /// The number of instructions which may be executed before `Error::OutOfFuel` is returned
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    fuel: Option<u64>,
 
     /// Stack of polling functions used for extern functions returning futures
     #[cfg_attr(feature = "serde_derive", serde(skip))]
     poll_fns: Vec<PollFn>,
+
+    /// Error returned by an extern function which is kept as is instead of being pushed to the
+    /// stack (see `api::push_error`)
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    pub(crate) error: Option<Error>,
 }
 
 impl Context {
@@ -1235,9 +1699,12 @@ impl Context {
                 function: None,
                 flags: HookFlags::empty(),
                 previous_instruction_index: usize::max_value(),
+                profiler: None,
             },
             max_stack_size: VmIndex::max_value(),
+            fuel: None,
             poll_fns: Vec::new(),
+            error: None,
         }
     }
 
@@ -1335,6 +1802,17 @@ impl Context {
         self.max_stack_size = limit;
     }
 
+    /// Limits the number of instructions that may be executed by this context. Once the limit is
+    /// reached the running code is aborted with `Error::OutOfFuel`. `None` removes the limit.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Returns the fuel which remains or `None` if no limit is set
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
     pub fn stacktrace(&self, frame_level: usize) -> crate::stack::Stacktrace {
         self.stack.stacktrace(frame_level)
     }
@@ -1442,6 +1920,12 @@ impl<'b> DerefMut for OwnedContext<'b> {
 
 impl<'b> OwnedContext<'b> {
     fn exit_scope(mut self) -> StdResult<OwnedContext<'b>, ()> {
+        {
+            let context = &mut *self;
+            if let Some(ref mut profiler) = context.hook.profiler {
+                profiler.exit(context.stack.get_frames().len() - 1);
+            }
+        }
         let exists = StackFrame::<State>::current(&mut self.stack)
             .exit_scope()
             .is_ok();
@@ -1453,9 +1937,10 @@ impl<'b> OwnedContext<'b> {
     }
 
     fn execute(self) -> Result<Async<Option<OwnedContext<'b>>>> {
+        self.thread.interrupt.start();
         let mut maybe_context = Some(self);
         while let Some(mut context) = maybe_context {
-            if context.thread.interrupted() {
+            if context.thread.interrupt.take_cancelled() {
                 return Err(Error::Interrupted);
             }
             debug!("STACK\n{:?}", context.stack.get_frames());
@@ -1588,7 +2073,13 @@ impl<'b> OwnedContext<'b> {
                 // Make sure that the stack is not borrowed during the external function call
                 // Necessary since we do not know what will happen during the function call
                 let thread = self.thread;
+                let finalizers = self.gc.take_finalizers();
                 drop(self);
+                // Finalizers may use the thread so they are run here instead of during the
+                // collection which found their values to be unreachable
+                if !finalizers.is_empty() {
+                    unsafe { finalizers.run(thread) }
+                }
                 status = (function.function)(thread);
 
                 if status == Status::Yield {
@@ -1598,15 +2089,16 @@ impl<'b> OwnedContext<'b> {
                 self = thread.owned_context();
 
                 if status == Status::Error {
-                    return match self.stack.pop().get_repr() {
-                        String(s) => {
-                            Err(Error::Panic(s.to_string(), Some(self.stack.stacktrace(0))))
-                        }
-                        _ => Err(Error::Message(format!(
-                            "Unexpected error calling function `{}`",
-                            function.id
-                        ))),
-                    };
+                    let err = self.stack.pop();
+                    let stacktrace = self.stack.stacktrace(0);
+                    let error = self.error.take();
+                    return Err(crate::api::pop_error(thread, error, &err, stacktrace)
+                        .unwrap_or_else(|| {
+                            Error::Message(format!(
+                                "Unexpected error calling function `{}`",
+                                function.id
+                            ))
+                        }));
                 }
 
                 // The `poll_fn` at the top may be for a stack frame at a lower level, return to the
@@ -1686,13 +2178,21 @@ impl<'b> OwnedContext<'b> {
         match status {
             Status::Ok => Ok(Async::Ready(self)),
             Status::Yield => Ok(Async::NotReady),
-            Status::Error => match self.stack.pop().get_repr() {
-                String(s) => Err(Error::Panic(s.to_string(), Some(self.stack.stacktrace(0)))),
-                _ => Err(Error::Message(format!(
-                    "Unexpected error calling function `{}`",
-                    function.id
-                ))),
-            },
+            Status::Error => {
+                let err = self.stack.pop();
+                let stacktrace = self.stack.stacktrace(0);
+                let error = self.error.take();
+                Err(
+                    crate::api::pop_error(self.thread, error, &err, stacktrace).unwrap_or_else(
+                        || {
+                            Error::Message(format!(
+                                "Unexpected error calling function `{}`",
+                                function.id
+                            ))
+                        },
+                    ),
+                )
+            }
         }
     }
 
@@ -1704,6 +2204,7 @@ impl<'b> OwnedContext<'b> {
             gc: &mut context.gc,
             stack: StackFrame::current(&mut context.stack),
             hook: &mut context.hook,
+            fuel: &mut context.fuel,
         }
     }
 }
@@ -1713,6 +2214,7 @@ struct ExecuteContext<'b, S: StackState = ClosureState> {
     stack: StackFrame<'b, S>,
     gc: &'b mut Gc,
     hook: &'b mut Hook,
+    fuel: &'b mut Option<u64>,
 }
 
 impl<'b> ExecuteContext<'b> {
@@ -1733,6 +2235,13 @@ impl<'b> ExecuteContext<'b> {
         while let Some(&instr) = instructions.get(index) {
             debug_instruction(&self.stack, index, instr);
 
+            if let Some(ref mut fuel) = *self.fuel {
+                if *fuel == 0 {
+                    return Err(Error::OutOfFuel);
+                }
+                *fuel -= 1;
+            }
+
             if self.hook.flags.contains(HookFlags::LINE_FLAG) {
                 if let Some(ref mut hook) = self.hook.function {
                     let current_line = function.debug_info.source_map.line(index);
@@ -2022,6 +2531,21 @@ impl<'b> ExecuteContext<'b> {
                         continue;
                     }
                 },
+                Switch(n) => match self.stack.pop().get_repr() {
+                    ValueRepr::Int(i) if 0 <= i && i < VmInt::from(n) => {
+                        index += 1 + i as usize;
+                        continue;
+                    }
+                    ValueRepr::Int(_) => {
+                        index += 1 + n as usize;
+                        continue;
+                    }
+                    _ => {
+                        return Err(Error::Message(
+                            "Op Switch called on non integer".to_string(),
+                        ));
+                    }
+                },
                 Pop(n) => self.stack.pop_many(n),
                 Slide(n) => {
                     debug!("{:?}", &self.stack[..]);
@@ -2116,6 +2640,11 @@ impl<'b> ExecuteContext<'b> {
             self.stack.frame.state,
             function.name
         );
+        // Tail calls leave the profiler alone so that the callee takes over the time from the
+        // exact point where the caller stopped
+        if let Some(ref mut profiler) = self.hook.profiler {
+            profiler.exit(self.stack.stack.get_frames().len() - 1);
+        }
         let (stack_exists, mut context) = {
             let r = self.exit_scope();
             (
@@ -2159,6 +2688,7 @@ impl<'b> ExecuteContext<'b, State> {
             stack: self.stack.from_state(),
             gc: self.gc,
             hook: self.hook,
+            fuel: self.fuel,
         }
     }
 }
@@ -2178,6 +2708,7 @@ where
             stack,
             gc: self.gc,
             hook: self.hook,
+            fuel: self.fuel,
         }
     }
 
@@ -2197,6 +2728,7 @@ where
                     stack,
                     gc: self.gc,
                     hook: self.hook,
+                    fuel: self.fuel,
                 })
             }
             Err(stack) => Err(ExecuteContext {
@@ -2204,11 +2736,23 @@ where
                 stack: StackFrame::current(stack),
                 gc: self.gc,
                 hook: self.hook,
+                fuel: self.fuel,
             }),
         }
     }
 
     fn execute_callable(self, function: &Callable, excess: bool) -> Result<()> {
+        if let Some(ref mut profiler) = self.hook.profiler {
+            let depth = self.stack.stack.get_frames().len() + 1;
+            match *function {
+                Callable::Closure(closure) => profiler.enter(
+                    &closure.function.name,
+                    Some(&closure.function.debug_info.source_name),
+                    depth,
+                ),
+                Callable::Extern(ref ext) => profiler.enter(&ext.id, None, depth),
+            }
+        }
         match *function {
             Callable::Closure(closure) => {
                 let mut next = self.enter_scope(
@@ -2483,6 +3027,16 @@ impl<'vm> ActiveThread<'vm> {
         &mut self.context.as_mut().unwrap().gc
     }
 }
+
+/// Marks the thread of `interrupt` as idle once a call made at the `level` of an idle thread
+/// returns. Calls made from inside another call, such as those of `io.run_expr`, leave the thread
+/// running.
+fn finish_top_call(interrupt: &InterruptToken, level: usize) {
+    if level == 1 {
+        interrupt.finish();
+    }
+}
+
 #[doc(hidden)]
 pub fn reset_stack(mut stack: StackFrame<State>, level: usize) -> Result<crate::stack::Stacktrace> {
     let trace = stack.stack.stacktrace(level);
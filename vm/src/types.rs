@@ -91,6 +91,10 @@ pub enum Instruction {
     /// Jumps to the instruction at `index` in the currently executing function if `True` is at the
     /// top of the stack and pops that value.
     CJump(VmIndex),
+    /// Pops the integer `i` at the top of the stack and jumps to the `i`th of the `n` instructions
+    /// which follows this instruction, these are expected to be `Jump` instructions which form a
+    /// jump table. If `i` lies outside of `0..n` the jump table is skipped.
+    Switch(VmIndex),
     /// Pops the top `n` values from the stack.
     Pop(VmIndex),
     /// Pops the top value from the stack, then pops `n` more values, finally the first value is
@@ -156,7 +160,7 @@ impl Instruction {
             Split => -1,
             TestTag(_) | TestPolyTag(_) => 1,
             Jump(_) => 0,
-            CJump(_) => -1,
+            CJump(_) | Switch(_) => -1,
             Pop(n) => -(n as i32),
             Slide(n) => -(n as i32),
             NewVariant { .. } => 1,
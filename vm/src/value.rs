@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::collections::hash_map::Entry;
 use std::fmt;
 use std::iter;
@@ -16,10 +17,12 @@ use crate::base::types::{ArcType, Type, TypeEnv, TypeExt};
 use crate::base::DebugLevel;
 use crate::types::*;
 
+use crate::api::generic::A;
 use crate::array::Array;
 use crate::compiler::DebugInfo;
 use crate::gc::{DataDef, Gc, GcPtr, Generation, Move, Traverseable, WriteOnly};
 use crate::interner::InternedStr;
+use crate::reference::Reference;
 use crate::thread::{Status, Thread};
 use crate::{Error, Result, Variants};
 
@@ -31,6 +34,24 @@ pub trait Userdata: ::mopa::Any + Traverseable + fmt::Debug + Send + Sync {
         let _ = deep_cloner;
         Err(Error::Message("Userdata cannot be cloned".into()))
     }
+
+    /// Called after a collection has found this value to be unreachable, right before it is
+    /// dropped. `thread` is the thread which owns the heap the value was allocated on.
+    ///
+    /// Finalizers are not run during the collection itself but once the heap has been released,
+    /// either when `Thread::collect` returns or before the next extern function is called on
+    /// `thread`. `thread` may therefore be used to allocate values or call gluon functions, but:
+    ///
+    /// * The garbage collected values that this value refers to may already have been freed
+    ///   so they must not be accessed.
+    /// * No references to `self` or values it refers to may be stored anywhere, the value can not
+    ///   be resurrected.
+    ///
+    /// Values which are still alive when their thread is dropped are dropped without being
+    /// finalized.
+    fn finalize(&mut self, thread: &Thread) {
+        let _ = thread;
+    }
 }
 
 impl PartialEq for Userdata {
@@ -558,11 +579,35 @@ enum Prec {
 }
 use self::Prec::*;
 
+/// Options for rendering a value with `Thread::render_value`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// How many levels of nested values are rendered before they are replaced by `..`
+    pub max_depth: i32,
+    /// How many elements of an array or characters of a string are rendered before the rest
+    /// is replaced by `...`
+    pub max_len: usize,
+    /// Annotates records and variants with their type
+    pub show_types: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            max_depth: 50,
+            max_len: usize::max_value(),
+            show_types: false,
+        }
+    }
+}
+
 pub struct ValuePrinter<'a> {
     pub typ: &'a ArcType,
     pub env: &'a TypeEnv<Type = ArcType>,
     pub value: Variants<'a>,
     pub max_level: i32,
+    pub max_len: usize,
+    pub show_types: bool,
     pub width: usize,
     pub debug_level: &'a DebugLevel,
 }
@@ -579,6 +624,8 @@ impl<'t> ValuePrinter<'t> {
             env,
             value,
             max_level: 50,
+            max_len: usize::max_value(),
+            show_types: false,
             width: 80,
             debug_level,
         }
@@ -589,6 +636,22 @@ impl<'t> ValuePrinter<'t> {
         self
     }
 
+    pub fn max_len(&mut self, max_len: usize) -> &mut ValuePrinter<'t> {
+        self.max_len = max_len;
+        self
+    }
+
+    pub fn show_types(&mut self, show_types: bool) -> &mut ValuePrinter<'t> {
+        self.show_types = show_types;
+        self
+    }
+
+    pub fn options(&mut self, options: &RenderOptions) -> &mut ValuePrinter<'t> {
+        self.max_level(options.max_depth)
+            .max_len(options.max_len)
+            .show_types(options.show_types)
+    }
+
     pub fn width(&mut self, width: usize) -> &mut ValuePrinter<'t> {
         self.width = width;
         self
@@ -603,11 +666,16 @@ struct InternalPrinter<'a, 't> {
     arena: &'a Arena<'a>,
     prec: Prec,
     level: i32,
+    max_len: usize,
+    show_types: bool,
     debug_level: &'t DebugLevel,
+    // The values which are currently being printed, used to detect cycles
+    visiting: &'a RefCell<Vec<usize>>,
 }
 
 impl<'a> fmt::Display for ValuePrinter<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let visiting = RefCell::new(Vec::new());
         let arena = Arena::new();
         let mut s = Vec::new();
         InternalPrinter {
@@ -616,7 +684,10 @@ impl<'a> fmt::Display for ValuePrinter<'a> {
             arena: &arena,
             prec: Top,
             level: self.max_level,
+            max_len: self.max_len,
+            show_types: self.show_types,
             debug_level: self.debug_level,
+            visiting: &visiting,
         }
         .pretty(self.value)
         .group()
@@ -634,15 +705,20 @@ impl<'a, 't> InternalPrinter<'a, 't> {
         let arena = self.arena;
         match value.0 {
             _ if self.level == 0 => arena.text(".."),
-            ValueRepr::String(s) => arena.text(format!("{:?}", &s[..])),
-            ValueRepr::Data(ref data) => self.pretty_data(data.tag(), variant_iter(&data.fields)),
+            ValueRepr::String(s) => match s.char_indices().nth(self.max_len) {
+                Some((end, _)) => arena.text(format!("{:?}...", &s[..end])),
+                None => arena.text(format!("{:?}", &s[..])),
+            },
+            ValueRepr::Data(ref data) => self.guard(&**data, || {
+                self.pretty_data(data.tag(), variant_iter(&data.fields))
+            }),
             ValueRepr::Tag(tag) => self.pretty_data(tag, iter::empty()),
             ValueRepr::Function(ref function) => chain![arena;
                 "<extern ",
                 function.id.declared_name().to_string(),
                 ">"
             ],
-            ValueRepr::Closure(ref closure) => match self.debug_level {
+            ValueRepr::Closure(ref closure) => self.guard(&**closure, || match self.debug_level {
                 &DebugLevel::None => chain![arena;
                     "<",
                  arena.text(closure.function.name.declared_name().to_string()),
@@ -663,20 +739,45 @@ impl<'a, 't> InternalPrinter<'a, 't> {
                         }).intersperse(arena.text(","))).nest(INDENT),
                     ">"
                 ],
-            },
-            ValueRepr::Array(ref array) => chain![arena;
+            }),
+            ValueRepr::Array(ref array) => self.guard(&**array, || chain![arena;
                 "[",
-                arena.concat(array.iter().map(|field| {
+                arena.concat(array.iter().take(self.max_len).map(|field| {
                     match **self.typ {
                         Type::App(_, ref args) => self.p(&args[0], Top).pretty(field),
                         _ => arena.text(format!("{:?}", field)),
                     }
+                }).chain(if array.len() > self.max_len {
+                    Some(arena.text("..."))
+                } else {
+                    None
                 }).intersperse(arena.text(",").append(arena.space())))
                     .nest(INDENT),
                 "]"
-            ],
+            ]),
             ValueRepr::PartialApplication(p) => arena.text(format!("{:?}", p)),
-            ValueRepr::Userdata(ref data) => arena.text(format!("{:?}", data)),
+            ValueRepr::Userdata(ref data) => self.guard(&**data, || {
+                // References are printed through the printer so that cycles are detected
+                match data.downcast_ref::<Reference<A>>() {
+                    Some(reference) => {
+                        let value = reference.value();
+                        let value = unsafe { Variants::new(&value) };
+                        let doc = match **self.typ.remove_forall() {
+                            Type::App(_, ref args) if args.len() == 1 => {
+                                self.p(&args[0], Constructor).pretty(value)
+                            }
+                            _ => self.p(&Type::hole(), Constructor).pretty(value),
+                        };
+                        let doc = chain![arena; "ref", arena.space(), doc];
+                        if self.prec >= Constructor {
+                            chain![arena; "(", doc, ")"]
+                        } else {
+                            doc
+                        }
+                    }
+                    None => arena.text(format!("{:?}", data)),
+                }
+            }),
             ValueRepr::Thread(thread) => arena.text(format!("{:?}", thread)),
             ValueRepr::Byte(b) => arena.text(format!("{}", b)),
             ValueRepr::Int(i) => {
@@ -722,6 +823,20 @@ impl<'a, 't> InternalPrinter<'a, 't> {
 
         let typ = remove_aliases_cow(self.env, &mut NullInterner, self.typ);
         let arena = self.arena;
+        let annotate = |doc: DocBuilder<'a, Arena<'a>>| {
+            if self.show_types {
+                let doc = chain![arena;
+                    doc,
+                    arena.space(),
+                    ":",
+                    arena.space(),
+                    self.typ.to_string()
+                ];
+                enclose(self.prec, Constructor, arena, doc)
+            } else {
+                doc
+            }
+        };
         match **typ {
             Type::Record(ref row) => {
                 let mut is_empty = true;
@@ -744,7 +859,7 @@ impl<'a, 't> InternalPrinter<'a, 't> {
                         })
                         .intersperse(arena.space()),
                 );
-                chain![arena;
+                annotate(chain![arena;
                     "{",
                     chain![arena;
                         arena.space(),
@@ -756,25 +871,30 @@ impl<'a, 't> InternalPrinter<'a, 't> {
                         arena.space()
                     },
                     "}"
-                ]
+                ])
             }
             Type::Variant(ref row) => {
                 let type_field = row
                     .row_iter()
                     .nth(tag as usize)
                     .expect("Variant tag is out of bounds");
+                // Constructors store their arguments as a tuple record
+                let arg_types: Vec<_> = match *type_field.typ {
+                    Type::Record(ref args) => args.row_iter().map(|arg| &arg.typ).collect(),
+                    _ => arg_iter(&type_field.typ).collect(),
+                };
                 let mut empty = true;
                 let doc = chain![arena;
                     type_field.name.declared_name().to_string(),
-                    arena.concat(fields.into_iter().zip(arg_iter(&type_field.typ))
+                    arena.concat(fields.into_iter().zip(arg_types)
                         .map(|(field, typ)| {
                             empty = false;
                             arena.space().append(self.p(typ, Constructor).pretty(field))
                         }))
                         .nest(INDENT)
                 ];
-                if empty {
-                    doc
+                if empty || self.show_types {
+                    annotate(doc)
                 } else {
                     enclose(self.prec, Constructor, arena, doc)
                 }
@@ -798,8 +918,28 @@ impl<'a, 't> InternalPrinter<'a, 't> {
             arena: self.arena,
             prec: prec,
             level: self.level - 1,
+            max_len: self.max_len,
+            show_types: self.show_types,
             debug_level: self.debug_level,
+            visiting: self.visiting,
+        }
+    }
+
+    /// Calls `f` to print the value at `ptr` unless it is already being printed further up in
+    /// which case the value contains itself and `<cycle>` is printed instead
+    fn guard<T, F>(&self, ptr: &T, f: F) -> DocBuilder<'a, Arena<'a>>
+    where
+        T: ?Sized,
+        F: FnOnce() -> DocBuilder<'a, Arena<'a>>,
+    {
+        let ptr = ptr as *const T as *const () as usize;
+        if self.visiting.borrow().contains(&ptr) {
+            return self.arena.text("<cycle>");
         }
+        self.visiting.borrow_mut().push(ptr);
+        let doc = f();
+        self.visiting.borrow_mut().pop();
+        doc
     }
 }
 
@@ -1249,6 +1389,23 @@ impl ValueArray {
         }
     }
 
+    /// Returns the value at `index` without checking that it is in bounds.
+    ///
+    /// Calling this with an `index` which is not less than `self.len()` is undefined behavior.
+    pub(crate) unsafe fn get_unchecked(&self, index: usize) -> Variants {
+        let value = match self.repr {
+            Repr::Byte => ValueRepr::Byte(self.unsafe_get_unchecked(index)),
+            Repr::Int => ValueRepr::Int(self.unsafe_get_unchecked(index)),
+            Repr::Float => ValueRepr::Float(self.unsafe_get_unchecked(index)),
+            Repr::String => ValueRepr::String(self.unsafe_get_unchecked(index)),
+            Repr::Array => ValueRepr::Array(self.unsafe_get_unchecked(index)),
+            Repr::Unknown => self.unsafe_get_unchecked(index),
+            Repr::Userdata => ValueRepr::Userdata(self.unsafe_get_unchecked(index)),
+            Repr::Thread => ValueRepr::Thread(self.unsafe_get_unchecked(index)),
+        };
+        Variants::with_root(value.into(), self)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
@@ -1318,6 +1475,10 @@ impl ValueArray {
         self.unsafe_array()[index]
     }
 
+    unsafe fn unsafe_get_unchecked<T: Copy>(&self, index: usize) -> T {
+        *self.unsafe_array().get_unchecked(index)
+    }
+
     unsafe fn unsafe_array<T>(&self) -> &Array<T> {
         ::std::mem::transmute::<&Array<_>, &Array<T>>(&self.array)
     }
@@ -1386,6 +1547,7 @@ pub struct Cloner<'t> {
     thread: &'t Thread,
     gc: &'t mut Gc,
     receiver_generation: Generation,
+    migrate: bool,
 }
 
 impl<'t> Cloner<'t> {
@@ -1395,6 +1557,7 @@ impl<'t> Cloner<'t> {
             thread: thread,
             receiver_generation: gc.generation(),
             gc: gc,
+            migrate: false,
         }
     }
 
@@ -1412,6 +1575,15 @@ impl<'t> Cloner<'t> {
         self
     }
 
+    /// Deep clones the entire value into a thread of another vm. The field names of records and
+    /// the tags of polymorphic variants are interned again in the vm of `thread` and any value
+    /// which can't exist outside of the vm it was created in, such as closures and threads, or
+    /// userdata whose type is not registered in the vm of `thread`, returns an error.
+    pub fn migrate(&mut self) -> &mut Self {
+        self.migrate = true;
+        self.force_full_clone()
+    }
+
     pub(crate) fn deep_clone(&mut self, value: &Value) -> Result<Value> {
         // Only need to clone values which belong to a younger generation than the gc that the new
         // value will live in
@@ -1421,6 +1593,23 @@ impl<'t> Cloner<'t> {
         {
             return Ok(value.clone());
         }
+        if self.migrate {
+            match value.0 {
+                Closure(_) | PartialApplication(_) | Function(_) => {
+                    return Err(Error::Message(
+                        "Functions can't be migrated to another vm, only plain data such as \
+                         numbers, strings, arrays, records and variants can"
+                            .into(),
+                    ));
+                }
+                ValueRepr::Thread(_) => {
+                    return Err(Error::Message(
+                        "Threads can't be migrated to another vm".into(),
+                    ));
+                }
+                _ => (),
+            }
+        }
         let result = match value.0 {
             String(data) => self.deep_clone_str(data),
             ValueRepr::Data(data) => self.deep_clone_data(data).map(ValueRepr::Data),
@@ -1437,7 +1626,9 @@ impl<'t> Cloner<'t> {
             ValueRepr::Byte(i) => Ok(ValueRepr::Byte(i)),
             Int(i) => Ok(Int(i)),
             Float(f) => Ok(Float(f)),
-            ValueRepr::Userdata(userdata) => userdata.deep_clone(self).map(ValueRepr::Userdata),
+            ValueRepr::Userdata(userdata) => {
+                self.deep_clone_userdata(userdata).map(ValueRepr::Userdata)
+            }
             ValueRepr::Thread(_) => Err(Error::Message("Threads cannot be deep cloned yet".into())),
         };
         result.map(Value::from)
@@ -1474,16 +1665,34 @@ impl<'t> Cloner<'t> {
     }
 
     fn deep_clone_data(&mut self, data_ptr: GcPtr<DataStruct>) -> Result<GcPtr<DataStruct>> {
+        let thread = self.thread;
+        let migrate = self.migrate;
         let result = self.deep_clone_ptr(data_ptr, |gc, data| {
             let ptr = if data.is_record() {
-                gc.alloc(RecordDef {
-                    fields: data_ptr.field_names(),
-                    elems: &data.fields,
-                })?
+                if migrate {
+                    let fields = data_ptr
+                        .field_names()
+                        .iter()
+                        .map(|field| thread.global_env().intern(field))
+                        .collect::<Result<Vec<_>>>()?;
+                    gc.alloc(RecordDef {
+                        fields: &fields,
+                        elems: &data.fields,
+                    })?
+                } else {
+                    gc.alloc(RecordDef {
+                        fields: data_ptr.field_names(),
+                        elems: &data.fields,
+                    })?
+                }
             } else {
+                let poly_tag = match data_ptr.poly_tag() {
+                    Some(tag) if migrate => Some(thread.global_env().intern(&tag)?),
+                    poly_tag => poly_tag,
+                };
                 gc.alloc(VariantDef {
                     tag: data.tag,
-                    poly_tag: data_ptr.poly_tag(),
+                    poly_tag,
                     elems: &data.fields,
                 })?
             };
@@ -1505,6 +1714,16 @@ impl<'t> Cloner<'t> {
     }
 
     fn deep_clone_userdata(&mut self, ptr: GcPtr<Box<Userdata>>) -> Result<GcPtr<Box<Userdata>>> {
+        if self.migrate {
+            let id = ::mopa::Any::get_type_id(&**ptr);
+            if !self.thread.global_env().is_registered_type_id(id) {
+                return Err(Error::Message(format!(
+                    "The userdata `{:?}` can't be migrated as its type is not registered in the \
+                     target vm",
+                    ptr
+                )));
+            }
+        }
         ptr.deep_clone(self)
     }
 
@@ -1533,6 +1752,12 @@ impl<'t> Cloner<'t> {
             Err(new_array) => {
                 unsafe {
                     match new_array.repr() {
+                        Repr::String if self.migrate => deep_clone_elems(new_array, |e: &GcStr| {
+                            match self.deep_clone_str(*e)? {
+                                String(s) => Ok(s),
+                                _ => unreachable!(),
+                            }
+                        }),
                         Repr::Byte | Repr::Int | Repr::Float | Repr::String => Ok(()),
                         Repr::Array => deep_clone_elems(new_array, |e| self.deep_clone_array(*e)),
                         Repr::Unknown => deep_clone_elems(new_array, |e| self.deep_clone(e)),
@@ -1746,5 +1971,4 @@ mod tests {
     fn value_size() {
         assert!(::std::mem::size_of::<Value>() <= 16);
     }
-
 }
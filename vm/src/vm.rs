@@ -23,6 +23,7 @@ use crate::base::{
 use crate::{
     api::{ValueRef, IO},
     compiler::{CompiledFunction, CompiledModule, CompilerEnv, Variable},
+    core::inline::InlineFunctions,
     gc::{Gc, GcPtr, Generation, Move, Traverseable},
     interner::{InternedStr, Interner},
     lazy::Lazy,
@@ -180,6 +181,10 @@ pub struct GlobalVmState {
 
     #[cfg_attr(feature = "serde_derive", serde(skip))]
     debug_level: RwLock<DebugLevel>,
+
+    /// The functions of each module which may be inlined into the modules that import it
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    inline_functions: RwLock<FnvMap<StdString, Arc<InlineFunctions>>>,
 }
 
 impl Traverseable for GlobalVmState {
@@ -423,6 +428,7 @@ impl GlobalVmStateBuilder {
             type_cache: TypeCache::default(),
             generation_0_threads: RwLock::new(Vec::new()),
             debug_level: RwLock::new(DebugLevel::default()),
+            inline_functions: RwLock::new(FnvMap::default()),
         };
         vm.add_types().unwrap();
         vm
@@ -492,6 +498,10 @@ impl GlobalVmState {
         self.typeids.read().unwrap().get(&id).cloned()
     }
 
+    pub(crate) fn is_registered_type_id(&self, id: TypeId) -> bool {
+        self.typeids.read().unwrap().contains_key(&id)
+    }
+
     /// Checks if a global exists called `name`
     pub fn global_exists(&self, name: &str) -> bool {
         self.env.read().unwrap().globals.get(name).is_some()
@@ -521,6 +531,31 @@ impl GlobalVmState {
         Ok(())
     }
 
+    /// Removes the global `name`, returning `true` if it existed. Values which already refer to the
+    /// global keep using the removed value.
+    pub fn remove_global(&self, name: &str) -> bool {
+        self.inline_functions.write().unwrap().remove(name);
+        self.env.write().unwrap().globals.remove(name).is_some()
+    }
+
+    /// Stores the functions of the module `name` which may be inlined into other modules
+    pub fn set_inline_functions(&self, name: &str, functions: Option<InlineFunctions>) {
+        let mut inline_functions = self.inline_functions.write().unwrap();
+        match functions {
+            Some(functions) => {
+                inline_functions.insert(StdString::from(name), Arc::new(functions));
+            }
+            None => {
+                inline_functions.remove(name);
+            }
+        }
+    }
+
+    /// Returns the functions of the module `name` which may be inlined into other modules
+    pub fn get_inline_functions(&self, name: &str) -> Option<Arc<InlineFunctions>> {
+        self.inline_functions.read().unwrap().get(name).cloned()
+    }
+
     // Currently necessary for the language server
     #[doc(hidden)]
     pub fn set_dummy_global(&self, id: &str, typ: ArcType, metadata: Metadata) -> Result<()> {
@@ -569,8 +604,10 @@ impl GlobalVmState {
     ) -> Result<ArcType> {
         let mut env = self.env.write().unwrap();
         let type_infos = &mut env.type_infos;
-        if type_infos.id_to_type.contains_key(name.declared_name()) {
-            Err(Error::TypeAlreadyExists(name.declared_name().into()))
+        // Key the type by its module qualified name so that extern (sub)modules can each
+        // register a type with the same short name
+        if type_infos.id_to_type.contains_key(name.definition_name()) {
+            Err(Error::TypeAlreadyExists(name.definition_name().into()))
         } else {
             self.typeids
                 .write()
@@ -579,7 +616,7 @@ impl GlobalVmState {
             let t = alias.clone().into_type();
             type_infos
                 .id_to_type
-                .insert(name.declared_name().into(), alias);
+                .insert(name.definition_name().into(), alias);
             Ok(t)
         }
     }